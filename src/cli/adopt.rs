@@ -0,0 +1,219 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::cli::push::{self, PushOptions};
+use crate::db::{establish_connection, ItemManager};
+use crate::status;
+
+/// Directory-wide sidecar mapping entry names to their tags and note
+const METADATA_FILE: &str = "metadata.json";
+
+/// Extension of a per-entry sidecar (`report.pdf.meta`) holding a front-matter block
+const SIDECAR_EXTENSION: &str = "meta";
+
+/// Tags and note for one adopted entry
+#[derive(Debug, Default, Deserialize, PartialEq)]
+struct Sidecar {
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+/// Push every file and directory in `dir` as a separate item, in name order.
+/// Tags and notes are read from `metadata.json` in `dir` and from `<name>.meta` sidecars;
+/// `tags` are added to every item. Sidecars are removed along with the entries they describe.
+pub fn adopt(dir: &str, tags: Option<Vec<String>>, dry_run: bool) -> Result<()> {
+    let dir = PathBuf::from(dir);
+    if !dir.is_dir() {
+        return Err(anyhow!("Not a directory: {}", dir.display()));
+    }
+
+    let metadata_path = dir.join(METADATA_FILE);
+    let mut metadata: HashMap<String, Sidecar> = if metadata_path.is_file() {
+        serde_json::from_str(&std::fs::read_to_string(&metadata_path)?)
+            .map_err(|e| anyhow!("Invalid {}: {}", metadata_path.display(), e))?
+    } else {
+        HashMap::new()
+    };
+
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name == METADATA_FILE || is_sidecar(&path) {
+            continue;
+        }
+        entries.push((name, path));
+    }
+    entries.sort();
+
+    if entries.is_empty() {
+        status!("Nothing to adopt in {}", dir.display());
+        return Ok(());
+    }
+
+    let mut adopted = 0;
+    for (name, path) in entries {
+        let sidecar_path = sidecar_path(&path);
+        let mut sidecar = metadata.remove(&name).unwrap_or_default();
+        if sidecar_path.is_file() {
+            let front_matter = parse_front_matter(&std::fs::read_to_string(&sidecar_path)?);
+            for tag in front_matter.tags {
+                if !sidecar.tags.contains(&tag) {
+                    sidecar.tags.push(tag);
+                }
+            }
+            sidecar.note = front_matter.note.or(sidecar.note);
+        }
+
+        let mut item_tags = tags.clone().unwrap_or_default();
+        for tag in sidecar.tags {
+            if !item_tags.contains(&tag) {
+                item_tags.push(tag);
+            }
+        }
+
+        if dry_run {
+            status!(
+                "Would adopt {}{}{}",
+                name,
+                if item_tags.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", item_tags.join(", "))
+                },
+                sidecar
+                    .note
+                    .as_ref()
+                    .map(|note| format!(" - {}", note))
+                    .unwrap_or_default()
+            );
+            continue;
+        }
+
+        let options = PushOptions {
+            tags: Some(item_tags),
+            ..Default::default()
+        };
+        let Some(item_id) = push::push(&path.to_string_lossy(), options)? else {
+            continue;
+        };
+        if sidecar.note.is_some() {
+            let conn = establish_connection()?;
+            ItemManager::set_note(&conn, item_id, sidecar.note.as_deref())?;
+        }
+        if sidecar_path.is_file() {
+            std::fs::remove_file(&sidecar_path)?;
+        }
+        adopted += 1;
+    }
+
+    if !dry_run {
+        status!("Adopted {} item(s) from {}", adopted, dir.display());
+    }
+
+    Ok(())
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(SIDECAR_EXTENSION);
+    PathBuf::from(sidecar)
+}
+
+/// Whether `path` is the sidecar of another entry (`x.meta` next to `x`)
+fn is_sidecar(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == SIDECAR_EXTENSION) && path.with_extension("").exists()
+}
+
+/// Read `tags:` and `note:` from a front-matter block:
+///
+/// ```text
+/// ---
+/// tags: [invoices, 2024]
+/// note: Scanned from the paper copy
+/// ---
+/// ```
+///
+/// Text after the block is the note if there is no `note:` line.
+fn parse_front_matter(text: &str) -> Sidecar {
+    let mut sidecar = Sidecar::default();
+    let mut lines = text.lines().peekable();
+
+    if lines.peek().map(|line| line.trim()) == Some("---") {
+        lines.next();
+    }
+
+    for line in lines.by_ref() {
+        let line = line.trim();
+        if line == "---" {
+            break;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = unquote(value.trim());
+        match key.trim() {
+            "tags" => {
+                sidecar.tags = value
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split(',')
+                    .map(|tag| unquote(tag.trim()).to_string())
+                    .filter(|tag| !tag.is_empty())
+                    .collect();
+            }
+            "note" if !value.is_empty() => sidecar.note = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    let body = lines.collect::<Vec<_>>().join("\n");
+    if sidecar.note.is_none() && !body.trim().is_empty() {
+        sidecar.note = Some(body.trim().to_string());
+    }
+
+    sidecar
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_front_matter() {
+        assert_eq!(
+            parse_front_matter("---\ntags: [invoices, \"2024\"]\nnote: \"Paper copy\"\n---\n"),
+            Sidecar {
+                tags: vec!["invoices".to_string(), "2024".to_string()],
+                note: Some("Paper copy".to_string()),
+            }
+        );
+
+        // Without a note line, the text after the block is the note
+        assert_eq!(
+            parse_front_matter("---\ntags: taxes\n---\nSort out\nbefore April\n"),
+            Sidecar {
+                tags: vec!["taxes".to_string()],
+                note: Some("Sort out\nbefore April".to_string()),
+            }
+        );
+
+        assert_eq!(parse_front_matter(""), Sidecar::default());
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use clap::CommandFactory;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+
+use crate::cli::Cli;
+use crate::config;
+
+/// Global options that take a separate value, which must not be mistaken for the command name
+const GLOBAL_OPTIONS_WITH_VALUE: &[&str] = &["--remote", "--symlink-fallback"];
+
+/// Replace a command alias from the `[aliases]` config table with its definition, like git
+/// does: with `inbox = "list -t inbox"`, `fstk inbox --format json` runs
+/// `fstk list -t inbox --format json`.
+/// Built-in commands always win over aliases. The config is only read when the command is not
+/// a built-in one.
+pub fn expand_args(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let Some(position) = command_position(&args) else {
+        return Ok(args);
+    };
+    let is_builtin = |name: &str| Cli::command().find_subcommand(name).is_some();
+    match args[position].to_str() {
+        Some(name) if !is_builtin(name) => {}
+        _ => return Ok(args),
+    }
+
+    let aliases = config::load()?.aliases;
+    expand(args, position, &aliases, is_builtin)
+}
+
+/// Index of the command name in `args`, skipping the program name and global options
+fn command_position(args: &[OsString]) -> Option<usize> {
+    let mut index = 1;
+    while index < args.len() {
+        let arg = args[index].to_string_lossy();
+        if arg == "--" {
+            return None;
+        }
+        if GLOBAL_OPTIONS_WITH_VALUE.contains(&arg.as_ref()) {
+            index += 2;
+            continue;
+        }
+        if !arg.starts_with('-') {
+            return Some(index);
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Expand the alias at `position`; aliases may refer to other aliases, but not in a loop.
+fn expand(
+    mut args: Vec<OsString>,
+    position: usize,
+    aliases: &BTreeMap<String, String>,
+    is_builtin: impl Fn(&str) -> bool,
+) -> Result<Vec<OsString>> {
+    let mut seen = Vec::new();
+    while let Some(name) = args[position].to_str().map(str::to_string) {
+        if is_builtin(&name) {
+            break;
+        }
+        let Some(definition) = aliases.get(&name) else {
+            break;
+        };
+        if seen.contains(&name) {
+            return Err(anyhow!(
+                "Alias loop in configuration: {} -> {}",
+                seen.join(" -> "),
+                name
+            ));
+        }
+
+        let words = shlex::split(definition)
+            .filter(|words| !words.is_empty())
+            .ok_or_else(|| anyhow!("Invalid definition of alias '{}': {}", name, definition))?;
+        args.splice(position..=position, words.into_iter().map(OsString::from));
+        seen.push(name);
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<OsString> {
+        line.split(' ').map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let aliases = BTreeMap::from([
+            ("inbox".to_string(), "list -t inbox".to_string()),
+            ("in".to_string(), "inbox".to_string()),
+            (
+                "grab".to_string(),
+                "pop --as \"{stem} copy.{ext}\"".to_string(),
+            ),
+            ("list".to_string(), "list -t hidden".to_string()),
+            ("loop".to_string(), "loop2".to_string()),
+            ("loop2".to_string(), "loop".to_string()),
+        ]);
+        let is_builtin = |name: &str| name == "list" || name == "pop";
+        let expand_line = |line: &str| {
+            let args = args(line);
+            let position = command_position(&args).unwrap();
+            expand(args, position, &aliases, is_builtin)
+        };
+
+        assert_eq!(
+            expand_line("fstk --global in --format json").unwrap(),
+            args("fstk --global list -t inbox --format json")
+        );
+        assert_eq!(
+            expand_line("fstk --remote http://host grab 2").unwrap(),
+            vec![
+                "fstk",
+                "--remote",
+                "http://host",
+                "pop",
+                "--as",
+                "{stem} copy.{ext}",
+                "2"
+            ]
+        );
+
+        // Built-in commands cannot be redefined
+        assert_eq!(expand_line("fstk list").unwrap(), args("fstk list"));
+        assert!(expand_line("fstk loop").is_err());
+        assert_eq!(command_position(&args("fstk --global")), None);
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use std::path::PathBuf;
+
+use crate::config;
+use crate::db::{establish_connection, get_item_stored_path, ItemManager};
+use crate::fs;
+use crate::utils::duration::parse_duration;
+
+/// Move the blobs of items older than `older_than` to secondary storage.
+/// Archived items stay on the stack and are pulled back transparently when popped.
+pub fn archive(older_than: String, to: Option<String>) -> Result<()> {
+    let age = parse_duration(&older_than)?;
+
+    // The command line location takes precedence over the configured one
+    let archive_dir = match to {
+        Some(dir) => dir,
+        None => config::load()?.archive_dir.ok_or_else(|| {
+            anyhow!("No archive location given; use --to or set archive_dir in the config file")
+        })?,
+    };
+    let archive_dir = PathBuf::from(archive_dir);
+    std::fs::create_dir_all(&archive_dir)?;
+    let archive_dir = fs::get_absolute_path(&archive_dir)?;
+    let location = archive_dir.to_string_lossy().to_string();
+
+    // Connect to database
+    let conn = establish_connection()?;
+
+    let candidates: Vec<_> = ItemManager::list_pushed_before(&conn, Local::now() - age)?
+        .into_iter()
+        .filter(|item| item.storage_location.as_deref() != Some(location.as_str()))
+        .collect();
+
+    if candidates.is_empty() {
+        println!("No items older than {} to archive.", older_than);
+        return Ok(());
+    }
+
+    let mut success_count = 0;
+    let mut failed_count = 0;
+
+    for item in candidates {
+        let source_path = get_item_stored_path(&item)?;
+        if !source_path.exists() {
+            println!(
+                "Source file missing for '{}': {}",
+                item.original_name,
+                source_path.display()
+            );
+            failed_count += 1;
+            continue;
+        }
+
+        let target_path = archive_dir.join(&item.stored_hash);
+        match fs::move_or_copy(&source_path, &target_path) {
+            Ok(_) => {
+                if ItemManager::set_storage_location(&conn, item.id, Some(&location))? {
+                    success_count += 1;
+                } else {
+                    // Put the blob back so the item stays consistent
+                    let _ = fs::move_or_copy(&target_path, &source_path);
+                    failed_count += 1;
+                }
+            }
+            Err(e) => {
+                println!("Error archiving '{}': {}", item.original_name, e);
+                failed_count += 1;
+            }
+        }
+    }
+
+    println!(
+        "Summary: {} item(s) archived to {}, {} failed",
+        success_count,
+        archive_dir.display(),
+        failed_count
+    );
+
+    Ok(())
+}
@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use rusqlite::{Connection, DatabaseName};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::config::CONFIG_FILE_NAME;
+use crate::db::{establish_connection, get_fstk_dir, DB_FILE_NAME};
+use crate::fs;
+use crate::utils::output;
+
+/// Name of the checksum list stored at the root of every backup archive
+const CHECKSUMS_FILE_NAME: &str = "CHECKSUMS";
+
+/// Write a tar archive of the current fstk home: a consistent snapshot of the database
+/// (taken with the SQLite backup API), the stored blobs and the configuration.
+pub fn backup(dest: &str) -> Result<()> {
+    let dest_path = PathBuf::from(dest);
+    if dest_path.exists() {
+        return Err(anyhow!(
+            "Backup file already exists: {}",
+            dest_path.display()
+        ));
+    }
+
+    let fstk_dir = get_fstk_dir()?;
+    let conn = establish_connection()?;
+
+    // Snapshot the database instead of copying a file that may be in use
+    let snapshot = fstk_dir.join(format!("{}.backup-{}", DB_FILE_NAME, std::process::id()));
+    conn.backup(DatabaseName::Main, &snapshot, None)?;
+
+    let archived_elsewhere: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM stack_items WHERE storage_location IS NOT NULL",
+        [],
+        |row| row.get(0),
+    )?;
+
+    let result = write_archive(&fstk_dir, &snapshot, &dest_path);
+    let _ = std::fs::remove_file(&snapshot);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&dest_path);
+    }
+    let file_count = result?;
+
+    println!(
+        "Backed up {} to {} ({} files)",
+        fstk_dir.display(),
+        dest_path.display(),
+        file_count
+    );
+    if archived_elsewhere > 0 {
+        println!(
+            "Note: {} archived item(s) live in secondary storage and are not included",
+            archived_elsewhere
+        );
+    }
+
+    Ok(())
+}
+
+/// Replace the current fstk home with the contents of a backup archive.
+/// The archive is unpacked and verified first; the previous home is kept next to it.
+pub fn restore_backup(archive: &str) -> Result<()> {
+    let archive_path = PathBuf::from(archive);
+    if !archive_path.is_file() {
+        return Err(anyhow!("Backup file not found: {}", archive_path.display()));
+    }
+
+    let fstk_dir = get_fstk_dir()?;
+    let parent = fstk_dir
+        .parent()
+        .ok_or_else(|| anyhow!("Invalid fstk directory: {}", fstk_dir.display()))?;
+    let dir_name = fs::get_file_name(&fstk_dir)?;
+
+    // Unpack next to the current home so the final swap is a rename
+    let staging = parent.join(format!("{}.restore-{}", dir_name, std::process::id()));
+    let unpacked = tar::Archive::new(File::open(&archive_path)?)
+        .unpack(&staging)
+        .map_err(|e| anyhow!("Failed to unpack {}: {}", archive_path.display(), e))
+        .and_then(|_| verify_backup(&staging));
+    let file_count = match unpacked {
+        Ok(count) => count,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    if fstk_dir.exists() {
+        println!(
+            "This replaces the stack at {} with the backup ({} files).",
+            fstk_dir.display(),
+            file_count
+        );
+        let input = output::prompt("Do you want to continue? [y/N]: ")?;
+        if input != "y" && input != "yes" {
+            std::fs::remove_dir_all(&staging)?;
+            println!("Operation cancelled.");
+            return Ok(());
+        }
+    }
+
+    let previous = parent.join(format!(
+        "{}.pre-restore-{}",
+        dir_name,
+        Local::now().format("%Y%m%d%H%M%S")
+    ));
+    let had_previous = fstk_dir.exists();
+    if had_previous {
+        std::fs::rename(&fstk_dir, &previous)?;
+    }
+    if let Err(e) = std::fs::rename(&staging, &fstk_dir) {
+        if had_previous {
+            std::fs::rename(&previous, &fstk_dir)?;
+        }
+        return Err(anyhow!("Failed to put the restored stack in place: {}", e));
+    }
+
+    println!(
+        "Restored {} from {}",
+        fstk_dir.display(),
+        archive_path.display()
+    );
+    if had_previous {
+        println!("The previous stack was moved to {}", previous.display());
+    }
+
+    Ok(())
+}
+
+/// Write the archive and return the number of files it contains (excluding the checksum list).
+fn write_archive(fstk_dir: &Path, db_snapshot: &Path, dest: &Path) -> Result<usize> {
+    let mut builder = tar::Builder::new(File::create(dest)?);
+    // Symlinks inside stored directories are archived as links
+    builder.follow_symlinks(false);
+
+    let mut checksums = String::new();
+    let mut add_file = |builder: &mut tar::Builder<File>, path: &Path, name: &str| -> Result<()> {
+        builder.append_path_with_name(path, name)?;
+        checksums.push_str(&format!("{}  {}\n", fs::hash_file(path)?, name));
+        Ok(())
+    };
+
+    add_file(&mut builder, db_snapshot, DB_FILE_NAME)?;
+
+    let config_path = fstk_dir.join(CONFIG_FILE_NAME);
+    if config_path.is_file() {
+        add_file(&mut builder, &config_path, CONFIG_FILE_NAME)?;
+    }
+
+    let data_dir = fstk_dir.join(".data");
+    builder.append_dir(".data", &data_dir)?;
+    let entries = WalkDir::new(&data_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        // Content of pushes that are still in progress, or were interrupted, is not part of the
+        // stack
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.depth() > 1
+                || ![fs::STAGING_SUFFIX, fs::PARTIAL_SUFFIX, fs::PROGRESS_SUFFIX]
+                    .iter()
+                    .any(|suffix| name.ends_with(suffix))
+        });
+    for entry in entries {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(fstk_dir)?;
+        let name = relative.to_string_lossy().replace('\\', "/");
+
+        if entry.file_type().is_file() {
+            add_file(&mut builder, entry.path(), &name)?;
+        } else {
+            builder.append_path_with_name(entry.path(), &name)?;
+        }
+    }
+
+    let file_count = checksums.lines().count();
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(checksums.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Local::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, CHECKSUMS_FILE_NAME, checksums.as_bytes())?;
+
+    builder.into_inner()?.sync_all()?;
+
+    Ok(file_count)
+}
+
+/// Check an unpacked backup against its checksum list and the database's own integrity check.
+/// Returns the number of verified files.
+fn verify_backup(dir: &Path) -> Result<usize> {
+    let checksums_path = dir.join(CHECKSUMS_FILE_NAME);
+    let checksums = std::fs::read_to_string(&checksums_path)
+        .map_err(|_| anyhow!("Not an fstk backup: {} is missing", CHECKSUMS_FILE_NAME))?;
+
+    let mut count = 0;
+    for line in checksums.lines() {
+        let (expected, name) = line
+            .split_once("  ")
+            .ok_or_else(|| anyhow!("Malformed checksum line: {}", line))?;
+        let path = dir.join(name);
+        if !path.is_file() {
+            return Err(anyhow!("Backup is incomplete: {} is missing", name));
+        }
+        if fs::hash_file(&path)? != expected {
+            return Err(anyhow!(
+                "Backup is corrupted: checksum mismatch for {}",
+                name
+            ));
+        }
+        count += 1;
+    }
+
+    let conn = Connection::open(dir.join(DB_FILE_NAME))?;
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(anyhow!(
+            "Backup database failed its integrity check: {}",
+            integrity
+        ));
+    }
+    drop(conn);
+
+    std::fs::remove_file(&checksums_path)?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+    use tempfile::tempdir;
+
+    /// Create a minimal fstk home with a database snapshot and one stored file
+    fn create_test_home(root: &Path) -> Result<(PathBuf, PathBuf)> {
+        let home = root.join(".fstk");
+        std::fs::create_dir_all(home.join(".data/dirhash"))?;
+        std::fs::write(home.join(".data/filehash"), "file content")?;
+        std::fs::write(home.join(".data/dirhash/inner.txt"), "inner content")?;
+        std::fs::write(home.join(".data/pending.staging"), "in flight")?;
+
+        let snapshot = root.join("snapshot.db");
+        let conn = Connection::open(&snapshot)?;
+        schema::initialize_schema(&conn)?;
+
+        Ok((home, snapshot))
+    }
+
+    #[test]
+    fn test_backup_roundtrip() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let (home, snapshot) = create_test_home(temp_dir.path())?;
+        let archive = temp_dir.path().join("backup.tar");
+
+        let count = write_archive(&home, &snapshot, &archive)?;
+        assert_eq!(count, 3);
+
+        let restored = temp_dir.path().join("restored");
+        tar::Archive::new(File::open(&archive)?).unpack(&restored)?;
+        assert_eq!(verify_backup(&restored)?, 3);
+
+        assert_eq!(
+            std::fs::read_to_string(restored.join(".data/dirhash/inner.txt"))?,
+            "inner content"
+        );
+        assert!(!restored.join(".data/pending.staging").exists());
+        assert!(!restored.join(CHECKSUMS_FILE_NAME).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_detects_corruption() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let (home, snapshot) = create_test_home(temp_dir.path())?;
+        let archive = temp_dir.path().join("backup.tar");
+        write_archive(&home, &snapshot, &archive)?;
+
+        let restored = temp_dir.path().join("restored");
+        tar::Archive::new(File::open(&archive)?).unpack(&restored)?;
+        std::fs::write(restored.join(".data/filehash"), "tampered")?;
+
+        assert!(verify_backup(&restored).is_err());
+
+        Ok(())
+    }
+}
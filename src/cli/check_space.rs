@@ -0,0 +1,167 @@
+use anyhow::Result;
+use std::env;
+use std::path::PathBuf;
+
+use crate::db::{establish_connection, get_item_stored_path, item_by_number, ItemManager};
+use crate::fs;
+use crate::utils::display;
+use crate::utils::error::FstkError;
+use crate::utils::numbers::parse_number_range;
+
+/// Space that a set of pops or restores takes on one destination filesystem
+#[derive(Debug, PartialEq)]
+struct Demand {
+    /// First destination directory on the filesystem, to report it by
+    dir: PathBuf,
+    needed: u64,
+}
+
+/// Check that the selected items (the top one by default) fit where they would be popped or
+/// restored, without moving anything. Moves within one filesystem need no room; copies and
+/// moves across filesystems need the whole item on the destination.
+pub fn check_space(
+    numbers: Option<String>,
+    to: Option<String>,
+    original: bool,
+    keep: bool,
+) -> Result<()> {
+    let conn = establish_connection()?;
+
+    let mut all_items = ItemManager::list(&conn, &[])?;
+    all_items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+    let selected = parse_number_range(numbers.as_deref().unwrap_or("1"))?;
+
+    let output_dir = match to {
+        Some(dir) => fs::get_absolute_path(&PathBuf::from(dir))?,
+        None => env::current_dir()?,
+    };
+
+    let mut needs = Vec::new();
+    for number in selected {
+        let item = item_by_number(&all_items, number)
+            .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+        let dir = if original {
+            PathBuf::from(&item.original_path)
+        } else {
+            output_dir.clone()
+        };
+
+        let Some(size) = item.size else {
+            println!(
+                "#{} {}: size unknown, not checked",
+                number, item.original_name
+            );
+            continue;
+        };
+        let dest_path = dir.join(&item.original_name);
+        let needed = fs::space_needed(
+            &get_item_stored_path(item)?,
+            &dest_path,
+            size,
+            keep || item.packed,
+        );
+        println!(
+            "#{} {} ({}) -> {}: {}",
+            number,
+            item.original_name,
+            display::format_size(size),
+            dir.display(),
+            if needed == 0 {
+                "needs no room (same filesystem)".to_string()
+            } else {
+                format!("needs {}", display::format_size(needed))
+            }
+        );
+        needs.push((fs::filesystem_id(&dir), dir, needed));
+    }
+
+    let mut short = 0;
+    for demand in by_filesystem(needs) {
+        match fs::available_space(&demand.dir) {
+            Some(available) => {
+                let fits = available >= demand.needed;
+                println!(
+                    "{}: {} needed, {} free, {}",
+                    demand.dir.display(),
+                    display::format_size(demand.needed),
+                    display::format_size(available),
+                    if fits { "OK" } else { "NOT ENOUGH" }
+                );
+                if !fits {
+                    short += 1;
+                }
+            }
+            None => println!(
+                "{}: {} needed, free space unknown",
+                demand.dir.display(),
+                display::format_size(demand.needed)
+            ),
+        }
+    }
+
+    if short > 0 {
+        return Err(FstkError::InsufficientSpace(format!(
+            "{} destination filesystem(s) too full",
+            short
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Add up what each destination filesystem needs, in the order they first come up.
+/// Destinations on filesystems that cannot be told apart are kept separate.
+fn by_filesystem(needs: Vec<(Option<u64>, PathBuf, u64)>) -> Vec<Demand> {
+    let mut demands: Vec<(Option<u64>, Demand)> = Vec::new();
+    for (filesystem, dir, needed) in needs {
+        let existing = demands.iter_mut().find(|(id, demand)| match filesystem {
+            Some(_) => *id == filesystem,
+            None => demand.dir == dir,
+        });
+        match existing {
+            Some((_, demand)) => demand.needed += needed,
+            None => demands.push((filesystem, Demand { dir, needed })),
+        }
+    }
+
+    demands.into_iter().map(|(_, demand)| demand).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_by_filesystem() {
+        let demands = by_filesystem(vec![
+            (Some(1), PathBuf::from("/home/a"), 10),
+            (Some(2), PathBuf::from("/mnt/usb"), 5),
+            (Some(1), PathBuf::from("/home/b"), 0),
+            (Some(1), PathBuf::from("/home/a"), 7),
+            (None, PathBuf::from("/x"), 1),
+            (None, PathBuf::from("/y"), 2),
+        ]);
+
+        assert_eq!(
+            demands,
+            vec![
+                Demand {
+                    dir: PathBuf::from("/home/a"),
+                    needed: 17
+                },
+                Demand {
+                    dir: PathBuf::from("/mnt/usb"),
+                    needed: 5
+                },
+                Demand {
+                    dir: PathBuf::from("/x"),
+                    needed: 1
+                },
+                Demand {
+                    dir: PathBuf::from("/y"),
+                    needed: 2
+                },
+            ]
+        );
+    }
+}
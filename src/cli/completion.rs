@@ -1,34 +1,91 @@
 use anyhow::Result;
 use clap::{Command, CommandFactory};
 use clap_complete::{generate, Generator, Shell};
-use std::io;
+use clap_complete_nushell::Nushell;
 
-use crate::cli::Cli;
+use crate::cli::{Cli, CompletionShell};
+use crate::db::{establish_connection, ItemManager};
+
+/// Subcommands whose item argument also accepts (part of) an item name
+const NAME_COMMANDS: [&str; 3] = ["pop", "peek", "restore"];
+
+/// Completes the item names matching the current word for bash, escaped with `printf %q` so
+/// names with spaces or quotes stay one word
+const BASH_NAMES: &str = r#"
+_fstk__item_names() {
+    local name quoted word scope=()
+    for word in "${COMP_WORDS[@]}"; do
+        case "${word}" in
+            --global|--local) scope+=("${word}") ;;
+        esac
+    done
+    COMPREPLY=()
+    while IFS= read -r name; do
+        printf -v quoted '%q' "${name}"
+        if [[ "${quoted}" == "${cur}"* || "${name}" == "${cur}"* ]]; then
+            COMPREPLY+=( "${quoted}" )
+        fi
+    done < <(fstk "${scope[@]}" __complete-names 2>/dev/null)
+}
+"#;
+
+/// Completes item names for zsh; `compadd` does the quoting
+const ZSH_NAMES: &str = r#"(( $+functions[_fstk__item_names] )) ||
+_fstk__item_names() {
+    local expl
+    local -a names
+    names=(${(f)"$(_call_program names fstk ${(M)words:#--(global|local)} __complete-names 2>/dev/null)"})
+    _wanted names expl 'item name' compadd -a names
+}
+
+"#;
+
+/// Completes item names for fish, which quotes candidates itself
+const FISH_NAMES: &str = r#"
+function __fstk_item_names
+    fstk (commandline -opc | string match -r -- '^--(global|local)$') __complete-names 2>/dev/null
+end
+"#;
 
 /// Generate shell completion scripts
-pub fn generate_completion<G: Generator>(gen: G, cmd: &mut Command, name: &str) -> Result<()> {
-    generate(gen, cmd, name, &mut io::stdout());
-    Ok(())
+pub fn generate_completion<G: Generator>(gen: G, cmd: &mut Command, name: &str) -> Result<String> {
+    let mut script = Vec::new();
+    generate(gen, cmd, name, &mut script);
+    Ok(String::from_utf8(script)?)
 }
 
 /// Generate shell completion script for the given shell
-pub fn completion(shell: Shell) -> Result<()> {
+pub fn completion(shell: CompletionShell) -> Result<()> {
     let mut cmd = Cli::command();
     let bin_name = cmd.get_name().to_string();
 
-    generate_completion(shell, &mut cmd, &bin_name)?;
+    let script = match shell {
+        CompletionShell::Bash => {
+            bash_item_names(&generate_completion(Shell::Bash, &mut cmd, &bin_name)?)
+        }
+        CompletionShell::Zsh => {
+            zsh_item_names(&generate_completion(Shell::Zsh, &mut cmd, &bin_name)?)
+        }
+        CompletionShell::Fish => {
+            fish_item_names(&generate_completion(Shell::Fish, &mut cmd, &bin_name)?)
+        }
+        CompletionShell::PowerShell => generate_completion(Shell::PowerShell, &mut cmd, &bin_name)?,
+        CompletionShell::Elvish => generate_completion(Shell::Elvish, &mut cmd, &bin_name)?,
+        CompletionShell::Nushell => generate_completion(Nushell, &mut cmd, &bin_name)?,
+    };
+    print!("{}", script);
 
     // Print instructions for how to install the completion script
     println!("\n# Shell completion script generated for {}", bin_name);
 
     match shell {
-        Shell::Bash => {
+        CompletionShell::Bash => {
             println!("# To use, add this to your ~/.bashrc or ~/.bash_profile:");
             println!("# source <(fstk completion bash)");
             println!("# Or save it to a file in the bash completions directory:");
             println!("# fstk completion bash > ~/.local/share/bash-completion/completions/fstk");
         }
-        Shell::Zsh => {
+        CompletionShell::Zsh => {
             println!("# To use, add this to your ~/.zshrc:");
             println!("# source <(fstk completion zsh)");
             println!("# Or save it to a file in the zsh completions directory:");
@@ -38,24 +95,140 @@ pub fn completion(shell: Shell) -> Result<()> {
             println!("# fpath=(~/.zsh/completions $fpath)");
             println!("# autoload -U compinit && compinit");
         }
-        Shell::Fish => {
+        CompletionShell::Fish => {
             println!("# To use, save it to the fish completions directory:");
             println!("# fstk completion fish > ~/.config/fish/completions/fstk.fish");
         }
-        Shell::PowerShell => {
+        CompletionShell::PowerShell => {
             println!("# To use, save it to a file and source it from your PowerShell profile:");
             println!("# fstk completion powershell > fstk-completion.ps1");
             println!("# . ./fstk-completion.ps1");
         }
-        Shell::Elvish => {
+        CompletionShell::Elvish => {
             println!("# To use, save it to your elvish config directory:");
             println!("# mkdir -p ~/.elvish/lib");
             println!("# fstk completion elvish > ~/.elvish/lib/fstk-completions.elv");
             println!("# Then add to your ~/.elvish/rc.elv:");
             println!("# use fstk-completions");
         }
-        _ => {}
+        CompletionShell::Nushell => {
+            println!("# To use, save it to your nushell config directory:");
+            println!("# fstk completion nushell | save -f ~/.config/nushell/fstk-completions.nu");
+            println!("# Then add this to your config.nu:");
+            println!("# source ~/.config/nushell/fstk-completions.nu");
+            println!("# Structured output: fstk list --format nuon | from nuon");
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the distinct original names on the stack, newest first, for `__complete-names`.
+/// Names that are plain numbers are left out since they would select by display number.
+pub fn complete_names() -> Result<()> {
+    let conn = establish_connection()?;
+
+    let mut names: Vec<String> = Vec::new();
+    for item in ItemManager::list(&conn, &[])? {
+        if item.original_name.parse::<usize>().is_err()
+            && !item.original_name.contains('\n')
+            && !names.contains(&item.original_name)
+        {
+            names.push(item.original_name);
+        }
     }
 
+    for name in names {
+        println!("{}", name);
+    }
     Ok(())
 }
+
+/// In the blocks of the name-taking subcommands, complete words not starting with `-` as names
+fn bash_item_names(script: &str) -> String {
+    let mut out = String::new();
+    let mut in_name_command = false;
+    let mut previous = "";
+    for line in script.lines() {
+        let trimmed = line.trim();
+        if let Some(block) = trimmed.strip_prefix("fstk__subcmd__") {
+            in_name_command = NAME_COMMANDS.iter().any(|cmd| block == format!("{})", cmd));
+        }
+
+        if in_name_command && trimmed == "if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then" {
+            // Right after the subcommand, offer names rather than its options
+            out.push_str(&line.replace(" || ${COMP_CWORD} -eq 2", ""));
+        } else if in_name_command
+            && previous == "esac"
+            && trimmed == r#"COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )"#
+        {
+            out.push_str(&line.replace(trimmed, "_fstk__item_names"));
+        } else {
+            out.push_str(line);
+        }
+        out.push('\n');
+        previous = trimmed;
+    }
+    out.push_str(BASH_NAMES);
+    out
+}
+
+/// Complete the item argument of the name-taking subcommands with `_fstk__item_names`
+fn zsh_item_names(script: &str) -> String {
+    let mut out = String::new();
+    let mut in_name_command = false;
+    for line in script.lines() {
+        if line.starts_with('(') && line.ends_with(')') {
+            in_name_command = NAME_COMMANDS.iter().any(|cmd| line == format!("({})", cmd));
+        }
+
+        if in_name_command
+            && (line.starts_with("'::number") || line.starts_with("':number"))
+            && line.ends_with(":_default' \\")
+        {
+            out.push_str(&line.replace(":_default' \\", ":_fstk__item_names' \\"));
+        } else {
+            if line.starts_with("if [ \"$funcstack[1]\" = \"_fstk\" ]") {
+                out.push_str(ZSH_NAMES);
+            }
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Offer item names for the item argument of the name-taking subcommands
+fn fish_item_names(script: &str) -> String {
+    let mut out = script.to_string();
+    out.push_str(FISH_NAMES);
+    for cmd in NAME_COMMANDS {
+        out.push_str(&format!(
+            "complete -c fstk -n \"__fish_fstk_using_subcommand {}\" -f -a \"(__fstk_item_names)\"\n",
+            cmd
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_name_completion() {
+        let mut cmd = Cli::command();
+        let bash = bash_item_names(&generate_completion(Shell::Bash, &mut cmd, "fstk").unwrap());
+        let zsh = zsh_item_names(&generate_completion(Shell::Zsh, &mut cmd, "fstk").unwrap());
+        let fish = fish_item_names(&generate_completion(Shell::Fish, &mut cmd, "fstk").unwrap());
+
+        // Hooked into pop, peek and restore only
+        assert_eq!(bash.matches("            _fstk__item_names\n").count(), 3);
+        assert_eq!(zsh.matches(":_fstk__item_names' \\").count(), 3);
+        assert_eq!(fish.matches("-a \"(__fstk_item_names)\"").count(), 3);
+
+        assert!(bash.contains("_fstk__item_names() {"));
+        assert!(zsh.contains("_fstk__item_names() {"));
+        assert!(fish.contains("function __fstk_item_names"));
+    }
+}
@@ -0,0 +1,565 @@
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::cli::{recovery, sync};
+use crate::config;
+use crate::db::{
+    establish_connection, get_fstk_dir, get_item_stored_path, ItemManager, OperationLog, StackItem,
+};
+use crate::fs;
+use crate::status;
+use crate::utils::http;
+
+/// Name of the daemon's socket inside the fstk directory of the stack it serves
+pub const SOCKET_FILE_NAME: &str = "fstk.sock";
+
+/// How often the daemon polls for connections and the shutdown flag
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a client waits for the daemon before falling back to the database
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_shutdown_signal(_signal: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+/// A request sent to the daemon, one JSON object per line
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    Ping,
+    /// Items carrying all of the given tags, in database order
+    List {
+        tags: Vec<String>,
+    },
+    /// Finish or roll back interrupted operations, as every command does before it runs
+    Recover,
+}
+
+/// The daemon's answer to a request, one JSON object per line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    Pong,
+    Items { items: Vec<StackItem> },
+    Recovered { messages: Vec<String> },
+    Error { message: String },
+}
+
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(get_fstk_dir()?.join(SOCKET_FILE_NAME))
+}
+
+/// Serve requests for the active stack over a Unix socket until interrupted.
+/// The database stays open, so `list` and the recovery check every command starts with skip
+/// connecting and checking the schema; other commands still open the database themselves.
+/// With `http`, items can also be listed, downloaded and popped by `--remote` clients, which
+/// must send the configured `http_token`; without one, a token is generated and saved for them.
+#[cfg(unix)]
+pub fn daemon(http: Option<String>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    let socket = socket_path()?;
+    if socket.exists() {
+        if send_request(&socket, &Request::Ping).is_ok() {
+            return Err(anyhow!(
+                "A daemon is already serving this stack ({})",
+                socket.display()
+            ));
+        }
+        // Left behind by a daemon that did not shut down cleanly
+        std::fs::remove_file(&socket)?;
+    }
+
+    let mut conn = establish_connection()?;
+    let http_server = match &http {
+        Some(address) => {
+            let token = match config::load()?.http_token() {
+                Some(token) => token,
+                None => generate_http_token(&conn, &config::get_http_token_path()?)?,
+            };
+            Some((bind_http(address)?, token))
+        }
+        None => None,
+    };
+    let listener = UnixListener::bind(&socket)?;
+    std::fs::set_permissions(&socket, std::fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+
+    // Remove the socket on Ctrl-C or SIGTERM so clients stop trying it
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            handle_shutdown_signal as *const () as libc::sighandler_t,
+        );
+        libc::signal(
+            libc::SIGTERM,
+            handle_shutdown_signal as *const () as libc::sighandler_t,
+        );
+    }
+
+    status!("Serving {} (Ctrl-C to stop)", socket.display());
+
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        let mut idle = true;
+
+        match listener.accept() {
+            Ok((stream, _)) => {
+                idle = false;
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+                if let Err(e) = handle_connection(&mut conn, &stream) {
+                    status!("Request failed: {}", e);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => {
+                let _ = std::fs::remove_file(&socket);
+                return Err(e.into());
+            }
+        }
+
+        if let Some((http_listener, token)) = &http_server {
+            match http_listener.accept() {
+                Ok((stream, peer)) => {
+                    idle = false;
+                    stream.set_nonblocking(false)?;
+                    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+                    if let Err(e) = handle_http(&mut conn, &stream, token) {
+                        status!("Request from {} failed: {}", peer, e);
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    let _ = std::fs::remove_file(&socket);
+                    return Err(e.into());
+                }
+            }
+        }
+
+        if idle {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    std::fs::remove_file(&socket)?;
+    status!("Daemon stopped");
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemon(_http: Option<String>) -> Result<()> {
+    Err(anyhow!(
+        "The daemon needs Unix domain sockets, which this platform lacks"
+    ))
+}
+
+/// Answer every request line of a single client connection
+fn handle_connection<S>(conn: &mut Connection, stream: S) -> Result<()>
+where
+    S: std::io::Read + Write + Copy,
+{
+    let mut writer = stream;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => respond(conn, request),
+            Err(e) => Response::Error {
+                message: format!("Invalid request: {}", e),
+            },
+        };
+        serde_json::to_writer(&mut writer, &response)?;
+        writeln!(writer)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+fn respond(conn: &mut Connection, request: Request) -> Response {
+    let result = match request {
+        Request::Ping => Ok(Response::Pong),
+        Request::List { tags } => {
+            ItemManager::list(conn, &tags).map(|items| Response::Items { items })
+        }
+        Request::Recover => {
+            recovery::recover(conn).map(|messages| Response::Recovered { messages })
+        }
+    };
+    result.unwrap_or_else(|e| Response::Error {
+        message: e.to_string(),
+    })
+}
+
+fn bind_http(address: &str) -> Result<TcpListener> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+
+    status!(
+        "Serving remote clients on http://{}",
+        listener.local_addr()?
+    );
+
+    Ok(listener)
+}
+
+/// Generate a random token and save it to `path`, readable only by the user, so that local
+/// `--remote` clients pick it up and it can be copied to other machines. Even on a loopback
+/// address other users could otherwise list, download and pop items.
+fn generate_http_token(conn: &Connection, path: &Path) -> Result<String> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let token: String =
+        conn.query_row("SELECT lower(hex(randomblob(32)))", [], |row| row.get(0))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{}", token)?;
+
+    status!(
+        "No http_token is configured; generated one in {} (clients on other machines need it \
+         as http_token or FSTK_HTTP_TOKEN)",
+        path.display()
+    );
+
+    Ok(token)
+}
+
+/// Answer a single HTTP request of a `--remote` client:
+/// `GET /items`, `GET /items/<id>/content` (a tar stream for directories) and `DELETE /items/<id>`.
+/// Requests not carrying `token` are refused.
+fn handle_http(conn: &mut Connection, stream: &TcpStream, token: &str) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = http::read_request(&mut reader)?;
+    let mut writer = stream;
+
+    if !http::token_matches(request.token.as_deref(), token) {
+        return http::write_json(
+            &mut writer,
+            401,
+            &json!({"error": "Invalid or missing token"}),
+        );
+    }
+
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let item_id = |segment: &str| segment.parse::<i64>().ok();
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["items"]) => {
+            let mut items = ItemManager::list(conn, &[])?;
+            items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+            http::write_json(&mut writer, 200, &serde_json::to_value(items)?)
+        }
+        ("POST", ["items"]) => {
+            receive_upload(conn, reader.take(request.content_length), &mut writer)
+        }
+        ("GET", ["items", id, "content"]) => match item_id(id) {
+            Some(id) => send_content(conn, id, &mut writer),
+            None => http::write_json(&mut writer, 400, &json!({"error": "Invalid item ID"})),
+        },
+        ("DELETE", ["items", id]) => match item_id(id) {
+            Some(id) => delete_item(conn, id, &mut writer),
+            None => http::write_json(&mut writer, 400, &json!({"error": "Invalid item ID"})),
+        },
+        _ => http::write_json(&mut writer, 404, &json!({"error": "Not found"})),
+    }
+}
+
+/// Send an item's stored content: the file itself, or a tar stream of a directory
+fn send_content<W: Write>(conn: &Connection, id: i64, writer: &mut W) -> Result<()> {
+    let Some(item) = ItemManager::get_by_id(conn, id)? else {
+        return http::write_json(
+            writer,
+            404,
+            &json!({"error": format!("No item with ID {}", id)}),
+        );
+    };
+    if item.is_bundle() {
+        return http::write_json(
+            writer,
+            400,
+            &json!({"error": "Bundles can only be popped on the machine that stores them"}),
+        );
+    }
+
+    let stored_path = get_item_stored_path(&item)?;
+    if !stored_path.exists() {
+        return http::write_json(
+            writer,
+            404,
+            &json!({"error": format!("Stored data of '{}' is missing", item.original_name)}),
+        );
+    }
+
+    if stored_path.is_dir() {
+        http::write_head(writer, 200, "application/x-tar", None)?;
+    } else {
+        http::write_head(
+            writer,
+            200,
+            "application/octet-stream",
+            Some(std::fs::metadata(&stored_path)?.len()),
+        )?;
+    }
+    sync::write_content(&stored_path, writer)
+}
+
+/// Store an item uploaded by `sync` on another machine: a JSON line describing it, then its content
+fn receive_upload<R: BufRead, W: Write>(
+    conn: &mut Connection,
+    mut body: R,
+    writer: &mut W,
+) -> Result<()> {
+    let mut header = String::new();
+    body.read_line(&mut header)?;
+    let item: StackItem = match serde_json::from_str(&header) {
+        Ok(item) => item,
+        Err(e) => {
+            return http::write_json(
+                writer,
+                400,
+                &json!({"error": format!("Invalid item: {}", e)}),
+            )
+        }
+    };
+
+    if item.uuid.is_empty() || item.is_bundle() {
+        return http::write_json(
+            writer,
+            400,
+            &json!({"error": "Only files and directories with a UUID can be uploaded"}),
+        );
+    }
+    if ItemManager::get_by_uuid(conn, &item.uuid)?.is_some() {
+        return http::write_json(
+            writer,
+            409,
+            &json!({"error": format!("'{}' is already on this stack", item.original_name)}),
+        );
+    }
+
+    match sync::store_received(conn, &item, body) {
+        Ok(id) => http::write_json(writer, 201, &json!({ "id": id })),
+        Err(e) => http::write_json(writer, 400, &json!({"error": e.to_string()})),
+    }
+}
+
+/// Drop an item a remote client has downloaded, as a pop on this stack
+fn delete_item<W: Write>(conn: &mut Connection, id: i64, writer: &mut W) -> Result<()> {
+    let Some(item) = ItemManager::get_by_id(conn, id)? else {
+        return http::write_json(
+            writer,
+            404,
+            &json!({"error": format!("No item with ID {}", id)}),
+        );
+    };
+    if item.locked {
+        return http::write_json(
+            writer,
+            409,
+            &json!({"error": format!("'{}' is locked", item.original_name)}),
+        );
+    }
+
+    let stored_path = get_item_stored_path(&item)?;
+    ItemManager::delete(conn, item.id)?;
+    OperationLog::record(conn, "pop", &item)?;
+    if stored_path.exists() {
+        fs::remove_item(&stored_path)?;
+    }
+
+    http::write_head(writer, 204, "application/json", Some(0))
+}
+
+/// Send a single request to the daemon listening on `socket`
+#[cfg(unix)]
+pub fn send_request(socket: &Path, request: &Request) -> Result<Response> {
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    serde_json::to_writer(&mut stream, request)?;
+    writeln!(stream)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(not(unix))]
+pub fn send_request(_socket: &Path, _request: &Request) -> Result<Response> {
+    Err(anyhow!("The daemon needs Unix domain sockets"))
+}
+
+/// Send `request` to the daemon of the active stack, if one is running.
+/// Returns `None` when there is no daemon or it did not answer, so callers can use the database.
+fn ask_daemon(request: &Request) -> Option<Response> {
+    let socket = socket_path().ok()?;
+    if !socket.exists() {
+        return None;
+    }
+    send_request(&socket, request).ok()
+}
+
+/// List items through the daemon of the active stack, if one is running
+pub fn list_items(tags: &[String]) -> Option<Vec<StackItem>> {
+    match ask_daemon(&Request::List {
+        tags: tags.to_vec(),
+    })? {
+        Response::Items { items } => Some(items),
+        _ => None,
+    }
+}
+
+/// Have the daemon of the active stack recover interrupted operations, if one is running.
+/// Returns what it did.
+pub fn recover() -> Option<Vec<String>> {
+    match ask_daemon(&Request::Recover)? {
+        Response::Recovered { messages } => Some(messages),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::db::{schema, JournalManager};
+    use std::os::unix::net::UnixListener;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_request_round_trip() -> Result<()> {
+        let dir = TempDir::new()?;
+        let socket = dir.path().join(SOCKET_FILE_NAME);
+        let listener = UnixListener::bind(&socket)?;
+        let staged = fs::staging_path(&dir.path().join("hash3"));
+        std::fs::write(&staged, "archive")?;
+        let staged_path = staged.to_string_lossy().to_string();
+
+        let server = std::thread::spawn(move || -> Result<()> {
+            let mut conn = Connection::open_in_memory()?;
+            schema::initialize_schema(&conn)?;
+            ItemManager::insert(
+                &mut conn,
+                "notes.md",
+                "/home/user",
+                "hash1",
+                "file",
+                &["work".to_string()],
+            )?;
+            ItemManager::insert(&mut conn, "photo.jpg", "/home/user", "hash2", "file", &[])?;
+            // An archived push that was interrupted before the item was recorded
+            JournalManager::begin(&conn, "pack", "hash3", None, "/home/user/src", &staged_path)?;
+
+            for _ in 0..3 {
+                let (stream, _) = listener.accept()?;
+                handle_connection(&mut conn, &stream)?;
+            }
+            Ok(())
+        });
+
+        assert!(matches!(
+            send_request(&socket, &Request::Ping)?,
+            Response::Pong
+        ));
+        match send_request(
+            &socket,
+            &Request::List {
+                tags: vec!["work".to_string()],
+            },
+        )? {
+            Response::Items { items } => {
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].original_name, "notes.md");
+                assert_eq!(items[0].tags, vec!["work"]);
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+        match send_request(&socket, &Request::Recover)? {
+            Response::Recovered { messages } => assert_eq!(messages.len(), 1),
+            other => panic!("unexpected response: {:?}", other),
+        }
+        assert!(!staged.exists());
+
+        server.join().unwrap()
+    }
+
+    #[test]
+    fn test_http_items() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?.to_string();
+
+        let server = std::thread::spawn(move || -> Result<()> {
+            let mut conn = Connection::open_in_memory()?;
+            schema::initialize_schema(&conn)?;
+            ItemManager::insert(&mut conn, "notes.md", "/home/user", "hash1", "file", &[])?;
+
+            for _ in 0..4 {
+                let (stream, _) = listener.accept()?;
+                handle_http(&mut conn, &stream, "s3cret")?;
+            }
+            Ok(())
+        });
+
+        let (status, body) = http::send(&address, Some("s3cret"), "GET", "/items")?;
+        assert_eq!(status, 200);
+        let items: Vec<StackItem> = serde_json::from_reader(body)?;
+        assert_eq!(items[0].original_name, "notes.md");
+
+        let (status, _) = http::send(&address, Some("s3cret"), "DELETE", "/items/42")?;
+        assert_eq!(status, 404);
+
+        // Without the token nothing is listed or removed
+        let (status, _) = http::send(&address, None, "GET", "/items")?;
+        assert_eq!(status, 401);
+        let (status, _) = http::send(&address, Some("guess"), "DELETE", "/items/1")?;
+        assert_eq!(status, 401);
+
+        server.join().unwrap()
+    }
+
+    #[test]
+    fn test_generate_http_token() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new()?;
+        let path = dir.path().join(".fstk").join(config::HTTP_TOKEN_FILE_NAME);
+        let conn = Connection::open_in_memory()?;
+
+        let token = generate_http_token(&conn, &path)?;
+        assert_eq!(token.len(), 64);
+        assert_eq!(std::fs::read_to_string(&path)?.trim(), token);
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode() & 0o777,
+            0o600
+        );
+
+        // A saved token is never silently replaced
+        assert!(generate_http_token(&conn, &path).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use std::collections::HashMap;
+
+use crate::cli::top::backfill_sizes;
+use crate::cli::UsageGroup;
+use crate::db::{establish_connection, ItemManager, StackItem};
+use crate::utils::display;
+
+/// Age buckets of `du --by age` as (upper bound in days, label), youngest first
+const AGE_BUCKETS: &[(i64, &str)] = &[
+    (1, "< 1 day"),
+    (7, "1-7 days"),
+    (30, "1-4 weeks"),
+    (365, "1-12 months"),
+];
+
+/// Label of items older than the last age bucket
+const OLDEST_BUCKET: &str = "> 1 year";
+
+/// Storage used by one group of items
+#[derive(Debug, PartialEq)]
+pub struct Usage {
+    pub label: String,
+    pub size: u64,
+    pub count: usize,
+    /// Files in the group's items, as recorded on push
+    pub files: u64,
+}
+
+/// Show how storage is used, per item or grouped by tag, type or age, largest first.
+pub fn du(by: Option<UsageGroup>) -> Result<()> {
+    let conn = establish_connection()?;
+
+    backfill_sizes(&conn)?;
+    let items = ItemManager::list(&conn, &[])?;
+
+    if items.is_empty() {
+        println!("No items in the stack.");
+        return Ok(());
+    }
+
+    let usage = breakdown(&items, by, Local::now());
+    display::display_usage_bars(&usage, by.is_some());
+
+    let total: u64 = items.iter().filter_map(|item| item.size).sum();
+    let files: u64 = items.iter().filter_map(|item| item.file_count).sum();
+    println!(
+        "{:>10}  total ({} files)",
+        display::format_size(total),
+        display::format_count(files)
+    );
+
+    Ok(())
+}
+
+/// Sum item sizes per group, largest first.
+/// Items with several tags count towards each of them, so tag totals may exceed the overall total.
+pub fn breakdown(items: &[StackItem], by: Option<UsageGroup>, now: DateTime<Local>) -> Vec<Usage> {
+    // Without grouping every item is listed on its own, even when names repeat
+    let Some(by) = by else {
+        let mut usage: Vec<Usage> = items
+            .iter()
+            .map(|item| Usage {
+                label: item.original_name.clone(),
+                size: item.size.unwrap_or(0),
+                count: 1,
+                files: item.file_count.unwrap_or(0),
+            })
+            .collect();
+        usage.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.label.cmp(&b.label)));
+        return usage;
+    };
+
+    let mut groups: HashMap<String, Usage> = HashMap::new();
+    let mut add = |label: String, item: &StackItem| {
+        let usage = groups.entry(label.clone()).or_insert(Usage {
+            label,
+            size: 0,
+            count: 0,
+            files: 0,
+        });
+        usage.size += item.size.unwrap_or(0);
+        usage.count += 1;
+        usage.files += item.file_count.unwrap_or(0);
+    };
+
+    for item in items {
+        match by {
+            UsageGroup::Type => add(item.item_type.clone(), item),
+            UsageGroup::Age => add(age_bucket(now - item.pushed_at).to_string(), item),
+            UsageGroup::Tag if item.tags.is_empty() => add("(untagged)".to_string(), item),
+            UsageGroup::Tag => {
+                for tag in &item.tags {
+                    add(tag.clone(), item);
+                }
+            }
+        }
+    }
+
+    let mut usage: Vec<Usage> = groups.into_values().collect();
+    usage.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.label.cmp(&b.label)));
+    usage
+}
+
+fn age_bucket(age: Duration) -> &'static str {
+    AGE_BUCKETS
+        .iter()
+        .find(|(days, _)| age < Duration::days(*days))
+        .map(|(_, label)| *label)
+        .unwrap_or(OLDEST_BUCKET)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_item(
+        name: &str,
+        item_type: &str,
+        tags: &[&str],
+        size: u64,
+        age_days: i64,
+    ) -> StackItem {
+        let file_count = if item_type == "file" { 1 } else { 12 };
+        StackItem {
+            original_name: name.to_string(),
+            item_type: item_type.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            size: Some(size),
+            file_count: Some(file_count),
+            pushed_at: Local::now() - Duration::days(age_days),
+            ..Default::default()
+        }
+    }
+
+    fn sizes(usage: &[Usage]) -> Vec<(&str, u64, usize)> {
+        usage
+            .iter()
+            .map(|u| (u.label.as_str(), u.size, u.count))
+            .collect()
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let items = vec![
+            create_item("a.txt", "file", &["work"], 100, 0),
+            create_item("b", "directory", &["work", "q3"], 300, 3),
+            create_item("c.txt", "file", &[], 50, 400),
+            create_item("c.txt", "file", &[], 20, 1),
+        ];
+        let now = Local::now();
+
+        assert_eq!(
+            sizes(&breakdown(&items, None, now)),
+            vec![
+                ("b", 300, 1),
+                ("a.txt", 100, 1),
+                ("c.txt", 50, 1),
+                ("c.txt", 20, 1)
+            ]
+        );
+        assert_eq!(
+            sizes(&breakdown(&items, Some(UsageGroup::Type), now)),
+            vec![("directory", 300, 1), ("file", 170, 3)]
+        );
+        assert_eq!(
+            sizes(&breakdown(&items, Some(UsageGroup::Tag), now)),
+            vec![("work", 400, 2), ("q3", 300, 1), ("(untagged)", 70, 2)]
+        );
+        let files: Vec<u64> = breakdown(&items, Some(UsageGroup::Tag), now)
+            .iter()
+            .map(|u| u.files)
+            .collect();
+        assert_eq!(files, vec![13, 12, 2]);
+        assert_eq!(
+            sizes(&breakdown(&items, Some(UsageGroup::Age), now)),
+            vec![
+                ("1-7 days", 320, 2),
+                ("< 1 day", 100, 1),
+                ("> 1 year", 50, 1)
+            ]
+        );
+    }
+}
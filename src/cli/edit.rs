@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use crate::db::{establish_connection, ItemManager, StackItem, TagManager};
+use crate::fs;
+use crate::utils::error::FstkError;
+
+/// Editor used when neither `VISUAL` nor `EDITOR` is set
+const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+/// The fields of an item that `edit` lets the user change, as written to the edit buffer
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EditBuffer {
+    name: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    locked: bool,
+}
+
+impl EditBuffer {
+    fn from_item(item: &StackItem) -> Self {
+        EditBuffer {
+            name: item.original_name.clone(),
+            tags: item.tags.clone(),
+            note: item.note.clone().unwrap_or_default(),
+            pinned: item.pinned,
+            locked: item.locked,
+        }
+    }
+}
+
+/// Open the name, tags, note and flags of an item as TOML in `$VISUAL` or `$EDITOR`, and apply
+/// the saved changes in one transaction. Saving an empty buffer cancels the edit.
+pub fn edit(number: usize) -> Result<()> {
+    let mut conn = establish_connection()?;
+
+    let id = ItemManager::get_id_by_display_number(&conn, number, &[])?
+        .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+    let item = ItemManager::get_by_id(&conn, id)?
+        .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+    let original = EditBuffer::from_item(&item);
+
+    // Created with a random name and only readable by the user, since notes may be private.
+    // The suffix lets editors pick TOML highlighting.
+    let mut buffer = tempfile::Builder::new()
+        .prefix("fstk-edit-")
+        .suffix(".toml")
+        .tempfile()?;
+    buffer.write_all(render(number, &original)?.as_bytes())?;
+    buffer.flush()?;
+    let result =
+        run_editor(buffer.path()).and_then(|()| Ok(std::fs::read_to_string(buffer.path())?));
+    drop(buffer);
+
+    let Some(edited) = parse(&result?)? else {
+        println!("Edit cancelled (empty buffer)");
+        return Ok(());
+    };
+    if edited == original {
+        println!("No changes to '{}'", item.original_name);
+        return Ok(());
+    }
+
+    let name = edited.name.trim();
+    if !fs::is_plain_name(name) {
+        return Err(anyhow!("Invalid name: '{}'", edited.name));
+    }
+    let note = edited.note.trim();
+
+    let tx = conn.transaction()?;
+    ItemManager::set_name(&tx, item.id, &fs::normalize_name(name))?;
+    TagManager::replace_for_item(&tx, item.id, &edited.tags)?;
+    ItemManager::set_note(&tx, item.id, (!note.is_empty()).then_some(note))?;
+    ItemManager::set_pinned(&tx, item.id, edited.pinned)?;
+    ItemManager::set_locked(&tx, item.id, edited.locked)?;
+    tx.commit()?;
+
+    println!("Updated '{}'", name);
+    Ok(())
+}
+
+/// The edit buffer for an item: instructions as comments, then its fields
+fn render(number: usize, buffer: &EditBuffer) -> Result<String> {
+    Ok(format!(
+        "# Editing item #{}. Save and quit to apply the changes;\n\
+         # save an empty buffer to cancel.\n\n{}",
+        number,
+        toml::to_string(buffer)?
+    ))
+}
+
+/// Read a saved edit buffer; `None` if it was emptied
+fn parse(text: &str) -> Result<Option<EditBuffer>> {
+    let emptied = text
+        .lines()
+        .all(|line| line.trim().is_empty() || line.trim_start().starts_with('#'));
+    if emptied {
+        return Ok(None);
+    }
+
+    toml::from_str(text)
+        .map(Some)
+        .map_err(|e| anyhow!("Invalid edit buffer, nothing was changed: {}", e))
+}
+
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+
+    // Editors are often configured with arguments, e.g. "code --wait"; like git, let the
+    // shell split them
+    let mut command = if cfg!(windows) {
+        let mut words = editor.split_whitespace();
+        let mut command = Command::new(words.next().unwrap_or(DEFAULT_EDITOR));
+        command.args(words);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("{} \"$@\"", editor))
+            .arg(&editor);
+        command
+    };
+    let status = command
+        .arg(path)
+        .status()
+        .map_err(|e| anyhow!("Cannot start editor '{}': {}", editor, e))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Editor '{}' failed ({}), nothing was changed",
+            editor,
+            status
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_buffer_round_trip() {
+        let buffer = EditBuffer {
+            name: "report \"final\".pdf".to_string(),
+            tags: vec!["taxes".to_string(), "2024".to_string()],
+            note: "Scanned\nfrom the paper copy".to_string(),
+            pinned: true,
+            locked: false,
+        };
+
+        let text = render(3, &buffer).unwrap();
+        assert!(text.starts_with("# Editing item #3."));
+        assert_eq!(parse(&text).unwrap(), Some(buffer));
+
+        // Emptied buffers cancel, broken ones are errors
+        assert_eq!(parse("# only comments\n\n").unwrap(), None);
+        assert!(parse("name = \"x\"\nsize = 3").is_err());
+    }
+}
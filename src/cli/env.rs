@@ -0,0 +1,78 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::config::{self, PopDestination};
+use crate::db::{establish_connection, get_fstk_dir, get_project_root, ItemManager};
+use crate::fs;
+
+/// Print the state of the active stack as shell assignments, for `eval "$(fstk env)"`.
+/// Variables of an empty stack are set to empty values so that stale ones are cleared.
+pub fn env() -> Result<()> {
+    let conn = establish_connection()?;
+    let items = ItemManager::list(&conn, &[])?;
+    let top = items.first();
+
+    // Where `pop` without options would restore the top item
+    let config = config::load()?;
+    let pop_dir = match &config.pop_output_dir {
+        Some(dir) => {
+            let dir = PathBuf::from(dir);
+            fs::get_absolute_path(&dir).unwrap_or(dir)
+        }
+        None => std::env::current_dir()?,
+    };
+    let top_dir = top.and_then(|item| match config.pop_destination {
+        PopDestination::Cwd => Some(pop_dir.clone()),
+        PopDestination::Original => Some(PathBuf::from(&item.original_path)),
+        PopDestination::Pushdir => item.push_dir.as_ref().map(PathBuf::from),
+    });
+
+    let variables = [
+        ("FSTK_COUNT", items.len().to_string()),
+        (
+            "FSTK_TOP",
+            top.zip(top_dir)
+                .map(|(item, dir)| dir.join(&item.original_name).display().to_string())
+                .unwrap_or_default(),
+        ),
+        (
+            "FSTK_TOP_NAME",
+            top.map(|item| item.original_name.clone())
+                .unwrap_or_default(),
+        ),
+        (
+            "FSTK_TOP_ID",
+            top.map(|item| item.id.to_string()).unwrap_or_default(),
+        ),
+        ("FSTK_DIR", get_fstk_dir()?.display().to_string()),
+        (
+            "FSTK_PROJECT",
+            get_project_root()
+                .map(|root| root.display().to_string())
+                .unwrap_or_default(),
+        ),
+    ];
+
+    for (name, value) in variables {
+        println!("export {}={}", name, shell_quote(&value));
+    }
+
+    Ok(())
+}
+
+/// Quote a value for POSIX shells; only single quotes need escaping inside single quotes
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/tmp/my notes.txt"), "'/tmp/my notes.txt'");
+        assert_eq!(shell_quote("it's $HOME"), "'it'\\''s $HOME'");
+        assert_eq!(shell_quote(""), "''");
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::cli::MetaFormat;
+use crate::db::{establish_connection, ItemManager, StackItem};
+
+/// Metadata of a single item as written by `export-meta`
+#[derive(Debug, Serialize)]
+pub(crate) struct ItemRecord {
+    number: usize,
+    id: i64,
+    name: String,
+    original_path: String,
+    #[serde(rename = "type")]
+    item_type: String,
+    pushed_at: String,
+    tags: Vec<String>,
+    size_bytes: Option<u64>,
+    stored_hash: String,
+    content_hash: Option<String>,
+    pinned: bool,
+    archived_to: Option<String>,
+    uuid: String,
+}
+
+impl ItemRecord {
+    pub(crate) fn new(number: usize, item: &StackItem) -> Self {
+        ItemRecord {
+            number,
+            id: item.id,
+            name: item.original_name.clone(),
+            original_path: item.original_path.clone(),
+            item_type: item.item_type.clone(),
+            pushed_at: item.pushed_at.to_rfc3339(),
+            tags: item.tags.clone(),
+            size_bytes: item.size,
+            stored_hash: item.stored_hash.clone(),
+            content_hash: item.content_hash.clone(),
+            pinned: item.pinned,
+            archived_to: item.storage_location.clone(),
+            uuid: item.uuid.clone(),
+        }
+    }
+}
+
+/// Export the metadata of all items (not their content) as CSV or JSON.
+/// Writes to `out` if given, otherwise to stdout.
+pub fn export_meta(format: MetaFormat, out: Option<String>) -> Result<()> {
+    let conn = establish_connection()?;
+
+    let mut items = ItemManager::list(&conn, &[])?;
+    items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+
+    let records: Vec<ItemRecord> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| ItemRecord::new(index + 1, item))
+        .collect();
+
+    let writer: Box<dyn Write> = match &out {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+
+    match format {
+        MetaFormat::Csv => write_csv(&records, writer)?,
+        MetaFormat::Json => write_json(&records, writer)?,
+    }
+
+    if let Some(path) = out {
+        println!("Exported metadata of {} item(s) to {}", records.len(), path);
+    }
+
+    Ok(())
+}
+
+/// Write records as CSV with a header row; tags are joined with commas.
+fn write_csv<W: Write>(records: &[ItemRecord], writer: W) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+
+    csv.write_record([
+        "number",
+        "id",
+        "name",
+        "original_path",
+        "type",
+        "pushed_at",
+        "tags",
+        "size_bytes",
+        "stored_hash",
+        "content_hash",
+        "pinned",
+        "archived_to",
+        "uuid",
+    ])?;
+
+    for record in records {
+        csv.write_record([
+            record.number.to_string(),
+            record.id.to_string(),
+            record.name.clone(),
+            record.original_path.clone(),
+            record.item_type.clone(),
+            record.pushed_at.clone(),
+            record.tags.join(","),
+            record.size_bytes.map(|s| s.to_string()).unwrap_or_default(),
+            record.stored_hash.clone(),
+            record.content_hash.clone().unwrap_or_default(),
+            record.pinned.to_string(),
+            record.archived_to.clone().unwrap_or_default(),
+            record.uuid.clone(),
+        ])?;
+    }
+
+    csv.flush()?;
+    Ok(())
+}
+
+/// Write records as a pretty-printed JSON array.
+pub(crate) fn write_json<W: Write>(records: &[ItemRecord], mut writer: W) -> Result<()> {
+    serde_json::to_writer_pretty(&mut writer, records)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record() -> ItemRecord {
+        let item = StackItem {
+            id: 7,
+            original_name: "report, final.pdf".to_string(),
+            original_path: "/home/user/docs".to_string(),
+            stored_hash: "abcdef1234567890".to_string(),
+            item_type: "file".to_string(),
+            tags: vec!["work".to_string(), "q3".to_string()],
+            size: Some(1024),
+            ..Default::default()
+        };
+        ItemRecord::new(1, &item)
+    }
+
+    #[test]
+    fn test_write_csv() -> Result<()> {
+        let mut buffer = Vec::new();
+        write_csv(&[create_test_record()], &mut buffer)?;
+
+        let output = String::from_utf8(buffer)?;
+        let mut lines = output.lines();
+        assert!(lines.next().unwrap().starts_with("number,id,name,"));
+
+        let row = lines.next().unwrap();
+        assert!(row.starts_with("1,7,\"report, final.pdf\",/home/user/docs,file,"));
+        assert!(row.contains("\"work,q3\",1024,abcdef1234567890,,false,"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_json() -> Result<()> {
+        let mut buffer = Vec::new();
+        write_json(&[create_test_record()], &mut buffer)?;
+
+        let value: serde_json::Value = serde_json::from_slice(&buffer)?;
+        assert_eq!(value[0]["name"], "report, final.pdf");
+        assert_eq!(value[0]["type"], "file");
+        assert_eq!(value[0]["tags"][1], "q3");
+        assert_eq!(value[0]["size_bytes"], 1024);
+        assert!(value[0]["content_hash"].is_null());
+
+        Ok(())
+    }
+}
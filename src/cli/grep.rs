@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Result};
+use regex::RegexBuilder;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::db::{establish_connection, get_item_stored_path, item_by_number, ItemManager};
+use crate::utils::numbers::parse_number_range;
+
+/// Number of leading bytes inspected to decide whether a file is binary
+pub(crate) const BINARY_CHECK_LEN: usize = 8192;
+
+/// Search the contents of stored items for a regular expression.
+pub fn grep(
+    pattern: String,
+    numbers: Option<String>,
+    tags: Option<Vec<String>>,
+    ignore_case: bool,
+) -> Result<()> {
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| anyhow!("Invalid pattern '{}': {}", pattern, e))?;
+
+    // Connect to database
+    let conn = establish_connection()?;
+
+    let tag_vec = tags.unwrap_or_default();
+    let mut all_items = ItemManager::list(&conn, &tag_vec)?;
+    all_items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+
+    // Search the selected items, or every (tag-filtered) item if no numbers are given
+    let selected: Vec<usize> = match numbers {
+        Some(numbers) => parse_number_range(&numbers)?,
+        None => (1..=all_items.len()).collect(),
+    };
+
+    let mut match_count = 0;
+
+    for number in selected {
+        let item = match item_by_number(&all_items, number) {
+            Some(item) => item,
+            None => {
+                println!("No item found with number={}", number);
+                continue;
+            }
+        };
+
+        let stored_path = get_item_stored_path(item)?;
+        if !stored_path.exists() {
+            continue;
+        }
+
+        for entry in WalkDir::new(&stored_path).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            // Show paths as they will appear once popped
+            let relative = entry.path().strip_prefix(&stored_path)?;
+            let display_path = Path::new(&item.original_name).join(relative);
+
+            match_count += grep_file(entry.path(), &regex, |line_number, line| {
+                println!(
+                    "#{} {}:{}: {}",
+                    number,
+                    display_path.display(),
+                    line_number,
+                    line
+                );
+            })?;
+        }
+    }
+
+    if match_count == 0 {
+        return Err(anyhow!("No matches found for '{}'", pattern));
+    }
+
+    Ok(())
+}
+
+/// Report every matching line of a text file, returning the number of matches.
+/// Binary files are skipped.
+fn grep_file<F: FnMut(usize, &str)>(
+    path: &Path,
+    regex: &regex::Regex,
+    mut on_match: F,
+) -> Result<usize> {
+    let mut file = std::fs::File::open(path)?;
+
+    let mut head = vec![0; BINARY_CHECK_LEN];
+    let read = file.read(&mut head)?;
+    if head[..read].contains(&0) {
+        return Ok(0);
+    }
+
+    let reader = BufReader::new(head[..read].chain(file));
+    let mut matches = 0;
+
+    for (index, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end_matches('\r');
+
+        if regex.is_match(line) {
+            on_match(index + 1, line);
+            matches += 1;
+        }
+    }
+
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_grep_file() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "first line\nTODO: fix this\nlast line\ntodo again\n")?;
+
+        let regex = RegexBuilder::new("todo").case_insensitive(true).build()?;
+        let mut found = Vec::new();
+        let count = grep_file(&path, &regex, |n, line| found.push((n, line.to_string())))?;
+
+        assert_eq!(count, 2);
+        assert_eq!(
+            found,
+            vec![
+                (2, "TODO: fix this".to_string()),
+                (4, "todo again".to_string())
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_grep_file_skips_binary() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, b"match\0match")?;
+
+        let regex = RegexBuilder::new("match").build()?;
+        assert_eq!(grep_file(&path, &regex, |_, _| {})?, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Context, Result};
+use rusqlite::Connection;
+use std::path::Path;
+
+use crate::db::{establish_connection, get_item_stored_path, ItemManager, StackItem};
+use crate::fs;
+use crate::status;
+use crate::utils::error::FstkError;
+
+/// Adopt an external file or directory as the stored blob of an item whose storage went missing.
+/// The recorded checksum, when there is one, must match; the source is moved unless `keep` is set.
+pub fn heal(number: usize, from: String, keep: bool) -> Result<()> {
+    let conn = establish_connection()?;
+
+    let id = ItemManager::get_id_by_display_number(&conn, number, &[])?
+        .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+    let item = ItemManager::get_by_id(&conn, id)?
+        .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+
+    heal_item(&conn, number, &item, Path::new(&from), keep)
+}
+
+/// Adopt `source` as the stored blob of `item`, displayed as `number`
+fn heal_item(
+    conn: &Connection,
+    number: usize,
+    item: &StackItem,
+    source: &Path,
+    keep: bool,
+) -> Result<()> {
+    let stored_path = get_item_stored_path(item)?;
+    if stored_path.exists() {
+        return Err(anyhow!(
+            "Item #{} ('{}') still has its stored data; use 'verify' to check it",
+            number,
+            item.original_name
+        ));
+    }
+
+    if !source.exists() {
+        return Err(FstkError::PathNotFound(source.to_string_lossy().to_string()).into());
+    }
+    if source.is_dir() != item.is_stored_as_directory() {
+        return Err(anyhow!(
+            "Item #{} is a {}, but {} is not",
+            number,
+            item.item_type,
+            source.display()
+        ));
+    }
+
+    match &item.content_hash {
+        Some(expected) => {
+            if fs::content_hash(source)? != *expected {
+                return Err(anyhow!(
+                    "Checksum of {} does not match item #{} ('{}')",
+                    source.display(),
+                    number,
+                    item.original_name
+                ));
+            }
+        }
+        None => status!(
+            "No checksum recorded for item #{}; adopting {} without verification",
+            number,
+            source.display()
+        ),
+    }
+
+    fs::ensure_parent_dirs(&stored_path)?;
+    if keep {
+        fs::copy_item(source, &stored_path)
+    } else {
+        fs::move_or_copy(source, &stored_path)
+    }
+    .with_context(|| format!("Failed to adopt {}", source.display()))?;
+
+    if item.size.is_none() {
+        ItemManager::set_size(conn, item.id, fs::path_size(&stored_path)?)?;
+    }
+
+    status!(
+        "Healed item #{} ('{}') from {}",
+        number,
+        item.original_name,
+        source.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{schema, ItemMetadata};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_heal_item_adopts_matching_file() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+        let dir = tempdir()?;
+        let storage = dir.path().join("storage");
+        let found = dir.path().join("found.txt");
+        std::fs::write(&found, "hello")?;
+
+        let metadata = ItemMetadata {
+            content_hash: Some(fs::content_hash(&found)?),
+            ..Default::default()
+        };
+        let id = ItemManager::insert_with_metadata(
+            &mut conn,
+            "notes.txt",
+            "/p",
+            "stored-notes",
+            "file",
+            &[],
+            &metadata,
+        )?;
+        ItemManager::set_storage_location(&conn, id, Some(&storage.to_string_lossy()))?;
+        let item = ItemManager::get_by_id(&conn, id)?.unwrap();
+        let stored_path = storage.join("stored-notes");
+
+        // A file with other content is refused and left where it is
+        let other = dir.path().join("other.txt");
+        std::fs::write(&other, "changed")?;
+        let err = heal_item(&conn, 1, &item, &other, false).unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+        assert!(other.exists());
+        assert!(!stored_path.exists());
+
+        // With --keep the matching file is copied into storage
+        heal_item(&conn, 1, &item, &found, true)?;
+        assert_eq!(std::fs::read_to_string(&stored_path)?, "hello");
+        assert!(found.exists());
+
+        // Storage that is not missing is never replaced
+        assert!(heal_item(&conn, 1, &item, &found, false).is_err());
+
+        // Without --keep the file is moved
+        std::fs::remove_file(&stored_path)?;
+        heal_item(&conn, 1, &item, &found, false)?;
+        assert_eq!(std::fs::read_to_string(&stored_path)?, "hello");
+        assert!(!found.exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use std::io::{self, Write};
+
+use crate::cli::HistoryFormat;
+use crate::db::{establish_connection, OperationLog, OperationRecord};
+use crate::utils::display;
+use crate::utils::duration::parse_duration;
+
+/// Show the operations performed on the stack, oldest first.
+/// `since` is a date (2024-01-01, local midnight) or a duration back from now (30d).
+pub fn history(format: HistoryFormat, since: Option<String>) -> Result<()> {
+    let conn = establish_connection()?;
+
+    let since = since.as_deref().map(parse_since).transpose()?;
+    let records = OperationLog::list_since(&conn, since)?;
+
+    match format {
+        HistoryFormat::Table => {
+            if records.is_empty() {
+                println!("No operations recorded");
+            } else {
+                display::display_history_table(&records);
+            }
+        }
+        HistoryFormat::Csv => write_csv(&records, io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+fn parse_since(since: &str) -> Result<DateTime<Local>> {
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|start| start.and_local_timezone(Local).earliest())
+            .ok_or_else(|| anyhow!("Invalid date: {}", since));
+    }
+
+    parse_duration(since)
+        .map(|duration| Local::now() - duration)
+        .map_err(|_| {
+            anyhow!(
+                "Invalid --since value: {} (use a date like 2024-01-01 or a duration like 30d)",
+                since
+            )
+        })
+}
+
+/// Write operations as CSV with a header row; tags are joined with commas.
+/// Columns are fixed so that exports can be compared and appended over time.
+fn write_csv<W: Write>(records: &[OperationRecord], writer: W) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+
+    csv.write_record([
+        "performed_at",
+        "operation",
+        "item_id",
+        "name",
+        "original_path",
+        "tags",
+        "pushed_at",
+    ])?;
+
+    for record in records {
+        csv.write_record([
+            record.performed_at.to_rfc3339(),
+            record.operation.clone(),
+            record.item_id.to_string(),
+            record.item_name.clone(),
+            record.original_path.clone(),
+            record.tags.join(","),
+            record.item_pushed_at.to_rfc3339(),
+        ])?;
+    }
+
+    csv.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_write_csv() -> Result<()> {
+        let performed_at = Local.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap();
+        let record = OperationRecord {
+            operation: "pop".to_string(),
+            item_id: 12,
+            item_name: "notes, \"draft\".md".to_string(),
+            original_path: "/home/user".to_string(),
+            tags: vec!["work".to_string(), "q3".to_string()],
+            item_pushed_at: performed_at - Duration::hours(2),
+            performed_at,
+        };
+
+        let mut buffer = Vec::new();
+        write_csv(&[record], &mut buffer)?;
+
+        let output = String::from_utf8(buffer)?;
+        let mut lines = output.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "performed_at,operation,item_id,name,original_path,tags,pushed_at"
+        );
+
+        let row = lines.next().unwrap();
+        assert!(row.starts_with(&format!("{},pop,12,", performed_at.to_rfc3339())));
+        assert!(row.contains(",\"notes, \"\"draft\"\".md\",/home/user,\"work,q3\","));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let date = parse_since("2024-01-01").unwrap();
+        assert_eq!(
+            date.format("%Y-%m-%d %H:%M").to_string(),
+            "2024-01-01 00:00"
+        );
+
+        let recent = parse_since("7d").unwrap();
+        assert!(recent < Local::now() - Duration::days(6));
+
+        assert!(parse_since("yesterday").is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+use crate::cli::sync;
+use crate::db::{
+    establish_connection, get_data_dir, get_item_stored_path, BundleManager, BundleMember,
+    ItemManager, OperationLog, StackItem,
+};
+use crate::fs;
+use crate::utils::error::FstkError;
+
+/// Archive entry describing the item
+const METADATA_ENTRY: &str = "metadata.json";
+
+/// Archive entry holding the item's content: the file itself, or a directory tree
+const CONTENT_ENTRY: &str = "content";
+
+/// Contents of `metadata.json` in an item archive
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveMetadata {
+    item: StackItem,
+    #[serde(default)]
+    members: Vec<BundleMember>,
+}
+
+/// Write a single item to a self-contained tar archive at `out` that `import-item` reads back,
+/// with its metadata in `metadata.json` and its content under `content`.
+pub fn export_item(number: usize, out: &str) -> Result<()> {
+    let conn = establish_connection()?;
+
+    let id = ItemManager::get_id_by_display_number(&conn, number, &[])?
+        .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+    let item = ItemManager::get_by_id(&conn, id)?
+        .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+    let members = if item.is_bundle() {
+        BundleManager::get_for_item(&conn, item.id)?
+    } else {
+        Vec::new()
+    };
+
+    let stored_path = get_item_stored_path(&item)?;
+    export(&item, members, &stored_path, Path::new(out))?;
+
+    println!("Exported '{}' to {}", item.original_name, out);
+    Ok(())
+}
+
+/// Write the archive of `item`, whose content is at `stored_path`, to `out`
+fn export(
+    item: &StackItem,
+    members: Vec<BundleMember>,
+    stored_path: &Path,
+    out: &Path,
+) -> Result<()> {
+    if !stored_path.exists() {
+        return Err(anyhow!(
+            "Stored content of '{}' is missing: {}",
+            item.original_name,
+            stored_path.display()
+        ));
+    }
+
+    let metadata = serde_json::to_vec_pretty(&ArchiveMetadata {
+        item: item.clone(),
+        members,
+    })?;
+    if let Err(e) = write_archive(out, &metadata, stored_path) {
+        let _ = std::fs::remove_file(out);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn write_archive(out: &Path, metadata: &[u8], stored_path: &Path) -> Result<()> {
+    let mut archive = tar::Builder::new(File::create(out)?);
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(metadata.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, METADATA_ENTRY, metadata)?;
+
+    if stored_path.is_dir() {
+        archive.append_dir_all(CONTENT_ENTRY, stored_path)?;
+    } else {
+        archive.append_path_with_name(stored_path, CONTENT_ENTRY)?;
+    }
+
+    archive.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// Add the item in an archive written by `export-item` to the stack, keeping its UUID, push
+/// time, tags and note. Fails if the item is already on the stack.
+pub fn import_item(archive_path: &str) -> Result<()> {
+    let mut conn = establish_connection()?;
+    let item = import(&mut conn, Path::new(archive_path), &get_data_dir()?)?;
+
+    println!(
+        "Imported '{}' (pushed {})",
+        item.original_name,
+        item.pushed_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    Ok(())
+}
+
+/// Add the item in the archive at `archive_path` to the stack whose content lives in `data_dir`
+fn import(
+    conn: &mut rusqlite::Connection,
+    archive_path: &Path,
+    data_dir: &Path,
+) -> Result<StackItem> {
+    // Unpack inside the data directory so the content can be renamed into place
+    let unpack_dir = data_dir.join(format!(".import-{}", std::process::id()));
+    let result = File::open(archive_path)
+        .map_err(|e| anyhow!("Cannot open {}: {}", archive_path.display(), e))
+        .and_then(|file| {
+            tar::Archive::new(file)
+                .unpack(&unpack_dir)
+                .map_err(|e| anyhow!("Cannot unpack {}: {}", archive_path.display(), e))
+        })
+        .and_then(|()| store_unpacked(conn, &unpack_dir, data_dir));
+    if unpack_dir.exists() {
+        fs::remove_item(&unpack_dir)?;
+    }
+    result
+}
+
+fn store_unpacked(
+    conn: &mut rusqlite::Connection,
+    unpack_dir: &Path,
+    data_dir: &Path,
+) -> Result<StackItem> {
+    let metadata_path = unpack_dir.join(METADATA_ENTRY);
+    let content_path = unpack_dir.join(CONTENT_ENTRY);
+    if !metadata_path.is_file() || !content_path.exists() {
+        return Err(anyhow!("Not an item archive written by 'fstk export-item'"));
+    }
+
+    let ArchiveMetadata { item, members } =
+        serde_json::from_str(&std::fs::read_to_string(&metadata_path)?)
+            .map_err(|e| anyhow!("Invalid {} in the archive: {}", METADATA_ENTRY, e))?;
+
+    check_names(&item, &members)?;
+    if !item.uuid.is_empty() && ItemManager::get_by_uuid(conn, &item.uuid)?.is_some() {
+        return Err(anyhow!(
+            "'{}' is already on the stack (UUID {})",
+            item.original_name,
+            item.uuid
+        ));
+    }
+    if content_path.is_dir() != item.is_stored_as_directory() {
+        return Err(anyhow!(
+            "Content in the archive does not match the item type '{}'",
+            item.item_type
+        ));
+    }
+    if let Some(expected) = &item.content_hash {
+        if fs::content_hash(&content_path)? != *expected {
+            return Err(anyhow!(
+                "Checksum of '{}' in the archive does not match",
+                item.original_name
+            ));
+        }
+    }
+
+    // The stored hash is picked here rather than taken from the archive
+    let (hash, target_path) = sync::free_target(conn, &item, data_dir)?;
+    let staged_path = fs::staging_path(&target_path);
+    std::fs::rename(&content_path, &staged_path)?;
+    let item_id = sync::commit_received(conn, &item, members, &hash, &staged_path, &target_path)?;
+
+    let imported = ItemManager::get_by_id(conn, item_id)?
+        .ok_or_else(|| FstkError::ItemNotFound(item_id.to_string()))?;
+    OperationLog::record(conn, "push", &imported)?;
+
+    Ok(imported)
+}
+
+/// Refuse metadata whose names would reach outside the directories they are joined to: the
+/// item's name on pop, and each bundle member's name inside the stored bundle and on pop.
+fn check_names(item: &StackItem, members: &[BundleMember]) -> Result<()> {
+    let invalid = std::iter::once(&item.original_name)
+        .chain(members.iter().map(|member| &member.name))
+        .find(|name| !fs::is_plain_name(name));
+    match invalid {
+        Some(name) => Err(anyhow!(
+            "Invalid name '{}' in the {} of the archive",
+            name,
+            METADATA_ENTRY
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{schema, ItemMetadata};
+    use rusqlite::Connection;
+    use tempfile::tempdir;
+
+    fn stack() -> Result<Connection> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        schema::initialize_schema(&conn)?;
+        Ok(conn)
+    }
+
+    /// A pinned, locked and tagged item whose content (a file or a directory) is at `stored_path`
+    fn pushed_item(conn: &mut Connection, name: &str, stored_path: &Path) -> Result<StackItem> {
+        let metadata = ItemMetadata {
+            content_hash: Some(fs::content_hash(stored_path)?),
+            pushed_at: Some(chrono::Local::now() - chrono::Duration::days(2)),
+            pinned: true,
+            locked: true,
+            note: Some("from the old laptop".to_string()),
+            ..Default::default()
+        };
+        let item_type = if stored_path.is_dir() {
+            "directory"
+        } else {
+            "file"
+        };
+        let id = ItemManager::insert_with_metadata(
+            conn,
+            name,
+            "/home/me",
+            &format!("stored-{}", name),
+            item_type,
+            &["keep".to_string(), "photos".to_string()],
+            &metadata,
+        )?;
+        Ok(ItemManager::get_by_id(conn, id)?.unwrap())
+    }
+
+    #[test]
+    fn test_export_and_import_round_trip() -> Result<()> {
+        let dir = tempdir()?;
+        let data_dir = dir.path().join("data");
+        std::fs::create_dir(&data_dir)?;
+        let stored_file = dir.path().join("stored-file");
+        std::fs::write(&stored_file, "hello")?;
+        let stored_dir = dir.path().join("stored-dir");
+        std::fs::create_dir_all(stored_dir.join("sub"))?;
+        std::fs::write(stored_dir.join("sub/a.txt"), "a")?;
+
+        let mut source = stack()?;
+        let mut target = stack()?;
+        for (name, stored_path) in [("notes.txt", &stored_file), ("album", &stored_dir)] {
+            let item = pushed_item(&mut source, name, stored_path)?;
+            let archive = dir.path().join(format!("{}.tar", name));
+            export(&item, Vec::new(), stored_path, &archive)?;
+
+            let imported = import(&mut target, &archive, &data_dir)?;
+            assert_eq!(imported.original_name, name);
+            assert_eq!(imported.uuid, item.uuid);
+            assert_eq!(imported.pushed_at.timestamp(), item.pushed_at.timestamp());
+            assert_eq!(imported.tags, item.tags);
+            assert_eq!(imported.note, item.note);
+            assert!(imported.pinned);
+            assert!(imported.locked);
+            let content = data_dir.join(&imported.stored_hash);
+            assert_eq!(fs::content_hash(&content)?, fs::content_hash(stored_path)?);
+
+            // The same item cannot be imported twice
+            assert!(import(&mut target, &archive, &data_dir).is_err());
+        }
+
+        // Nothing but the imported content is left in the data directory
+        assert_eq!(std::fs::read_dir(&data_dir)?.count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_unsafe_names() -> Result<()> {
+        let dir = tempdir()?;
+        let data_dir = dir.path().join("data");
+        std::fs::create_dir(&data_dir)?;
+        let stored_file = dir.path().join("stored-file");
+        std::fs::write(&stored_file, "* * * * * root sh -c evil")?;
+
+        let mut source = stack()?;
+        let item = pushed_item(&mut source, "x", &stored_file)?;
+        let mut target = stack()?;
+        for name in ["../../etc/cron.d/x", "/etc/cron.d/x", ".."] {
+            let archive = dir.path().join("evil.tar");
+            let escaping = StackItem {
+                original_name: name.to_string(),
+                ..item.clone()
+            };
+            export(&escaping, Vec::new(), &stored_file, &archive)?;
+
+            let err = import(&mut target, &archive, &data_dir).unwrap_err();
+            assert!(err.to_string().contains("Invalid name"), "{}", err);
+        }
+        assert!(ItemManager::list(&target, &[])?.is_empty());
+        assert_eq!(std::fs::read_dir(&data_dir)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_names() {
+        let item = StackItem {
+            original_name: "photos".to_string(),
+            item_type: "bundle".to_string(),
+            ..Default::default()
+        };
+        let member = |name: &str| BundleMember {
+            name: name.to_string(),
+            original_path: "/home/me".to_string(),
+        };
+
+        assert!(check_names(&item, &[member("a.jpg"), member("b.jpg")]).is_ok());
+        assert!(check_names(&item, &[member("../../.bashrc")]).is_err());
+        let escaping = StackItem {
+            original_name: "/etc/cron.d/x".to_string(),
+            ..item
+        };
+        assert!(check_names(&escaping, &[]).is_err());
+    }
+}
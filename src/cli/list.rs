@@ -1,20 +1,89 @@
 use anyhow::Result;
+use owo_colors::OwoColorize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-use crate::db::{establish_connection, ItemManager};
-use crate::utils::display;
-
-/// List items in the stack, optionally filtered by tags.
-pub fn list(tags: Option<Vec<String>>) -> Result<()> {
-    // Connect to database
-    let conn = establish_connection()?;
+use crate::cli::daemon;
+use crate::cli::export_meta::{self, ItemRecord};
+use crate::cli::verify::{check_health, ItemHealth};
+use crate::cli::{GroupBy, ListFormat};
+use crate::config;
+use crate::db::{establish_connection, get_project_root, item_number, ItemManager, StackItem};
+use crate::utils::ssh::RemotePath;
+use crate::utils::{display, nuon};
 
+/// List items in the stack, optionally filtered by tags (or to untagged items) and split into
+/// sections. The HEALTH column checks every stored blob; `verify` also compares checksums.
+/// Names and tags are truncated to the terminal width unless `no_truncate` is set.
+pub fn list(
+    tags: Option<Vec<String>>,
+    untagged: bool,
+    group_by: Option<GroupBy>,
+    format: ListFormat,
+    verify: bool,
+    no_truncate: bool,
+) -> Result<()> {
     // Get items with optional tag filtering
     let tags_vec = tags.unwrap_or_default();
-    let mut items = ItemManager::list(&conn, &tags_vec)?;
+
+    // Untagged items keep the numbers they have in the full list, so that e.g.
+    // `tag add <n>` addresses the listed item
+    let shown = |item: &StackItem| !untagged || item.tags.is_empty();
+
+    // Stream items straight from the database, keeping memory flat for huge stacks
+    if format == ListFormat::Jsonl {
+        let conn = establish_connection()?;
+        let mut out = BufWriter::new(std::io::stdout().lock());
+        let mut number = 0;
+        ItemManager::for_each(&conn, &tags_vec, |item| {
+            number += 1;
+            if shown(&item) {
+                serde_json::to_writer(&mut out, &ItemRecord::new(number, &item))?;
+                writeln!(out)?;
+            }
+            Ok(())
+        })?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    // A running daemon already has the database open
+    let mut items = match daemon::list_items(&tags_vec) {
+        Some(items) => items,
+        None => ItemManager::list(&establish_connection()?, &tags_vec)?,
+    };
+
+    if format != ListFormat::Table {
+        items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+        let records: Vec<ItemRecord> = items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| shown(item))
+            .map(|(index, item)| ItemRecord::new(index + 1, item))
+            .collect();
+        if format == ListFormat::Nuon {
+            println!("{}", nuon::to_nuon(&records)?);
+            return Ok(());
+        }
+        return export_meta::write_json(&records, std::io::stdout());
+    }
+
+    // Sort items by pushed_at in descending order (newest first)
+    items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+
+    let numbered: Vec<(usize, StackItem)> = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| (item_number(index, &item), item))
+        .filter(|(_, item)| shown(item))
+        .collect();
 
     // Check if there are any items
-    if items.is_empty() {
-        if tags_vec.is_empty() {
+    if numbered.is_empty() {
+        if untagged {
+            println!("No untagged items in the stack.");
+        } else if tags_vec.is_empty() {
             println!("No items in the stack.");
         } else {
             println!("No items found with tags=[{}].", tags_vec.join(", "));
@@ -22,11 +91,233 @@ pub fn list(tags: Option<Vec<String>>) -> Result<()> {
         return Ok(());
     }
 
-    // Sort items by pushed_at in descending order (newest first)
-    items.sort_by(|a, b| b.pushed_at.cmp(&a.pushed_at));
+    let health: HashMap<i64, ItemHealth> = numbered
+        .iter()
+        .map(|(_, item)| (item.id, check_health(item, verify)))
+        .collect();
+    let config = config::load()?;
+    let tag_colors = config.tag_colors()?;
+    let min_widths = if no_truncate {
+        None
+    } else {
+        Some(config.min_column_widths()?)
+    };
+
+    // Show which stack the items belong to when working in a project stack
+    if let Some(root) = get_project_root() {
+        println!("Stack: local ({})", root.display());
+    }
+
+    match group_by {
+        Some(GroupBy::Tag) => {
+            // Items keep the numbers they have in the flat list
+            for (tag, section) in group_by_tag(&numbered) {
+                let heading = tag.unwrap_or_else(|| "(untagged)".to_string());
+                println!("{} ({})", heading.bold(), section.len());
+                display::display_numbered_items_table(&section, &health, &tag_colors, min_widths);
+            }
+        }
+        Some(GroupBy::Dir) => {
+            for (dir, section) in group_by_dir(&numbered) {
+                println!("{} ({})", dir.bold(), section.len());
+                display::display_numbered_items_table(&section, &health, &tag_colors, min_widths);
+            }
+        }
+        // Display the items as a formatted table
+        None => display::display_numbered_items_table(&numbered, &health, &tag_colors, min_widths),
+    }
+
+    Ok(())
+}
+
+/// Print where each item came from (its original path joined with its name), newest first,
+/// one per line and without the table, for piping into other tools.
+pub fn list_paths(tags: Option<Vec<String>>, untagged: bool) -> Result<()> {
+    let tags_vec = tags.unwrap_or_default();
+    let mut items = match daemon::list_items(&tags_vec) {
+        Some(items) => items,
+        None => ItemManager::list(&establish_connection()?, &tags_vec)?,
+    };
+    items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for item in items
+        .iter()
+        .filter(|item| !untagged || item.tags.is_empty())
+    {
+        writeln!(
+            out,
+            "{}",
+            Path::new(&item.original_path)
+                .join(&item.original_name)
+                .display()
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// List every version of an original path, oldest first, with the numbers used by other commands.
+pub fn list_versions(path: &str) -> Result<()> {
+    let conn = establish_connection()?;
+    let group = version_group_for(Path::new(path))?;
+
+    let versions = ItemManager::list_versions(&conn, &group.to_string_lossy())?;
+    if versions.is_empty() {
+        println!("No versions of {} in the stack.", group.display());
+        return Ok(());
+    }
 
-    // Display the items as a formatted table
-    display::display_items_table(&items);
+    // Map database IDs to the display numbers shown by list
+    let mut all_items = ItemManager::list(&conn, &[])?;
+    all_items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+    let numbers: HashMap<i64, usize> = all_items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (item.id, item_number(index, item)))
+        .collect();
+
+    let rows: Vec<(usize, usize, StackItem)> = versions
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| (numbers.get(&item.id).copied().unwrap_or(0), index + 1, item))
+        .collect();
+
+    println!("Versions of {}", group.display());
+    display::display_versions_table(&rows);
 
     Ok(())
 }
+
+/// Resolve the absolute original path of a (usually no longer existing) file or directory.
+fn version_group_for(path: &Path) -> Result<PathBuf> {
+    // Items fetched over SSH are grouped by their remote path
+    if !path.exists() {
+        if let Some(remote) = path.to_str().and_then(RemotePath::parse) {
+            return Ok(PathBuf::from(remote.to_string()));
+        }
+    }
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+
+    // Resolve symlinks and `..` in the parent, which still exists, as push does
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => match parent.canonicalize() {
+            Ok(parent) => Ok(parent.join(name)),
+            Err(_) => Ok(absolute),
+        },
+        _ => Ok(absolute),
+    }
+}
+
+/// A section of the grouped list: its heading (`None` for the catch-all section) and numbered items
+type Section = (Option<String>, Vec<(usize, StackItem)>);
+
+/// Split numbered items into one section per tag, sorted by tag name.
+/// Items with several tags appear in each of their sections; untagged items come last.
+fn group_by_tag(items: &[(usize, StackItem)]) -> Vec<Section> {
+    let mut sections: BTreeMap<String, Vec<(usize, StackItem)>> = BTreeMap::new();
+    let mut untagged = Vec::new();
+
+    for (number, item) in items {
+        if item.tags.is_empty() {
+            untagged.push((*number, item.clone()));
+        }
+        for tag in &item.tags {
+            sections
+                .entry(tag.clone())
+                .or_default()
+                .push((*number, item.clone()));
+        }
+    }
+
+    let mut groups: Vec<_> = sections
+        .into_iter()
+        .map(|(tag, section)| (Some(tag), section))
+        .collect();
+    if !untagged.is_empty() {
+        groups.push((None, untagged));
+    }
+
+    groups
+}
+
+/// Split numbered items into one section per original directory, sorted by path
+fn group_by_dir(items: &[(usize, StackItem)]) -> Vec<(String, Vec<(usize, StackItem)>)> {
+    let mut sections: BTreeMap<String, Vec<(usize, StackItem)>> = BTreeMap::new();
+    for (number, item) in items {
+        sections
+            .entry(item.original_path.clone())
+            .or_default()
+            .push((*number, item.clone()));
+    }
+    sections.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, tags: &[&str]) -> StackItem {
+        StackItem {
+            original_name: name.to_string(),
+            original_path: format!("/home/me/{}", tags.first().unwrap_or(&"inbox")),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_group_by_tag() {
+        let items = vec![
+            (1, item("a", &["work", "docs"])),
+            (2, item("b", &[])),
+            (3, item("c", &["work"])),
+        ];
+
+        let groups = group_by_tag(&items);
+        let summary: Vec<(Option<&str>, Vec<usize>)> = groups
+            .iter()
+            .map(|(tag, section)| {
+                (
+                    tag.as_deref(),
+                    section.iter().map(|(number, _)| *number).collect(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (Some("docs"), vec![1]),
+                (Some("work"), vec![1, 3]),
+                (None, vec![2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_by_dir() {
+        let items = vec![
+            (1, item("a", &["work"])),
+            (2, item("b", &[])),
+            (3, item("c", &["work", "docs"])),
+        ];
+
+        let summary: Vec<(String, Vec<usize>)> = group_by_dir(&items)
+            .into_iter()
+            .map(|(dir, section)| (dir, section.iter().map(|(number, _)| *number).collect()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("/home/me/inbox".to_string(), vec![2]),
+                ("/home/me/work".to_string(), vec![1, 3]),
+            ]
+        );
+    }
+}
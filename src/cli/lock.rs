@@ -0,0 +1,37 @@
+use anyhow::Result;
+
+use crate::db::{establish_connection, ItemManager};
+use crate::utils::error::FstkError;
+use crate::utils::numbers::parse_number_range;
+
+/// Lock items so that `remove` and `pop` refuse them without `--force`.
+pub fn lock(numbers: String) -> Result<()> {
+    set_locked(&numbers, true)
+}
+
+/// Unlock previously locked items.
+pub fn unlock(numbers: String) -> Result<()> {
+    set_locked(&numbers, false)
+}
+
+fn set_locked(numbers: &str, locked: bool) -> Result<()> {
+    let number_list = parse_number_range(numbers)?;
+
+    // Connect to database
+    let conn = establish_connection()?;
+
+    // Resolve every display number before changing anything
+    let empty_tags = Vec::new();
+    let mut ids = Vec::new();
+    for number in number_list {
+        let id = ItemManager::get_id_by_display_number(&conn, number, &empty_tags)?
+            .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+        ids.push(id);
+    }
+
+    for id in ids {
+        ItemManager::set_locked(&conn, id, locked)?;
+    }
+
+    Ok(())
+}
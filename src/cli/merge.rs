@@ -0,0 +1,281 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+
+use crate::db::{
+    establish_connection, get_data_dir, get_fstk_dir, schema, BundleManager, ItemManager,
+    ItemMetadata, ManifestManager, StackItem, DB_FILE_NAME,
+};
+use crate::fs;
+use crate::status;
+
+/// Import the items and blobs of another fstk home (e.g. `~/.fstk` copied from another machine)
+/// into the current stack. Items keep their push time, pin state and tags; the other home is
+/// only read, so its database must already have the current schema.
+pub fn merge(source: &str) -> Result<()> {
+    let other_dir = fs::get_absolute_path(Path::new(source))?;
+    let other_db = other_dir.join(DB_FILE_NAME);
+    if !other_db.is_file() {
+        return Err(anyhow!("No fstk database found in {}", other_dir.display()));
+    }
+
+    let own_dir = get_fstk_dir()?;
+    if other_dir.canonicalize()? == own_dir.canonicalize()? {
+        return Err(anyhow!("Cannot merge a stack into itself"));
+    }
+
+    let other = Connection::open_with_flags(&other_db, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    schema::check_schema(&other).map_err(|e| {
+        anyhow!(
+            "Cannot merge {}: {}. Run any fstk command on that home (e.g. with HOME set to its \
+             parent) to upgrade it first.",
+            other_dir.display(),
+            e
+        )
+    })?;
+
+    let mut conn = establish_connection()?;
+    let data_dir = get_data_dir()?;
+
+    let (merged_count, present_count, failed_count) =
+        merge_items(&other, &other_dir, &mut conn, &data_dir)?;
+
+    println!(
+        "Merged {} item(s) from {}; {} already present, {} failed",
+        merged_count,
+        other_dir.display(),
+        present_count,
+        failed_count
+    );
+
+    Ok(())
+}
+
+/// Copy the items of the stack in `other_dir` that the current stack lacks, recognized by their
+/// UUID. Returns how many were merged, already present, and failed.
+fn merge_items(
+    other: &Connection,
+    other_dir: &Path,
+    conn: &mut Connection,
+    data_dir: &Path,
+) -> Result<(usize, usize, usize)> {
+    // Import oldest first so the merged stack keeps a sensible order
+    let mut items = ItemManager::list(other, &[])?;
+    items.sort_by_key(|item| item.stack_position());
+
+    let mut merged_count = 0;
+    let mut present_count = 0;
+    let mut failed_count = 0;
+
+    for item in items {
+        // Merging the same home twice must not duplicate its items
+        if ItemManager::get_by_uuid(conn, &item.uuid)?.is_some() {
+            present_count += 1;
+            continue;
+        }
+        // Items both stacks got before they had UUIDs are recognized by their content
+        if let Some(existing) = ItemManager::get_by_stored_hash(conn, &item.stored_hash)? {
+            if fs::same_name(&existing.original_name, &item.original_name)
+                && existing.content_hash.is_some()
+                && existing.content_hash == item.content_hash
+            {
+                present_count += 1;
+                continue;
+            }
+        }
+
+        match merge_item(other, other_dir, conn, data_dir, &item) {
+            Ok(()) => merged_count += 1,
+            Err(e) => {
+                status!("Failed to merge '{}': {}", item.original_name, e);
+                failed_count += 1;
+            }
+        }
+    }
+
+    Ok((merged_count, present_count, failed_count))
+}
+
+/// Copy one item's blob into the data directory and record it in the current stack.
+fn merge_item(
+    other: &Connection,
+    other_dir: &Path,
+    conn: &mut Connection,
+    data_dir: &Path,
+    item: &StackItem,
+) -> Result<()> {
+    let source_path = match &item.storage_location {
+        Some(location) => PathBuf::from(location).join(&item.stored_hash),
+        None => other_dir.join(".data").join(&item.stored_hash),
+    };
+    if !source_path.exists() {
+        return Err(anyhow!("content missing at {}", source_path.display()));
+    }
+
+    // Stored hashes only have to be unique within one stack; pick a new one on conflict
+    let is_dir = item.is_stored_as_directory();
+    let mut hash = item.stored_hash.clone();
+    while ItemManager::get_by_stored_hash(conn, &hash)?.is_some() || data_dir.join(&hash).exists() {
+        hash = fs::generate_hash(&source_path, is_dir)?;
+    }
+
+    let target_path = data_dir.join(&hash);
+    let staged_path = fs::staging_path(&target_path);
+    if let Err(e) = fs::copy_item(&source_path, &staged_path) {
+        let _ = fs::remove_item(&staged_path);
+        return Err(e);
+    }
+
+    let metadata = ItemMetadata {
+        content_hash: item.content_hash.clone(),
+        size: item.size,
+        manifest: ManifestManager::get_for_item(other, item.id)?,
+        pushed_at: Some(item.pushed_at),
+        pinned: item.pinned,
+        locked: item.locked,
+        owner: item.owner_uid.zip(item.owner_gid),
+        version_group: item.version_group.clone(),
+        members: BundleManager::get_for_item(other, item.id)?,
+        uuid: Some(item.uuid.clone()),
+        note: item.note.clone(),
+        push_dir: item.push_dir.clone(),
+        copied: item.copied,
+        remote_origin: item.remote_origin.clone(),
+        file_count: item.file_count,
+        push_millis: item.push_millis,
+        packed: item.packed,
+        provenance: item.provenance.clone(),
+    };
+    let item_id = match ItemManager::insert_with_metadata(
+        conn,
+        &item.original_name,
+        &item.original_path,
+        &hash,
+        &item.item_type,
+        &item.tags,
+        &metadata,
+    ) {
+        Ok(id) => id,
+        Err(e) => {
+            let _ = fs::remove_item(&staged_path);
+            return Err(e);
+        }
+    };
+
+    if item.partial {
+        ItemManager::mark_partial(
+            conn,
+            item_id,
+            fs::path_size(&staged_path)?,
+            item.content_hash.as_deref(),
+        )?;
+    }
+
+    if let Err(e) = std::fs::rename(&staged_path, &target_path) {
+        let _ = ItemManager::delete(conn, item_id);
+        let _ = fs::remove_item(&staged_path);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn stack(dir: &Path) -> Result<Connection> {
+        std::fs::create_dir_all(dir.join(".data"))?;
+        let conn = Connection::open(dir.join(DB_FILE_NAME))?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        schema::initialize_schema(&conn)?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn test_merge_items_matches_by_uuid() -> Result<()> {
+        let root = tempdir()?;
+        let other_dir = root.path().join("other");
+        let own_dir = root.path().join("own");
+        let mut other = stack(&other_dir)?;
+        let mut conn = stack(&own_dir)?;
+        let pushed_at = chrono::Local::now() - chrono::Duration::days(1);
+
+        // Both stacks hold a notes.txt from the same path, pushed at the same time, but they
+        // are different items; the other stack also has a copy of this stack's item
+        let notes = |uuid: &str| ItemMetadata {
+            pushed_at: Some(pushed_at),
+            uuid: Some(uuid.to_string()),
+            ..Default::default()
+        };
+        ItemManager::insert_with_metadata(
+            &mut conn,
+            "notes.txt",
+            "/p",
+            "hash_ours",
+            "file",
+            &[],
+            &notes("uuid-ours"),
+        )?;
+        for (hash, uuid) in [
+            ("hash_ours_copy", "uuid-ours"),
+            ("hash_theirs", "uuid-theirs"),
+        ] {
+            std::fs::write(other_dir.join(".data").join(hash), uuid)?;
+            ItemManager::insert_with_metadata(
+                &mut other,
+                "notes.txt",
+                "/p",
+                hash,
+                "file",
+                &[],
+                &notes(uuid),
+            )?;
+        }
+
+        let data_dir = own_dir.join(".data");
+        assert_eq!(
+            merge_items(&other, &other_dir, &mut conn, &data_dir)?,
+            (1, 1, 0)
+        );
+        let theirs = ItemManager::get_by_uuid(&conn, "uuid-theirs")?.unwrap();
+        assert_eq!(theirs.original_name, "notes.txt");
+        assert_eq!(ItemManager::list(&conn, &[])?.len(), 2);
+
+        // Merging again finds both items
+        assert_eq!(
+            merge_items(&other, &other_dir, &mut conn, &data_dir)?,
+            (0, 2, 0)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_item_keeps_pin_and_lock() -> Result<()> {
+        let root = tempdir()?;
+        let other_dir = root.path().join("other");
+        let own_dir = root.path().join("own");
+        let mut other = stack(&other_dir)?;
+        let mut conn = stack(&own_dir)?;
+
+        std::fs::write(other_dir.join(".data/hash_keep"), "keep me")?;
+        let id = ItemManager::insert(&mut other, "keepsake.txt", "/p", "hash_keep", "file", &[])?;
+        ItemManager::set_pinned(&other, id, true)?;
+        ItemManager::set_locked(&other, id, true)?;
+        let item = ItemManager::get_by_id(&other, id)?.unwrap();
+
+        merge_item(&other, &other_dir, &mut conn, &own_dir.join(".data"), &item)?;
+
+        let merged = ItemManager::get_by_uuid(&conn, &item.uuid)?.unwrap();
+        assert!(merged.pinned);
+        assert!(merged.locked);
+        assert_eq!(
+            std::fs::read_to_string(own_dir.join(".data").join(&merged.stored_hash))?,
+            "keep me"
+        );
+
+        Ok(())
+    }
+}
@@ -1,13 +1,46 @@
+pub mod adopt;
+pub mod alias;
+pub mod archive;
+pub mod backup;
+pub mod check_space;
 pub mod completion;
+pub mod daemon;
+pub mod du;
+pub mod edit;
+pub mod env;
+pub mod export_meta;
+pub mod grep;
+pub mod heal;
+pub mod history;
+pub mod item_archive;
 pub mod list;
+pub mod lock;
+pub mod merge;
 pub mod peek;
+pub mod pin;
 pub mod pop;
+pub mod prune;
 pub mod push;
+pub mod query;
+pub mod recovery;
+pub mod remote;
 pub mod remove;
+pub mod reorder;
 pub mod restore;
+pub mod select;
+pub mod stash;
+pub mod stats;
+pub mod sync;
 pub mod tag;
+pub mod timer;
+pub mod top;
+pub mod tree;
+pub mod verify;
+pub mod watch;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::db::TagQuery;
 
 #[derive(Parser)]
 #[command(name = "fstk")]
@@ -16,6 +49,27 @@ use clap::{Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Use the global stack even inside a project with its own stack
+    #[arg(long, global = true, conflicts_with = "local")]
+    pub global: bool,
+
+    /// Use the project-local stack (created at the git root if it does not exist yet)
+    #[arg(long, global = true)]
+    pub local: bool,
+
+    /// Address items by database ID instead of display number; IDs never change (see peek)
+    #[arg(long = "id", global = true)]
+    pub by_id: bool,
+
+    /// Run list, peek or pop against the stack of a remote `fstk daemon --http` (http://host:7878)
+    #[arg(long, global = true, value_name = "URL", conflicts_with_all = ["global", "local"])]
+    pub remote: Option<String>,
+
+    /// What to do with a symlink that cannot be recreated when copying, e.g. on Windows without
+    /// the privilege to create symlinks
+    #[arg(long, global = true, value_enum, default_value_t = SymlinkFallback::Copy)]
+    pub symlink_fallback: SymlinkFallback,
 }
 
 #[derive(Subcommand)]
@@ -25,18 +79,102 @@ pub enum Commands {
     Completion {
         /// Shell to generate completion for
         #[arg(value_enum)]
-        shell: clap_complete::Shell,
+        shell: CompletionShell,
     },
 
+    /// Print the original names of the items, one per line, for the shell completion scripts
+    #[command(name = "__complete-names", hide = true)]
+    CompleteNames,
+
     /// Push a file or directory to the stack
     #[command(alias = "p")]
     Push {
-        /// Path to the file or directory to push
-        path: String,
+        /// Path to the file or directory to push (several with --bundle), or [user@]host:path to
+        /// fetch a copy over SSH with scp
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Tags to associate with the pushed item (comma-separated); {date}, {time}, {host},
+        /// {cwd} and {git_branch} are expanded, e.g. backup-{date}
+        #[arg(long, short = 't', value_delimiter = ',')]
+        tags: Option<Vec<String>>,
+
+        /// Do not push if identical content is already on the stack
+        #[arg(long, conflicts_with = "link_duplicates")]
+        skip_duplicates: bool,
+
+        /// Share the existing blob if identical content is already on the stack; for a
+        /// directory, share the blocks of every file that is already stored, e.g. across several
+        /// builds of the same project. Files are shared as clones on filesystems that support
+        /// them (Btrfs, XFS, APFS), so editing one copy never changes another
+        #[arg(long)]
+        link_duplicates: bool,
+
+        /// Tag the item with its git repository and branch (e.g. myrepo, myrepo/feature-x)
+        #[arg(long)]
+        git_tags: bool,
+
+        /// Store all given paths as a single item that pops back out as all of its members
+        #[arg(long, requires = "name", conflicts_with_all = ["skip_duplicates", "link_duplicates"])]
+        bundle: bool,
+
+        /// Name of the bundle item
+        #[arg(long, requires = "bundle")]
+        name: Option<String>,
 
-        /// Tags to associate with the pushed item (comma-separated)
+        /// Do not ask for confirmation before pushing a lot of data (see confirm_push_size)
+        #[arg(long, short = 'y')]
+        yes: bool,
+
+        /// Note to keep with the item (shown by peek), with the same placeholders as tags
+        #[arg(long)]
+        note: Option<String>,
+
+        /// Push a copy and leave the original in place (a snapshot rather than a stash)
+        #[arg(long, conflicts_with_all = ["bundle", "link_duplicates"])]
+        keep: bool,
+
+        /// Do not print the file count, size and time taken after pushing a directory
+        #[arg(long, short = 'q')]
+        quiet: bool,
+
+        /// Store a directory as a single tar archive rather than a tree of files, which is
+        /// much faster to move around for huge trees; pop and restore unpack it
+        #[arg(long, conflicts_with_all = ["bundle", "link_duplicates"])]
+        as_archive: bool,
+
+        /// Suggest tags from earlier pushes of similar items (same extension or directory,
+        /// frequent and recent tags), each added with a single keypress
+        #[arg(long, short = 'i')]
+        interactive: bool,
+    },
+
+    /// Push every file and directory in a folder as separate items, with tags and notes
+    /// from a metadata.json or <name>.meta sidecar
+    Adopt {
+        /// Directory whose entries to push
+        dir: String,
+
+        /// Tags to add to every adopted item (comma-separated)
         #[arg(long, short = 't', value_delimiter = ',')]
         tags: Option<Vec<String>>,
+
+        /// Only list what would be adopted
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+    },
+
+    /// Push every modified and untracked file of the current git repository as its own item,
+    /// tagged with the repository and branch, and reset the working tree (bring the files back
+    /// with 'restore --overwrite')
+    Stash {
+        /// Tags to add to every stashed item (comma-separated)
+        #[arg(long, short = 't', value_delimiter = ',')]
+        tags: Option<Vec<String>>,
+
+        /// Only list what would be stashed
+        #[arg(long, short = 'n')]
+        dry_run: bool,
     },
 
     /// Pop an item from the stack and restore it to the current directory
@@ -44,102 +182,774 @@ pub enum Commands {
     Pop {
         /// Pop specific item(s) by number (as shown in the list command)
         /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        /// Anything else is matched fuzzily against item names
         #[arg(index = 1)]
         numbers: Option<String>,
 
-        /// Pop the most recent item with the specified tags (comma-separated)
+        /// Pop the most recent item with the specified tags (comma-separated; also tag queries
+        /// like "inbox AND NOT draft")
         #[arg(long, short = 't', value_delimiter = ',')]
         tags: Option<Vec<String>>,
 
-        /// Custom output directory path (defaults to current directory)
+        /// Match items with any of the --tags instead of all of them
+        #[arg(long, requires = "tags")]
+        any_tags: bool,
+
+        /// Custom output directory path (defaults to pop_destination and pop_output_dir from the config, or the current directory)
         #[arg(long = "output", short = 'o')]
         output: Option<String>,
+
+        /// Pop into the output directory used by the last pop --output on this stack
+        #[arg(long, visible_alias = "again", conflicts_with = "output")]
+        last_out: bool,
+
+        /// Pop into the current directory, whatever pop_destination is set to in the config
+        #[arg(long, conflicts_with_all = ["output", "last_out", "to_original", "to_pushdir"])]
+        here: bool,
+
+        /// Pop each item back into the directory it originally lived in
+        #[arg(long, conflicts_with_all = ["output", "last_out", "to_pushdir"])]
+        to_original: bool,
+
+        /// Pop each item into the directory it was pushed from
+        #[arg(long, conflicts_with_all = ["output", "last_out"])]
+        to_pushdir: bool,
+
+        /// If any item of a batch fails, move the ones already popped back onto the stack and
+        /// put back the files they overwrote
+        #[arg(long, conflicts_with = "merge")]
+        rollback_on_error: bool,
+
+        /// Pop some items of the batch into their own directories: N=DIR, where N may also be
+        /// a list or range (pop 1-3 --out-map 1=/tmp 2,3=~/docs)
+        #[arg(long, value_name = "N=DIR", num_args = 1.., requires = "numbers", conflicts_with_all = ["subpath", "version"])]
+        out_map: Vec<String>,
+
+        /// Ask for the directory of each item of the batch (Enter keeps the default)
+        #[arg(long, requires = "numbers", conflicts_with_all = ["subpath", "version"])]
+        ask_output: bool,
+
+        /// Pop under a different name (supports {name}, {stem}, {ext}, {date}, {time}, {pushed})
+        #[arg(long = "as", value_name = "NAME")]
+        rename: Option<String>,
+
+        /// Print the destination path of each popped item (other messages go to stderr)
+        #[arg(long)]
+        print_path: bool,
+
+        /// Extract only this entry (relative path) of a directory item, keeping the rest
+        #[arg(long = "path", value_name = "SUBPATH")]
+        subpath: Option<String>,
+
+        /// Pop this version (1 = oldest, see 'list --versions') of the item's original path
+        #[arg(long, value_name = "N", conflicts_with = "subpath")]
+        version: Option<usize>,
+
+        /// Pop locked items as well
+        #[arg(long, short = 'f')]
+        force: bool,
+
+        /// Merge a directory item into an existing destination directory instead of failing
+        #[arg(long, conflicts_with = "subpath")]
+        merge: bool,
+
+        /// What --merge does with files that differ from an existing one
+        #[arg(long, value_enum, default_value_t = MergePolicy::Skip, requires = "merge")]
+        on_conflict: MergePolicy,
     },
 
     /// List all items in the stack
     #[command(alias = "ls")]
     List {
-        /// Filter by tags (comma-separated)
+        /// Filter by tags (comma-separated, all must match; also "work AND NOT done", "a OR b")
         #[arg(long, short = 't', value_delimiter = ',')]
         tags: Option<Vec<String>>,
+
+        /// Match items with any of the --tags instead of all of them
+        #[arg(long, requires = "tags")]
+        any_tags: bool,
+
+        /// Only show items without any tags (numbered as in the full list)
+        #[arg(long, conflicts_with_all = ["tags", "versions"])]
+        untagged: bool,
+
+        /// Show the items in separate sections instead of a single table
+        #[arg(long, value_enum)]
+        group_by: Option<GroupBy>,
+
+        /// Show every pushed version of this original path
+        #[arg(long, value_name = "PATH", conflicts_with = "group_by")]
+        versions: Option<String>,
+
+        /// Output format (json also reports errors as JSON on stderr)
+        #[arg(long, value_enum, default_value_t = ListFormat::Table, conflicts_with_all = ["group_by", "versions"])]
+        format: ListFormat,
+
+        /// Also compare every item's checksum in the HEALTH column (reads all stored data)
+        #[arg(long, conflicts_with_all = ["versions", "format"])]
+        verify: bool,
+
+        /// Show names and tags in full instead of truncating them to the terminal width
+        #[arg(long, conflicts_with = "format")]
+        no_truncate: bool,
+
+        /// Only print the full original path of each item, one per line (for xargs or fzf)
+        #[arg(long, conflicts_with_all = ["group_by", "versions", "format", "verify", "no_truncate"])]
+        paths: bool,
     },
 
     /// Tag management commands
     #[command(subcommand)]
     Tag(TagCommands),
 
+    /// Edit the name, tags, note and flags of an item in $VISUAL or $EDITOR
+    Edit {
+        /// Number of the item to edit (as shown in the list command)
+        #[arg(index = 1)]
+        number: usize,
+    },
+
     /// Remove an item from the stack without restoring it
     #[command(alias = "rm")]
     Remove {
         /// Number(s) of the item(s) to remove (as shown in the list command)
         /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        /// May be omitted when selecting items with --tags or --older-than
         #[arg(index = 1)]
-        numbers: String,
+        numbers: Option<String>,
 
-        /// Remove the items matching these numbers with the specified tags (comma-separated)
+        /// Remove the items matching these numbers with the specified tags (comma-separated;
+        /// also tag queries like "tmp AND NOT keep")
         #[arg(long, short = 't', value_delimiter = ',')]
         tags: Option<Vec<String>>,
+
+        /// Match items with any of the --tags instead of all of them
+        #[arg(long, requires = "tags")]
+        any_tags: bool,
+
+        /// Only remove items pushed longer ago than this (e.g. 12h, 30d, 2w)
+        #[arg(long, value_name = "DURATION")]
+        older_than: Option<String>,
+
+        /// Remove pinned and locked items as well
+        #[arg(long, short = 'f')]
+        force: bool,
+    },
+
+    /// Print the stack state as shell exports (FSTK_TOP, FSTK_COUNT, ...) for `eval "$(fstk env)"`
+    Env,
+
+    /// Remove items that expired under the [[retention]] policies of the configuration
+    Prune {
+        /// Apply the retention policies (e.g. tmp items expire after 7d, keep at most 200 items)
+        #[arg(long)]
+        policy: bool,
+
+        /// Remove the items pushed before this date (e.g. 2023-12-31), after listing them
+        #[arg(long, value_name = "DATE", conflicts_with = "policy")]
+        before: Option<String>,
+
+        /// Only prune items with all of these tags (comma-separated, or a tag query like "tmp AND NOT keep"; with --before)
+        #[arg(long, short = 't', value_delimiter = ',', requires = "before")]
+        tags: Option<Vec<String>>,
+
+        /// Only list what would be pruned, and why (with --install-timer, print the timer files)
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+
+        /// Remove the listed items without asking first
+        #[arg(long, short = 'y', conflicts_with = "dry_run")]
+        yes: bool,
+
+        /// Apply the retention policies of this stack periodically with a systemd user timer
+        /// (Linux) or a launchd agent (macOS)
+        #[arg(long, conflicts_with_all = ["policy", "before", "uninstall_timer"])]
+        install_timer: bool,
+
+        /// How often the installed timer prunes (e.g. 12h, 1d, 1w)
+        #[arg(
+            long,
+            value_name = "DURATION",
+            default_value = "1d",
+            requires = "install_timer"
+        )]
+        every: String,
+
+        /// Disable and remove the prune timer of this stack
+        #[arg(long, conflicts_with_all = ["policy", "before", "dry_run"])]
+        uninstall_timer: bool,
     },
 
     /// Restore an item from the stack to its original location and remove it
     #[command(alias = "res")]
     Restore {
-        /// Number of the item to restore (as shown in the list command)
+        /// Number of the item to restore (as shown in the list command), or part of its name
         #[arg(index = 1)]
-        number: Option<usize>,
+        number: Option<String>,
 
         /// Restore the most recent item with the specified tags (comma-separated)
         #[arg(long, short = 't', value_delimiter = ',')]
         tags: Option<Vec<String>>,
+
+        /// Restore into this directory instead of the original path (created if missing)
+        #[arg(long = "to", value_name = "DIR")]
+        to: Option<String>,
+
+        /// Copy the item back but keep it on the stack (checkout semantics)
+        #[arg(long, short = 'k')]
+        keep: bool,
+
+        /// Print the destination path of the restored item (other messages go to stderr)
+        #[arg(long)]
+        print_path: bool,
+
+        /// Treat a destination that already holds identical content as restored: drop the
+        /// item from the stack and succeed instead of reporting a conflict
+        #[arg(long)]
+        skip_existing: bool,
+
+        /// Only restore the entries of a directory item matching this glob (e.g. 'src/**');
+        /// the rest stays on the stack as a partial item
+        #[arg(long, value_name = "GLOB", conflicts_with = "skip_existing")]
+        only: Option<String>,
+
+        /// Restore every item that was pushed from this directory or below it (with --tags,
+        /// only those having the tags); items whose destination exists stay on the stack
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["number", "to", "only"])]
+        from_dir: Option<String>,
+
+        /// Replace a destination that already exists, e.g. the checked-out version of a file
+        /// put away with 'stash'; it is only deleted once the item is restored
+        #[arg(long, conflicts_with = "only")]
+        overwrite: bool,
+
+        /// Restore locked items as well (with --keep they are restored either way)
+        #[arg(long, short = 'f')]
+        force: bool,
+    },
+
+    /// Move an item to another position in the stack (e.g. `mv 3 1` puts item 3 on top)
+    #[command(alias = "mv")]
+    Move {
+        /// Number of the item to move (as shown in the list command)
+        #[arg(index = 1)]
+        from: usize,
+
+        /// Number of the position to move it to
+        #[arg(index = 2)]
+        to: usize,
+    },
+
+    /// Exchange the positions of two items in the stack
+    Swap {
+        /// Number of the first item (as shown in the list command)
+        #[arg(index = 1)]
+        first: usize,
+
+        /// Number of the second item
+        #[arg(index = 2)]
+        second: usize,
+    },
+
+    /// Pin items so that plain pop skips them and remove requires --force
+    Pin {
+        /// Number(s) of the item(s) to pin (as shown in the list command)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        #[arg(index = 1)]
+        numbers: String,
+    },
+
+    /// Unpin previously pinned items
+    Unpin {
+        /// Number(s) of the item(s) to unpin (as shown in the list command)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        #[arg(index = 1)]
+        numbers: String,
+    },
+
+    /// Lock items so that remove and pop refuse them without --force
+    Lock {
+        /// Number(s) of the item(s) to lock (as shown in the list command)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        #[arg(index = 1)]
+        numbers: String,
+    },
+
+    /// Unlock previously locked items
+    Unlock {
+        /// Number(s) of the item(s) to unlock (as shown in the list command)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        #[arg(index = 1)]
+        numbers: String,
+    },
+
+    /// Move blobs of old items to secondary storage (pulled back automatically on pop)
+    Archive {
+        /// Archive items pushed longer ago than this (e.g. 90d, 12w)
+        #[arg(long, value_name = "DURATION")]
+        older_than: String,
+
+        /// Archive location (defaults to archive_dir from the config file)
+        #[arg(long, value_name = "DIR")]
+        to: Option<String>,
+    },
+
+    /// Search the contents of stored items
+    Grep {
+        /// Regular expression to search for
+        pattern: String,
+
+        /// Number(s) of the item(s) to search (all items if omitted)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        numbers: Option<String>,
+
+        /// Only search items with the specified tags (comma-separated; also tag queries like
+        /// "work OR notes")
+        #[arg(long, short = 't', value_delimiter = ',')]
+        tags: Option<Vec<String>>,
+
+        /// Match items with any of the --tags instead of all of them
+        #[arg(long, requires = "tags")]
+        any_tags: bool,
+
+        /// Match case-insensitively
+        #[arg(long, short = 'i')]
+        ignore_case: bool,
+    },
+
+    /// List the largest items on the stack by stored size
+    Top {
+        /// Number of items to show
+        #[arg(long, short = 'n', default_value_t = 10)]
+        count: usize,
+    },
+
+    /// Show how much storage the stack uses, largest first
+    Du {
+        /// Group items instead of listing them one by one
+        #[arg(long, value_enum)]
+        by: Option<UsageGroup>,
+    },
+
+    /// Show a summary of the stack and how it is used over time
+    Stats {
+        /// Also show pushes and pops per period, time spent on the stack and the busiest tags
+        #[arg(long)]
+        activity: bool,
+
+        /// Period used to group the activity
+        #[arg(long, value_enum, default_value_t = ActivityPeriod::Day, requires = "activity")]
+        per: ActivityPeriod,
+    },
+
+    /// Show the push, pop, restore and remove operations recorded on the stack
+    History {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = HistoryFormat::Table)]
+        format: HistoryFormat,
+
+        /// Only show operations since a date (2024-01-01) or for a duration back from now (30d)
+        #[arg(long, value_name = "DATE|DURATION")]
+        since: Option<String>,
+    },
+
+    /// Run a read-only SQL statement against the metadata database (e.g. for custom reports)
+    Query {
+        /// SQL statement to run; statements that modify the database are rejected
+        #[arg(index = 1)]
+        sql: String,
+
+        /// Output format (json also reports errors as JSON on stderr)
+        #[arg(long, value_enum, default_value_t = QueryFormat::Table)]
+        format: QueryFormat,
+    },
+
+    /// Back up the whole fstk home (database, stored items, config) into a tar archive
+    #[command(args_conflicts_with_subcommands = true)]
+    Backup {
+        /// Path of the archive to create
+        #[arg(index = 1, required = true)]
+        dest: Option<String>,
+
+        #[command(subcommand)]
+        command: Option<BackupCommands>,
+    },
+
+    /// Import the items of another fstk home (e.g. a copied ~/.fstk) into this stack
+    Merge {
+        /// Path of the other fstk home (the directory containing fstk.db)
+        #[arg(index = 1)]
+        source: String,
+    },
+
+    /// Reconcile this stack with the one served by `fstk daemon --http` on another machine
+    Sync {
+        /// URL of the other stack (e.g. http://desktop:7878)
+        url: String,
+
+        /// Only list what would be copied, removed or reported
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+    },
+
+    /// Export the metadata of all items (not their content) for spreadsheets or auditing
+    ExportMeta {
+        /// Output format (json also reports errors as JSON on stderr)
+        #[arg(long, value_enum, default_value_t = MetaFormat::Json)]
+        format: MetaFormat,
+
+        /// Write to this file instead of stdout
+        #[arg(long, short = 'o', value_name = "FILE")]
+        out: Option<String>,
+    },
+
+    /// Write one item to a self-contained tar archive for another fstk to import-item
+    ExportItem {
+        /// Number of the item (as shown in the list command)
+        #[arg(index = 1)]
+        number: usize,
+
+        /// Archive to write
+        #[arg(long, short = 'o', value_name = "FILE")]
+        out: String,
+    },
+
+    /// Add the item in an archive written by export-item to the stack
+    ImportItem {
+        /// Archive to read
+        archive: String,
+    },
+
+    /// Verify stored items against the checksums recorded at push time
+    Verify {
+        /// Number(s) of the item(s) to verify (all items if omitted)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        #[arg(index = 1)]
+        numbers: Option<String>,
+    },
+
+    /// Check that items fit where they would be popped or restored, without moving anything
+    CheckSpace {
+        /// Number(s) of the item(s) to check (the top item if omitted)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        #[arg(index = 1)]
+        numbers: Option<String>,
+
+        /// Destination directory (the current directory if omitted)
+        #[arg(long, value_name = "DIR", conflicts_with = "original")]
+        to: Option<String>,
+
+        /// Check the directories the items originally lived in, as restore uses
+        #[arg(long)]
+        original: bool,
+
+        /// Check for copies that leave the items on the stack (restore --keep)
+        #[arg(long, short = 'k')]
+        keep: bool,
+    },
+
+    /// Adopt a file or directory as the stored data of an item whose storage went missing
+    Heal {
+        /// Number of the item to heal (as shown in the list command)
+        #[arg(index = 1)]
+        number: usize,
+
+        /// File or directory holding the item's content (checked against its checksum)
+        #[arg(long, value_name = "PATH")]
+        from: String,
+
+        /// Copy the file into storage instead of moving it
+        #[arg(long, short = 'k')]
+        keep: bool,
+    },
+
+    /// Keep the database open and serve `list` and the start-up recovery check of every command
+    /// over a Unix socket until interrupted
+    Daemon {
+        /// Also serve list, peek and pop to `--remote` clients on this address (e.g. 0.0.0.0:7878);
+        /// clients must send `http_token` from the configuration, which is generated if not set
+        #[arg(long, value_name = "ADDR")]
+        http: Option<String>,
+    },
+
+    /// Watch a directory and automatically push new files dropped into it
+    Watch {
+        /// Directory to watch
+        dir: String,
+
+        /// Tags to associate with every pushed item (comma-separated)
+        #[arg(long, short = 't', value_delimiter = ',')]
+        tags: Option<Vec<String>>,
+
+        /// Only push files whose name matches one of these glob patterns (comma-separated)
+        #[arg(long, short = 'p', value_delimiter = ',')]
+        patterns: Option<Vec<String>>,
     },
 
     /// Preview an item's metadata without restoring it
     #[command(alias = "pk")]
     Peek {
-        /// Number of the item to peek (as shown in the list command)
+        /// Number of the item to peek (as shown in the list command), or part of its name
         #[arg(index = 1)]
-        number: Option<usize>,
+        number: Option<String>,
 
         /// Peek the most recent item with the specified tags (comma-separated)
         #[arg(long, short = 't', value_delimiter = ',')]
         tags: Option<Vec<String>>,
+
+        /// Print only the raw value of a single field (no table, no color)
+        #[arg(long, value_enum)]
+        field: Option<PeekField>,
+
+        /// Also show the first lines of a text file item, syntax-highlighted on a terminal
+        #[arg(long, conflicts_with = "field")]
+        preview: bool,
+
+        /// Show the host, user, terminal, directory and fstk version the item was pushed with
+        #[arg(long, conflicts_with_all = ["field", "preview"])]
+        provenance: bool,
+    },
+
+    /// Show the files inside a stored directory or bundle without popping it
+    Tree {
+        /// Number of the item (as shown in the list command)
+        #[arg(index = 1)]
+        number: usize,
+
+        /// Descend at most this many levels (directories below still show their totals)
+        #[arg(long, short = 'L')]
+        depth: Option<usize>,
+    },
+}
+
+/// Shells that `completion` can generate scripts for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    #[value(name = "powershell")]
+    PowerShell,
+    Elvish,
+    Nushell,
+}
+
+/// Item fields that can be printed on their own by `peek --field`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum PeekField {
+    /// Database ID
+    Id,
+    /// Globally unique ID, the same in every stack the item is merged into
+    Uuid,
+    /// Original file or directory name
+    Name,
+    /// Original parent directory
+    Path,
+    /// Storage hash
+    Hash,
+    /// Item type ("file", "directory" or "bundle")
+    Type,
+    /// Push time (YYYY-MM-DD HH:MM:SS)
+    PushedAt,
+    /// Comma-separated tags
+    Tags,
+    /// Absolute path of the stored blob
+    Stored,
+    /// Note kept with the item (empty if there is none)
+    Note,
+}
+
+/// File formats supported by `export-meta`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MetaFormat {
+    /// One row per item with a header row; tags are comma-separated
+    Csv,
+    /// A JSON array of item objects
+    Json,
+}
+
+/// Output formats of `list`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// A drawn table for reading in the terminal
+    Table,
+    /// A JSON array of item objects, as written by `export-meta`
+    Json,
+    /// A NUON table for Nushell (`fstk list --format nuon | from nuon`)
+    Nuon,
+    /// One JSON object per line, streamed as items are read
+    Jsonl,
+}
+
+/// What copies do with a symlink they cannot recreate (see `fs::copy_symlink`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SymlinkFallback {
+    /// Copy what the link points to
+    #[default]
+    Copy,
+    /// Leave the link out
+    Skip,
+}
+
+/// How `pop --merge` handles a file that exists in the destination with different content
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MergePolicy {
+    /// Keep the existing file; the stored one stays on the stack
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Keep both, giving the popped file a free name (notes-1.txt)
+    Rename,
+}
+
+/// Output formats of `history`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum HistoryFormat {
+    /// A drawn table for reading in the terminal
+    Table,
+    /// One row per operation with a header row, for timesheets and audits
+    Csv,
+}
+
+/// Output formats of `query`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum QueryFormat {
+    /// A drawn table with one column per result column
+    Table,
+    /// A JSON array with one object per row, keyed by column name
+    Json,
+}
+
+/// Ways to group items in `du --by`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum UsageGroup {
+    /// One group per tag; items with several tags count towards each of them
+    Tag,
+    /// Files and directories
+    Type,
+    /// How long ago items were pushed
+    Age,
+}
+
+/// Periods used to group `stats --activity`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ActivityPeriod {
+    /// The last two weeks, day by day
+    Day,
+    /// The last eight weeks, starting on Mondays
+    Week,
+}
+
+/// Ways to split the output of `list --group-by` into sections
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum GroupBy {
+    /// One section per tag; items with several tags appear under each of them
+    Tag,
+    /// One section per original directory
+    Dir,
+}
+
+#[derive(Subcommand)]
+pub enum BackupCommands {
+    /// Replace the fstk home with the contents of a backup archive
+    Restore {
+        /// Path of the archive created by 'fstk backup'
+        #[arg(index = 1)]
+        archive: String,
     },
 }
 
 #[derive(Subcommand)]
 pub enum TagCommands {
-    /// Add tags to an item
+    /// Add tags to items
     #[command(alias = "a")]
     Add {
-        /// Number of the item to tag (as shown in the list command)
+        /// Number(s) of the item(s) to tag (as shown in the list command)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
         #[arg(index = 1)]
-        number: usize,
+        numbers: String,
 
         /// Tags to add (comma-separated)
         #[arg(long, short = 't', value_delimiter = ',')]
         tags: Vec<String>,
     },
 
-    /// Remove tags from an item
+    /// Remove tags from items
     #[command(alias = "rm")]
     Remove {
-        /// Number of the item to remove tags from (as shown in the list command)
+        /// Number(s) of the item(s) to remove tags from (as shown in the list command)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
         #[arg(index = 1)]
-        number: usize,
+        numbers: String,
 
         /// Tags to remove (comma-separated)
         #[arg(long, short = 't', value_delimiter = ',')]
         tags: Vec<String>,
     },
 
+    /// Replace all tags of items with the given ones
+    Set {
+        /// Number(s) of the item(s) to tag (as shown in the list command)
+        /// Supports individual numbers (1), comma-separated lists (1,3,5), and ranges (1-5)
+        #[arg(index = 1)]
+        numbers: String,
+
+        /// The items' new tags (comma-separated); tags not listed are removed
+        #[arg(long, short = 't', value_delimiter = ',', required = true)]
+        tags: Vec<String>,
+    },
+
     /// List all tags
     #[command(visible_alias = "l")]
-    List,
+    List {
+        /// Also show the total stored size and the last use of each tag
+        #[arg(long)]
+        sizes: bool,
+
+        /// Output format; json and nuon always include sizes and last use
+        #[arg(long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+    },
 
     /// Alias for 'list' (automatically added by clap)
-    Ls,
+    Ls {
+        /// Also show the total stored size and the last use of each tag
+        #[arg(long)]
+        sizes: bool,
+
+        /// Output format; json and nuon always include sizes and last use
+        #[arg(long, value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+    },
+}
+
+impl Commands {
+    /// Whether the command was asked for JSON output (`--format json`)
+    pub fn wants_json(&self) -> bool {
+        match self {
+            Commands::List { format, .. } => {
+                matches!(format, ListFormat::Json | ListFormat::Jsonl)
+            }
+            Commands::Tag(TagCommands::List { format, .. } | TagCommands::Ls { format, .. }) => {
+                matches!(format, ListFormat::Json | ListFormat::Jsonl)
+            }
+            Commands::ExportMeta { format, .. } => *format == MetaFormat::Json,
+            Commands::Query { format, .. } => *format == QueryFormat::Json,
+            _ => false,
+        }
+    }
+}
+
+/// The `-t` filters of a command, turned into a single query matching any of the tags when
+/// `--any-tags` is given (see `db::TagQuery`)
+pub fn tag_filters(tags: Option<Vec<String>>, any_tags: bool) -> Option<Vec<String>> {
+    match tags {
+        Some(tags) if any_tags => Some(TagQuery::any_of(&tags)),
+        tags => tags,
+    }
 }
 
-pub fn parse_cli() -> Cli {
-    Cli::parse()
+pub fn parse_cli() -> anyhow::Result<Cli> {
+    let args = alias::expand_args(std::env::args_os().collect())?;
+    Ok(Cli::parse_from(args))
 }
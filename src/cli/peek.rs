@@ -1,8 +1,29 @@
 use anyhow::{anyhow, Result};
 use owo_colors::OwoColorize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, IsTerminal, Read};
+use std::path::Path;
 use tabled::{settings::Style, Table, Tabled};
 
-use crate::db::{establish_connection, ItemManager};
+use crate::cli::grep::BINARY_CHECK_LEN;
+use crate::cli::tree::{self, Overview};
+use crate::cli::{select, PeekField};
+use crate::db::{
+    establish_connection, get_item_stored_path, BundleManager, BundleMember, ItemManager, StackItem,
+};
+use crate::utils::display::format_size;
+use crate::utils::error::FstkError;
+use crate::utils::media::{self, MediaInfo};
+use crate::utils::output;
+
+/// Number of lines `peek --preview` shows of a text file
+const PREVIEW_LINES: usize = 20;
+
+/// Color theme of `peek --preview` (one of syntect's built-in themes)
+const PREVIEW_THEME: &str = "base16-ocean.dark";
+
+/// Number of top-level entries shown of a directory item
+const DIRECTORY_ENTRIES: usize = 10;
 
 // A structure for displaying item metadata as key-value pairs
 #[derive(Tabled)]
@@ -15,10 +36,36 @@ struct KeyValue {
 }
 
 /// Peek at an item's metadata without restoring it.
-pub fn peek(number: Option<usize>, tags: Option<Vec<String>>) -> Result<()> {
+/// With `field`, only the raw value of that field is printed; with `preview`, the first lines
+/// of a text file follow the metadata; with `provenance`, only where the item was pushed from
+/// is shown.
+pub fn peek(
+    number: Option<String>,
+    tags: Option<Vec<String>>,
+    field: Option<PeekField>,
+    preview: bool,
+    provenance: bool,
+) -> Result<()> {
+    // Keep stdout clean for the raw field value
+    if field.is_some() {
+        output::reserve_stdout();
+    }
+
     // Connect to database
     let conn = establish_connection()?;
 
+    // Resolve a name query to a display number
+    let number = match number {
+        Some(arg) => {
+            let tag_vec = tags.clone().unwrap_or_default();
+            match select::resolve_number(&conn, &arg, &tag_vec)? {
+                Some(number) => Some(number),
+                None => return Ok(()),
+            }
+        }
+        None => None,
+    };
+
     // Get item based on provided criteria
     let item = match (number, tags.as_ref()) {
         (Some(num), Some(tag_vec)) if !tag_vec.is_empty() => {
@@ -33,16 +80,16 @@ pub fn peek(number: Option<usize>, tags: Option<Vec<String>>) -> Result<()> {
                 })?;
 
             ItemManager::get_by_id(&conn, id)?
-                .ok_or_else(|| anyhow!("No item found with number={}", num))?
+                .ok_or_else(|| FstkError::ItemNotFound(num.to_string()))?
         }
         (Some(num), _) => {
             // Get item by number from full list (no tag filtering)
             let empty_tags = Vec::new();
             let id = ItemManager::get_id_by_display_number(&conn, num, &empty_tags)?
-                .ok_or_else(|| anyhow!("No item found with number={}", num))?;
+                .ok_or_else(|| FstkError::ItemNotFound(num.to_string()))?;
 
             ItemManager::get_by_id(&conn, id)?
-                .ok_or_else(|| anyhow!("No item found with number={}", num))?
+                .ok_or_else(|| FstkError::ItemNotFound(num.to_string()))?
         }
         (None, Some(tags)) => {
             // Get latest item by tags
@@ -55,18 +102,225 @@ pub fn peek(number: Option<usize>, tags: Option<Vec<String>>) -> Result<()> {
         }
     };
 
+    // Print a single raw value for scripts
+    if let Some(field) = field {
+        let value = match field {
+            PeekField::Id => item.id.to_string(),
+            PeekField::Uuid => item.uuid.clone(),
+            PeekField::Note => item.note.clone().unwrap_or_default(),
+            PeekField::Name => item.original_name.clone(),
+            PeekField::Path => item.original_path.clone(),
+            PeekField::Hash => item.stored_hash.clone(),
+            PeekField::Type => item.item_type.clone(),
+            PeekField::PushedAt => item.pushed_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            PeekField::Tags => item.tags.join(","),
+            PeekField::Stored => get_item_stored_path(&item)?.to_string_lossy().to_string(),
+        };
+        println!("{}", value);
+
+        return Ok(());
+    }
+
+    if provenance {
+        print_provenance(&item);
+        return Ok(());
+    }
+
+    let members = if item.is_bundle() {
+        BundleManager::get_for_item(&conn, item.id)?
+    } else {
+        Vec::new()
+    };
+    // Describe what a stored file holds, e.g. the size of a screenshot
+    let media = if item.is_stored_as_directory() || item.packed {
+        None
+    } else {
+        get_item_stored_path(&item)
+            .ok()
+            .and_then(|path| media::probe(&path).ok())
+    };
+    // Show what a stored directory holds; archived items may be out of reach
+    let overview = if item.item_type == "directory" {
+        tree::overview(&conn, &item, DIRECTORY_ENTRIES).ok()
+    } else {
+        None
+    };
+    print_item(&item, &members, media.as_ref(), overview.as_ref());
+
+    if preview {
+        print_preview(&item)?;
+    }
+
+    Ok(())
+}
+
+/// Print the host, user, terminal, directory and fstk version an item was pushed with.
+fn print_provenance(item: &StackItem) {
+    let Some(provenance) = &item.provenance else {
+        println!(
+            "No provenance recorded for '{}' (pushed before fstk kept track of it)",
+            item.original_name
+        );
+        return;
+    };
+
+    let unknown = || "-".to_string();
+    let rows = vec![
+        KeyValue {
+            key: "NAME".to_string(),
+            value: item.original_name.clone(),
+        },
+        KeyValue {
+            key: "HOST".to_string(),
+            value: provenance.hostname.clone().unwrap_or_else(unknown),
+        },
+        KeyValue {
+            key: "USER".to_string(),
+            value: provenance.username.clone().unwrap_or_else(unknown),
+        },
+        KeyValue {
+            key: "TTY".to_string(),
+            value: provenance.tty.clone().unwrap_or_else(unknown),
+        },
+        KeyValue {
+            key: "CWD".to_string(),
+            value: provenance.cwd.clone().unwrap_or_else(unknown),
+        },
+        KeyValue {
+            key: "FSTK_VERSION".to_string(),
+            value: provenance.version.clone(),
+        },
+    ];
+
+    let mut table = Table::new(rows);
+    table.with(Style::modern_rounded());
+    println!("{}", table);
+}
+
+/// Print the first lines of a stored text file with line numbers, highlighted for the language
+/// its name or first line suggests when printing to a terminal.
+/// Binary files, directories and bundles are only described.
+fn print_preview(item: &StackItem) -> Result<()> {
+    if item.is_stored_as_directory() || item.packed {
+        println!(
+            "{}",
+            format!("(no preview for a {})", item.item_type).dimmed()
+        );
+        return Ok(());
+    }
+
+    let stored_path = get_item_stored_path(item)?;
+    let mut file = File::open(&stored_path)
+        .map_err(|e| anyhow!("Cannot read {}: {}", stored_path.display(), e))?;
+
+    let mut head = vec![0; BINARY_CHECK_LEN];
+    let read = file.read(&mut head)?;
+    if head[..read].contains(&0) {
+        let size = file.metadata()?.len();
+        println!(
+            "{}",
+            format!("(binary content, {})", format_size(size)).dimmed()
+        );
+        return Ok(());
+    }
+
+    let mut reader = BufReader::new(head[..read].chain(file)).split(b'\n');
+    let lines = reader
+        .by_ref()
+        .take(PREVIEW_LINES)
+        .map(|line| {
+            let line = String::from_utf8_lossy(&line?).to_string();
+            Ok(line.trim_end_matches('\r').to_string())
+        })
+        .collect::<Result<Vec<String>>>()?;
+
+    let lines = if std::io::stdout().is_terminal() {
+        highlight(&item.original_name, &lines)
+    } else {
+        lines
+    };
+    for (index, line) in lines.iter().enumerate() {
+        println!("{} {}", format!("{:>4} │", index + 1).dimmed(), line);
+    }
+    if reader.next().is_some() {
+        println!("{}", "     ...".dimmed());
+    }
+
+    Ok(())
+}
+
+/// Color `lines` of the file `name` with terminal escapes. The syntax is found by the file's
+/// extension (or its whole name, like `Makefile`), then by its first line (`#!/bin/sh`); lines
+/// stay plain if neither is known.
+fn highlight(name: &str, lines: &[String]) -> Vec<String> {
+    use syntect::easy::HighlightLines;
+    use syntect::highlighting::ThemeSet;
+    use syntect::parsing::SyntaxSet;
+    use syntect::util::as_24_bit_terminal_escaped;
+
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let extension = Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_string());
+    let syntax = extension
+        .and_then(|extension| syntaxes.find_syntax_by_extension(&extension))
+        .or_else(|| syntaxes.find_syntax_by_extension(name))
+        .or_else(|| {
+            lines
+                .first()
+                .and_then(|line| syntaxes.find_syntax_by_first_line(line))
+        });
+    let Some(syntax) = syntax else {
+        return lines.to_vec();
+    };
+
+    let themes = ThemeSet::load_defaults();
+    let mut highlighter = HighlightLines::new(syntax, &themes.themes[PREVIEW_THEME]);
+    let mut highlighted = Vec::new();
+    for line in lines {
+        // The syntaxes expect each line with its newline
+        match highlighter.highlight_line(&format!("{}\n", line), &syntaxes) {
+            Ok(ranges) => highlighted.push(format!(
+                "{}\x1b[0m",
+                as_24_bit_terminal_escaped(&ranges, false).trim_end_matches('\n')
+            )),
+            Err(_) => return lines.to_vec(),
+        }
+    }
+    highlighted
+}
+
+/// Print an item's metadata as a table of fields, listing bundle members where they go back to
+/// and, given `media` or `overview`, the size and format of a stored file or the top entries of
+/// a stored directory.
+pub fn print_item(
+    item: &StackItem,
+    members: &[BundleMember],
+    media: Option<&MediaInfo>,
+    overview: Option<&Overview>,
+) {
     // Apply direct coloring in strings instead of using tabled's built-in coloring
-    let is_directory = item.item_type == "directory";
+    let is_directory = item.is_stored_as_directory() || item.packed;
 
     // Build key-value pairs for display with colors applied
-    let rows = vec![
+    let mut rows = vec![
         KeyValue {
             key: "DATABASE ID".to_string(),
             value: item.id.to_string(),
         },
+        KeyValue {
+            key: "UUID".to_string(),
+            value: item.uuid.clone(),
+        },
         KeyValue {
             key: "TYPE".to_string(),
-            value: if is_directory {
+            value: if item.packed {
+                format!(
+                    "{} {}",
+                    item.item_type.blue(),
+                    "(stored as a tar archive)".dimmed()
+                )
+            } else if is_directory {
                 format!("{}", item.item_type.blue())
             } else {
                 item.item_type.clone()
@@ -88,6 +342,18 @@ pub fn peek(number: Option<usize>, tags: Option<Vec<String>>) -> Result<()> {
             key: "PUSHED_AT".to_string(),
             value: item.pushed_at.format("%Y-%m-%d %H:%M:%S").to_string(),
         },
+        KeyValue {
+            key: "PUSHED_FROM".to_string(),
+            value: item.push_dir.clone().unwrap_or_else(|| "-".to_string()),
+        },
+        KeyValue {
+            key: "PUSH_MODE".to_string(),
+            value: if item.copied {
+                "copied (original kept)".to_string()
+            } else {
+                "moved".to_string()
+            },
+        },
         KeyValue {
             key: "TAGS".to_string(),
             value: if item.tags.is_empty() {
@@ -100,14 +366,126 @@ pub fn peek(number: Option<usize>, tags: Option<Vec<String>>) -> Result<()> {
             key: "STORAGE_HASH".to_string(),
             value: item.stored_hash.clone(),
         },
+        KeyValue {
+            key: "ARCHIVED_TO".to_string(),
+            value: item
+                .storage_location
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+        },
+        KeyValue {
+            key: "CONTENT_HASH".to_string(),
+            value: item.content_hash.clone().unwrap_or_else(|| "-".to_string()),
+        },
+        KeyValue {
+            key: "OWNER".to_string(),
+            value: match (item.owner_uid, item.owner_gid) {
+                (Some(uid), Some(gid)) => format!("{}:{}", uid, gid),
+                _ => "-".to_string(),
+            },
+        },
     ];
 
+    if let Some(media) = media {
+        rows.push(KeyValue {
+            key: "SIZE".to_string(),
+            value: format_size(media.size),
+        });
+        rows.push(KeyValue {
+            key: "MIME".to_string(),
+            value: media.mime.to_string(),
+        });
+        if let Some((width, height)) = media.dimensions {
+            rows.push(KeyValue {
+                key: "DIMENSIONS".to_string(),
+                value: format!("{}x{}", width, height),
+            });
+        }
+        if let Some(duration) = media.duration {
+            rows.push(KeyValue {
+                key: "DURATION".to_string(),
+                value: media::format_playing_time(duration),
+            });
+        }
+    }
+
+    if let Some(overview) = overview {
+        rows.push(KeyValue {
+            key: "ENTRIES".to_string(),
+            value: format!(
+                "{} ({} file{} in all, {})",
+                overview.entries,
+                overview.files,
+                if overview.files == 1 { "" } else { "s" },
+                format_size(overview.size)
+            ),
+        });
+        if !overview.first_entries.is_empty() {
+            rows.push(KeyValue {
+                key: "CONTENTS".to_string(),
+                value: overview.first_entries.join("\n"),
+            });
+        }
+    }
+
+    if let Some(origin) = &item.remote_origin {
+        rows.push(KeyValue {
+            key: "REMOTE_ORIGIN".to_string(),
+            value: origin.clone(),
+        });
+    }
+
+    if let Some(note) = &item.note {
+        rows.push(KeyValue {
+            key: "NOTE".to_string(),
+            value: note.clone(),
+        });
+    }
+
+    // Bundles list where each member goes back to on restore
+    if !members.is_empty() {
+        rows.push(KeyValue {
+            key: "MEMBERS".to_string(),
+            value: members
+                .iter()
+                .map(|member| {
+                    Path::new(&member.original_path)
+                        .join(&member.name)
+                        .display()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        });
+    }
+
     // Format table with simple styling
     let mut table = Table::new(rows);
     table.with(Style::modern_rounded());
 
     // Print table
     println!("{}", table);
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let lines = vec!["fn main() {".to_string(), "}".to_string()];
+        let highlighted = highlight("main.rs", &lines);
+        assert_eq!(highlighted.len(), 2);
+        assert!(highlighted[0].contains("\x1b[38;2;"));
+        assert!(highlighted[0].contains("main"));
+        assert!(!highlighted[0].contains('\n'));
+
+        // By the first line when the name says nothing
+        let script = vec!["#!/bin/sh".to_string(), "echo hi".to_string()];
+        assert!(highlight("run", &script)[1].contains("\x1b["));
+
+        // Unknown content stays as it is
+        let notes = vec!["just some words".to_string()];
+        assert_eq!(highlight("notes.unknownext", &notes), notes);
+    }
 }
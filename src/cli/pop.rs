@@ -1,45 +1,439 @@
 use anyhow::{anyhow, Result};
+use std::collections::HashMap;
 use std::env;
-use std::io::{self, Write};
 
-use crate::db::{establish_connection, get_stored_path, ItemManager};
+use rusqlite::Connection;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::cli::{select, MergePolicy};
+use crate::config::{self, PopDestination};
+use crate::db::{
+    establish_connection, get_item_stored_path, id_addressing, item_by_number_with, BundleManager,
+    BundleMember, ItemManager, JournalManager, ManifestManager, OperationLog, StackItem,
+    StateManager, LAST_POP_OUTPUT,
+};
 use crate::fs;
-use crate::utils::numbers::parse_number_range;
+use crate::hooks;
+use crate::status;
+use crate::utils::display;
+use crate::utils::error::FstkError;
+use crate::utils::numbers::{is_number_range, parse_number_range};
+use crate::utils::output;
+use crate::utils::template;
 
-/// Pop items from the stack and restore them to the current directory or a specified output directory.
-pub fn pop(
-    numbers: Option<String>,
-    tags: Option<Vec<String>>,
-    output: Option<String>,
+/// Determine the name an item is restored under, applying the `--as` template if given.
+fn destination_name(item: &StackItem, rename: Option<&str>) -> Result<String> {
+    match rename {
+        Some(name_template) => template::render_name(name_template, item),
+        None => Ok(item.original_name.clone()),
+    }
+}
+
+/// Move an item's stored content to `dest_path` and drop the item from the stack.
+/// The move is journaled so that an interrupted run can be finished or rolled back later.
+pub fn move_out_of_stack(
+    conn: &mut Connection,
+    operation: &str,
+    item: &StackItem,
+    source_path: &Path,
+    dest_path: &Path,
 ) -> Result<()> {
-    let tag_vec = tags.unwrap_or_default();
-    let filter_by_tags = !tag_vec.is_empty();
+    let journal_id = begin_move(conn, operation, item, source_path, dest_path)?;
 
-    // Determine output directory (default to current directory if not specified)
-    let output_dir = match &output {
-        Some(path) => {
-            let dir_path = std::path::PathBuf::from(path);
-            // Check if the output directory exists and is a directory
-            if !dir_path.exists() {
-                return Err(anyhow!(
-                    "Output directory does not exist: {}",
-                    dir_path.display()
-                ));
+    let tx = conn.transaction()?;
+    if let Err(e) = finish_move(&tx, operation, item, journal_id) {
+        drop(tx);
+        // Put the content back so the item stays usable
+        put_back(item, source_path, dest_path)?;
+        JournalManager::complete(conn, journal_id)?;
+        return Err(e);
+    }
+    tx.commit()?;
+    drop_archive(item, source_path);
+    hooks::after(operation, item, Some(dest_path));
+
+    Ok(())
+}
+
+/// Take the content of an item out of storage: move it, or unpack it if it is stored as an
+/// archive (`push --as-archive`), which then stays in storage until the item is dropped
+fn take_content(item: &StackItem, source_path: &Path, dest_path: &Path) -> Result<()> {
+    if item.packed {
+        fs::unpack_dir(source_path, dest_path)
+    } else {
+        fs::move_or_copy(source_path, dest_path)
+    }
+}
+
+/// Undo `take_content`
+fn put_back(item: &StackItem, source_path: &Path, dest_path: &Path) -> Result<()> {
+    if item.packed {
+        fs::remove_item(dest_path)
+    } else {
+        fs::move_or_copy(dest_path, source_path)
+    }
+}
+
+/// Remove the archive of a packed item that was dropped from the stack
+fn drop_archive(item: &StackItem, source_path: &Path) {
+    if item.packed {
+        if let Err(e) = fs::remove_item(source_path) {
+            status!("Could not remove {}: {}", source_path.display(), e);
+        }
+    }
+}
+
+/// Fail early if the content of an item will not fit at `dest_path`, rather than running out of
+/// space halfway through a copy. Moves within one filesystem need no room.
+pub fn ensure_room(
+    item: &StackItem,
+    source_path: &Path,
+    dest_path: &Path,
+    copy: bool,
+) -> Result<()> {
+    match item.size {
+        Some(size) => fs::ensure_space(
+            dest_path,
+            fs::space_needed(source_path, dest_path, size, copy),
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Whether `dest_path` holds the partial copy of an interrupted pop or restore of the item
+/// stored at `source_path`, which moving it there again resumes
+pub fn resuming(source_path: &Path, dest_path: &Path) -> bool {
+    if !fs::can_resume(source_path, dest_path) {
+        return false;
+    }
+    status!("Resuming the interrupted copy to {}", dest_path.display());
+    true
+}
+
+/// First half of a move out of the stack: journal it and move the content. The item stays in
+/// the database until `finish_move`; if that never happens, recovery completes the move.
+/// Returns the journal entry.
+fn begin_move(
+    conn: &Connection,
+    operation: &str,
+    item: &StackItem,
+    source_path: &Path,
+    dest_path: &Path,
+) -> Result<i64> {
+    hooks::before(operation, item, Some(dest_path))?;
+    ensure_room(item, source_path, dest_path, item.packed)?;
+
+    let journal_id = JournalManager::begin(
+        conn,
+        operation,
+        &item.stored_hash,
+        item.content_hash.as_deref(),
+        &source_path.to_string_lossy(),
+        &dest_path.to_string_lossy(),
+    )?;
+
+    take_content(item, source_path, dest_path)?;
+    fs::warn_world_writable(dest_path);
+
+    Ok(journal_id)
+}
+
+/// Second half of a move out of the stack: drop the moved item and close its journal entry,
+/// within the caller's transaction.
+fn finish_move(tx: &Connection, operation: &str, item: &StackItem, journal_id: i64) -> Result<()> {
+    if !ItemManager::delete_row(tx, item.id)? {
+        return Err(anyhow!("Item '{}' no longer exists", item.original_name));
+    }
+    JournalManager::complete(tx, journal_id)?;
+    OperationLog::record(tx, operation, item)?;
+
+    Ok(())
+}
+
+/// An item of a batch pop whose content was moved but which is still in the database
+struct PendingPop {
+    display_number: usize,
+    item: StackItem,
+    source_path: PathBuf,
+    dest_path: PathBuf,
+    journal_id: i64,
+    /// The destination this pop overwrites, moved aside (see `fs::move_aside`)
+    replaced: Option<fs::MovedAside>,
+}
+
+/// Drop the items of a batch pop whose content was moved, in one transaction with a savepoint
+/// per item. An item that cannot be dropped gets its content moved back; with `all_or_nothing`,
+/// every item does (see `rollback_batch`).
+/// Returns how many items were popped and how many failed.
+fn finish_batch(
+    conn: &mut Connection,
+    pending: Vec<PendingPop>,
+    print_path: bool,
+    all_or_nothing: bool,
+) -> Result<(usize, usize)> {
+    let mut tx = conn.transaction()?;
+    let mut failed = Vec::new();
+
+    for (index, pop) in pending.iter().enumerate() {
+        // Dropping the savepoint without committing rolls back this item only
+        let savepoint = tx.savepoint()?;
+        match finish_move(&savepoint, "pop", &pop.item, pop.journal_id) {
+            Ok(()) => savepoint.commit()?,
+            Err(e) => {
+                status!("Error popping item #{}: {}", pop.display_number, e);
+                failed.push(index);
+                if all_or_nothing {
+                    break;
+                }
             }
-            if !dir_path.is_dir() {
-                return Err(anyhow!(
-                    "Specified output path is not a directory: {}",
-                    dir_path.display()
-                ));
+        }
+    }
+
+    if all_or_nothing && !failed.is_empty() {
+        drop(tx);
+        rollback_batch(conn, &pending)?;
+        return Ok((0, failed.len()));
+    }
+
+    for &index in &failed {
+        move_back(&tx, &pending[index])?;
+        restore_replaced(pending[index].replaced.as_ref(), &pending[index].dest_path);
+    }
+    tx.commit()?;
+
+    let popped: Vec<&PendingPop> = pending
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !failed.contains(index))
+        .map(|(_, pop)| pop)
+        .collect();
+    for pop in &popped {
+        // The pop is committed, so what it overwrote can go
+        if let Some(replaced) = &pop.replaced {
+            if let Err(e) = fs::remove_item(&replaced.aside) {
+                status!(
+                    "Could not remove the replaced {}: {}",
+                    replaced.aside.display(),
+                    e
+                );
             }
-            dir_path
         }
-        None => env::current_dir()?,
+        drop_archive(&pop.item, &pop.source_path);
+        hooks::after("pop", &pop.item, Some(&pop.dest_path));
+        if print_path {
+            println!("{}", pop.dest_path.display());
+        }
+    }
+
+    Ok((popped.len(), failed.len()))
+}
+
+/// Move the content of every item of a batch back into storage, leaving the stack as it was
+/// before the command
+fn rollback_batch(conn: &Connection, pending: &[PendingPop]) -> Result<()> {
+    for pop in pending.iter().rev() {
+        move_back(conn, pop)?;
+    }
+    status!(
+        "Rolled back {} popped item(s); the stack is unchanged",
+        pending.len()
+    );
+    Ok(())
+}
+
+/// Move a popped item's content back into storage, put back what it overwrote and close its
+/// journal entry. If that fails, the entry is left for recovery.
+fn move_back(conn: &Connection, pop: &PendingPop) -> Result<()> {
+    let result = put_back(&pop.item, &pop.source_path, &pop.dest_path);
+    restore_replaced(pop.replaced.as_ref(), &pop.dest_path);
+    match result {
+        Ok(()) => JournalManager::complete(conn, pop.journal_id),
+        Err(e) => {
+            status!(
+                "Could not move {} back into the stack: {}",
+                pop.dest_path.display(),
+                e
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Put back the destination a pop or restore that did not happen was to overwrite. If the popped content
+/// could not be moved out of `dest_path`, the old destination stays where it was moved aside.
+pub fn restore_replaced(replaced: Option<&fs::MovedAside>, dest_path: &Path) {
+    let Some(replaced) = replaced else {
+        return;
     };
+    if std::fs::symlink_metadata(dest_path).is_ok()
+        || std::fs::symlink_metadata(&replaced.path).is_ok()
+    {
+        status!(
+            "{} was kept as {}",
+            replaced.path.display(),
+            replaced.aside.display()
+        );
+    } else if let Err(e) = std::fs::rename(&replaced.aside, &replaced.path) {
+        status!(
+            "Could not put {} back as {}: {}",
+            replaced.aside.display(),
+            replaced.path.display(),
+            e
+        );
+    }
+}
+
+/// Options controlling how items are popped
+#[derive(Debug, Clone, Default)]
+pub struct PopOptions {
+    /// Only consider items with all of these tags
+    pub tags: Option<Vec<String>>,
+    /// Output directory (defaults to the current directory)
+    pub output: Option<String>,
+    /// Name template for the popped item (see `utils::template`)
+    pub rename: Option<String>,
+    /// Print each destination path to stdout, sending other messages to stderr
+    pub print_path: bool,
+    /// Extract only this entry of a stored directory, leaving the rest on the stack
+    pub subpath: Option<String>,
+    /// Pop this version (1 = oldest) of the selected item's original path
+    pub version: Option<usize>,
+    /// Pop locked items as well
+    pub force: bool,
+    /// Merge directory items into existing destination directories with this conflict policy
+    pub merge: Option<MergePolicy>,
+    /// Pop into the output directory used last time (unless `output` is given)
+    pub last_out: bool,
+    /// Where to put the items when no output directory is given (overrides `pop_destination`)
+    pub destination: Option<PopDestination>,
+    /// Put every item of a batch back if any of them fails
+    pub rollback_on_error: bool,
+    /// Directories for some items of the batch, as `N=DIR` with `N` a number or range
+    pub out_map: Vec<String>,
+    /// Ask for the directory of each item of the batch
+    pub ask_output: bool,
+}
+
+/// Pop items from the stack and restore them to the current directory or a specified output directory.
+pub fn pop(numbers: Option<String>, options: PopOptions) -> Result<()> {
+    let PopOptions {
+        tags,
+        output,
+        rename,
+        print_path,
+        subpath,
+        version,
+        force,
+        merge,
+        last_out,
+        destination,
+        rollback_on_error,
+        out_map,
+        ask_output,
+    } = options;
+
+    // Keep stdout clean for the printed destination paths
+    if print_path {
+        output::reserve_stdout();
+    }
+
+    let tag_vec = tags.unwrap_or_default();
+    let filter_by_tags = !tag_vec.is_empty();
 
     // Connect to database
     let mut conn = establish_connection()?;
 
+    // An explicit output directory wins; otherwise the given or configured destination decides
+    let config = config::load()?;
+    let here = destination == Some(PopDestination::Cwd);
+    let destination = if output.is_some() || last_out {
+        PopDestination::Cwd
+    } else {
+        destination.unwrap_or(config.pop_destination)
+    };
+
+    // The output directory is --output, the last one used with --last-out, the configured
+    // default, or the current directory
+    let output = match (output, last_out) {
+        (Some(path), _) => Some(path),
+        (None, true) => Some(StateManager::get(&conn, LAST_POP_OUTPUT)?.ok_or_else(|| {
+            anyhow!("No previous pop destination recorded for this stack; use --output first")
+        })?),
+        (None, false) if here || destination != PopDestination::Cwd => None,
+        (None, false) => config.pop_output_dir,
+    };
+    let output_dir = match &output {
+        Some(path) => checked_output_dir(Path::new(path))?,
+        None => env::current_dir()?,
+    };
+    let out_map = parse_out_map(&out_map)?;
+
+    // Remember the destination so that the next pop can reuse it with --last-out
+    if output.is_some() {
+        StateManager::set(&conn, LAST_POP_OUTPUT, &output_dir.to_string_lossy())?;
+    }
+
+    // A non-numeric argument selects an item by name
+    let numbers = match numbers {
+        Some(arg) if !is_number_range(&arg) => {
+            match select::resolve_number(&conn, &arg, &tag_vec)? {
+                Some(number) => Some(number.to_string()),
+                None => return Ok(()),
+            }
+        }
+        numbers => numbers,
+    };
+
+    // Extract a single entry from a stored directory
+    if let Some(subpath) = subpath {
+        let item = resolve_single(&conn, numbers.as_deref(), &tag_vec, "--path")?;
+        ensure_unlocked(&item, force)?;
+        let output_dir = item_output_dir(&item, &output_dir, destination)?;
+        return pop_subpath(
+            &mut conn,
+            item,
+            &subpath,
+            &output_dir,
+            rename.as_deref(),
+            print_path,
+        );
+    }
+
+    // Pop a specific version of the selected item's original path
+    if let Some(version) = version {
+        let base = resolve_single(&conn, numbers.as_deref(), &tag_vec, "--version")?;
+
+        let versions = match &base.version_group {
+            Some(group) => ItemManager::list_versions(&conn, group)?,
+            None => vec![base.clone()],
+        };
+        let item = version
+            .checked_sub(1)
+            .and_then(|index| versions.get(index))
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "'{}' has {} version(s); no version {}",
+                    base.original_name,
+                    versions.len(),
+                    version
+                )
+            })?;
+
+        ensure_unlocked(&item, force)?;
+        let output_dir = item_output_dir(&item, &output_dir, destination)?;
+        return pop_single(
+            &mut conn,
+            item,
+            &output_dir,
+            rename.as_deref(),
+            print_path,
+            merge,
+            destination,
+        );
+    }
+
     // If no numbers are specified, pop the latest item
     if numbers.is_none() {
         let item = if filter_by_tags {
@@ -48,100 +442,62 @@ pub fn pop(
                 .ok_or_else(|| anyhow!("No items found with tags=[{}]", tag_vec.join(", ")))?
         } else {
             // Get latest item
-            ItemManager::get_latest(&conn)?.ok_or_else(|| anyhow!("No items in the stack"))?
+            ItemManager::get_latest(&conn)?
+                .ok_or_else(|| anyhow!("No unpinned items in the stack"))?
         };
 
-        // Construct destination path using output_dir
-        let dest_path = output_dir.join(&item.original_name);
-
-        // Check if destination already exists
-        if fs::check_destination_conflict(&dest_path) {
-            return Err(anyhow!(
-                "Destination already exists: {}. Use 'restore' with a different destination to avoid conflicts.",
-                dest_path.display()
-            ));
-        }
-
-        // Get source path
-        let source_path = get_stored_path(&item.stored_hash)?;
-
-        // Ensure source exists
-        if !source_path.exists() {
-            return Err(anyhow!(
-                "Error: Source file missing from storage: {}",
-                source_path.display()
-            ));
-        }
-
-        // Move the item
-        fs::move_or_copy(&source_path, &dest_path)?;
-
-        // Remove from database
-        ItemManager::delete(&mut conn, item.id)?;
-
-        // Skip success message for better CLI silence
-
-        return Ok(());
+        ensure_unlocked(&item, force)?;
+        let output_dir = item_output_dir(&item, &output_dir, destination)?;
+        return pop_single(
+            &mut conn,
+            item,
+            &output_dir,
+            rename.as_deref(),
+            print_path,
+            merge,
+            destination,
+        );
     }
 
     // Parse the number range
     let number_list = parse_number_range(&numbers.unwrap())?;
 
-    // First, collect all the items to process based on the current state
-    // This ensures we're working with a snapshot of the current display numbers
-    let mut items_to_process = Vec::new();
-
     // Get list of all items with current display numbers
-    let mut all_items = if filter_by_tags {
-        ItemManager::list(&conn, &tag_vec)?
-    } else {
-        let empty_tags = Vec::new();
-        ItemManager::list(&conn, &empty_tags)?
-    };
+    let mut all_items = ItemManager::list(&conn, &tag_vec)?;
 
     // Sort by pushed_at (descending) to match display order
-    all_items.sort_by(|a, b| b.pushed_at.cmp(&a.pushed_at));
-
-    // Map display numbers to database IDs
-    for &number in &number_list {
-        if number > 0 && number <= all_items.len() {
-            // Convert display number to zero-based index
-            let idx = number - 1;
-            items_to_process.push((number, all_items[idx].clone()));
-        } else {
-            // Report invalid number
-            if filter_by_tags {
-                println!(
-                    "No item found with number={} and tags=[{}]",
-                    number,
-                    tag_vec.join(", ")
-                );
-            } else {
-                println!("No item found with number={}", number);
-            }
-        }
-    }
+    all_items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+
+    // Collect all the items to process based on the current state, so that we're working
+    // with a snapshot of the current display numbers
+    let items_to_process = batch_items(&all_items, &number_list, id_addressing(), &tag_vec);
 
     // Exit early if no valid items to process
     if items_to_process.is_empty() {
         return Err(anyhow!("No valid items to pop"));
     }
 
+    if let Some(number) = out_map.keys().find(|number| !number_list.contains(number)) {
+        return Err(anyhow!(
+            "--out-map gives a directory for #{}, which is not being popped",
+            number
+        ));
+    }
+
+    if rollback_on_error {
+        check_rollback(&items_to_process, rename.is_some(), merge.is_some())?;
+    }
+
     // Ask for confirmation before batch processing
     if items_to_process.len() > 1 {
-        println!(
+        status!(
             "You are about to pop {} items from the stack.",
             items_to_process.len()
         );
-        print!("Do you want to continue? [y/N]: ");
-        io::stdout().flush()?;
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let input = input.trim().to_lowercase();
+        let input = output::prompt("Do you want to continue? [y/N]: ")?;
 
         if input != "y" && input != "yes" {
-            println!("Operation cancelled.");
+            status!("Operation cancelled.");
             return Ok(());
         }
     }
@@ -157,58 +513,91 @@ pub fn pop(
     let items_count = items_to_process.len();
 
     // Process all items atomically (based on the initial state)
-    for (display_number, item) in items_to_process {
-        // Construct destination path in output directory
-        let dest_path = output_dir.join(&item.original_name);
-
-        // Check if destination already exists
-        if fs::check_destination_conflict(&dest_path) {
-            println!("Destination already exists: {}", dest_path.display());
-
-            if items_count > 1 {
-                print!("Skip this item? [Y/n]: ");
-                io::stdout().flush()?;
+    // A conflict answer the user asked to apply to all remaining items
+    let mut remembered_choice = None;
+    // Items whose content was moved, dropped from the database in one transaction at the end
+    let mut pending = Vec::new();
 
-                let mut input = String::new();
-                io::stdin().read_line(&mut input)?;
-                let input = input.trim().to_lowercase();
+    for (index, (display_number, item)) in items_to_process.into_iter().enumerate() {
+        if item.locked && !force {
+            status!(
+                "Item #{} ('{}') is locked; use --force to pop it",
+                display_number,
+                item.original_name
+            );
+            skipped_count += 1;
+            continue;
+        }
 
-                if input != "n" && input != "no" {
-                    println!("Skipping item #{}", display_number);
-                    skipped_count += 1;
+        // A directory given for this item in particular wins over the batch destination
+        let chosen_dir = match out_map.get(&display_number) {
+            Some(dir) => Ok(Some(dir.clone())),
+            None if ask_output => ask_output_dir(display_number, &item),
+            None => Ok(None),
+        };
+        let (output_dir, destination) = match chosen_dir {
+            Ok(Some(dir)) => (dir, PopDestination::Cwd),
+            Ok(None) => match item_output_dir(&item, &output_dir, destination) {
+                Ok(dir) => (dir, destination),
+                Err(e) => {
+                    status!("Cannot pop item #{}: {}", display_number, e);
+                    failed_count += 1;
                     continue;
                 }
-
-                println!(
-                    "Cannot continue with item #{} due to destination conflict",
-                    display_number
-                );
+            },
+            Err(e) => {
+                status!("Cannot pop item #{}: {}", display_number, e);
                 failed_count += 1;
                 continue;
-            } else {
-                return Err(anyhow!(
-                    "Destination already exists: {}. Use 'restore' with a different destination to avoid conflicts.",
-                    dest_path.display()
-                ));
             }
+        };
+
+        if item.is_bundle() && rename.is_none() {
+            match unpack_bundle(&mut conn, "pop", &item, |member| {
+                member_destination(member, &output_dir, destination)
+            }) {
+                Ok(paths) => {
+                    if print_path {
+                        for path in paths {
+                            println!("{}", path.display());
+                        }
+                    }
+                    success_count += 1;
+                }
+                Err(e) => {
+                    status!("Error popping bundle #{}: {}", display_number, e);
+                    failed_count += 1;
+                }
+            }
+            continue;
         }
 
+        // Construct destination path in output directory
+        let dest_name = match destination_name(&item, rename.as_deref()) {
+            Ok(name) => name,
+            Err(e) => {
+                status!("Invalid name for item #{}: {}", display_number, e);
+                failed_count += 1;
+                continue;
+            }
+        };
         // Get source path from the data directory
-        let source_path = match get_stored_path(&item.stored_hash) {
+        let source_path = match get_item_stored_path(&item) {
             Ok(path) => path,
             Err(e) => {
-                println!(
+                status!(
                     "Error getting stored path for item #{}: {}",
-                    display_number, e
+                    display_number,
+                    e
                 );
                 failed_count += 1;
                 continue;
             }
         };
 
-        // Ensure source exists
+        // Ensure source exists before anything at the destination is touched
         if !source_path.exists() {
-            println!(
+            status!(
                 "Source file missing for item #{}: {}",
                 display_number,
                 source_path.display()
@@ -217,35 +606,112 @@ pub fn pop(
             continue;
         }
 
-        // Move the item to the current directory
-        match fs::move_or_copy(&source_path, &dest_path) {
-            Ok(_) => {
-                // Remove item from database
-                match ItemManager::delete(&mut conn, item.id) {
-                    Ok(true) => {
-                        // Skip detailed success messages for batch operations
+        // Check if destination already exists
+        let mut dest_path = output_dir.join(dest_name);
+        let mut replaced = None;
+        if fs::check_destination_conflict(&dest_path) && !resuming(&source_path, &dest_path) {
+            if let Some(policy) = merge.filter(|_| can_merge(&item, &dest_path)) {
+                match merge_out_of_stack(&mut conn, &item, &source_path, &dest_path, policy) {
+                    Ok(()) => {
+                        if print_path {
+                            println!("{}", dest_path.display());
+                        }
                         success_count += 1;
                     }
-                    _ => {
-                        println!("Error removing database entry for item #{}", display_number);
-                        // Try to undo the file operation
-                        let _ = fs::move_or_copy(&dest_path, &source_path);
+                    Err(e) => {
+                        status!("Error merging item #{}: {}", display_number, e);
+                        failed_count += 1;
+                    }
+                }
+                continue;
+            }
+
+            if items_count == 1 {
+                return Err(destination_conflict(&dest_path));
+            }
+
+            status!("Destination already exists: {}", dest_path.display());
+            let choice = match remembered_choice {
+                Some(choice) => choice,
+                None => {
+                    let (choice, apply_to_all) =
+                        ask_conflict_choice(&dest_path, &source_path, index + 1 < items_count)?;
+                    if apply_to_all {
+                        remembered_choice = Some(choice);
+                    }
+                    choice
+                }
+            };
+
+            match choice {
+                ConflictChoice::Skip => {
+                    status!("Skipping item #{}", display_number);
+                    skipped_count += 1;
+                    continue;
+                }
+                // The destination is only deleted once the pop is committed
+                ConflictChoice::Overwrite => match fs::move_aside(&dest_path) {
+                    Ok(moved) => replaced = Some(moved),
+                    Err(e) => {
+                        status!("Could not overwrite {}: {}", dest_path.display(), e);
                         failed_count += 1;
+                        continue;
                     }
+                },
+                ConflictChoice::Rename => {
+                    dest_path = fs::free_path(&dest_path);
+                    status!(
+                        "Popping item #{} as {}",
+                        display_number,
+                        dest_path.display()
+                    );
+                }
+                ConflictChoice::Abort => {
+                    status!("Aborted; remaining items were left on the stack");
+                    break;
                 }
             }
+        }
+
+        // Move the item to the output directory; it is removed from the database with the
+        // rest of the batch
+        match begin_move(&conn, "pop", &item, &source_path, &dest_path) {
+            Ok(journal_id) => pending.push(PendingPop {
+                display_number,
+                item,
+                source_path,
+                dest_path,
+                journal_id,
+                replaced,
+            }),
             Err(e) => {
-                println!("Error moving item #{}: {}", display_number, e);
+                status!("Error moving item #{}: {}", display_number, e);
+                restore_replaced(replaced.as_ref(), &dest_path);
                 failed_count += 1;
             }
         }
     }
 
+    // With --rollback-on-error, any failure puts everything back
+    if rollback_on_error && failed_count > 0 {
+        rollback_batch(&conn, &pending)?;
+        return Err(anyhow!(
+            "Failed to pop {} item(s); nothing was popped",
+            failed_count
+        ));
+    }
+
+    let (popped, failed) = finish_batch(&mut conn, pending, print_path, rollback_on_error)?;
+    success_count += popped;
+    failed_count += failed;
+
     // Print summary if multiple items were processed
     if items_count > 1 {
-        println!(
+        status!(
             "Summary: {} item(s) popped successfully, {} skipped, {} failed",
-            success_count, skipped_count, failed_count
+            success_count,
+            skipped_count,
+            failed_count
         );
     }
 
@@ -255,3 +721,902 @@ pub fn pop(
         Err(anyhow!("Failed to pop any items"))
     }
 }
+
+/// The items of `all_items` (in display order) that the numbers of a batch address, paired
+/// with their number (a database ID when `by_id` is set). Numbers addressing nothing are
+/// reported and left out.
+fn batch_items(
+    all_items: &[StackItem],
+    numbers: &[usize],
+    by_id: bool,
+    tags: &[String],
+) -> Vec<(usize, StackItem)> {
+    let mut items = Vec::new();
+    for &number in numbers {
+        if let Some(item) = item_by_number_with(all_items, number, by_id) {
+            items.push((number, item.clone()));
+        } else if tags.is_empty() {
+            // Report invalid number
+            status!("No item found with number={}", number);
+        } else {
+            status!(
+                "No item found with number={} and tags=[{}]",
+                number,
+                tags.join(", ")
+            );
+        }
+    }
+    items
+}
+
+/// Refuse a `--rollback-on-error` batch containing pops that are committed as they happen and
+/// so cannot be rolled back with the rest: merges, and bundles, whose members are taken out one
+/// by one (unless the bundle is popped whole under another name).
+fn check_rollback(items: &[(usize, StackItem)], renamed: bool, merge: bool) -> Result<()> {
+    if merge {
+        return Err(anyhow!(
+            "--rollback-on-error cannot be combined with --merge, since merged items cannot be \
+             put back"
+        ));
+    }
+    if !renamed {
+        if let Some((number, bundle)) = items.iter().find(|(_, item)| item.is_bundle()) {
+            return Err(anyhow!(
+                "--rollback-on-error cannot pop bundle #{} ('{}'); pop it separately",
+                number,
+                bundle.original_name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The absolute path of an output directory, which must exist.
+fn checked_output_dir(dir_path: &Path) -> Result<PathBuf> {
+    if !dir_path.exists() {
+        return Err(anyhow!(
+            "Output directory does not exist: {}",
+            dir_path.display()
+        ));
+    }
+    if !dir_path.is_dir() {
+        return Err(anyhow!(
+            "Specified output path is not a directory: {}",
+            dir_path.display()
+        ));
+    }
+    fs::get_absolute_path(dir_path)
+}
+
+/// Expand a leading `~` to the home directory; shells leave it alone after `N=`.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(""), Some(home)) => home,
+        (Some(rest), Some(home)) if rest.starts_with('/') => home.join(&rest[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Parse `--out-map` entries (`N=DIR`, where `N` may be a list or range like `1-3`) into the
+/// directory for each item number. Every directory must exist.
+fn parse_out_map(entries: &[String]) -> Result<HashMap<usize, PathBuf>> {
+    let mut map = HashMap::new();
+    for entry in entries {
+        let (numbers, dir) = entry
+            .split_once('=')
+            .filter(|(numbers, dir)| !numbers.is_empty() && !dir.is_empty())
+            .ok_or_else(|| anyhow!("Invalid --out-map entry '{}'; expected N=DIR", entry))?;
+        let dir = checked_output_dir(&expand_home(dir))?;
+        for number in parse_number_range(numbers)? {
+            if map.insert(number, dir.clone()).is_some() {
+                return Err(anyhow!(
+                    "--out-map gives #{} more than one directory",
+                    number
+                ));
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// Ask where to pop one item of a batch; `None` (an empty answer) keeps the batch destination.
+fn ask_output_dir(display_number: usize, item: &StackItem) -> Result<Option<PathBuf>> {
+    let answer = output::prompt_text(&format!(
+        "Directory for #{} '{}' (Enter for the default): ",
+        display_number, item.original_name
+    ))?;
+    if answer.is_empty() {
+        return Ok(None);
+    }
+    checked_output_dir(&expand_home(&answer)).map(Some)
+}
+
+/// Resolve the single item an option like `--path` applies to: the given number, or the latest item.
+fn resolve_single(
+    conn: &Connection,
+    numbers: Option<&str>,
+    tags: &[String],
+    option: &str,
+) -> Result<StackItem> {
+    match numbers {
+        Some(numbers) => {
+            let number_list = parse_number_range(numbers)?;
+            if number_list.len() != 1 {
+                return Err(anyhow!("{} can only be used with a single item", option));
+            }
+
+            let id = ItemManager::get_id_by_display_number(conn, number_list[0], tags)?
+                .ok_or_else(|| FstkError::ItemNotFound(number_list[0].to_string()))?;
+            Ok(ItemManager::get_by_id(conn, id)?
+                .ok_or_else(|| FstkError::ItemNotFound(number_list[0].to_string()))?)
+        }
+        None => ItemManager::get_latest_by_tags(conn, tags)?
+            .ok_or_else(|| anyhow!("No unpinned items in the stack")),
+    }
+}
+
+/// Error for a pop whose destination is already taken.
+fn destination_conflict(dest_path: &Path) -> anyhow::Error {
+    anyhow::Error::from(FstkError::DestinationConflict(
+        dest_path.to_string_lossy().to_string(),
+    ))
+    .context(format!(
+        "Destination already exists: {}. Use 'restore' with a different destination to avoid conflicts.",
+        dest_path.display()
+    ))
+}
+
+/// What to do with an item whose destination is already taken during a batch pop
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConflictChoice {
+    Skip,
+    Overwrite,
+    Rename,
+    Abort,
+}
+
+/// Ask how to resolve a destination conflict, showing a diff as often as requested.
+/// Returns the choice and whether it should apply to all remaining conflicts.
+fn ask_conflict_choice(
+    dest_path: &Path,
+    source_path: &Path,
+    more_items: bool,
+) -> Result<(ConflictChoice, bool)> {
+    let choice = loop {
+        let input =
+            output::prompt("[s]kip, [o]verwrite, [r]ename, [d]iff or [a]bort? [S/o/r/d/a]: ")?;
+        match input.as_str() {
+            "" | "s" | "skip" => break ConflictChoice::Skip,
+            "o" | "overwrite" => break ConflictChoice::Overwrite,
+            "r" | "rename" => break ConflictChoice::Rename,
+            "a" | "abort" => break ConflictChoice::Abort,
+            "d" | "diff" => show_diff(dest_path, source_path)?,
+            _ => status!("Please answer s, o, r, d or a"),
+        }
+    };
+
+    if choice == ConflictChoice::Abort || !more_items {
+        return Ok((choice, false));
+    }
+
+    let input = output::prompt("Apply to all remaining conflicts? [y/N]: ")?;
+    Ok((choice, input == "y" || input == "yes"))
+}
+
+/// Compare the existing destination with the stored item, using `diff` when it is available.
+fn show_diff(existing: &Path, stored: &Path) -> Result<()> {
+    let existing_size = fs::path_size(existing)?;
+    let stored_size = fs::path_size(stored)?;
+    let identical = existing.is_dir() == stored.is_dir()
+        && fs::content_hash(existing)? == fs::content_hash(stored)?;
+
+    status!(
+        "Existing: {} ({}), on the stack: {} ({})",
+        existing.display(),
+        display::format_size(existing_size),
+        if stored.is_dir() { "directory" } else { "file" },
+        display::format_size(stored_size)
+    );
+    if identical {
+        status!("Contents are identical");
+        return Ok(());
+    }
+
+    let diff = Command::new("diff")
+        .arg("-ru")
+        .arg(existing)
+        .arg(stored)
+        .stdout(if output::is_stdout_reserved() {
+            Stdio::from(std::io::stderr())
+        } else {
+            Stdio::inherit()
+        })
+        .status();
+    if diff.is_err() {
+        status!("Contents differ ('diff' is not available to show how)");
+    }
+
+    Ok(())
+}
+
+/// Refuse to pop a locked item unless forced.
+fn ensure_unlocked(item: &StackItem, force: bool) -> Result<()> {
+    if item.locked && !force {
+        return Err(FstkError::ItemLocked(item.original_name.clone()).into());
+    }
+    Ok(())
+}
+
+/// The directory to pop `item` into: `output_dir`, the directory the item originally lived in
+/// (created if needed, like `restore` does), or the directory it was pushed from.
+fn item_output_dir(
+    item: &StackItem,
+    output_dir: &Path,
+    destination: PopDestination,
+) -> Result<PathBuf> {
+    match destination {
+        PopDestination::Cwd => return Ok(output_dir.to_path_buf()),
+        PopDestination::Original => {
+            let original_dir = PathBuf::from(&item.original_path);
+            fs::create_dirs(&original_dir)?;
+            return Ok(original_dir);
+        }
+        PopDestination::Pushdir => {}
+    }
+
+    let push_dir = item.push_dir.as_deref().ok_or_else(|| {
+        anyhow!(
+            "No push directory recorded for '{}' (pushed before fstk kept track of it)",
+            item.original_name
+        )
+    })?;
+    let push_dir = PathBuf::from(push_dir);
+    if !push_dir.is_dir() {
+        return Err(anyhow!(
+            "Directory '{}' was pushed from no longer exists: {}",
+            item.original_name,
+            push_dir.display()
+        ));
+    }
+
+    Ok(push_dir)
+}
+
+/// Where a bundle member is popped to: into `output_dir`, or back to where it came from when
+/// popping to the original location.
+fn member_destination(
+    member: &BundleMember,
+    output_dir: &Path,
+    destination: PopDestination,
+) -> PathBuf {
+    match destination {
+        PopDestination::Original => Path::new(&member.original_path).join(&member.name),
+        _ => output_dir.join(&member.name),
+    }
+}
+
+/// Pop a single item into the output directory.
+fn pop_single(
+    conn: &mut Connection,
+    item: StackItem,
+    output_dir: &Path,
+    rename: Option<&str>,
+    print_path: bool,
+    merge: Option<MergePolicy>,
+    destination: PopDestination,
+) -> Result<()> {
+    // A bundle spills its members into the output directory unless it is given a name
+    if item.is_bundle() && rename.is_none() {
+        let paths = unpack_bundle(conn, "pop", &item, |member| {
+            member_destination(member, output_dir, destination)
+        })?;
+        if print_path {
+            for path in paths {
+                println!("{}", path.display());
+            }
+        }
+        return Ok(());
+    }
+
+    // Construct destination path using output_dir
+    let dest_path = output_dir.join(destination_name(&item, rename)?);
+
+    // Get source path
+    let source_path = get_item_stored_path(&item)?;
+
+    // Check if destination already exists
+    if fs::check_destination_conflict(&dest_path) && !resuming(&source_path, &dest_path) {
+        match merge {
+            Some(policy) if can_merge(&item, &dest_path) => {
+                merge_out_of_stack(conn, &item, &source_path, &dest_path, policy)?;
+                if print_path {
+                    println!("{}", dest_path.display());
+                }
+                return Ok(());
+            }
+            _ => return Err(destination_conflict(&dest_path)),
+        }
+    }
+
+    // Ensure source exists
+    if !source_path.exists() {
+        return Err(anyhow!(
+            "Error: Source file missing from storage: {}",
+            source_path.display()
+        ));
+    }
+
+    // Move the item and remove it from the database
+    move_out_of_stack(conn, "pop", &item, &source_path, &dest_path)?;
+
+    // Skip success message for better CLI silence
+    if print_path {
+        println!("{}", dest_path.display());
+    }
+
+    Ok(())
+}
+
+/// Extract a single entry of a stored directory or bundle item into the output directory.
+/// The rest of the directory stays on the stack and the item is marked as partial.
+fn pop_subpath(
+    conn: &mut Connection,
+    item: StackItem,
+    subpath: &str,
+    output_dir: &Path,
+    rename: Option<&str>,
+    print_path: bool,
+) -> Result<()> {
+    if item.packed {
+        return Err(anyhow!(
+            "Item '{}' is stored as an archive; --path cannot take entries out of it",
+            item.original_name
+        ));
+    }
+    if !item.is_stored_as_directory() {
+        return Err(anyhow!(
+            "Item '{}' is not a directory; --path only applies to directory and bundle items",
+            item.original_name
+        ));
+    }
+
+    // Only plain relative paths inside the stored directory are allowed
+    let relative = Path::new(subpath.trim_end_matches('/'));
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return Err(anyhow!("Invalid path inside directory item: {}", subpath));
+    }
+
+    let entry_name = fs::get_file_name(relative)?;
+    let dest_name = match rename {
+        Some(name_template) => {
+            let entry_item = StackItem {
+                original_name: entry_name,
+                ..item.clone()
+            };
+            template::render_name(name_template, &entry_item)?
+        }
+        None => entry_name,
+    };
+    let dest_path = output_dir.join(dest_name);
+
+    hooks::before("pop", &item, Some(&dest_path))?;
+    extract_entry(conn, "pop", &item, relative, &dest_path)?;
+    hooks::after("pop", &item, Some(&dest_path));
+
+    if print_path {
+        println!("{}", dest_path.display());
+    }
+
+    Ok(())
+}
+
+/// Take the members of a bundle item out of the stack, each to the path given by `destination`.
+/// Members are extracted one at a time, so an interruption leaves the rest in the bundle.
+/// Returns the paths the members were moved to.
+pub fn unpack_bundle<F>(
+    conn: &mut Connection,
+    operation: &str,
+    item: &StackItem,
+    destination: F,
+) -> Result<Vec<PathBuf>>
+where
+    F: Fn(&BundleMember) -> PathBuf,
+{
+    hooks::before(operation, item, None)?;
+    let paths = extract_members(conn, operation, item, destination)?;
+    hooks::after(operation, item, None);
+    Ok(paths)
+}
+
+/// `unpack_bundle` without the hooks, for callers that run them around more than the unpacking
+pub fn extract_members<F>(
+    conn: &mut Connection,
+    operation: &str,
+    item: &StackItem,
+    destination: F,
+) -> Result<Vec<PathBuf>>
+where
+    F: Fn(&BundleMember) -> PathBuf,
+{
+    let targets: Vec<(String, PathBuf)> = BundleManager::get_for_item(conn, item.id)?
+        .iter()
+        .map(|member| (member.name.clone(), destination(member)))
+        .collect();
+
+    // Check every destination first so that a conflict leaves the bundle untouched
+    if let Some((_, dest_path)) = targets
+        .iter()
+        .find(|(_, dest_path)| fs::check_destination_conflict(dest_path))
+    {
+        return Err(FstkError::DestinationConflict(dest_path.to_string_lossy().to_string()).into());
+    }
+
+    for (name, dest_path) in &targets {
+        fs::ensure_parent_dirs(dest_path)?;
+        extract_entry(conn, operation, item, Path::new(name), dest_path)?;
+    }
+
+    Ok(targets
+        .into_iter()
+        .map(|(_, dest_path)| dest_path)
+        .collect())
+}
+
+/// Move one entry out of a stored directory or bundle and update the item's bookkeeping.
+/// The item is dropped once nothing is left in it.
+pub fn extract_entry(
+    conn: &mut Connection,
+    operation: &str,
+    item: &StackItem,
+    relative: &Path,
+    dest_path: &Path,
+) -> Result<()> {
+    let stored_dir = get_item_stored_path(item)?;
+    let source_path = stored_dir.join(relative);
+    if !source_path.exists() {
+        return Err(anyhow!(
+            "No entry '{}' in item '{}'",
+            relative.display(),
+            item.original_name
+        ));
+    }
+
+    if fs::check_destination_conflict(dest_path) {
+        return Err(FstkError::DestinationConflict(dest_path.to_string_lossy().to_string()).into());
+    }
+
+    fs::ensure_space(
+        dest_path,
+        fs::space_needed(&source_path, dest_path, fs::path_size(&source_path)?, false),
+    )?;
+    fs::move_or_copy(&source_path, dest_path)?;
+
+    entry_taken(conn, operation, item, relative, &stored_dir)
+}
+
+/// Delete one entry of a stored directory or bundle whose content is already in place
+/// elsewhere, with the same bookkeeping as extracting it.
+pub fn discard_entry(
+    conn: &mut Connection,
+    operation: &str,
+    item: &StackItem,
+    relative: &Path,
+) -> Result<()> {
+    let stored_dir = get_item_stored_path(item)?;
+    fs::remove_item(&stored_dir.join(relative))?;
+
+    entry_taken(conn, operation, item, relative, &stored_dir)
+}
+
+/// Update the bookkeeping of a directory or bundle after an entry left it
+fn entry_taken(
+    conn: &mut Connection,
+    operation: &str,
+    item: &StackItem,
+    relative: &Path,
+    stored_dir: &Path,
+) -> Result<()> {
+    // Drop the directories the entry leaves empty, so that the item goes away once all of its
+    // files were taken
+    let mut parent = relative.parent();
+    while let Some(dir) = parent.filter(|dir| !dir.as_os_str().is_empty()) {
+        if std::fs::remove_dir(stored_dir.join(dir)).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+
+    let prefix = relative.to_string_lossy().replace('\\', "/");
+    ManifestManager::remove_path(conn, item.id, &prefix)?;
+    if item.is_bundle() {
+        BundleManager::remove(conn, item.id, &prefix)?;
+    }
+
+    finish_extraction(conn, operation, item, stored_dir)
+}
+
+/// Update an item after entries were taken out of its stored directory: drop it once
+/// nothing is left, otherwise mark it as partial with the size and hash of the rest.
+fn finish_extraction(
+    conn: &mut Connection,
+    operation: &str,
+    item: &StackItem,
+    stored_dir: &Path,
+) -> Result<()> {
+    if fs::path_size(stored_dir)? == 0 && std::fs::read_dir(stored_dir)?.next().is_none() {
+        // Nothing is left: drop the item entirely
+        std::fs::remove_dir(stored_dir)?;
+        ItemManager::delete(conn, item.id)?;
+        OperationLog::record(conn, operation, item)?;
+    } else {
+        let remaining = ManifestManager::get_for_item(conn, item.id)?;
+        let content_hash = (!remaining.is_empty()).then(|| fs::manifest_hash(&remaining));
+        ItemManager::mark_partial(
+            conn,
+            item.id,
+            fs::path_size(stored_dir)?,
+            content_hash.as_deref(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Bring the bookkeeping of an item whose stored directory lost entries in an interrupted merge
+/// up to date: forget the entries that are gone and drop or mark the item as partial.
+pub fn settle_extraction(conn: &mut Connection, operation: &str, item: &StackItem) -> Result<()> {
+    let stored_dir = get_item_stored_path(item)?;
+    if !stored_dir.exists() {
+        ItemManager::delete(conn, item.id)?;
+        OperationLog::record(conn, operation, item)?;
+        return Ok(());
+    }
+
+    for entry in ManifestManager::get_for_item(conn, item.id)? {
+        if std::fs::symlink_metadata(stored_dir.join(&entry.relative_path)).is_err() {
+            ManifestManager::remove_path(conn, item.id, &entry.relative_path)?;
+        }
+    }
+    finish_extraction(conn, operation, item, &stored_dir)
+}
+
+/// Whether `pop --merge` can merge `item` into the existing `dest_path`.
+fn can_merge(item: &StackItem, dest_path: &Path) -> bool {
+    item.is_stored_as_directory() && !item.is_bundle() && dest_path.is_dir()
+}
+
+/// Merge a stored directory into an existing directory file by file and report conflicts.
+/// Files left behind by the conflict policy stay on the stack as a partial item.
+fn merge_out_of_stack(
+    conn: &mut Connection,
+    item: &StackItem,
+    source_path: &Path,
+    dest_path: &Path,
+    policy: MergePolicy,
+) -> Result<()> {
+    if !source_path.exists() {
+        return Err(anyhow!(
+            "Source file missing from storage: {}",
+            source_path.display()
+        ));
+    }
+
+    hooks::before("pop", item, Some(dest_path))?;
+
+    // Journaled so that recovery can settle the bookkeeping if the merge is interrupted
+    let journal_id = JournalManager::begin(
+        conn,
+        "merge",
+        &item.stored_hash,
+        None,
+        &source_path.to_string_lossy(),
+        &dest_path.to_string_lossy(),
+    )?;
+    let mut report = fs::MergeReport::default();
+    let merged = fs::merge_dir(source_path, dest_path, policy, &mut report);
+
+    // Files merged before a failure are out of storage all the same
+    for path in report.taken() {
+        ManifestManager::remove_path(conn, item.id, path)?;
+    }
+    finish_extraction(conn, "pop", item, source_path)?;
+    JournalManager::complete(conn, journal_id)?;
+    if let Err(e) = merged {
+        return Err(e.context(format!(
+            "Merging '{}' into {} stopped after {} file(s); the rest stays on the stack",
+            item.original_name,
+            dest_path.display(),
+            report.taken().count()
+        )));
+    }
+    hooks::after("pop", item, Some(dest_path));
+
+    status!(
+        "Merged '{}' into {}: {} new, {} identical, {} overwritten, {} renamed, {} skipped",
+        item.original_name,
+        dest_path.display(),
+        report.moved.len(),
+        report.identical.len(),
+        report.overwritten.len(),
+        report.renamed.len(),
+        report.skipped.len()
+    );
+    for path in &report.overwritten {
+        status!("  overwrote {}", path);
+    }
+    for (path, new_path) in &report.renamed {
+        status!("  renamed {} -> {}", path, new_path);
+    }
+    for path in &report.skipped {
+        status!("  skipped {}", path);
+    }
+    if !report.skipped.is_empty() {
+        status!(
+            "Skipped files stay on the stack as partial item '{}'",
+            item.original_name
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+    use clap::Parser;
+    use tempfile::{tempdir, TempDir};
+
+    /// Two items whose content was moved out of the stack but which are still in the database,
+    /// each overwriting an older file
+    fn moved_items(conn: &mut Connection) -> Result<(TempDir, Vec<PendingPop>)> {
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        schema::initialize_schema(conn)?;
+        let dir = tempdir()?;
+
+        let mut pending = Vec::new();
+        for (number, name) in [(1, "a.txt"), (2, "b.txt")] {
+            let id = ItemManager::insert(conn, name, "/p", name, "file", &[])?;
+            let item = ItemManager::get_by_id(conn, id)?.unwrap();
+            let source_path = dir.path().join(format!("stored-{}", name));
+            let dest_path = dir.path().join(name);
+            std::fs::write(&source_path, name)?;
+            std::fs::write(&dest_path, format!("old {}", name))?;
+            let replaced = Some(fs::move_aside(&dest_path)?);
+
+            let journal_id = begin_move(conn, "pop", &item, &source_path, &dest_path)?;
+            pending.push(PendingPop {
+                display_number: number,
+                item,
+                source_path,
+                dest_path,
+                journal_id,
+                replaced,
+            });
+        }
+
+        Ok((dir, pending))
+    }
+
+    #[test]
+    fn test_batch_keeps_items_whose_rows_cannot_be_dropped() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        let (dir, pending) = moved_items(&mut conn)?;
+
+        // The second item disappears before the batch is finished
+        ItemManager::delete(&mut conn, pending[1].item.id)?;
+
+        assert_eq!(finish_batch(&mut conn, pending, false, false)?, (1, 1));
+        assert_eq!(std::fs::read_to_string(dir.path().join("a.txt"))?, "a.txt");
+        assert!(dir.path().join("stored-b.txt").exists());
+        // What the failed pop was to overwrite is back; what the other one overwrote is gone
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("b.txt"))?,
+            "old b.txt"
+        );
+        assert!(!dir.path().join("a.txt.fstk-replaced").exists());
+        assert!(!dir.path().join("b.txt.fstk-replaced").exists());
+        assert!(JournalManager::pending(&conn)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_rolls_back_everything_on_error() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        let (dir, pending) = moved_items(&mut conn)?;
+        let first_id = pending[0].item.id;
+        ItemManager::delete(&mut conn, pending[1].item.id)?;
+
+        assert_eq!(finish_batch(&mut conn, pending, false, true)?, (0, 1));
+        assert!(dir.path().join("stored-a.txt").exists());
+        // The file the rolled back pop overwrote is back
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a.txt"))?,
+            "old a.txt"
+        );
+        assert!(!dir.path().join("a.txt.fstk-replaced").exists());
+        assert!(ItemManager::get_by_id(&conn, first_id)?.is_some());
+        assert!(JournalManager::pending(&conn)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_items() {
+        // IDs in display order, as after items were moved around
+        let items: Vec<StackItem> = [(7, "a.txt"), (3, "b.txt"), (12, "c.txt")]
+            .into_iter()
+            .map(|(id, name)| StackItem {
+                id,
+                original_name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let names = |batch: Vec<(usize, StackItem)>| {
+            batch
+                .into_iter()
+                .map(|(number, item)| (number, item.original_name))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(batch_items(&items, &[1, 3, 4], false, &[])),
+            [(1, "a.txt".to_string()), (3, "c.txt".to_string())]
+        );
+        // With --id, the numbers are the IDs
+        assert_eq!(
+            names(batch_items(&items, &[3, 12, 1], true, &[])),
+            [(3, "b.txt".to_string()), (12, "c.txt".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_locked_items_need_force() {
+        let item = StackItem {
+            original_name: "keepsake.txt".to_string(),
+            locked: true,
+            ..Default::default()
+        };
+
+        let err = ensure_unlocked(&item, false).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FstkError>(),
+            Some(FstkError::ItemLocked(name)) if name == "keepsake.txt"
+        ));
+        assert!(ensure_unlocked(&item, true).is_ok());
+        let unlocked = StackItem {
+            locked: false,
+            ..item
+        };
+        assert!(ensure_unlocked(&unlocked, false).is_ok());
+    }
+
+    #[test]
+    fn test_rollback_refuses_merges_and_bundles() {
+        let file = StackItem {
+            original_name: "a.txt".to_string(),
+            item_type: "file".to_string(),
+            ..Default::default()
+        };
+        let bundle = StackItem {
+            original_name: "photos".to_string(),
+            item_type: "bundle".to_string(),
+            ..Default::default()
+        };
+        let items = vec![(1, file.clone()), (2, bundle)];
+
+        assert!(check_rollback(&[(1, file.clone())], false, false).is_ok());
+        assert!(check_rollback(&[(1, file)], false, true).is_err());
+        assert!(check_rollback(&items, false, false).is_err());
+        // A renamed bundle is popped whole and can be moved back
+        assert!(check_rollback(&items, true, false).is_ok());
+
+        // The command line refuses the combination before anything is popped
+        let args = ["fstk", "pop", "1-2", "--merge", "--rollback-on-error"];
+        assert!(crate::cli::Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_parse_out_map() -> Result<()> {
+        let dir = tempdir()?;
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        std::fs::create_dir(&a)?;
+        std::fs::create_dir(&b)?;
+
+        let map = parse_out_map(&[
+            format!("1={}", a.display()),
+            format!("2-3,5={}", b.display()),
+        ])?;
+        assert_eq!(map.len(), 4);
+        assert_eq!(map[&1], a);
+        assert_eq!(map[&3], b);
+        assert_eq!(map[&5], b);
+
+        assert!(
+            parse_out_map(&[format!("1={}", a.display()), format!("1={}", b.display())]).is_err()
+        );
+        assert!(parse_out_map(&[a.display().to_string()]).is_err());
+        assert!(parse_out_map(&[format!("1={}", dir.path().join("missing").display())]).is_err());
+
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(expand_home("~/docs"), home.join("docs"));
+            assert_eq!(expand_home("~"), home);
+        }
+        assert_eq!(expand_home("~other/docs"), PathBuf::from("~other/docs"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_item_output_dir_for_pushdir() -> Result<()> {
+        let dir = tempdir()?;
+        let mut item = StackItem {
+            original_name: "notes.txt".to_string(),
+            ..Default::default()
+        };
+
+        // Items pushed before the push directory was recorded cannot go back there
+        assert!(item_output_dir(&item, dir.path(), PopDestination::Pushdir).is_err());
+
+        item.push_dir = Some(dir.path().join("work").to_string_lossy().to_string());
+        assert!(item_output_dir(&item, dir.path(), PopDestination::Pushdir).is_err());
+        std::fs::create_dir(dir.path().join("work"))?;
+        assert_eq!(
+            item_output_dir(&item, dir.path(), PopDestination::Pushdir)?,
+            dir.path().join("work")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pop_subpath_stays_inside_the_item() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+        let dir = tempdir()?;
+        let out = dir.path().join("out");
+        std::fs::create_dir(&out)?;
+        std::fs::create_dir_all(dir.path().join("stored-project/src"))?;
+        std::fs::write(
+            dir.path().join("stored-project/src/main.rs"),
+            "fn main() {}",
+        )?;
+        std::fs::write(dir.path().join("stored-project/README.md"), "readme")?;
+        std::fs::write(dir.path().join("secret.txt"), "secret")?;
+
+        let id = ItemManager::insert(
+            &mut conn,
+            "project",
+            "/p",
+            "stored-project",
+            "directory",
+            &[],
+        )?;
+        ItemManager::set_storage_location(&conn, id, Some(&dir.path().to_string_lossy()))?;
+        let item = ItemManager::get_by_id(&conn, id)?.unwrap();
+
+        let absolute = dir.path().join("secret.txt").to_string_lossy().to_string();
+        for subpath in ["../secret.txt", "src/../../secret.txt", absolute.as_str()] {
+            let err = pop_subpath(&mut conn, item.clone(), subpath, &out, None, false).unwrap_err();
+            assert!(err.to_string().contains("Invalid path"), "{}", subpath);
+        }
+        assert!(dir.path().join("secret.txt").exists());
+        assert_eq!(std::fs::read_dir(&out)?.count(), 0);
+
+        // A plain entry is taken out and the rest stays on the stack
+        pop_subpath(&mut conn, item, "src/main.rs", &out, None, false)?;
+        assert_eq!(
+            std::fs::read_to_string(out.join("main.rs"))?,
+            "fn main() {}"
+        );
+        let item = ItemManager::get_by_id(&conn, id)?.unwrap();
+        assert!(item.partial);
+        assert!(dir.path().join("stored-project/README.md").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use rusqlite::Connection;
+use std::io::IsTerminal;
+
+use crate::cli::remove;
+use crate::config::{self, Config, RetentionPolicy};
+use crate::db::{
+    establish_connection, get_project_root, item_number, ItemManager, StackItem, TagQuery,
+};
+use crate::status;
+use crate::utils::display::format_size;
+use crate::utils::duration::parse_duration;
+use crate::utils::output;
+
+/// Remove the items that have expired under the retention policies of the configuration, or
+/// the items pushed `before` a date, after listing them and asking unless `yes` is given.
+/// With `dry_run`, only list them along with the reason.
+pub fn prune(
+    policy: bool,
+    before: Option<String>,
+    tags: Vec<String>,
+    dry_run: bool,
+    yes: bool,
+) -> Result<()> {
+    if let Some(date) = before {
+        return prune_before(&date, &tags, dry_run, yes);
+    }
+    if !policy {
+        return Err(anyhow!("Choose what to prune by: --policy or --before (or --install-timer to prune periodically)"));
+    }
+
+    let config = config::load()?;
+    if config.retention.is_empty() {
+        return Err(anyhow!(
+            "No retention policies configured; add [[retention]] tables to {}",
+            config::get_config_path()?.display()
+        ));
+    }
+
+    let Some((expired, pruned)) = run_policies(&config, dry_run, !yes)? else {
+        println!("Operation cancelled.");
+        return Ok(());
+    };
+    if expired == 0 {
+        println!("Nothing to prune");
+    } else if dry_run {
+        println!("{} item(s) would be pruned", expired);
+    } else {
+        println!("Pruned {} item(s)", pruned);
+    }
+
+    Ok(())
+}
+
+/// Remove the items having all of `tags` that were pushed before the start of `date`.
+/// Unlike `rm`, the items are always listed with their total size before asking to go on.
+fn prune_before(date: &str, tags: &[String], dry_run: bool, yes: bool) -> Result<()> {
+    let cutoff = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|start| start.and_local_timezone(Local).earliest())
+        .ok_or_else(|| anyhow!("Invalid date: {} (use a date like 2023-12-31)", date))?;
+
+    let mut conn = establish_connection()?;
+    let items = ItemManager::list(&conn, &[])?;
+    let (selected, protected) = pushed_before(&items, TagQuery::all(tags)?.as_ref(), cutoff);
+
+    if protected > 0 {
+        println!("Skipping {} pinned or locked item(s)", protected);
+    }
+    if selected.is_empty() {
+        println!("Nothing to prune");
+        return Ok(());
+    }
+
+    let total: u64 = selected.iter().filter_map(|&index| items[index].size).sum();
+    println!(
+        "{} item(s) pushed before {} ({}):",
+        selected.len(),
+        date,
+        format_size(total)
+    );
+    for &index in &selected {
+        let item = &items[index];
+        println!(
+            "  #{} {} (pushed {}, {})",
+            item_number(index, item),
+            item.original_name,
+            item.pushed_at.format("%Y-%m-%d %H:%M:%S"),
+            item.size.map_or_else(|| "-".to_string(), format_size)
+        );
+    }
+    if dry_run {
+        println!("{} item(s) would be pruned", selected.len());
+        return Ok(());
+    }
+    if !yes && !confirm()? {
+        println!("Operation cancelled.");
+        return Ok(());
+    }
+
+    let mut pruned = 0;
+    for index in selected {
+        let item = &items[index];
+        match remove::remove_item(&mut conn, item) {
+            Ok(()) => pruned += 1,
+            Err(e) => status!(
+                "  #{} {} - failed: {}",
+                item_number(index, item),
+                item.original_name,
+                e
+            ),
+        }
+    }
+    println!("Pruned {} item(s)", pruned);
+
+    Ok(())
+}
+
+/// Indices of the unprotected `items` matching `query` that were pushed before `cutoff`,
+/// and how many pinned or locked items were left out
+fn pushed_before(
+    items: &[StackItem],
+    query: Option<&TagQuery>,
+    cutoff: DateTime<Local>,
+) -> (Vec<usize>, usize) {
+    let mut selected = Vec::new();
+    let mut protected = 0;
+    for (index, item) in items.iter().enumerate() {
+        if item.pushed_at >= cutoff || query.is_some_and(|query| !query.matches(&item.tags)) {
+            continue;
+        }
+        if item.pinned || item.locked {
+            protected += 1;
+        } else {
+            selected.push(index);
+        }
+    }
+    (selected, protected)
+}
+
+/// Ask whether to remove the listed items; without a terminal to ask on, fail rather than
+/// remove them unasked
+fn confirm() -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "Not pruning without confirmation, and there is no terminal to ask on; pass --yes"
+        ));
+    }
+    let input = output::prompt("Do you want to continue? [y/N]: ")?;
+    Ok(input == "y" || input == "yes")
+}
+
+/// Apply the retention policies after a push when `auto_prune` is set
+pub fn auto_prune(config: &Config) -> Result<()> {
+    if !config.auto_prune || config.retention.is_empty() {
+        return Ok(());
+    }
+
+    // Setting auto_prune is the consent to prune without asking
+    let (_, pruned) = run_policies(config, false, false)?.unwrap_or_default();
+    if pruned > 0 {
+        status!("Pruned {} expired item(s) (auto_prune)", pruned);
+    }
+
+    Ok(())
+}
+
+/// Remove (or with `dry_run` only list) expired items of the active stack, with `confirm`
+/// listing them and asking first. Returns how many items expired and how many of them were
+/// pruned, or `None` if the user declined.
+fn run_policies(config: &Config, dry_run: bool, confirm: bool) -> Result<Option<(usize, usize)>> {
+    let project_root = get_project_root();
+    let policies: Vec<&RetentionPolicy> = config
+        .retention
+        .iter()
+        .filter(|policy| policy.applies_to(project_root.as_deref()))
+        .collect();
+
+    let mut conn = establish_connection()?;
+    let items = ItemManager::list(&conn, &[])?;
+    let expired = expired_items(&items, &policies, Local::now())?;
+    let line = |index: usize, reason: &str| {
+        let item = &items[index];
+        format!(
+            "#{} {} (pushed {}): {}",
+            item_number(index, item),
+            item.original_name,
+            item.pushed_at.format("%Y-%m-%d %H:%M:%S"),
+            reason
+        )
+    };
+
+    if dry_run || (confirm && !expired.is_empty()) {
+        for (index, reason) in &expired {
+            status!("  {}", line(*index, reason));
+        }
+        if dry_run {
+            return Ok(Some((expired.len(), 0)));
+        }
+        if !self::confirm()? {
+            return Ok(None);
+        }
+    }
+
+    let pruned = remove_expired(&mut conn, &items, &expired, !confirm, &line);
+    Ok(Some((expired.len(), pruned)))
+}
+
+/// Remove the `expired` ones of `items`, reporting failures and, with `report`, each removal.
+/// Returns how many were removed.
+fn remove_expired(
+    conn: &mut Connection,
+    items: &[StackItem],
+    expired: &[(usize, String)],
+    report: bool,
+    line: &dyn Fn(usize, &str) -> String,
+) -> usize {
+    let mut pruned = 0;
+    for (index, reason) in expired {
+        match remove::remove_item(conn, &items[*index]) {
+            Ok(()) => {
+                pruned += 1;
+                if report {
+                    status!("  {}", line(*index, reason));
+                }
+            }
+            Err(e) => status!("  {} - failed: {}", line(*index, reason), e),
+        }
+    }
+    pruned
+}
+
+/// Decide which of `items` (newest first) have expired under `policies`.
+/// Returns their indices, oldest last, each with the first policy that expires it.
+fn expired_items(
+    items: &[StackItem],
+    policies: &[&RetentionPolicy],
+    now: DateTime<Local>,
+) -> Result<Vec<(usize, String)>> {
+    let mut reasons: Vec<Option<String>> = vec![None; items.len()];
+
+    for policy in policies {
+        if policy.max_age.is_none() && policy.max_items.is_none() {
+            return Err(anyhow!(
+                "Retention policy for tags [{}] sets neither max_age nor max_items",
+                policy.tags.join(", ")
+            ));
+        }
+        let cutoff = match &policy.max_age {
+            Some(age) => Some(now - parse_duration(age)?),
+            None => None,
+        };
+        let scope = if policy.tags.is_empty() {
+            String::new()
+        } else {
+            format!("tagged {}, ", policy.tags.join(", "))
+        };
+
+        let considered: Vec<(usize, &StackItem)> = items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| policy.tags.iter().all(|tag| item.tags.contains(tag)))
+            .collect();
+
+        if let (Some(cutoff), Some(age)) = (cutoff, &policy.max_age) {
+            for &(index, item) in &considered {
+                if !item.pinned
+                    && !item.locked
+                    && reasons[index].is_none()
+                    && item.pushed_at < cutoff
+                {
+                    reasons[index] = Some(format!("{}older than {}", scope, age));
+                }
+            }
+        }
+        // Only the items still left count towards the limit
+        if let Some(max_items) = policy.max_items {
+            for index in beyond_newest(considered, max_items, |index, _| reasons[index].is_some()) {
+                reasons[index] = Some(format!("{}beyond the newest {}", scope, max_items));
+            }
+        }
+    }
+
+    Ok(reasons
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, reason)| reason.map(|reason| (index, reason)))
+        .collect())
+}
+
+/// The indices of `items` (newest first) beyond the newest `max_items` that could be removed,
+/// for a limit on the number of items. Pinned and locked items, and those `exempt` picks, are
+/// neither removed nor counted.
+pub fn beyond_newest<'a, I, F>(items: I, max_items: usize, exempt: F) -> Vec<usize>
+where
+    I: IntoIterator<Item = (usize, &'a StackItem)>,
+    F: Fn(usize, &StackItem) -> bool,
+{
+    items
+        .into_iter()
+        .filter(|&(index, item)| !item.pinned && !item.locked && !exempt(index, item))
+        .skip(max_items)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn item(name: &str, age_days: i64, tags: &[&str], now: DateTime<Local>) -> StackItem {
+        StackItem {
+            original_name: name.to_string(),
+            pushed_at: now - Duration::days(age_days),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_expired_items() {
+        let now = Local::now();
+        // Newest first, as the stack lists them
+        let mut items = vec![
+            item("new-tmp", 1, &["tmp"], now),
+            item("old-tmp", 10, &["tmp"], now),
+            item("pinned-tmp", 20, &["tmp"], now),
+            item("notes", 30, &[], now),
+            item("oldest", 40, &[], now),
+        ];
+        items[2].pinned = true;
+
+        let tmp = RetentionPolicy {
+            tags: vec!["tmp".to_string()],
+            max_age: Some("7d".to_string()),
+            ..Default::default()
+        };
+        let cap = RetentionPolicy {
+            max_items: Some(4),
+            ..Default::default()
+        };
+
+        // Neither the pinned item nor the expired one takes one of the 4 places
+        assert_eq!(
+            expired_items(&items, &[&tmp, &cap], now).unwrap(),
+            vec![(1, "tagged tmp, older than 7d".to_string())]
+        );
+        let cap = RetentionPolicy {
+            max_items: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            expired_items(&items, &[&tmp, &cap], now).unwrap(),
+            vec![
+                (1, "tagged tmp, older than 7d".to_string()),
+                (4, "beyond the newest 2".to_string()),
+            ]
+        );
+
+        let empty = RetentionPolicy::default();
+        assert!(expired_items(&items, &[&empty], now).is_err());
+    }
+
+    #[test]
+    fn test_pushed_before() {
+        let now = Local::now();
+        let mut items = vec![
+            item("new", 1, &["old"], now),
+            item("tagged", 10, &["old", "work"], now),
+            item("untagged", 20, &[], now),
+            item("locked", 30, &["old"], now),
+        ];
+        items[3].locked = true;
+
+        let cutoff = now - Duration::days(5);
+        assert_eq!(pushed_before(&items, None, cutoff), (vec![1, 2], 1));
+        assert_eq!(
+            pushed_before(&items, Some(&TagQuery::parse("old").unwrap()), cutoff),
+            (vec![1], 1)
+        );
+        assert_eq!(
+            pushed_before(&items, Some(&TagQuery::parse("NOT work").unwrap()), cutoff),
+            (vec![2], 1)
+        );
+    }
+
+    #[test]
+    fn test_remove_expired_counts_only_removed_items() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        crate::db::schema::initialize_schema(&conn)?;
+        let dir = tempfile::tempdir()?;
+
+        let mut items = Vec::new();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(dir.path().join(name), name)?;
+            let id = ItemManager::insert(&mut conn, name, "/p", name, "file", &[])?;
+            ItemManager::set_storage_location(&conn, id, Some(&dir.path().to_string_lossy()))?;
+            items.push(ItemManager::get_by_id(&conn, id)?.unwrap());
+        }
+        // Another run removed the first item in the meantime
+        ItemManager::delete(&mut conn, items[0].id)?;
+
+        let expired = vec![(0, "old".to_string()), (1, "old".to_string())];
+        let line = |index: usize, _: &str| items[index].original_name.clone();
+        assert_eq!(remove_expired(&mut conn, &items, &expired, false, &line), 1);
+        assert!(!dir.path().join("b.txt").exists());
+
+        Ok(())
+    }
+}
@@ -1,11 +1,131 @@
 use anyhow::{anyhow, Result};
-use std::path::PathBuf;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use crate::db::{establish_connection, get_data_dir, ItemManager};
+use crate::cli::{prune, remove};
+use crate::config::{self, DuplicatePathPolicy, MaxItemsPolicy};
+use crate::db::{
+    establish_connection, get_data_dir, get_item_stored_path, BundleMember, ItemManager,
+    ItemMetadata, JournalManager, ManifestManager, OperationLog, Provenance, StackItem,
+    TagUsageManager,
+};
 use crate::fs;
+use crate::hooks;
+use crate::status;
+use crate::utils::ssh::RemotePath;
+use crate::utils::template::{self, PushVariables};
+use crate::utils::{display, git, output};
+
+/// Most tags `push --interactive` offers
+const SUGGESTED_TAGS: usize = 5;
+
+/// Options controlling how an item is pushed
+#[derive(Debug, Clone, Default)]
+pub struct PushOptions {
+    /// Tags to associate with the pushed item
+    pub tags: Option<Vec<String>>,
+    /// Leave the path untouched if identical content is already on the stack
+    pub skip_duplicates: bool,
+    /// Store a clone of the existing blob when identical content is already on the stack, or of
+    /// already stored copies of the files inside a directory (see `fs::clone_like`)
+    pub link_duplicates: bool,
+    /// Add the git repository name and branch of the pushed path as tags
+    pub git_tags: bool,
+    /// Ask for confirmation before pushing more than this many bytes
+    pub confirm_above: Option<u64>,
+    /// Copy the path onto the stack and leave the original in place
+    pub keep: bool,
+    /// Remote path the pushed content was fetched from (see `push_remote`)
+    pub remote_origin: Option<RemotePath>,
+    /// Note to keep with the item
+    pub note: Option<String>,
+    /// What to do if an item from the same path is already on the stack
+    pub duplicate_paths: DuplicatePathPolicy,
+    /// Most items the stack should hold
+    pub max_items: Option<usize>,
+    /// What to do when the stack already holds `max_items` items
+    pub max_items_policy: MaxItemsPolicy,
+    /// Do not print the summary after pushing a directory
+    pub quiet: bool,
+    /// Store a directory as a single tar archive instead of a file tree
+    pub as_archive: bool,
+    /// Offer tags learned from earlier pushes, accepted with a single keypress
+    pub interactive: bool,
+}
+
+/// Expand the placeholders (`{date}`, `{git_branch}`, ...) in the tags and note given on push;
+/// `{git_branch}` is the branch of the repository containing `dir`.
+fn render_tags_and_note(
+    tags: Option<Vec<String>>,
+    note: Option<String>,
+    dir: &Path,
+) -> Result<(Vec<String>, Option<String>)> {
+    let vars = PushVariables {
+        cwd: std::env::current_dir()?.to_string_lossy().to_string(),
+        git_branch: git::discover(dir).and_then(|context| context.branch),
+    };
+
+    let tags = tags
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| template::render_push_text(tag, false, &vars))
+        .collect::<Result<_>>()?;
+    let note = note
+        .map(|note| template::render_push_text(&note, true, &vars))
+        .transpose()?;
+    Ok((tags, note))
+}
+
+/// Ask before pushing more data than `threshold`. Fails if the user declines, or if there is no
+/// terminal to ask on, so that scripts notice the push did not happen.
+fn confirm_large_push(paths: &[&Path], threshold: Option<u64>) -> Result<()> {
+    let Some(threshold) = threshold else {
+        return Ok(());
+    };
+
+    let (mut size, mut files) = (0, 0);
+    for path in paths {
+        let (path_size, path_files) = fs::path_usage(path)?;
+        size += path_size;
+        files += path_files;
+    }
+    if size <= threshold {
+        return Ok(());
+    }
+
+    let amount = format!(
+        "{} / {} files",
+        display::format_size(size),
+        display::format_count(files)
+    );
+    let hint = format!(
+        "pass --yes or raise confirm_push_size in {}",
+        config::get_config_path()?.display()
+    );
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "Not pushing {} without confirmation, and there is no terminal to ask on; {}",
+            amount,
+            hint
+        ));
+    }
+
+    status!("About to push {}", amount);
+    let input = output::prompt("Do you want to continue? [y/N]: ")?;
+    if input != "y" && input != "yes" {
+        return Err(anyhow!("Push cancelled; {} to push without asking", hint));
+    }
+
+    Ok(())
+}
 
 /// Push a file or directory to the stack.
-pub fn push(path_str: &str, tags: Option<Vec<String>>) -> Result<i64> {
+/// Returns the new item's ID, or `None` if the push was skipped.
+pub fn push(path_str: &str, options: PushOptions) -> Result<Option<i64>> {
+    let started = Instant::now();
     let path = PathBuf::from(path_str);
 
     if !fs::is_path_accessible(&path)? {
@@ -13,26 +133,732 @@ pub fn push(path_str: &str, tags: Option<Vec<String>>) -> Result<i64> {
     }
 
     let abs_path = fs::get_absolute_path(&path)?;
-    let name = fs::get_file_name(&abs_path)?;
+    let push_dir = std::env::current_dir()?.to_string_lossy().to_string();
+    confirm_large_push(&[&abs_path], options.confirm_above)?;
+
+    let name = fs::normalize_name(&fs::get_file_name(&abs_path)?);
     let parent = match abs_path.parent() {
-        Some(p) => p.to_string_lossy().to_string(),
+        Some(p) => fs::normalize_name(&p.to_string_lossy()),
         None => String::from("/"),
     };
+    let mut version_group = fs::normalize_name(&abs_path.to_string_lossy());
+
+    // Fetched content is restored to where it was pushed from, and earlier fetches of the same
+    // remote path are its older versions
+    let (parent, remote_origin) = match &options.remote_origin {
+        Some(remote) => {
+            version_group = remote.to_string();
+            (push_dir.clone(), Some(remote.to_string()))
+        }
+        None => (parent, None),
+    };
 
     let is_dir = abs_path.is_dir();
     let item_type = if is_dir { "directory" } else { "file" };
+    if options.as_archive && !is_dir {
+        return Err(anyhow!(
+            "--as-archive only applies to directories: {}",
+            abs_path.display()
+        ));
+    }
     let hash = fs::generate_hash(&abs_path, is_dir)?;
+    let size = fs::path_size(&abs_path)?;
+    let owner = fs::get_owner(&abs_path)?;
+
+    // Directories get a per-file manifest, which also yields their content hash. A file is
+    // hashed while it is stored, unless duplicates have to be found before touching it.
+    let (mut content_hash, manifest) = if is_dir {
+        let manifest = fs::build_manifest(&abs_path)?;
+        (Some(fs::manifest_hash(&manifest)), manifest)
+    } else if options.skip_duplicates || options.link_duplicates || options.keep {
+        (Some(fs::hash_file(&abs_path)?), Vec::new())
+    } else {
+        (None, Vec::new())
+    };
+
+    let mut conn = establish_connection()?;
+
+    let earlier_versions = ItemManager::list_versions(&conn, &version_group)?.len();
+    if earlier_versions > 0 {
+        match options.duplicate_paths {
+            DuplicatePathPolicy::Warn => status!(
+                "Warning: {} is already on the stack; popping both into one directory will \
+                 conflict (add --note to tell them apart)",
+                version_group
+            ),
+            DuplicatePathPolicy::Block => {
+                return Err(anyhow!(
+                    "{} is already on the stack (duplicate_paths = \"block\" in the config)",
+                    version_group
+                ))
+            }
+            DuplicatePathPolicy::Allow => {}
+        }
+    }
+    check_stack_room(&conn, options.max_items, options.max_items_policy)?;
+
+    let mut linked_blob = None;
+    let duplicate = match &content_hash {
+        Some(content_hash) => report_duplicate(&conn, content_hash)?,
+        None => None,
+    };
+    if let Some(existing) = &duplicate {
+        if options.skip_duplicates {
+            status!("Skipped {}", abs_path.display());
+            return Ok(None);
+        }
+
+        // The files of a directory are shared one by one once it is stored
+        if options.link_duplicates && !is_dir {
+            linked_blob = Some(get_item_stored_path(existing)?);
+        }
+    }
+
+    let (mut tags_vec, note) =
+        render_tags_and_note(options.tags, options.note, Path::new(&parent))?;
+    if options.git_tags {
+        if let Some(context) = git::discover(Path::new(&parent)) {
+            for tag in context.tags() {
+                if !tags_vec.contains(&tag) {
+                    tags_vec.push(tag);
+                }
+            }
+        }
+    }
+
+    let usage_contexts = TagUsageManager::contexts(&parent, &name, is_dir);
+    if options.interactive {
+        tags_vec = offer_tags(&conn, &name, &usage_contexts, tags_vec)?;
+    }
+
+    // The hook script may reject the push or change its tags and note
+    let candidate = hooks::before(
+        "push",
+        &StackItem {
+            original_name: name.clone(),
+            original_path: parent.clone(),
+            item_type: item_type.to_string(),
+            size: Some(size),
+            tags: tags_vec,
+            note,
+            ..Default::default()
+        },
+        None,
+    )?;
+    let tags_vec = candidate.tags;
 
     let data_dir = get_data_dir()?;
     let target_path = data_dir.join(&hash);
+    let staged_path = fs::staging_path(&target_path);
+
+    // Copies, and moves from another filesystem, need room in storage; a shared blob only does
+    // if it cannot be cloned, which is checked when it is staged
+    if linked_blob.is_none() {
+        fs::ensure_space(
+            &data_dir,
+            fs::space_needed(
+                &abs_path,
+                &data_dir,
+                size,
+                options.keep || options.as_archive,
+            ),
+        )?;
+    }
+
+    // Record the intent first so an interrupted push can be recovered on the next run
+    let journal_id = JournalManager::begin(
+        &conn,
+        if options.keep && !options.as_archive {
+            "copy"
+        } else if linked_blob.is_some() {
+            "link"
+        } else if options.as_archive {
+            "pack"
+        } else {
+            "push"
+        },
+        &hash,
+        content_hash.as_deref(),
+        &abs_path.to_string_lossy(),
+        &staged_path.to_string_lossy(),
+    )?;
+
+    // Pick up what an interrupted push of the same directory to another filesystem copied
+    let partial = fs::partial_path(&data_dir, &abs_path);
+    let resumable = is_dir && linked_blob.is_none() && !options.keep && !options.as_archive;
+    if resumable && fs::can_resume(&abs_path, &partial) {
+        fs::move_partial(&partial, &staged_path)?;
+        status!("Resuming the interrupted push of {}", abs_path.display());
+    }
+
+    // Phase 1: stage the content inside the data directory
+    let mut stored_size = size;
+    match &linked_blob {
+        // Share the existing blob's blocks instead of storing the content twice. The clone is a
+        // file of its own, so popping and editing either item never changes the other; where
+        // the filesystem cannot clone, the content is stored again.
+        Some(existing_blob) => {
+            if !fs::clone_like(existing_blob, &abs_path, &staged_path)? {
+                fs::ensure_space(
+                    &data_dir,
+                    fs::space_needed(&abs_path, &data_dir, size, true),
+                )?;
+                fs::copy_file(&abs_path, &staged_path)?;
+            }
+        }
+        // The archive is checked against its own hash from now on; the original stays in place
+        // until the item is committed
+        None if options.as_archive => {
+            if let Err(e) = fs::pack_dir(&abs_path, &staged_path) {
+                let _ = fs::remove_item(&staged_path);
+                JournalManager::complete(&conn, journal_id)?;
+                return Err(e);
+            }
+            content_hash = Some(fs::hash_file(&staged_path)?);
+            stored_size = fs::path_size(&staged_path)?;
+        }
+        None if options.keep => fs::copy_item(&abs_path, &staged_path)?,
+        None if content_hash.is_some() => fs::move_or_copy(&abs_path, &staged_path)?,
+        None => {
+            // A partial copy must not be mistaken for a finished one by recovery, so the hash
+            // is journaled before the original is removed
+            let hash = fs::move_file_hashed(&abs_path, &staged_path, |hash| {
+                JournalManager::set_content_hash(&conn, journal_id, hash)
+            })?;
+            report_duplicate(&conn, &hash)?;
+            content_hash = Some(hash);
+        }
+    }
+    let linked = linked_blob.is_some();
+    // Staged content that is a link, a copy or an archive is dropped on rollback; the original
+    // never moved
+    let original_kept = linked || options.keep || options.as_archive;
+
+    // Phase 2: record the item; undo the staging if that fails
+    let file_count = if is_dir { manifest.len() as u64 } else { 1 };
+    let metadata = ItemMetadata {
+        content_hash,
+        size: Some(stored_size),
+        file_count: Some(file_count),
+        push_millis: Some(started.elapsed().as_millis() as u64),
+        manifest,
+        owner,
+        version_group: Some(version_group.clone()),
+        provenance: Some(Provenance::current(Some(push_dir.clone()))),
+        push_dir: Some(push_dir),
+        copied: options.keep,
+        note: candidate.note,
+        remote_origin,
+        packed: options.as_archive,
+        ..Default::default()
+    };
+    let item_id = match ItemManager::insert_with_metadata(
+        &mut conn, &name, &parent, &hash, item_type, &tags_vec, &metadata,
+    ) {
+        Ok(id) => id,
+        Err(e) => {
+            if rollback(&staged_path, &abs_path, original_kept) {
+                JournalManager::complete(&conn, journal_id)?;
+            }
+            return Err(e);
+        }
+    };
+
+    // Phase 3: commit by moving the staged content to its final name
+    if let Err(e) = std::fs::rename(&staged_path, &target_path) {
+        let _ = ItemManager::delete(&mut conn, item_id);
+        if rollback(&staged_path, &abs_path, original_kept) {
+            JournalManager::complete(&conn, journal_id)?;
+        }
+        return Err(anyhow!("Failed to store '{}': {}", abs_path.display(), e));
+    }
+
+    // A linked or archived push leaves the original in place until the item is committed
+    if linked && !options.keep {
+        std::fs::remove_file(&abs_path)?;
+    }
+    if options.as_archive && !options.keep {
+        fs::remove_item(&abs_path)?;
+    }
+
+    JournalManager::complete(&conn, journal_id)?;
+    TagUsageManager::record(&conn, &tags_vec, &usage_contexts)?;
+    if is_dir && options.link_duplicates {
+        let (files, bytes) = share_stored_duplicates(&conn, item_id, &target_path)?;
+        if files > 0 {
+            status!(
+                "Shared the blocks of {} duplicate {} with stored copies, saving {}",
+                display::format_count(files),
+                if files == 1 { "file" } else { "files" },
+                display::format_size(bytes)
+            );
+        }
+    }
+    if let Some(item) = ItemManager::get_by_id(&conn, item_id)? {
+        OperationLog::record(&conn, "push", &item)?;
+        hooks::after("push", &item, None);
+    }
+    if is_dir && !options.quiet {
+        report_summary(&name, file_count, size, started, &hash);
+    }
+    rotate_stack(
+        &mut conn,
+        options.max_items,
+        options.max_items_policy,
+        item_id,
+    )?;
+
+    // Earlier pushes of the same path become older versions of this item
+    let versions = ItemManager::list_versions(&conn, &version_group)?.len();
+    if versions > 1 {
+        status!(
+            "Stored as version {} of {} (see 'fstk list --versions')",
+            versions,
+            version_group
+        );
+    }
+
+    Ok(Some(item_id))
+}
+
+/// Fetch `remote` with scp and push the copy, recording where it came from.
+/// The remote file or directory is left in place.
+pub fn push_remote(remote: &RemotePath, options: PushOptions) -> Result<Option<i64>> {
+    let name = remote
+        .name()
+        .ok_or_else(|| anyhow!("Cannot tell the name of the remote path {}", remote))?;
+
+    // Fetch into the data directory so that storing the copy is a rename
+    let fetch_dir = get_data_dir()?.join(format!(".fetch-{}", std::process::id()));
+    std::fs::create_dir_all(&fetch_dir)?;
+    let fetched = fetch_dir.join(name);
+    let result = remote.fetch(&fetched).and_then(|()| {
+        push(
+            &fetched.to_string_lossy(),
+            PushOptions {
+                remote_origin: Some(remote.clone()),
+                ..options
+            },
+        )
+    });
+    fs::remove_item(&fetch_dir)?;
+
+    result
+}
+
+/// Offer the tags that earlier pushes of similar items got (same extension, same directory,
+/// or just often and lately), and add the ones picked with a single keypress
+fn offer_tags(
+    conn: &Connection,
+    name: &str,
+    contexts: &[String],
+    mut tags: Vec<String>,
+) -> Result<Vec<String>> {
+    let suggestions = TagUsageManager::suggest(conn, contexts, &tags, SUGGESTED_TAGS)?;
+    if suggestions.is_empty() {
+        return Ok(tags);
+    }
+
+    let choices: Vec<String> = suggestions
+        .iter()
+        .enumerate()
+        .map(|(index, tag)| format!("{}) {}", index + 1, tag))
+        .collect();
+    status!("Suggested tags for '{}': {}", name, choices.join("  "));
+    let key = output::read_key(&format!(
+        "Press 1-{} to add one, a for all, any other key for none: ",
+        suggestions.len()
+    ))?;
+
+    let picked = match key {
+        Some('a') => suggestions,
+        Some(key) => key
+            .to_digit(10)
+            .and_then(|digit| suggestions.get((digit as usize).checked_sub(1)?))
+            .cloned()
+            .into_iter()
+            .collect(),
+        None => Vec::new(),
+    };
+    tags.extend(picked);
+    Ok(tags)
+}
+
+/// Report an item that already holds identical content, returning the most recent one.
+fn report_duplicate(conn: &Connection, content_hash: &str) -> Result<Option<StackItem>> {
+    let existing = ItemManager::find_by_content_hash(conn, content_hash)?
+        .into_iter()
+        .next();
+    if let Some(existing) = &existing {
+        status!(
+            "Identical content is already on the stack: '{}' (id {}, pushed {})",
+            existing.original_name,
+            existing.id,
+            existing.pushed_at.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+
+    Ok(existing)
+}
+
+/// Replace the files of the stored directory `root` (item `item_id`) that are identical to
+/// files already on the stack, or to earlier files in the same directory, with clones sharing
+/// their blocks (see `fs::clone_identical`). Every file stays a file of its own, so popping and
+/// editing one never changes another. Sharing is best effort: on filesystems that cannot
+/// clone, or across filesystems, files keep their own copy. Returns the number of files
+/// shared and the bytes saved.
+fn share_stored_duplicates(conn: &Connection, item_id: i64, root: &Path) -> Result<(u64, u64)> {
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+    let (mut files, mut bytes) = (0, 0);
+
+    for entry in ManifestManager::get_for_item(conn, item_id)? {
+        // Empty files take no space to begin with
+        if entry.size == 0 {
+            continue;
+        }
+        let path = root.join(&entry.relative_path);
+        let existing = match seen.get(&entry.hash) {
+            Some(existing) => Some(existing.clone()),
+            None => find_stored_file(conn, &entry.hash, entry.size, item_id)?,
+        };
+
+        match existing {
+            Some(existing) if fs::clone_identical(&existing, &path).unwrap_or(false) => {
+                files += 1;
+                bytes += entry.size;
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(entry.hash, path);
+            }
+        }
+    }
 
-    fs::move_or_copy(&abs_path, &target_path)?;
+    Ok((files, bytes))
+}
+
+/// A stored file with the given content in an item other than `exclude_item`: a file item or a
+/// file inside a stored directory
+fn find_stored_file(
+    conn: &Connection,
+    hash: &str,
+    size: u64,
+    exclude_item: i64,
+) -> Result<Option<PathBuf>> {
+    for item in ItemManager::find_by_content_hash(conn, hash)? {
+        if item.id != exclude_item && item.item_type == "file" && item.size == Some(size) {
+            return Ok(Some(get_item_stored_path(&item)?));
+        }
+    }
+
+    for (id, relative_path) in ManifestManager::find_by_hash(conn, hash, size, exclude_item)? {
+        let Some(item) = ItemManager::get_by_id(conn, id)? else {
+            continue;
+        };
+        if item.is_stored_as_directory() {
+            let path = get_item_stored_path(&item)?.join(relative_path);
+            if path.is_file() {
+                return Ok(Some(path));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Push several paths as a single bundle item named `name`.
+/// Members keep their names inside the bundle, so they must be distinct.
+pub fn push_bundle(paths: &[String], name: &str, options: PushOptions) -> Result<Option<i64>> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(anyhow!("Invalid bundle name: '{}'", name));
+    }
 
+    // Resolve every member before moving anything
+    let mut members: Vec<(PathBuf, BundleMember)> = Vec::new();
+    for path_str in paths {
+        let path = PathBuf::from(path_str);
+        fs::is_path_accessible(&path)?;
+
+        let abs_path = fs::get_absolute_path(&path)?;
+        let member_name = fs::normalize_name(&fs::get_file_name(&abs_path)?);
+        if members
+            .iter()
+            .any(|(_, member)| fs::same_name(&member.name, &member_name))
+        {
+            return Err(anyhow!(
+                "Bundle members must have distinct names; '{}' was given twice",
+                member_name
+            ));
+        }
+
+        let parent = match abs_path.parent() {
+            Some(p) => fs::normalize_name(&p.to_string_lossy()),
+            None => String::from("/"),
+        };
+        members.push((
+            abs_path,
+            BundleMember {
+                name: member_name,
+                original_path: parent,
+            },
+        ));
+    }
+
+    let started = Instant::now();
+    let member_paths: Vec<&Path> = members.iter().map(|(path, _)| path.as_path()).collect();
+    confirm_large_push(&member_paths, options.confirm_above)?;
+
+    let cwd = std::env::current_dir()?;
+    let hash = fs::generate_hash(&cwd.join(name), true)?;
     let mut conn = establish_connection()?;
-    let tags_vec = tags.unwrap_or_default();
-    let item_id = ItemManager::insert(&mut conn, &name, &parent, &hash, item_type, &tags_vec)?;
+    check_stack_room(&conn, options.max_items, options.max_items_policy)?;
+
+    let (mut tags_vec, note) = render_tags_and_note(options.tags, options.note, &cwd)?;
+    if options.git_tags {
+        if let Some(context) = git::discover(&cwd) {
+            for tag in context.tags() {
+                if !tags_vec.contains(&tag) {
+                    tags_vec.push(tag);
+                }
+            }
+        }
+    }
+
+    // The hook script may reject the push or change its tags and note
+    let mut size = 0;
+    for path in &member_paths {
+        size += fs::path_size(path)?;
+    }
+    let candidate = hooks::before(
+        "push",
+        &StackItem {
+            original_name: name.to_string(),
+            original_path: cwd.to_string_lossy().to_string(),
+            item_type: "bundle".to_string(),
+            size: Some(size),
+            tags: tags_vec,
+            note,
+            ..Default::default()
+        },
+        None,
+    )?;
+    let tags_vec = candidate.tags;
 
-    Ok(item_id)
+    let target_path = get_data_dir()?.join(&hash);
+    let staged_path = fs::staging_path(&target_path);
+    std::fs::create_dir(&staged_path)?;
+
+    // Phase 1: stage every member, journaled one by one so an interruption can be undone
+    let mut journal_ids = Vec::new();
+    for (abs_path, member) in &members {
+        let journal_id = JournalManager::begin(
+            &conn,
+            "bundle",
+            &hash,
+            Some(&fs::content_hash(abs_path)?),
+            &abs_path.to_string_lossy(),
+            &staged_path.to_string_lossy(),
+        )?;
+        journal_ids.push(journal_id);
+
+        if let Err(e) = fs::move_or_copy(abs_path, staged_path.join(&member.name)) {
+            rollback_bundle(&conn, &staged_path, &members, &journal_ids);
+            return Err(e);
+        }
+    }
+
+    // Phase 2: record the item; undo the staging if that fails
+    let manifest = fs::build_manifest(&staged_path)?;
+    let file_count = manifest.len() as u64;
+    let size = fs::path_size(&staged_path)?;
+    let metadata = ItemMetadata {
+        content_hash: Some(fs::manifest_hash(&manifest)),
+        size: Some(size),
+        file_count: Some(file_count),
+        push_millis: Some(started.elapsed().as_millis() as u64),
+        manifest,
+        members: members.iter().map(|(_, member)| member.clone()).collect(),
+        push_dir: Some(cwd.to_string_lossy().to_string()),
+        provenance: Some(Provenance::current(Some(cwd.to_string_lossy().to_string()))),
+        note: candidate.note,
+        ..Default::default()
+    };
+    let item_id = match ItemManager::insert_with_metadata(
+        &mut conn,
+        name,
+        &cwd.to_string_lossy(),
+        &hash,
+        "bundle",
+        &tags_vec,
+        &metadata,
+    ) {
+        Ok(id) => id,
+        Err(e) => {
+            rollback_bundle(&conn, &staged_path, &members, &journal_ids);
+            return Err(e);
+        }
+    };
+
+    // Phase 3: commit by moving the staged bundle to its final name
+    if let Err(e) = std::fs::rename(&staged_path, &target_path) {
+        let _ = ItemManager::delete(&mut conn, item_id);
+        rollback_bundle(&conn, &staged_path, &members, &journal_ids);
+        return Err(anyhow!("Failed to store bundle '{}': {}", name, e));
+    }
+
+    for journal_id in journal_ids {
+        JournalManager::complete(&conn, journal_id)?;
+    }
+    if let Some(item) = ItemManager::get_by_id(&conn, item_id)? {
+        OperationLog::record(&conn, "push", &item)?;
+        hooks::after("push", &item, None);
+    }
+
+    status!("Bundled {} path(s) as '{}'", members.len(), name);
+    if !options.quiet {
+        report_summary(name, file_count, size, started, &hash);
+    }
+    rotate_stack(
+        &mut conn,
+        options.max_items,
+        options.max_items_policy,
+        item_id,
+    )?;
+
+    Ok(Some(item_id))
+}
+
+/// Refuse the push, or warn about it, when the stack already holds `max_items` items
+fn check_stack_room(
+    conn: &Connection,
+    max_items: Option<usize>,
+    policy: MaxItemsPolicy,
+) -> Result<()> {
+    let Some(max_items) = max_items else {
+        return Ok(());
+    };
+    // The stack is full if the next push would put an item beyond the limit
+    let items = ItemManager::list(conn, &[])?;
+    let displaced = prune::beyond_newest(
+        items.iter().enumerate(),
+        max_items.saturating_sub(1),
+        |_, _| false,
+    );
+    if displaced.is_empty() {
+        return Ok(());
+    }
+
+    match policy {
+        MaxItemsPolicy::Reject => Err(anyhow!(
+            "The stack is full (max_items = {} in the config, not counting pinned or locked \
+             items); pop or remove items first",
+            max_items
+        )),
+        MaxItemsPolicy::Warn => {
+            status!(
+                "Warning: the stack already holds {} items besides pinned or locked ones \
+                 (max_items = {})",
+                max_items.saturating_sub(1) + displaced.len(),
+                max_items
+            );
+            Ok(())
+        }
+        MaxItemsPolicy::Rotate => Ok(()),
+    }
+}
+
+/// Under the rotate policy, remove the oldest items beyond `max_items` after pushing `pushed_id`
+fn rotate_stack(
+    conn: &mut Connection,
+    max_items: Option<usize>,
+    policy: MaxItemsPolicy,
+    pushed_id: i64,
+) -> Result<()> {
+    let (Some(max_items), MaxItemsPolicy::Rotate) = (max_items, policy) else {
+        return Ok(());
+    };
+
+    let items = ItemManager::list(conn, &[])?;
+    for item in rotated_items(&items, max_items, pushed_id) {
+        remove::remove_item(conn, item)?;
+        status!(
+            "Removed '{}' (id {}) to stay within max_items = {}",
+            item.original_name,
+            item.id,
+            max_items
+        );
+    }
+
+    Ok(())
+}
+
+/// The items (newest first) to remove so that at most `max_items` remain, oldest first, by the
+/// rule retention policies follow (see `prune::beyond_newest`). Pinned and locked items do not
+/// count, and the item just pushed always takes one of the places.
+fn rotated_items(items: &[StackItem], max_items: usize, pushed_id: i64) -> Vec<&StackItem> {
+    let mut rotated: Vec<&StackItem> = prune::beyond_newest(
+        items.iter().enumerate(),
+        max_items.saturating_sub(1),
+        |_, item| item.id == pushed_id,
+    )
+    .into_iter()
+    .map(|index| &items[index])
+    .collect();
+    rotated.reverse();
+    rotated
+}
+
+/// Print the one-line summary of a directory or bundle push
+fn report_summary(name: &str, files: u64, size: u64, started: Instant, hash: &str) {
+    status!(
+        "Pushed '{}': {} {}, {} in {} (stored as {})",
+        name,
+        display::format_count(files),
+        if files == 1 { "file" } else { "files" },
+        display::format_size(size),
+        display::format_elapsed(started.elapsed()),
+        hash
+    );
+}
+
+/// Move the staged members of a bundle back to where they came from.
+fn rollback_bundle(
+    conn: &rusqlite::Connection,
+    staged_path: &Path,
+    members: &[(PathBuf, BundleMember)],
+    journal_ids: &[i64],
+) {
+    let mut complete = true;
+    for (abs_path, member) in members {
+        let staged_member = staged_path.join(&member.name);
+        if staged_member.exists() && !rollback(&staged_member, abs_path, false) {
+            complete = false;
+        }
+    }
+
+    // Leave the journal in place for the next run if anything is still staged
+    if complete {
+        let _ = std::fs::remove_dir(staged_path);
+        for journal_id in journal_ids {
+            let _ = JournalManager::complete(conn, *journal_id);
+        }
+    }
+}
+
+/// Return staged content to its original location, reporting where it is left if that fails.
+/// Returns whether the rollback succeeded.
+fn rollback(staged_path: &Path, original_path: &Path, original_kept: bool) -> bool {
+    match fs::unstage(staged_path, original_path, original_kept) {
+        Ok(()) => true,
+        Err(e) => {
+            status!(
+                "Failed to roll back push; content remains at {}: {}",
+                staged_path.display(),
+                e
+            );
+            false
+        }
+    }
 }
 
 #[cfg(test)]
@@ -81,8 +907,11 @@ mod tests {
         // For example, by using a testing framework or dependency injection
 
         // Simplified test structure
-        let tags = Some(vec!["tag1".to_string(), "tag2".to_string()]);
-        let _item_id = push(file_path.to_str().unwrap(), tags)?;
+        let options = PushOptions {
+            tags: Some(vec!["tag1".to_string(), "tag2".to_string()]),
+            ..Default::default()
+        };
+        let _item_id = push(file_path.to_str().unwrap(), options)?;
 
         // In a real test we would verify:
         // 1. The file was moved/copied to the target location
@@ -91,4 +920,36 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_rotated_items() {
+        let item = |id: i64, pinned: bool, locked: bool| StackItem {
+            id,
+            pinned,
+            locked,
+            ..Default::default()
+        };
+        // Newest first: 5 was just pushed, 2 is pinned and 1 locked
+        let items = vec![
+            item(5, false, false),
+            item(4, false, false),
+            item(3, false, false),
+            item(2, true, false),
+            item(1, false, true),
+        ];
+        let ids = |max_items| -> Vec<i64> {
+            rotated_items(&items, max_items, 5)
+                .iter()
+                .map(|item| item.id)
+                .collect()
+        };
+
+        // The protected items do not count
+        assert_eq!(ids(5), Vec::<i64>::new());
+        assert_eq!(ids(3), Vec::<i64>::new());
+        assert_eq!(ids(2), vec![3]);
+        // Only the protected items and the new one are left
+        assert_eq!(ids(1), vec![3, 4]);
+        assert_eq!(ids(0), vec![3, 4]);
+    }
 }
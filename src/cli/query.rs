@@ -0,0 +1,120 @@
+use anyhow::Result;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+
+use crate::cli::QueryFormat;
+use crate::db::establish_connection;
+use crate::utils::display;
+
+/// Result of a query: column names and one row of values per result row
+type QueryResult = (Vec<String>, Vec<Vec<Value>>);
+
+/// Run a single read-only SQL statement against the metadata database and print its result.
+/// The connection is switched to `query_only`, so statements that write are rejected by SQLite.
+pub fn query(sql: String, format: QueryFormat) -> Result<()> {
+    let conn = establish_connection()?;
+    conn.pragma_update(None, "query_only", true)?;
+
+    let (columns, rows) = run_query(&conn, &sql)?;
+
+    match format {
+        QueryFormat::Table => {
+            if columns.is_empty() {
+                return Ok(());
+            }
+            let cells: Vec<Vec<String>> = rows
+                .iter()
+                .map(|row| row.iter().map(cell_text).collect())
+                .collect();
+            display::display_query_table(&columns, &cells);
+            println!("{} row(s)", rows.len());
+        }
+        QueryFormat::Json => {
+            let records: Vec<Value> = rows
+                .into_iter()
+                .map(|row| Value::Object(columns.iter().cloned().zip(row).collect::<Map<_, _>>()))
+                .collect();
+            let mut stdout = io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &records)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn run_query(conn: &Connection, sql: &str) -> Result<QueryResult> {
+    let mut stmt = conn.prepare(sql)?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+    let mut rows = Vec::new();
+    let mut result = stmt.query([])?;
+    while let Some(row) = result.next()? {
+        let mut values = Vec::with_capacity(columns.len());
+        for index in 0..columns.len() {
+            values.push(to_json(row.get_ref(index)?));
+        }
+        rows.push(values);
+    }
+
+    Ok((columns, rows))
+}
+
+/// Convert an SQLite value to JSON; blobs become lowercase hex strings
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(number) => Value::from(number),
+        ValueRef::Real(number) => Value::from(number),
+        ValueRef::Text(text) => Value::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(bytes) => {
+            Value::String(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+        }
+    }
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+
+    #[test]
+    fn test_read_only_query() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+        conn.execute(
+            "INSERT INTO stack_items (original_name, original_path, stored_hash, type, pushed_at)
+             VALUES ('notes.md', '/home/user', 'hash1', 'file', '2024-01-01 10:00:00')",
+            [],
+        )?;
+        conn.pragma_update(None, "query_only", true)?;
+
+        let (columns, rows) = run_query(
+            &conn,
+            "SELECT original_name AS name, size_bytes, 1.5 AS ratio FROM stack_items",
+        )?;
+        assert_eq!(columns, vec!["name", "size_bytes", "ratio"]);
+        assert_eq!(
+            rows,
+            vec![vec![Value::from("notes.md"), Value::Null, Value::from(1.5)]]
+        );
+
+        assert!(run_query(&conn, "DELETE FROM stack_items").is_err());
+        assert_eq!(
+            run_query(&conn, "SELECT COUNT(*) FROM stack_items")?.1[0][0],
+            1
+        );
+
+        Ok(())
+    }
+}
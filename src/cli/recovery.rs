@@ -0,0 +1,599 @@
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{daemon, pop};
+use crate::db::{establish_connection, ItemManager, JournalEntry, JournalManager, OperationLog};
+use crate::fs;
+use crate::status;
+
+/// Finish or roll back file operations that an earlier run left incomplete,
+/// e.g. because it crashed or was killed while moving an item.
+pub fn recover_incomplete_operations() -> Result<()> {
+    // A running daemon has the database open already
+    let messages = match daemon::recover() {
+        Some(messages) => messages,
+        None => recover(&mut establish_connection()?)?,
+    };
+    for message in messages {
+        status!("{}", message);
+    }
+
+    Ok(())
+}
+
+/// Recover the interrupted operations journaled in `conn`, returning what was done
+pub fn recover(conn: &mut Connection) -> Result<Vec<String>> {
+    let mut messages = Vec::new();
+
+    for entry in JournalManager::pending(conn)? {
+        // Another fstk process may still be working on this operation
+        if entry.pid != std::process::id() && is_process_running(entry.pid) {
+            continue;
+        }
+
+        match recover_entry(conn, &entry) {
+            Ok(Some(message)) => messages.push(message),
+            Ok(None) => {}
+            Err(e) => {
+                // Keep the record so the next run can try again
+                messages.push(format!(
+                    "Could not recover interrupted {} of {}: {}",
+                    entry.operation, entry.source, e
+                ));
+                continue;
+            }
+        }
+
+        JournalManager::complete(conn, entry.id)?;
+    }
+
+    Ok(messages)
+}
+
+/// Bring the files and database of one interrupted operation into a consistent state.
+/// Returns a message describing what was done, if anything.
+fn recover_entry(conn: &mut Connection, entry: &JournalEntry) -> Result<Option<String>> {
+    let source = PathBuf::from(&entry.source);
+    let destination = PathBuf::from(&entry.destination);
+    let item = ItemManager::get_by_stored_hash(conn, &entry.stored_hash)?;
+
+    match entry.operation.as_str() {
+        // Push: `source` is the original path, `destination` the staging path in storage
+        "push" | "link" => {
+            let linked = entry.operation == "link";
+            let target = PathBuf::from(entry.destination.trim_end_matches(fs::STAGING_SUFFIX));
+
+            if item.is_some() {
+                // The item was recorded: finish committing it
+                if destination.exists() {
+                    std::fs::rename(&destination, &target)?;
+                }
+                if linked && source.exists() {
+                    std::fs::remove_file(&source)?;
+                }
+                return Ok(Some(format!(
+                    "Completed interrupted push of {}",
+                    source.display()
+                )));
+            }
+
+            if !destination.exists() {
+                return Ok(None);
+            }
+
+            if linked || !source.exists() {
+                fs::unstage(&destination, &source, linked)?;
+            } else if copy_is_complete(&destination, entry.content_hash.as_deref())? {
+                // The copy finished but removing the original did not
+                fs::remove_item(&source)?;
+                fs::move_or_copy(&destination, &source)?;
+            } else if fs::can_resume(&source, &destination) {
+                // Keep what a copy to another filesystem got through for the next push
+                let storage = destination.parent().unwrap_or(Path::new("."));
+                let partial = fs::partial_path(storage, &source);
+                if partial.exists() {
+                    fs::remove_item(&partial)?;
+                }
+                fs::move_partial(&destination, &partial)?;
+                return Ok(Some(format!(
+                    "Interrupted push of {} stopped partway; pushing it again resumes the copy",
+                    source.display()
+                )));
+            } else {
+                fs::remove_item(&destination)?;
+            }
+
+            Ok(Some(format!(
+                "Rolled back interrupted push of {}",
+                source.display()
+            )))
+        }
+
+        // Push with `--keep`: `source` is the original path, `destination` the staged copy
+        "copy" => {
+            let target = PathBuf::from(entry.destination.trim_end_matches(fs::STAGING_SUFFIX));
+
+            if item.is_some() {
+                if destination.exists() {
+                    std::fs::rename(&destination, &target)?;
+                }
+                return Ok(Some(format!(
+                    "Completed interrupted push of {}",
+                    source.display()
+                )));
+            }
+
+            // The original was never touched, however far the copy got
+            if !destination.exists() {
+                return Ok(None);
+            }
+            fs::remove_item(&destination)?;
+            Ok(Some(format!(
+                "Rolled back interrupted push of {}",
+                source.display()
+            )))
+        }
+
+        // Archived push: `source` is the original directory, `destination` the staged archive
+        "pack" => {
+            let target = PathBuf::from(entry.destination.trim_end_matches(fs::STAGING_SUFFIX));
+
+            if let Some(item) = item {
+                // The item was recorded: finish committing it and drop the original
+                if destination.exists() {
+                    std::fs::rename(&destination, &target)?;
+                }
+                if !item.copied && source.exists() {
+                    fs::remove_item(&source)?;
+                }
+                return Ok(Some(format!(
+                    "Completed interrupted push of {}",
+                    source.display()
+                )));
+            }
+
+            // The original was never touched
+            if !destination.exists() {
+                return Ok(None);
+            }
+            fs::remove_item(&destination)?;
+            Ok(Some(format!(
+                "Rolled back interrupted push of {}",
+                source.display()
+            )))
+        }
+
+        // Bundle push: `source` is one member, `destination` the staging directory of the bundle
+        "bundle" => {
+            let target = PathBuf::from(entry.destination.trim_end_matches(fs::STAGING_SUFFIX));
+
+            if item.is_some() {
+                if destination.exists() {
+                    std::fs::rename(&destination, &target)?;
+                }
+                return Ok(Some(format!(
+                    "Completed interrupted push of {}",
+                    source.display()
+                )));
+            }
+
+            let name = source
+                .file_name()
+                .ok_or_else(|| anyhow!("invalid bundle member {}", source.display()))?;
+            let staged_member = destination.join(name);
+            if !staged_member.exists() {
+                // Never staged, or already moved back by an earlier entry of the same bundle
+                let _ = std::fs::remove_dir(&destination);
+                return Ok(None);
+            }
+
+            if !source.exists() {
+                fs::move_or_copy(&staged_member, &source)?;
+            } else if copy_is_complete(&staged_member, entry.content_hash.as_deref())? {
+                fs::remove_item(&source)?;
+                fs::move_or_copy(&staged_member, &source)?;
+            } else {
+                fs::remove_item(&staged_member)?;
+            }
+            // The staging directory goes away with its last member
+            let _ = std::fs::remove_dir(&destination);
+
+            Ok(Some(format!(
+                "Rolled back interrupted push of {}",
+                source.display()
+            )))
+        }
+
+        // Merging pop: `source` is the stored directory, `destination` the directory merged into.
+        // The merged files are in place; only the item's bookkeeping may lag behind.
+        "merge" => {
+            let Some(item) = item else {
+                return Ok(None);
+            };
+            pop::settle_extraction(conn, "pop", &item)?;
+            Ok(Some(format!(
+                "Settled interrupted merge of '{}' into {}",
+                item.original_name,
+                destination.display()
+            )))
+        }
+
+        // Pop and restore: `source` is the stored blob, `destination` the restore path
+        "pop" | "restore" => {
+            // The item is gone, so the operation had already completed
+            let item = match item {
+                Some(item) => item,
+                None => return Ok(None),
+            };
+
+            let finished = match (source.exists(), destination.exists()) {
+                (true, false) => false,
+                (false, true) => true,
+                (true, true) => {
+                    if copy_is_complete(&destination, entry.content_hash.as_deref())? {
+                        fs::remove_item(&source)?;
+                        true
+                    } else if fs::can_resume(&source, &destination) {
+                        // The item stays on the stack; what was copied stays for the next try
+                        return Ok(Some(format!(
+                            "Interrupted {} of '{}' stopped partway; the files copied so far \
+                             are kept in {} and running it again resumes the copy",
+                            entry.operation,
+                            item.original_name,
+                            destination.display()
+                        )));
+                    } else {
+                        fs::remove_item(&destination)?;
+                        false
+                    }
+                }
+                (false, false) => {
+                    return Err(anyhow!(
+                        "content of '{}' is missing from both {} and {}",
+                        item.original_name,
+                        source.display(),
+                        destination.display()
+                    ))
+                }
+            };
+
+            if finished {
+                ItemManager::delete(conn, item.id)?;
+                OperationLog::record(conn, &entry.operation, &item)?;
+                Ok(Some(format!(
+                    "Completed interrupted {} of '{}' to {}",
+                    entry.operation,
+                    item.original_name,
+                    destination.display()
+                )))
+            } else {
+                Ok(Some(format!(
+                    "Rolled back interrupted {} of '{}'",
+                    entry.operation, item.original_name
+                )))
+            }
+        }
+
+        other => Err(anyhow!("unknown operation '{}'", other)),
+    }
+}
+
+/// Check whether a copy holds all of the expected content.
+/// Without a recorded content hash the copy cannot be trusted.
+fn copy_is_complete(copy: &Path, content_hash: Option<&str>) -> Result<bool> {
+    match content_hash {
+        Some(expected) => Ok(fs::content_hash(copy)? == expected),
+        None => Ok(false),
+    }
+}
+
+/// Check whether a process with the given ID is still running.
+fn is_process_running(pid: u32) -> bool {
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{schema, ManifestManager};
+    use tempfile::tempdir;
+
+    fn setup_test_db() -> Result<Connection> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        schema::initialize_schema(&conn)?;
+        Ok(conn)
+    }
+
+    fn pending_entry(conn: &Connection) -> Result<JournalEntry> {
+        Ok(JournalManager::pending(conn)?.remove(0))
+    }
+
+    #[test]
+    fn test_recover_unrecorded_pack_keeps_original() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let original = dir.path().join("project");
+        std::fs::create_dir(&original)?;
+        std::fs::write(original.join("main.rs"), "fn main() {}")?;
+        let staged = fs::staging_path(&dir.path().join("abcdef"));
+        fs::pack_dir(&original, &staged)?;
+
+        JournalManager::begin(
+            &conn,
+            "pack",
+            "abcdef",
+            None,
+            &original.to_string_lossy(),
+            &staged.to_string_lossy(),
+        )?;
+        let entry = pending_entry(&conn)?;
+
+        assert!(recover_entry(&mut conn, &entry)?.is_some());
+        assert!(original.join("main.rs").is_file());
+        assert!(!staged.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_unrecorded_push_is_rolled_back() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let original = dir.path().join("file.txt");
+        let staged = fs::staging_path(&dir.path().join("abcdef"));
+        std::fs::write(&staged, "content")?;
+
+        JournalManager::begin(
+            &conn,
+            "push",
+            "abcdef",
+            None,
+            &original.to_string_lossy(),
+            &staged.to_string_lossy(),
+        )?;
+        let entry = pending_entry(&conn)?;
+
+        assert!(recover_entry(&mut conn, &entry)?.is_some());
+        assert_eq!(std::fs::read_to_string(&original)?, "content");
+        assert!(!staged.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_unrecorded_keep_push_drops_copy() -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let original = dir.path().join("file.txt");
+        std::fs::write(&original, "content")?;
+        let inode = std::fs::metadata(&original)?.ino();
+        let staged = fs::staging_path(&dir.path().join("abcdef"));
+        std::fs::copy(&original, &staged)?;
+
+        // The copy finished before the crash, so it would pass as complete
+        JournalManager::begin(
+            &conn,
+            "copy",
+            "abcdef",
+            Some(&fs::content_hash(&original)?),
+            &original.to_string_lossy(),
+            &staged.to_string_lossy(),
+        )?;
+        let entry = pending_entry(&conn)?;
+
+        assert!(recover_entry(&mut conn, &entry)?.is_some());
+        assert_eq!(std::fs::read_to_string(&original)?, "content");
+        assert_eq!(std::fs::metadata(&original)?.ino(), inode);
+        assert!(!staged.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_interrupted_merge_settles_manifest() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let stored = dir.path().join("stored-project");
+        let merged_into = dir.path().join("project");
+        std::fs::create_dir(&stored)?;
+        std::fs::create_dir(&merged_into)?;
+        std::fs::write(stored.join("a.txt"), "a")?;
+        std::fs::write(stored.join("b.txt"), "b")?;
+        let manifest = fs::build_manifest(&stored)?;
+
+        let id = ItemManager::insert(
+            &mut conn,
+            "project",
+            "/p",
+            "stored-project",
+            "directory",
+            &[],
+        )?;
+        ItemManager::set_storage_location(&conn, id, Some(&dir.path().to_string_lossy()))?;
+        ManifestManager::insert(&conn, id, &manifest)?;
+
+        // The merge moved a.txt before it was interrupted
+        std::fs::rename(stored.join("a.txt"), merged_into.join("a.txt"))?;
+        JournalManager::begin(
+            &conn,
+            "merge",
+            "stored-project",
+            None,
+            &stored.to_string_lossy(),
+            &merged_into.to_string_lossy(),
+        )?;
+        let entry = pending_entry(&conn)?;
+
+        assert!(recover_entry(&mut conn, &entry)?.is_some());
+        let paths: Vec<String> = ManifestManager::get_for_item(&conn, id)?
+            .into_iter()
+            .map(|entry| entry.relative_path)
+            .collect();
+        assert_eq!(paths, vec!["b.txt"]);
+        assert!(ItemManager::get_by_id(&conn, id)?.unwrap().partial);
+
+        // Once the rest is merged too, the item goes away
+        std::fs::rename(stored.join("b.txt"), merged_into.join("b.txt"))?;
+        assert!(recover_entry(&mut conn, &entry)?.is_some());
+        assert!(ItemManager::get_by_id(&conn, id)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_partial_push_keeps_copied_files() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let original = dir.path().join("project");
+        std::fs::create_dir(&original)?;
+        std::fs::write(original.join("a.txt"), "a")?;
+        std::fs::write(original.join("b.txt"), "b")?;
+        let staged = fs::staging_path(&dir.path().join("abcdef"));
+        std::fs::create_dir(&staged)?;
+        std::fs::write(staged.join("a.txt"), "a")?;
+        std::fs::write(
+            fs::progress_path(&staged),
+            format!("{}\n1\ta.txt\n", original.display()),
+        )?;
+
+        JournalManager::begin(
+            &conn,
+            "push",
+            "abcdef",
+            None,
+            &original.to_string_lossy(),
+            &staged.to_string_lossy(),
+        )?;
+        let entry = pending_entry(&conn)?;
+
+        assert!(recover_entry(&mut conn, &entry)?.is_some());
+        assert!(!staged.exists());
+        // The next push of the same directory finds the copied files again
+        let partial = fs::partial_path(dir.path(), &original);
+        assert!(fs::can_resume(&original, &partial));
+        assert!(original.join("b.txt").is_file());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_recorded_push_is_committed() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let original = dir.path().join("file.txt");
+        let target = dir.path().join("abcdef");
+        let staged = fs::staging_path(&target);
+        std::fs::write(&staged, "content")?;
+        ItemManager::insert(&mut conn, "file.txt", "/p", "abcdef", "file", &[])?;
+
+        JournalManager::begin(
+            &conn,
+            "push",
+            "abcdef",
+            None,
+            &original.to_string_lossy(),
+            &staged.to_string_lossy(),
+        )?;
+        let entry = pending_entry(&conn)?;
+
+        recover_entry(&mut conn, &entry)?;
+        assert_eq!(std::fs::read_to_string(&target)?, "content");
+        assert!(!original.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_pop() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let stored = dir.path().join("abcdef");
+        let dest = dir.path().join("file.txt");
+        let item_id = ItemManager::insert(&mut conn, "file.txt", "/p", "abcdef", "file", &[])?;
+
+        // A complete copy with a leftover blob finishes the pop
+        std::fs::write(&stored, "content")?;
+        std::fs::write(&dest, "content")?;
+        let hash = fs::hash_file(&stored)?;
+        JournalManager::begin(
+            &conn,
+            "pop",
+            "abcdef",
+            Some(&hash),
+            &stored.to_string_lossy(),
+            &dest.to_string_lossy(),
+        )?;
+        let entry = pending_entry(&conn)?;
+
+        recover_entry(&mut conn, &entry)?;
+        assert!(!stored.exists());
+        assert!(dest.exists());
+        assert!(ItemManager::get_by_id(&conn, item_id)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_partial_pop_is_rolled_back() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let stored = dir.path().join("abcdef");
+        let dest = dir.path().join("file.txt");
+        let item_id = ItemManager::insert(&mut conn, "file.txt", "/p", "abcdef", "file", &[])?;
+
+        std::fs::write(&stored, "content")?;
+        std::fs::write(&dest, "cont")?;
+        let hash = fs::hash_file(&stored)?;
+        JournalManager::begin(
+            &conn,
+            "restore",
+            "abcdef",
+            Some(&hash),
+            &stored.to_string_lossy(),
+            &dest.to_string_lossy(),
+        )?;
+        let entry = pending_entry(&conn)?;
+
+        recover_entry(&mut conn, &entry)?;
+        assert!(stored.exists());
+        assert!(!dest.exists());
+        assert!(ItemManager::get_by_id(&conn, item_id)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_unrecorded_bundle_is_rolled_back() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let staged = fs::staging_path(&dir.path().join("abcdef"));
+        std::fs::create_dir(&staged)?;
+
+        // Both members were staged before the run was interrupted
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(staged.join(name), name)?;
+            JournalManager::begin(
+                &conn,
+                "bundle",
+                "abcdef",
+                None,
+                &dir.path().join(name).to_string_lossy(),
+                &staged.to_string_lossy(),
+            )?;
+        }
+
+        for entry in JournalManager::pending(&conn)? {
+            assert!(recover_entry(&mut conn, &entry)?.is_some());
+        }
+        assert_eq!(std::fs::read_to_string(dir.path().join("a.txt"))?, "a.txt");
+        assert_eq!(std::fs::read_to_string(dir.path().join("b.txt"))?, "b.txt");
+        assert!(!staged.exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use crate::cli::peek::print_item;
+use crate::cli::sync;
+use crate::cli::Commands;
+use crate::config;
+use crate::db::{item_by_number, item_number, StackItem, TagQuery};
+use crate::fs;
+use crate::status;
+use crate::utils::display;
+use crate::utils::error::FstkError;
+use crate::utils::http;
+use crate::utils::numbers::parse_number_range;
+
+/// A stack served by `fstk daemon --http` on another machine
+pub(crate) struct Remote {
+    /// `host:port` to connect to
+    pub(crate) address: String,
+    /// Token the daemon requires (see `http_token` in the configuration)
+    token: Option<String>,
+}
+
+impl Remote {
+    pub(crate) fn new(url: &str) -> Result<Self> {
+        Ok(Remote {
+            address: http::parse_url(url)?,
+            token: config::load()?.http_token(),
+        })
+    }
+
+    /// Send a request, turning error responses into errors
+    pub(crate) fn request(&self, method: &str, path: &str) -> Result<BufReader<TcpStream>> {
+        let (status, body) = http::send(&self.address, self.token.as_deref(), method, path)?;
+        if !(200..300).contains(&status) {
+            return Err(http::read_error(status, body));
+        }
+        Ok(body)
+    }
+
+    /// Copy a local item to the remote stack, keeping its UUID, push time, tags and pin state
+    pub(crate) fn upload(&self, item: &StackItem, stored_path: &Path) -> Result<()> {
+        let mut header = serde_json::to_vec(item)?;
+        header.push(b'\n');
+        let length = header.len() as u64 + sync::content_length(stored_path)?;
+
+        let (status, body) = http::send_with_body(
+            &self.address,
+            self.token.as_deref(),
+            "POST",
+            "/items",
+            length,
+            |stream| {
+                stream.write_all(&header)?;
+                sync::write_content(stored_path, stream)
+            },
+        )?;
+        if !(200..300).contains(&status) {
+            return Err(http::read_error(status, body));
+        }
+        Ok(())
+    }
+
+    /// Items matching the tag queries in `tags`, in display order (newest first)
+    pub(crate) fn items(&self, tags: &[String]) -> Result<Vec<StackItem>> {
+        let query = TagQuery::all(tags)?;
+        let items: Vec<StackItem> = serde_json::from_reader(self.request("GET", "/items")?)?;
+        Ok(items
+            .into_iter()
+            .filter(|item| query.as_ref().is_none_or(|query| query.matches(&item.tags)))
+            .collect())
+    }
+}
+
+/// Run a command against a remote stack. Only list, peek and pop are supported.
+pub fn run(url: &str, command: Commands) -> Result<()> {
+    let remote = Remote::new(url)?;
+
+    match command {
+        Commands::List { tags, any_tags, .. } => list(
+            &remote,
+            super::tag_filters(tags, any_tags).unwrap_or_default(),
+        ),
+        Commands::Peek { number, tags, .. } => peek(&remote, number, tags.unwrap_or_default()),
+        Commands::Pop {
+            numbers,
+            tags,
+            any_tags,
+            output,
+            last_out,
+            here,
+            to_original,
+            to_pushdir,
+            rollback_on_error,
+            out_map,
+            ask_output,
+            rename,
+            print_path,
+            subpath,
+            version,
+            force,
+            merge,
+            on_conflict: _,
+        } => {
+            let unsupported = [
+                ("--last-out", last_out),
+                ("--here", here),
+                ("--to-original", to_original),
+                ("--to-pushdir", to_pushdir),
+                ("--rollback-on-error", rollback_on_error),
+                ("--out-map", !out_map.is_empty()),
+                ("--ask-output", ask_output),
+                ("--as", rename.is_some()),
+                ("--print-path", print_path),
+                ("--path", subpath.is_some()),
+                ("--version", version.is_some()),
+                ("--force", force),
+                ("--merge", merge),
+            ];
+            if let Some((flag, _)) = unsupported.iter().find(|(_, given)| *given) {
+                return Err(anyhow!(
+                    "pop --remote does not support {}; only --output and --tags",
+                    flag
+                ));
+            }
+            pop(
+                &remote,
+                numbers,
+                super::tag_filters(tags, any_tags).unwrap_or_default(),
+                output,
+            )
+        }
+        _ => Err(anyhow!("--remote only supports list, peek and pop")),
+    }
+}
+
+fn list(remote: &Remote, tags: Vec<String>) -> Result<()> {
+    let items = remote.items(&tags)?;
+    if items.is_empty() {
+        println!("No items on {}.", remote.address);
+        return Ok(());
+    }
+
+    println!("Stack: remote ({})", remote.address);
+    display::display_items_table(&items, &HashMap::new(), &config::load()?.tag_colors()?);
+    Ok(())
+}
+
+fn peek(remote: &Remote, number: Option<String>, tags: Vec<String>) -> Result<()> {
+    let items = remote.items(&tags)?;
+    let item = match number {
+        Some(arg) => {
+            let number = parse_number(&arg)?;
+            item_by_number(&items, number)
+                .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?
+        }
+        None => items
+            .first()
+            .ok_or_else(|| anyhow!("No items on {}", remote.address))?,
+    };
+
+    print_item(item, &[], None, None);
+    Ok(())
+}
+
+/// Download items into `output` (the current directory by default), then drop them from the
+/// remote stack. Content is checked against the recorded checksum before the remote item goes.
+fn pop(
+    remote: &Remote,
+    numbers: Option<String>,
+    tags: Vec<String>,
+    output: Option<String>,
+) -> Result<()> {
+    let items = remote.items(&tags)?;
+    // The newest unpinned item unless numbers are given, as for a local pop
+    let numbers = match numbers {
+        Some(numbers) => parse_number_range(&numbers)?,
+        None => {
+            let (index, newest) = items
+                .iter()
+                .enumerate()
+                .find(|(_, item)| !item.pinned)
+                .ok_or_else(|| anyhow!("No unpinned items on {}", remote.address))?;
+            vec![item_number(index, newest)]
+        }
+    };
+    let output_dir = match output {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::current_dir()?,
+    };
+
+    // Resolve every number first, since the remote numbering shifts with each pop
+    let selected = numbers
+        .iter()
+        .map(|&number| {
+            item_by_number(&items, number)
+                .ok_or_else(|| FstkError::ItemNotFound(number.to_string()).into())
+        })
+        .collect::<Result<Vec<&StackItem>>>()?;
+
+    // The server refuses to drop locked items, so don't leave a copy of them behind
+    if let Some(item) = selected.iter().find(|item| item.locked) {
+        return Err(anyhow!(
+            "'{}' is locked on {}; unlock it there to pop it",
+            item.original_name,
+            remote.address
+        ));
+    }
+
+    for item in selected {
+        // The name comes from the server, so it must not lead out of `output_dir`
+        if !fs::is_plain_name(&item.original_name) {
+            return Err(anyhow!(
+                "Refusing item with invalid name '{}' from {}",
+                item.original_name,
+                remote.address
+            ));
+        }
+        let dest_path = output_dir.join(&item.original_name);
+        if fs::check_destination_conflict(&dest_path) {
+            return Err(FstkError::DestinationConflict(dest_path.display().to_string()).into());
+        }
+
+        download(remote, item, &dest_path)?;
+        remote.request("DELETE", &format!("/items/{}", item.id))?;
+
+        status!(
+            "Popped '{}' from {} to {}",
+            item.original_name,
+            remote.address,
+            dest_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Download an item's content to `dest_path` through a staging path, verifying its checksum
+fn download(remote: &Remote, item: &StackItem, dest_path: &Path) -> Result<()> {
+    let body = remote.request("GET", &format!("/items/{}/content", item.id))?;
+    let staged = fs::staging_path(dest_path);
+
+    receive_verified(body, item, &staged)?;
+    // Directories stored as an archive arrive as the archive
+    if item.packed {
+        let result = fs::unpack_dir(&staged, dest_path);
+        fs::remove_item(&staged)?;
+        return result;
+    }
+    std::fs::rename(&staged, dest_path)?;
+    Ok(())
+}
+
+/// Write received content (a file, or a tar stream of a directory) to `staged` and check it
+/// against the item's recorded checksum. Nothing is left at `staged` if either step fails.
+pub(crate) fn receive_verified<R: Read>(body: R, item: &StackItem, staged: &Path) -> Result<()> {
+    let result = receive(body, item, staged).and_then(|()| match &item.content_hash {
+        Some(expected) if fs::content_hash(staged)? != *expected => Err(anyhow!(
+            "Checksum of the received '{}' does not match",
+            item.original_name
+        )),
+        _ => Ok(()),
+    });
+    if result.is_err() && staged.exists() {
+        fs::remove_item(staged)?;
+    }
+    result
+}
+
+fn receive<R: Read>(mut body: R, item: &StackItem, staged: &Path) -> Result<()> {
+    if item.is_stored_as_directory() {
+        std::fs::create_dir_all(staged)?;
+        tar::Archive::new(body).unpack(staged)?;
+    } else {
+        let mut file = std::fs::File::create(staged)?;
+        io::copy(&mut body, &mut file)?;
+    }
+    Ok(())
+}
+
+fn parse_number(arg: &str) -> Result<usize> {
+    arg.parse()
+        .map_err(|_| anyhow!("Use item numbers with --remote; name queries are not supported"))
+}
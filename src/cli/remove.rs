@@ -1,60 +1,96 @@
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use rusqlite::Connection;
 use std::fs;
 
-use crate::db::{establish_connection, get_stored_path, ItemManager};
+use crate::db::{
+    establish_connection, get_item_stored_path, id_addressing, item_by_number_with,
+    item_number_with, ItemManager, OperationLog, StackItem,
+};
+use crate::utils::duration::parse_duration;
 use crate::utils::numbers::parse_number_range;
+use crate::utils::output;
 
 /// Remove items from the stack without restoring them.
-pub fn remove(numbers: String, tags: Option<Vec<String>>) -> Result<()> {
-    // Parse number range
-    let number_list = parse_number_range(&numbers)?;
+/// Without `numbers`, every item matching `tags` and `older_than` is removed after a single
+/// confirmation. Pinned and locked items are only removed when `force` is set.
+pub fn remove(
+    numbers: Option<String>,
+    tags: Option<Vec<String>>,
+    older_than: Option<String>,
+    force: bool,
+) -> Result<()> {
+    let tag_vec = tags.unwrap_or_default();
+    let filter_by_tags = !tag_vec.is_empty();
+
+    if numbers.is_none() && !filter_by_tags && older_than.is_none() {
+        return Err(anyhow!(
+            "Specify item numbers, or select items with --tags and/or --older-than"
+        ));
+    }
+
+    // Only items pushed before this moment are considered
+    let cutoff = match &older_than {
+        Some(age) => Some(Local::now() - parse_duration(age)?),
+        None => None,
+    };
 
     // Connect to database
     let mut conn = establish_connection()?;
 
-    let tag_vec = tags.unwrap_or_default();
-    let filter_by_tags = !tag_vec.is_empty();
+    // Get list of all items with current display numbers
+    let mut all_items = ItemManager::list(&conn, &tag_vec)?;
 
-    // First, collect all the items to process based on the current state
-    // This ensures we're working with a snapshot of the current display numbers
-    let mut items_to_process = Vec::new();
+    // Sort by pushed_at (descending) to match display order
+    all_items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
 
-    // Get list of all items with current display numbers
-    let mut all_items = if filter_by_tags {
-        ItemManager::list(&conn, &tag_vec)?
-    } else {
-        let empty_tags = Vec::new();
-        ItemManager::list(&conn, &empty_tags)?
+    // Without numbers, every matching item is selected
+    let number_list = match &numbers {
+        Some(numbers) => Some(parse_number_range(numbers)?),
+        None => None,
     };
 
-    // Sort by pushed_at (descending) to match display order
-    all_items.sort_by(|a, b| b.pushed_at.cmp(&a.pushed_at));
-
-    // Map display numbers to database IDs
-    for &number in &number_list {
-        if number > 0 && number <= all_items.len() {
-            // Convert display number to zero-based index
-            let idx = number - 1;
-            items_to_process.push((number, all_items[idx].clone()));
-        } else {
-            // Report invalid number
-            if filter_by_tags {
-                println!(
-                    "No item found with number={} and tags=[{}]",
-                    number,
-                    tag_vec.join(", ")
-                );
-            } else {
-                println!("No item found with number={}", number);
-            }
-        }
-    }
+    // Collect the items to process based on the current state, so that we're working with a
+    // snapshot of the current display numbers
+    let items_to_process = select_items(
+        &all_items,
+        number_list.as_deref(),
+        id_addressing(),
+        &tag_vec,
+        older_than.as_deref().zip(cutoff),
+        force,
+    );
 
     // Exit early if no valid items to process
     if items_to_process.is_empty() {
+        if numbers.is_none() {
+            return Err(anyhow!("No items match the given filters"));
+        }
         return Err(anyhow!("No valid items to remove"));
     }
 
+    // Bulk removal by tag or age lists what will go and asks once
+    if numbers.is_none() {
+        println!(
+            "The following {} item(s) will be removed:",
+            items_to_process.len()
+        );
+        for (display_number, item) in &items_to_process {
+            println!(
+                "  #{} {} (pushed {})",
+                display_number,
+                item.original_name,
+                item.pushed_at.format("%Y-%m-%d %H:%M:%S")
+            );
+        }
+
+        let input = output::prompt("Do you want to continue? [y/N]: ")?;
+        if input != "y" && input != "yes" {
+            println!("Operation cancelled.");
+            return Ok(());
+        }
+    }
+
     // Track statistics
     let mut success_count = 0;
     let mut failed_count = 0;
@@ -65,7 +101,7 @@ pub fn remove(numbers: String, tags: Option<Vec<String>>) -> Result<()> {
     // Now process all the collected items (atomically, based on the initial state)
     for (display_number, item) in items_to_process {
         // Get source path from the data directory
-        let source_path = match get_stored_path(&item.stored_hash) {
+        let source_path = match get_item_stored_path(&item) {
             Ok(path) => path,
             Err(e) => {
                 println!(
@@ -80,9 +116,11 @@ pub fn remove(numbers: String, tags: Option<Vec<String>>) -> Result<()> {
         // Delete the item from the database
         match ItemManager::delete(&mut conn, item.id) {
             Ok(true) => {
+                OperationLog::record(&conn, "remove", &item)?;
+
                 // Delete the file or directory from storage if it exists
                 if source_path.exists() {
-                    let result = if item.item_type == "directory" {
+                    let result = if item.is_stored_as_directory() {
                         fs::remove_dir_all(&source_path)
                     } else {
                         fs::remove_file(&source_path)
@@ -133,3 +171,168 @@ pub fn remove(numbers: String, tags: Option<Vec<String>>) -> Result<()> {
         Err(anyhow!("Failed to remove any items"))
     }
 }
+
+/// Pick the items of `all_items` (in display order) that `numbers` address, or all of them
+/// without numbers, paired with their number (a database ID when `by_id` is set). Items pushed
+/// after the `older_than` cutoff, and pinned or locked ones unless `force` is set, are reported
+/// and left out.
+fn select_items(
+    all_items: &[StackItem],
+    numbers: Option<&[usize]>,
+    by_id: bool,
+    tags: &[String],
+    older_than: Option<(&str, DateTime<Local>)>,
+    force: bool,
+) -> Vec<(usize, StackItem)> {
+    let candidates: Vec<(usize, Option<&StackItem>)> = match numbers {
+        Some(numbers) => numbers
+            .iter()
+            .map(|&number| (number, item_by_number_with(all_items, number, by_id)))
+            .collect(),
+        None => all_items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (item_number_with(index, item, by_id), Some(item)))
+            .collect(),
+    };
+
+    let mut selected = Vec::new();
+    for (number, item) in candidates {
+        if let Some(item) = item {
+            if let Some((age, cutoff)) = older_than {
+                if item.pushed_at >= cutoff {
+                    if numbers.is_some() {
+                        println!(
+                            "Item #{} ('{}') is newer than {}; skipping",
+                            number, item.original_name, age
+                        );
+                    }
+                    continue;
+                }
+            }
+
+            if (item.pinned || item.locked) && !force {
+                println!(
+                    "Item #{} ('{}') is {}; use --force to remove it",
+                    number,
+                    item.original_name,
+                    if item.locked { "locked" } else { "pinned" }
+                );
+                continue;
+            }
+
+            selected.push((number, item.clone()));
+        } else if tags.is_empty() {
+            // Report invalid number
+            println!("No item found with number={}", number);
+        } else {
+            println!(
+                "No item found with number={} and tags=[{}]",
+                number,
+                tags.join(", ")
+            );
+        }
+    }
+
+    selected
+}
+
+/// Remove one item along with its stored content, recording the removal in the history
+pub(crate) fn remove_item(conn: &mut Connection, item: &StackItem) -> Result<()> {
+    discard_item(conn, "remove", item)
+}
+
+/// Drop an item and its stored content, recording `operation` in the history
+pub(crate) fn discard_item(conn: &mut Connection, operation: &str, item: &StackItem) -> Result<()> {
+    let stored_path = get_item_stored_path(item)?;
+    if !ItemManager::delete(conn, item.id)? {
+        return Err(anyhow!("'{}' no longer exists", item.original_name));
+    }
+
+    OperationLog::record(conn, operation, item)?;
+    if stored_path.exists() {
+        crate::fs::remove_item(&stored_path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: i64, name: &str) -> StackItem {
+        StackItem {
+            id,
+            original_name: name.to_string(),
+            pushed_at: Local::now() - chrono::Duration::days(id),
+            ..Default::default()
+        }
+    }
+
+    fn names(selected: &[(usize, StackItem)]) -> Vec<&str> {
+        selected
+            .iter()
+            .map(|(_, item)| item.original_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_select_items_skips_locked_and_pinned() {
+        let mut items = vec![item(1, "a.txt"), item(2, "b.txt"), item(3, "c.txt")];
+        items[0].locked = true;
+        items[1].pinned = true;
+
+        assert_eq!(
+            names(&select_items(
+                &items,
+                Some(&[1, 2, 3]),
+                false,
+                &[],
+                None,
+                false
+            )),
+            ["c.txt"]
+        );
+        assert_eq!(
+            names(&select_items(&items, None, false, &[], None, false)),
+            ["c.txt"]
+        );
+        assert_eq!(
+            names(&select_items(&items, Some(&[1, 2]), false, &[], None, true)),
+            ["a.txt", "b.txt"]
+        );
+    }
+
+    #[test]
+    fn test_select_items_older_than() {
+        let items = vec![item(1, "a.txt"), item(2, "b.txt"), item(3, "c.txt")];
+        let cutoff = Local::now() - chrono::Duration::hours(36);
+
+        let selected = select_items(&items, None, false, &[], Some(("36h", cutoff)), false);
+        assert_eq!(names(&selected), ["b.txt", "c.txt"]);
+        assert_eq!(selected[0].0, 2);
+    }
+
+    #[test]
+    fn test_select_items_by_id() {
+        // IDs in display order, as after items were moved around
+        let items = vec![item(7, "a.txt"), item(3, "b.txt"), item(12, "c.txt")];
+
+        let selected = select_items(&items, Some(&[3, 12, 1]), true, &[], None, false);
+        assert_eq!(names(&selected), ["b.txt", "c.txt"]);
+        assert_eq!(selected[0].0, 3);
+
+        // Without numbers, every item is taken and numbered by its ID, whatever the IDs are
+        let tagged = &items[1..];
+        let selected = select_items(tagged, None, true, &["tmp".to_string()], None, false);
+        assert_eq!(names(&selected), ["b.txt", "c.txt"]);
+        assert_eq!(
+            selected
+                .iter()
+                .map(|(number, _)| *number)
+                .collect::<Vec<_>>(),
+            [3, 12]
+        );
+    }
+}
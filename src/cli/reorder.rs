@@ -0,0 +1,54 @@
+use anyhow::Result;
+
+use crate::db::{establish_connection, item_by_number, ItemManager, StackItem};
+use crate::utils::error::FstkError;
+
+/// Move the item numbered `from` to the position of the item numbered `to`, shifting the items
+/// in between by one, so that e.g. `mv 3 1` puts the third item on top.
+pub fn move_item(from: usize, to: usize) -> Result<()> {
+    let mut conn = establish_connection()?;
+    let mut items = ItemManager::list(&conn, &[])?;
+
+    let from_index = position(&items, from)?;
+    let to_index = position(&items, to)?;
+    let item = items.remove(from_index);
+    let name = item.original_name.clone();
+    items.insert(to_index, item);
+
+    ItemManager::reorder(&mut conn, &ids(&items))?;
+    println!("Moved '{}' to position {}", name, to_index + 1);
+
+    Ok(())
+}
+
+/// Exchange the positions of two items
+pub fn swap(first: usize, second: usize) -> Result<()> {
+    let mut conn = establish_connection()?;
+    let mut items = ItemManager::list(&conn, &[])?;
+
+    let first_index = position(&items, first)?;
+    let second_index = position(&items, second)?;
+    items.swap(first_index, second_index);
+
+    ItemManager::reorder(&mut conn, &ids(&items))?;
+    println!(
+        "Swapped '{}' and '{}'",
+        items[second_index].original_name, items[first_index].original_name
+    );
+
+    Ok(())
+}
+
+/// Index in the stack (top first) of the item `number` addresses
+fn position(items: &[StackItem], number: usize) -> Result<usize> {
+    let item =
+        item_by_number(items, number).ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+    Ok(items
+        .iter()
+        .position(|other| other.id == item.id)
+        .unwrap_or_default())
+}
+
+fn ids(items: &[StackItem]) -> Vec<i64> {
+    items.iter().map(|item| item.id).collect()
+}
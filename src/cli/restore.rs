@@ -1,17 +1,63 @@
 use anyhow::{anyhow, Result};
-use std::path::PathBuf;
+use glob::{MatchOptions, Pattern};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
 
-use crate::db::{establish_connection, get_stored_path, ItemManager};
+use crate::cli::{pop, remove, select};
+use crate::db::{
+    establish_connection, get_item_stored_path, BundleManager, BundleMember, ItemManager, StackItem,
+};
 use crate::fs;
+use crate::hooks;
+use crate::status;
+use crate::utils::error::FstkError;
+use crate::utils::output;
+
+/// Programs that run a command as root, in order of preference
+const ELEVATION_PROGRAMS: &[&str] = &["sudo", "doas", "pkexec"];
 
 /// Restore an item from the stack to its original location and remove it from the stack.
-pub fn restore(number: Option<usize>, tags: Option<Vec<String>>) -> Result<()> {
+/// If `to` is given, the item is restored into that directory instead.
+/// With `keep`, the item is copied back and stays on the stack.
+/// With `skip_existing`, a destination that already holds identical content counts as
+/// restored, so that re-running an interrupted batch of restores succeeds.
+/// With `overwrite`, an existing destination is replaced once the item is in its place.
+/// With `only`, just the entries of a directory item matching the glob are restored.
+/// A locked item is only taken off the stack when `force` is set.
+#[allow(clippy::too_many_arguments)]
+pub fn restore(
+    number: Option<String>,
+    tags: Option<Vec<String>>,
+    to: Option<String>,
+    keep: bool,
+    print_path: bool,
+    skip_existing: bool,
+    overwrite: bool,
+    only: Option<String>,
+    force: bool,
+) -> Result<()> {
+    // Keep stdout clean for the printed destination path
+    if print_path {
+        output::reserve_stdout();
+    }
+
     let tag_vec = tags.unwrap_or_default();
     let filter_by_tags = !tag_vec.is_empty();
 
     // Connect to database
     let mut conn = establish_connection()?;
 
+    // Resolve a name query to a display number
+    let number = match number {
+        Some(arg) => match select::resolve_number(&conn, &arg, &tag_vec)? {
+            Some(number) => Some(number),
+            None => return Ok(()),
+        },
+        None => None,
+    };
+
     // Get item based on provided criteria
     let item = match number {
         Some(num) => {
@@ -27,12 +73,12 @@ pub fn restore(number: Option<usize>, tags: Option<Vec<String>>) -> Result<()> {
             } else {
                 let empty_tags = Vec::new();
                 ItemManager::get_id_by_display_number(&conn, num, &empty_tags)?
-                    .ok_or_else(|| anyhow!("No item found with number={}", num))?
+                    .ok_or_else(|| FstkError::ItemNotFound(num.to_string()))?
             };
 
             // Get item by DB ID
             ItemManager::get_by_id(&conn, id)?
-                .ok_or_else(|| anyhow!("No item found with number={}", num))?
+                .ok_or_else(|| FstkError::ItemNotFound(num.to_string()))?
         }
         None => {
             // Get latest item
@@ -45,41 +91,869 @@ pub fn restore(number: Option<usize>, tags: Option<Vec<String>>) -> Result<()> {
         }
     };
 
-    // Construct destination path using the original path and filename
-    let mut dest_path = PathBuf::from(&item.original_path);
-    dest_path.push(&item.original_name);
+    if let Some(dir) = &to {
+        let dir_path = Path::new(dir);
+        if dir_path.exists() && !dir_path.is_dir() {
+            return Err(anyhow!(
+                "Specified restore path is not a directory: {}",
+                dir_path.display()
+            ));
+        }
+    }
 
-    // Check if destination already exists
-    if fs::check_destination_conflict(&dest_path) {
+    ensure_restorable(&item, keep, force)?;
+
+    if let Some(pattern) = &only {
+        return restore_matching(&mut conn, &item, pattern, to.as_deref(), keep, print_path);
+    }
+
+    restore_item(
+        &mut conn,
+        &item,
+        to.as_deref(),
+        keep,
+        print_path,
+        skip_existing,
+        overwrite,
+    )
+}
+
+/// Restore every item whose original path lies in `dir` (or below it), newest first, optionally
+/// only those with all of `tags`. An item that cannot be restored, e.g. because its destination
+/// exists, is reported and stays on the stack while the others are restored. Locked items are
+/// skipped the same way unless `force` is set.
+pub fn restore_from_dir(
+    dir: &str,
+    tags: Option<Vec<String>>,
+    keep: bool,
+    print_path: bool,
+    skip_existing: bool,
+    overwrite: bool,
+    force: bool,
+) -> Result<()> {
+    if print_path {
+        output::reserve_stdout();
+    }
+
+    // The directory was usually emptied onto the stack, but may be gone altogether
+    let dir = match Path::new(dir).canonicalize() {
+        Ok(dir) => dir,
+        Err(_) => std::env::current_dir()?.join(dir),
+    };
+    let dir = PathBuf::from(fs::normalize_name(&dir.to_string_lossy()));
+
+    let mut conn = establish_connection()?;
+    let items = pushed_from(ItemManager::list(&conn, &tags.unwrap_or_default())?, &dir);
+    if items.is_empty() {
+        return Err(anyhow!("No items from {} on the stack", dir.display()));
+    }
+
+    let (mut restored, mut conflicts, mut failures) = (0, 0, 0);
+    for item in &items {
+        let result = ensure_restorable(item, keep, force).and_then(|()| {
+            restore_item(
+                &mut conn,
+                item,
+                None,
+                keep,
+                print_path,
+                skip_existing,
+                overwrite,
+            )
+        });
+        match result {
+            Ok(()) => restored += 1,
+            Err(e) => match e.downcast_ref::<FstkError>() {
+                Some(FstkError::DestinationConflict(path)) => {
+                    conflicts += 1;
+                    status!(
+                        "Skipped '{}' (id {}): {} already exists",
+                        item.original_name,
+                        item.id,
+                        path
+                    );
+                }
+                _ => {
+                    failures += 1;
+                    status!(
+                        "Failed to restore '{}' (id {}): {}",
+                        item.original_name,
+                        item.id,
+                        e
+                    );
+                }
+            },
+        }
+    }
+
+    status!(
+        "Restored {} of {} item(s) from {}",
+        restored,
+        items.len(),
+        dir.display()
+    );
+    if conflicts + failures > 0 {
         return Err(anyhow!(
-            "Original destination already exists: {}. Use 'pop' with a custom destination to avoid conflicts.",
-            dest_path.display()
+            "{} item(s) were left on the stack ({} conflict(s), {} failure(s))",
+            conflicts + failures,
+            conflicts,
+            failures
         ));
     }
 
+    Ok(())
+}
+
+/// Refuse to take a locked item off the stack unless forced; restoring a copy with `keep`
+/// leaves the item in place and is always allowed.
+fn ensure_restorable(item: &StackItem, keep: bool, force: bool) -> Result<()> {
+    if item.locked && !keep && !force {
+        return Err(FstkError::ItemLocked(item.original_name.clone()).into());
+    }
+    Ok(())
+}
+
+/// The items whose original path is `dir` or lies below it
+fn pushed_from(items: Vec<StackItem>, dir: &Path) -> Vec<StackItem> {
+    items
+        .into_iter()
+        .filter(|item| Path::new(&item.original_path).starts_with(dir))
+        .collect()
+}
+
+/// Restore a single item to its original location (or into `to`)
+fn restore_item(
+    conn: &mut Connection,
+    item: &StackItem,
+    to: Option<&str>,
+    keep: bool,
+    print_path: bool,
+    skip_existing: bool,
+    overwrite: bool,
+) -> Result<()> {
+    // Bundle members go back to their own original locations
+    if item.is_bundle() {
+        if overwrite {
+            return Err(anyhow!(
+                "--overwrite does not apply to bundle '{}'; restore it without",
+                item.original_name
+            ));
+        }
+        return restore_bundle(conn, item, to, keep, print_path, skip_existing);
+    }
+
+    // Construct destination path using the original (or alternate) path and filename
+    let mut dest_path = match to {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(&item.original_path),
+    };
+    dest_path.push(&item.original_name);
+
     // Get source path from the data directory
-    let source_path = get_stored_path(&item.stored_hash)?;
+    let source_path = get_item_stored_path(item)?;
+
+    // Check if destination already exists
+    let mut replaced = None;
+    let existing =
+        fs::existing_entry(&dest_path).filter(|_| keep || !pop::resuming(&source_path, &dest_path));
+    if let Some(existing) = existing {
+        if skip_existing && already_restored(item.content_hash.as_deref(), &source_path, &existing)?
+        {
+            if keep {
+                status!(
+                    "'{}' is already at {}",
+                    item.original_name,
+                    existing.display()
+                );
+            } else {
+                hooks::before("restore", item, Some(&existing))?;
+                remove::discard_item(conn, "restore", item)?;
+                hooks::after("restore", item, Some(&existing));
+                status!(
+                    "'{}' is already at {}; removed it from the stack",
+                    item.original_name,
+                    existing.display()
+                );
+            }
+            if print_path {
+                println!("{}", existing.display());
+            }
+            return Ok(());
+        }
+
+        if overwrite {
+            // The destination is only deleted once the item is in its place
+            check_source(&source_path)?;
+            replaced = Some(fs::move_aside(&existing)?);
+        } else if to.is_some() {
+            return Err(
+                FstkError::DestinationConflict(dest_path.to_string_lossy().to_string()).into(),
+            );
+        } else {
+            if item.copied {
+                status!(
+                    "'{}' was pushed with --keep, so the original was never removed",
+                    item.original_name
+                );
+            }
+            return Err(anyhow::Error::from(FstkError::DestinationConflict(
+                dest_path.to_string_lossy().to_string(),
+            ))
+            .context(format!(
+                "Original destination already exists: {}. Use 'pop' with a custom destination, 'restore --to' or 'restore --overwrite' to avoid conflicts.",
+                dest_path.display()
+            )));
+        }
+    }
+
+    let result = place_item(conn, item, &source_path, &dest_path, keep, print_path);
+    match (&result, replaced) {
+        (Ok(()), Some(replaced)) => {
+            if let Err(e) = fs::remove_item(&replaced.aside) {
+                status!(
+                    "Could not remove the replaced {}: {}",
+                    replaced.aside.display(),
+                    e
+                );
+            }
+        }
+        (Err(_), Some(replaced)) => pop::restore_replaced(Some(&replaced), &dest_path),
+        (_, None) => {}
+    }
+    result
+}
 
-    // Ensure source exists
+/// Fail if an item's stored content is missing
+fn check_source(source_path: &Path) -> Result<()> {
     if !source_path.exists() {
         return Err(anyhow!(
             "Error: Source file missing from storage: {}",
             source_path.display()
         ));
     }
+    Ok(())
+}
+
+/// Move (or with `keep`, copy) an item's content to a free `dest_path`
+fn place_item(
+    conn: &mut Connection,
+    item: &StackItem,
+    source_path: &Path,
+    dest_path: &Path,
+    keep: bool,
+    print_path: bool,
+) -> Result<()> {
+    check_source(source_path)?;
+
+    // A destination like /etc/nginx/nginx.conf can only be written as root
+    if fs::needs_privileges(dest_path) {
+        return restore_elevated(conn, item, source_path, dest_path, keep, print_path);
+    }
 
     // Ensure parent directory exists
-    if let Some(parent) = dest_path.parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent)?;
+    fs::ensure_parent_dirs(dest_path)?;
+
+    if keep {
+        // Copy the item so the stored snapshot stays intact
+        hooks::before("restore", item, Some(dest_path))?;
+        pop::ensure_room(item, source_path, dest_path, true)?;
+        if item.packed {
+            fs::unpack_dir(source_path, dest_path)?;
+        } else {
+            fs::copy_item(source_path, dest_path)?;
         }
+        restore_owner(item, dest_path);
+        fs::warn_world_writable(dest_path);
+        hooks::after("restore", item, Some(dest_path));
+
+        status!(
+            "Item '{}' was kept on the stack; its storage remains allocated.",
+            item.original_name
+        );
+
+        if print_path {
+            println!("{}", dest_path.display());
+        }
+
+        return Ok(());
     }
 
-    // Move the item to its original location
-    fs::move_or_copy(&source_path, &dest_path)?;
+    // Move the item to its original location and remove it from the database
+    pop::move_out_of_stack(conn, "restore", item, source_path, dest_path)?;
+    restore_owner(item, dest_path);
 
-    // Remove from database
-    ItemManager::delete(&mut conn, item.id)?;
+    if print_path {
+        println!("{}", dest_path.display());
+    }
 
     Ok(())
 }
+
+/// Copy an item to a destination that needs root privileges through sudo (or doas, or pkexec)
+/// once the user agrees, then drop it from the stack unless `keep` is set. Otherwise print the
+/// commands to run and leave the item untouched.
+fn restore_elevated(
+    conn: &mut Connection,
+    item: &StackItem,
+    source_path: &Path,
+    dest_path: &Path,
+    keep: bool,
+    print_path: bool,
+) -> Result<()> {
+    hooks::before("restore", item, Some(dest_path))?;
+
+    let program = ELEVATION_PROGRAMS
+        .iter()
+        .copied()
+        .find(|program| find_program(program));
+
+    let dest = dest_path.to_string_lossy().to_string();
+    let mut commands = Vec::new();
+    if let Some(parent) = dest_path.parent().filter(|parent| !parent.exists()) {
+        let parent = parent.to_string_lossy().to_string();
+        commands.push(vec!["mkdir".to_string(), "-p".to_string(), parent]);
+    }
+    if item.packed {
+        commands.push(vec!["mkdir".to_string(), "--".to_string(), dest.clone()]);
+        commands.push(vec![
+            "tar".to_string(),
+            "-xpf".to_string(),
+            source_path.to_string_lossy().to_string(),
+            "-C".to_string(),
+            dest.clone(),
+        ]);
+    } else {
+        commands.push(vec![
+            "cp".to_string(),
+            "-a".to_string(),
+            "--".to_string(),
+            source_path.to_string_lossy().to_string(),
+            dest.clone(),
+        ]);
+    }
+    if let (Some(uid), Some(gid)) = (item.owner_uid, item.owner_gid) {
+        commands.push(vec![
+            "chown".to_string(),
+            "-R".to_string(),
+            format!("{}:{}", uid, gid),
+            "--".to_string(),
+            dest,
+        ]);
+    }
+
+    status!(
+        "Restoring '{}' to {} needs root privileges",
+        item.original_name,
+        dest_path.display()
+    );
+
+    if let Some(program) = program {
+        let answer = output::prompt(&format!("Copy it with {}? [y/N]: ", program))?;
+        if answer == "y" || answer == "yes" {
+            for command in &commands {
+                let status = Command::new(program).args(command).status()?;
+                if !status.success() {
+                    return Err(anyhow!(
+                        "{} {} failed ({}); '{}' is still on the stack",
+                        program,
+                        command[0],
+                        status,
+                        item.original_name
+                    ));
+                }
+            }
+
+            if keep {
+                status!(
+                    "Item '{}' was kept on the stack; its storage remains allocated.",
+                    item.original_name
+                );
+            } else {
+                remove::discard_item(conn, "restore", item)?;
+            }
+            hooks::after("restore", item, Some(dest_path));
+            if print_path {
+                println!("{}", dest_path.display());
+            }
+            return Ok(());
+        }
+    }
+
+    status!("To restore it yourself, run:");
+    for command in &commands {
+        let words: Vec<String> = command
+            .iter()
+            .map(|word| {
+                shlex::try_quote(word)
+                    .map(|quoted| quoted.to_string())
+                    .unwrap_or_else(|_| word.clone())
+            })
+            .collect();
+        status!("  {} {}", program.unwrap_or("sudo"), words.join(" "));
+    }
+    if !keep {
+        status!("  fstk --id rm {}", item.id);
+    }
+
+    Err(anyhow!(
+        "'{}' was not restored and is still on the stack",
+        item.original_name
+    ))
+}
+
+/// Restore the entries of a stored directory matching `pattern` into the directory's original
+/// location (or into `to`). Taken entries leave the item partial; it is dropped once empty.
+fn restore_matching(
+    conn: &mut Connection,
+    item: &StackItem,
+    pattern: &str,
+    to: Option<&str>,
+    keep: bool,
+    print_path: bool,
+) -> Result<()> {
+    if item.item_type != "directory" {
+        return Err(anyhow!(
+            "Item '{}' is not a directory; --only applies to directory items",
+            item.original_name
+        ));
+    }
+    if item.packed {
+        return Err(anyhow!(
+            "Item '{}' is stored as an archive; restore it whole instead of using --only",
+            item.original_name
+        ));
+    }
+    let pattern =
+        Pattern::new(pattern).map_err(|e| anyhow!("Invalid pattern '{}': {}", pattern, e))?;
+
+    let stored_dir = get_item_stored_path(item)?;
+    if !stored_dir.exists() {
+        return Err(anyhow!(
+            "Error: Source file missing from storage: {}",
+            stored_dir.display()
+        ));
+    }
+    let entries = matching_entries(&stored_dir, &pattern)?;
+    if entries.is_empty() {
+        return Err(anyhow!(
+            "Nothing in '{}' matches '{}'",
+            item.original_name,
+            pattern
+        ));
+    }
+
+    let dest_root = match to {
+        Some(dir) => Path::new(dir).join(&item.original_name),
+        None => Path::new(&item.original_path).join(&item.original_name),
+    };
+    if fs::needs_privileges(&dest_root) {
+        return Err(anyhow!(
+            "Restoring into {} needs root privileges; restore the whole item instead",
+            dest_root.display()
+        ));
+    }
+
+    // Check every destination first so that a conflict leaves the item untouched
+    if let Some(conflict) = entries
+        .iter()
+        .map(|relative| dest_root.join(relative))
+        .find(|dest_path| fs::check_destination_conflict(dest_path))
+    {
+        return Err(FstkError::DestinationConflict(conflict.to_string_lossy().to_string()).into());
+    }
+
+    hooks::before("restore", item, Some(&dest_root))?;
+    for relative in &entries {
+        let dest_path = dest_root.join(relative);
+        fs::ensure_parent_dirs(&dest_path)?;
+        if keep {
+            fs::copy_item(stored_dir.join(relative), &dest_path)?;
+        } else {
+            pop::extract_entry(conn, "restore", item, relative, &dest_path)?;
+        }
+        restore_owner(item, &dest_path);
+        if print_path {
+            println!("{}", dest_path.display());
+        }
+    }
+    hooks::after("restore", item, Some(&dest_root));
+
+    if keep {
+        status!(
+            "Restored {} entr{} of '{}'; the item was kept on the stack.",
+            entries.len(),
+            if entries.len() == 1 { "y" } else { "ies" },
+            item.original_name
+        );
+    } else if ItemManager::get_by_id(conn, item.id)?.is_some() {
+        status!(
+            "Restored {} entr{} of '{}'; the rest stays on the stack as a partial item.",
+            entries.len(),
+            if entries.len() == 1 { "y" } else { "ies" },
+            item.original_name
+        );
+    } else {
+        status!(
+            "Restored {} entr{} of '{}', which was all of it.",
+            entries.len(),
+            if entries.len() == 1 { "y" } else { "ies" },
+            item.original_name
+        );
+    }
+
+    Ok(())
+}
+
+/// Paths inside `dir` matching `pattern`, relative to it. A matching directory is taken as a
+/// whole. Wildcards do not cross `/`, so `*.rs` only matches at the top and `**/*.rs` anywhere.
+fn matching_entries(dir: &Path, pattern: &Pattern) -> Result<Vec<PathBuf>> {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+
+    let mut entries = Vec::new();
+    let mut walker = WalkDir::new(dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(dir)?.to_path_buf();
+        let matched = pattern.matches_with(&relative.to_string_lossy().replace('\\', "/"), options);
+        if matched {
+            if entry.file_type().is_dir() {
+                walker.skip_current_dir();
+            }
+            entries.push(relative);
+        }
+    }
+    Ok(entries)
+}
+
+/// Whether `program` is an executable on the `PATH`
+fn find_program(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Restore every member of a bundle to its original location, or into `to` if given.
+fn restore_bundle(
+    conn: &mut Connection,
+    item: &StackItem,
+    to: Option<&str>,
+    keep: bool,
+    print_path: bool,
+    skip_existing: bool,
+) -> Result<()> {
+    let destination = |member: &BundleMember| match to {
+        Some(dir) => Path::new(dir).join(&member.name),
+        None => Path::new(&member.original_path).join(&member.name),
+    };
+    let stored_dir = get_item_stored_path(item)?;
+
+    // The hooks cover every member, whether it is restored, copied or already in place
+    hooks::before("restore", item, None)?;
+
+    // Members that an earlier run already restored are left where they are
+    let mut skipped = Vec::new();
+    if skip_existing {
+        for member in BundleManager::get_for_item(conn, item.id)? {
+            let dest_path = destination(&member);
+            let Some(existing) = fs::existing_entry(&dest_path) else {
+                continue;
+            };
+            if already_restored(None, &stored_dir.join(&member.name), &existing)? {
+                if !keep {
+                    pop::discard_entry(conn, "restore", item, Path::new(&member.name))?;
+                }
+                status!("'{}' is already at {}", member.name, existing.display());
+                skipped.push(dest_path);
+            }
+        }
+    }
+
+    let mut paths = if keep {
+        let members: Vec<BundleMember> = BundleManager::get_for_item(conn, item.id)?
+            .into_iter()
+            .filter(|member| !skipped.contains(&destination(member)))
+            .collect();
+
+        if let Some(conflict) = members
+            .iter()
+            .map(destination)
+            .find(|dest_path| fs::check_destination_conflict(dest_path))
+        {
+            return Err(
+                FstkError::DestinationConflict(conflict.to_string_lossy().to_string()).into(),
+            );
+        }
+
+        for member in &members {
+            let size = fs::path_size(&stored_dir.join(&member.name))?;
+            fs::ensure_space(&destination(member), size)?;
+        }
+
+        let mut paths = Vec::new();
+        for member in &members {
+            let dest_path = destination(member);
+            fs::ensure_parent_dirs(&dest_path)?;
+            fs::copy_item(stored_dir.join(&member.name), &dest_path)?;
+            paths.push(dest_path);
+        }
+
+        status!(
+            "Bundle '{}' was kept on the stack; its storage remains allocated.",
+            item.original_name
+        );
+        paths
+    } else if ItemManager::get_by_id(conn, item.id)?.is_some() {
+        pop::extract_members(conn, "restore", item, destination)?
+    } else {
+        // Every member was already in place
+        Vec::new()
+    };
+    hooks::after("restore", item, None);
+
+    if print_path {
+        paths.splice(0..0, skipped);
+        for path in paths {
+            println!("{}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `dest_path` already holds the content stored at `stored_path`, whose checksum is
+/// `expected` if known
+fn already_restored(expected: Option<&str>, stored_path: &Path, dest_path: &Path) -> Result<bool> {
+    if !dest_path.exists() || dest_path.is_dir() != stored_path.is_dir() {
+        return Ok(false);
+    }
+
+    let expected = match expected {
+        Some(hash) => hash.to_string(),
+        None => fs::content_hash(stored_path)?,
+    };
+    Ok(fs::content_hash(dest_path)? == expected)
+}
+
+/// Give a restored item back its original owner and group, warning when that is not possible.
+fn restore_owner(item: &StackItem, dest_path: &Path) {
+    let (uid, gid) = match (item.owner_uid, item.owner_gid) {
+        (Some(uid), Some(gid)) => (uid, gid),
+        _ => return,
+    };
+
+    // Moves within a filesystem keep the owner; only copies need fixing
+    if matches!(fs::get_owner(dest_path), Ok(Some(owner)) if owner == (uid, gid)) {
+        return;
+    }
+
+    match fs::set_owner(dest_path, uid, gid) {
+        Ok(true) => {}
+        Ok(false) => status!(
+            "Warning: '{}' was owned by {}:{}; run with root privileges to restore its ownership",
+            item.original_name,
+            uid,
+            gid
+        ),
+        Err(e) => status!(
+            "Warning: failed to restore the ownership of '{}': {}",
+            item.original_name,
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{schema, ItemMetadata};
+    use tempfile::tempdir;
+
+    fn setup_test_db() -> Result<Connection> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        schema::initialize_schema(&conn)?;
+        Ok(conn)
+    }
+
+    /// A file pushed from `original_dir` whose content is stored in `storage`
+    fn stored_file(
+        conn: &mut Connection,
+        storage: &Path,
+        original_dir: &Path,
+        name: &str,
+        content: &str,
+    ) -> Result<StackItem> {
+        let stored_hash = format!("stored-{}", name);
+        let stored_path = storage.join(&stored_hash);
+        std::fs::write(&stored_path, content)?;
+        let metadata = ItemMetadata {
+            content_hash: Some(fs::content_hash(&stored_path)?),
+            ..Default::default()
+        };
+        let id = ItemManager::insert_with_metadata(
+            conn,
+            name,
+            &original_dir.to_string_lossy(),
+            &stored_hash,
+            "file",
+            &[],
+            &metadata,
+        )?;
+        ItemManager::set_storage_location(conn, id, Some(&storage.to_string_lossy()))?;
+        Ok(ItemManager::get_by_id(conn, id)?.unwrap())
+    }
+
+    #[test]
+    fn test_restore_to_alternate_dir() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let storage = dir.path().join("storage");
+        let original_dir = dir.path().join("gone");
+        std::fs::create_dir(&storage)?;
+        let item = stored_file(&mut conn, &storage, &original_dir, "notes.txt", "hello")?;
+
+        // Missing parents of the alternate directory are created
+        let to = dir.path().join("elsewhere/nested");
+        restore_item(
+            &mut conn,
+            &item,
+            Some(&to.to_string_lossy()),
+            false,
+            false,
+            false,
+            false,
+        )?;
+
+        assert_eq!(std::fs::read_to_string(to.join("notes.txt"))?, "hello");
+        assert!(!original_dir.exists());
+        assert!(!storage.join(&item.stored_hash).exists());
+        assert!(ItemManager::get_by_id(&conn, item.id)?.is_none());
+
+        // An occupied destination in the alternate directory is a conflict
+        let item = stored_file(&mut conn, &storage, &original_dir, "notes.txt", "again")?;
+        let err = restore_item(
+            &mut conn,
+            &item,
+            Some(&to.to_string_lossy()),
+            false,
+            false,
+            false,
+            false,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FstkError>(),
+            Some(FstkError::DestinationConflict(_))
+        ));
+        assert_eq!(std::fs::read_to_string(to.join("notes.txt"))?, "hello");
+        assert!(ItemManager::get_by_id(&conn, item.id)?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_existing_leaves_identical_destination_alone() -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let mut conn = setup_test_db()?;
+        let dir = tempdir()?;
+        let storage = dir.path().join("storage");
+        let original_dir = dir.path().join("work");
+        std::fs::create_dir(&storage)?;
+        std::fs::create_dir(&original_dir)?;
+        let existing = original_dir.join("notes.txt");
+        std::fs::write(&existing, "hello")?;
+        let inode = std::fs::metadata(&existing)?.ino();
+
+        // A destination with other content is still a conflict
+        let other = stored_file(&mut conn, &storage, &original_dir, "notes.txt", "changed")?;
+        let err = restore_item(&mut conn, &other, None, false, false, true, false).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FstkError>(),
+            Some(FstkError::DestinationConflict(_))
+        ));
+        assert!(ItemManager::get_by_id(&conn, other.id)?.is_some());
+        ItemManager::delete(&mut conn, other.id)?;
+
+        // Identical content counts as restored: the item is dropped, the file is not touched
+        let item = stored_file(&mut conn, &storage, &original_dir, "notes.txt", "hello")?;
+        restore_item(&mut conn, &item, None, false, false, true, false)?;
+        assert!(ItemManager::get_by_id(&conn, item.id)?.is_none());
+        assert!(!storage.join(&item.stored_hash).exists());
+        assert_eq!(std::fs::read_to_string(&existing)?, "hello");
+        assert_eq!(std::fs::metadata(&existing)?.ino(), inode);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_entries() {
+        let dir = tempdir().unwrap();
+        for path in ["README.md", "src/lib.rs", "src/util/mod.rs", "tests/it.rs"] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "x").unwrap();
+        }
+        let matching = |pattern: &str| {
+            matching_entries(dir.path(), &Pattern::new(pattern).unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|path| path.to_string_lossy().replace('\\', "/"))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(matching("src/**"), vec!["src/lib.rs", "src/util"]);
+        // Matching directories are taken whole
+        assert_eq!(matching("src"), vec!["src"]);
+        assert_eq!(matching("*.rs"), Vec::<String>::new());
+        assert_eq!(
+            matching("**/*.rs"),
+            vec!["src/lib.rs", "src/util/mod.rs", "tests/it.rs"]
+        );
+    }
+
+    #[test]
+    fn test_locked_items_need_force_unless_kept() {
+        let item = StackItem {
+            original_name: "keepsake.txt".to_string(),
+            locked: true,
+            ..Default::default()
+        };
+
+        let err = ensure_restorable(&item, false, false).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FstkError>(),
+            Some(FstkError::ItemLocked(_))
+        ));
+        assert!(ensure_restorable(&item, false, true).is_ok());
+        // A copy leaves the locked item on the stack
+        assert!(ensure_restorable(&item, true, false).is_ok());
+    }
+
+    #[test]
+    fn test_pushed_from() {
+        let items = [
+            "/home/me/foo",
+            "/home/me/foo/src",
+            "/home/me/foobar",
+            "/home/me",
+        ]
+        .iter()
+        .map(|path| StackItem {
+            original_path: path.to_string(),
+            ..Default::default()
+        })
+        .collect();
+
+        let paths: Vec<String> = pushed_from(items, Path::new("/home/me/foo"))
+            .into_iter()
+            .map(|item| item.original_path)
+            .collect();
+        assert_eq!(paths, vec!["/home/me/foo", "/home/me/foo/src"]);
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+
+use crate::db::{item_number, ItemManager};
+use crate::status;
+use crate::utils::error::FstkError;
+use crate::utils::fuzzy::fuzzy_score;
+use crate::utils::output;
+
+/// Maximum number of candidates offered when a name query is ambiguous
+const MAX_CANDIDATES: usize = 10;
+
+/// Resolve an item argument to a display number.
+/// Numbers are used as-is; anything else is a fuzzy query over item names, and the user is
+/// asked to choose when several items match. Returns `None` if the user cancelled.
+pub fn resolve_number(conn: &Connection, arg: &str, tags: &[String]) -> Result<Option<usize>> {
+    if let Ok(number) = arg.trim().parse::<usize>() {
+        return Ok(Some(number));
+    }
+
+    let mut items = ItemManager::list(conn, tags)?;
+    items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+
+    // (score, display number, name) of every matching item, best first
+    let mut matches: Vec<(i64, usize, String)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            fuzzy_score(arg, &item.original_name)
+                .map(|score| (score, item_number(index, item), item.original_name.clone()))
+        })
+        .collect();
+    matches.sort_by_key(|(score, number, _)| (std::cmp::Reverse(*score), *number));
+
+    match matches.len() {
+        0 => Err(FstkError::ItemNotFound(arg.to_string()).into()),
+        1 => Ok(Some(matches[0].1)),
+        count => {
+            status!("{} items match '{}':", count, arg);
+            let shown = &matches[..count.min(MAX_CANDIDATES)];
+            for (choice, (_, number, name)) in shown.iter().enumerate() {
+                status!("  {}) #{} {}", choice + 1, number, name);
+            }
+
+            let input = output::prompt(&format!(
+                "Select an item [1-{}] (empty to cancel): ",
+                shown.len()
+            ))?;
+            if input.is_empty() {
+                status!("Operation cancelled.");
+                return Ok(None);
+            }
+
+            let choice = input
+                .parse::<usize>()
+                .ok()
+                .filter(|choice| (1..=shown.len()).contains(choice))
+                .ok_or_else(|| anyhow!("Invalid selection: {}", input))?;
+
+            Ok(Some(shown[choice - 1].1))
+        }
+    }
+}
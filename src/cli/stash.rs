@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::process::Command;
+
+use crate::cli::push::{self, PushOptions};
+use crate::config;
+use crate::db::find_git_root;
+use crate::status;
+use crate::utils::git;
+
+/// How a path differs from the checked-out commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChangeKind {
+    /// Not known to git
+    Untracked,
+    /// New in the index, not in the commit
+    Added,
+    /// In the commit, with local changes
+    Modified,
+}
+
+/// A changed file, relative to the repository root
+#[derive(Debug, PartialEq)]
+struct Change {
+    path: String,
+    kind: ChangeKind,
+}
+
+/// Push every modified and untracked file of the current git repository as its own item,
+/// tagged with the repository and branch, leaving the working tree clean: every file is moved
+/// onto the stack, then newly added files are dropped from the index and modified ones are
+/// checked out again, which `restore --overwrite` replaces with the stashed version. Files with
+/// both staged and unstaged changes are skipped, since the staged version would be lost.
+pub fn stash(tags: Option<Vec<String>>, dry_run: bool) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let root = find_git_root(&cwd).ok_or_else(|| anyhow!("Not inside a git repository"))?;
+    let context = git::discover(&root);
+
+    let (changes, skipped) = parse_status(&run_git(
+        &root,
+        &["status", "--porcelain", "-z", "--untracked-files=all"],
+    )?);
+    for (path, reason) in &skipped {
+        status!("Skipping {} ({})", path, reason);
+    }
+    if changes.is_empty() {
+        status!("Nothing to stash in {}", root.display());
+        return Ok(());
+    }
+
+    if dry_run {
+        for change in &changes {
+            status!(
+                "Would stash {} ({})",
+                change.path,
+                match change.kind {
+                    ChangeKind::Untracked => "untracked",
+                    ChangeKind::Added => "added",
+                    ChangeKind::Modified => "modified",
+                }
+            );
+        }
+        return Ok(());
+    }
+
+    let config = config::load()?;
+    let mut stashed = 0;
+    for change in &changes {
+        let path = root.join(&change.path);
+        let options = PushOptions {
+            tags: tags.clone(),
+            git_tags: true,
+            confirm_above: Some(config.confirm_push_size()?),
+            duplicate_paths: config.duplicate_paths,
+            max_items: config.max_items,
+            max_items_policy: config.max_items_policy,
+            ..Default::default()
+        };
+        if push::push(&path.to_string_lossy(), options)?.is_none() {
+            continue;
+        }
+
+        match change.kind {
+            ChangeKind::Untracked => {}
+            ChangeKind::Added => {
+                run_git(&root, &["rm", "--cached", "--quiet", "--", &change.path])?;
+            }
+            ChangeKind::Modified => {
+                run_git(&root, &["checkout", "HEAD", "--", &change.path])?;
+            }
+        }
+        stashed += 1;
+    }
+
+    status!(
+        "Stashed {} file(s) from {}{}",
+        stashed,
+        root.display(),
+        context
+            .and_then(|context| context.branch)
+            .map(|branch| format!(" ({})", branch))
+            .unwrap_or_default()
+    );
+    if stashed > 0 {
+        status!("Bring a file back with 'fstk restore <number> --overwrite'");
+    }
+    Ok(())
+}
+
+/// Run git in `root` and return its standard output
+fn run_git(root: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Cannot run git: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// Read `git status --porcelain -z`: the changes to stash, and the paths that cannot be
+/// stashed, with the reason
+fn parse_status(output: &[u8]) -> (Vec<Change>, Vec<(String, &'static str)>) {
+    let mut changes = Vec::new();
+    let mut skipped = Vec::new();
+
+    let mut records = output.split(|byte| *byte == 0);
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+        let (x, y) = (record[0], record[1]);
+        let path = String::from_utf8_lossy(&record[3..]).to_string();
+
+        let kind = match (x, y) {
+            (b'?', b'?') => Ok(ChangeKind::Untracked),
+            (b'R' | b'C', _) => {
+                // The original path follows as a record of its own
+                records.next();
+                Err("renamed or copied")
+            }
+            (b'D', _) | (_, b'D') => Err("deleted"),
+            (b'U', _) | (_, b'U') | (b'A', b'A') => Err("unmerged"),
+            // Only one version fits on the stack, and resetting the file drops the index
+            (x, y) if x != b' ' && y != b' ' => Err("staged and unstaged changes"),
+            (b'A', _) => Ok(ChangeKind::Added),
+            _ => Ok(ChangeKind::Modified),
+        };
+        match kind {
+            Ok(kind) => changes.push(Change { path, kind }),
+            Err(reason) => skipped.push((path, reason)),
+        }
+    }
+
+    (changes, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let output =
+            b" M src/lib.rs\0?? notes/todo.md\0A  new.rs\0R  moved.rs\0o\0 D gone.rs\0MM both.rs\0M  staged.rs\0";
+        let (changes, skipped) = parse_status(output);
+
+        let kinds: Vec<(&str, ChangeKind)> = changes
+            .iter()
+            .map(|change| (change.path.as_str(), change.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("src/lib.rs", ChangeKind::Modified),
+                ("notes/todo.md", ChangeKind::Untracked),
+                ("new.rs", ChangeKind::Added),
+                ("staged.rs", ChangeKind::Modified),
+            ]
+        );
+        assert_eq!(
+            skipped,
+            vec![
+                ("moved.rs".to_string(), "renamed or copied"),
+                ("gone.rs".to_string(), "deleted"),
+                ("both.rs".to_string(), "staged and unstaged changes"),
+            ]
+        );
+    }
+}
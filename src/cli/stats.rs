@@ -0,0 +1,269 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+use owo_colors::OwoColorize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::cli::top::backfill_sizes;
+use crate::cli::ActivityPeriod;
+use crate::db::{establish_connection, ItemManager, OperationLog, OperationRecord, StackItem};
+use crate::utils::display;
+
+/// Number of days shown by `stats --activity --per day`
+const DAYS_SHOWN: i32 = 14;
+
+/// Number of weeks shown by `stats --activity --per week`
+const WEEKS_SHOWN: i32 = 8;
+
+/// Number of tags listed as the busiest
+const BUSIEST_TAGS: usize = 5;
+
+/// Operation counts of a single day or week
+#[derive(Debug, Default, PartialEq)]
+pub struct PeriodActivity {
+    /// First day of the period
+    pub start: NaiveDate,
+    pub pushes: usize,
+    /// Pops and restores
+    pub pops: usize,
+    pub removes: usize,
+}
+
+/// Activity on the stack over the shown periods
+#[derive(Debug, Default)]
+pub struct Activity {
+    /// Oldest period first
+    pub periods: Vec<PeriodActivity>,
+    /// Average time between push and pop, restore or remove
+    pub average_stay: Option<Duration>,
+    /// (tag, number of operations), busiest first
+    pub busiest_tags: Vec<(String, usize)>,
+}
+
+/// Show a summary of the stack, and its activity over time with `activity`.
+pub fn stats(activity: bool, per: ActivityPeriod) -> Result<()> {
+    let conn = establish_connection()?;
+
+    backfill_sizes(&conn)?;
+    let items = ItemManager::list(&conn, &[])?;
+
+    let total_size: u64 = items.iter().filter_map(|item| item.size).sum();
+    println!("{:<16}{}", "Items:", items.len());
+    println!("{:<16}{}", "Total size:", display::format_size(total_size));
+    let files: u64 = items.iter().filter_map(|item| item.file_count).sum();
+    println!("{:<16}{}", "Files:", display::format_count(files));
+    if let Some(speed) = push_speed(&items) {
+        println!(
+            "{:<16}{}/s",
+            "Push speed:",
+            display::format_size(speed as u64)
+        );
+    }
+    println!(
+        "{:<16}{}",
+        "Pinned:",
+        items.iter().filter(|item| item.pinned).count()
+    );
+    println!(
+        "{:<16}{}",
+        "Locked:",
+        items.iter().filter(|item| item.locked).count()
+    );
+    if let Some(oldest) = items.iter().map(|item| item.pushed_at).min() {
+        println!("{:<16}{}", "Oldest item:", display::format_age(oldest));
+    }
+
+    if !activity {
+        return Ok(());
+    }
+
+    let now = Local::now();
+    let last = period_start(now.date_naive(), per);
+    let window_start = last - period_length(per) * (shown(per) - 1);
+    let since = window_start
+        .and_hms_opt(0, 0, 0)
+        .and_then(|start| start.and_local_timezone(Local).earliest());
+    let records = OperationLog::list_since(&conn, since)?;
+    let summary = summarize(&records, per, now);
+
+    println!();
+    println!(
+        "{}",
+        match per {
+            ActivityPeriod::Day => "Activity per day",
+            ActivityPeriod::Week => "Activity per week",
+        }
+        .bold()
+    );
+    display::display_activity_table(&summary.periods);
+
+    match summary.average_stay {
+        Some(stay) => println!(
+            "Items stay on the stack for {} on average",
+            display::format_duration(stay)
+        ),
+        None => println!("No items left the stack in this period"),
+    }
+
+    if !summary.busiest_tags.is_empty() {
+        println!();
+        println!("{}", "Busiest tags".bold());
+        display::display_tag_activity_table(&summary.busiest_tags);
+    }
+
+    Ok(())
+}
+
+/// Average bytes per second over the pushes that recorded how long they took; pushes faster
+/// than a millisecond tell nothing and are left out
+fn push_speed(items: &[StackItem]) -> Option<f64> {
+    let (bytes, millis) = items
+        .iter()
+        .filter_map(|item| Some((item.size?, item.push_millis.filter(|ms| *ms > 0)?)))
+        .fold((0, 0), |(bytes, millis), (size, ms)| {
+            (bytes + size, millis + ms)
+        });
+    (millis > 0).then(|| bytes as f64 * 1000.0 / millis as f64)
+}
+
+/// Count operations per period over the shown window ending at `now`.
+pub fn summarize(
+    records: &[OperationRecord],
+    per: ActivityPeriod,
+    now: DateTime<Local>,
+) -> Activity {
+    let last = period_start(now.date_naive(), per);
+    let mut periods: BTreeMap<NaiveDate, PeriodActivity> = (0..shown(per))
+        .map(|offset| {
+            let start = last - period_length(per) * offset;
+            (
+                start,
+                PeriodActivity {
+                    start,
+                    ..Default::default()
+                },
+            )
+        })
+        .collect();
+
+    let mut stays = Vec::new();
+    let mut tag_counts: HashMap<&str, usize> = HashMap::new();
+
+    for record in records {
+        let start = period_start(record.performed_at.date_naive(), per);
+        let Some(period) = periods.get_mut(&start) else {
+            continue;
+        };
+
+        match record.operation.as_str() {
+            "push" => period.pushes += 1,
+            "pop" | "restore" => period.pops += 1,
+            "remove" => period.removes += 1,
+            _ => continue,
+        }
+        if record.operation != "push" {
+            stays.push(record.performed_at - record.item_pushed_at);
+        }
+
+        for tag in &record.tags {
+            *tag_counts.entry(tag).or_default() += 1;
+        }
+    }
+
+    let average_stay = (!stays.is_empty()).then(|| {
+        let total = stays.iter().fold(Duration::zero(), |sum, stay| sum + *stay);
+        total / stays.len() as i32
+    });
+
+    let mut busiest_tags: Vec<(String, usize)> = tag_counts
+        .into_iter()
+        .map(|(tag, count)| (tag.to_string(), count))
+        .collect();
+    busiest_tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    busiest_tags.truncate(BUSIEST_TAGS);
+
+    Activity {
+        periods: periods.into_values().collect(),
+        average_stay,
+        busiest_tags,
+    }
+}
+
+/// First day of the period containing `date` (weeks start on Monday)
+fn period_start(date: NaiveDate, per: ActivityPeriod) -> NaiveDate {
+    match per {
+        ActivityPeriod::Day => date,
+        ActivityPeriod::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+    }
+}
+
+fn period_length(per: ActivityPeriod) -> Duration {
+    match per {
+        ActivityPeriod::Day => Duration::days(1),
+        ActivityPeriod::Week => Duration::weeks(1),
+    }
+}
+
+fn shown(per: ActivityPeriod) -> i32 {
+    match per {
+        ActivityPeriod::Day => DAYS_SHOWN,
+        ActivityPeriod::Week => WEEKS_SHOWN,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(operation: &str, tags: &[&str], age_days: i64, stay_days: i64) -> OperationRecord {
+        let performed_at = Local::now() - Duration::days(age_days);
+        OperationRecord {
+            operation: operation.to_string(),
+            item_id: 1,
+            item_name: "notes.md".to_string(),
+            original_path: "/home/user".to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            item_pushed_at: performed_at - Duration::days(stay_days),
+            performed_at,
+        }
+    }
+
+    #[test]
+    fn test_summarize_per_day() {
+        let records = vec![
+            record("push", &["work"], 3, 0),
+            record("push", &["work", "q3"], 1, 0),
+            record("pop", &["work"], 1, 2),
+            record("remove", &[], 0, 4),
+            // Outside the window
+            record("push", &["old"], DAYS_SHOWN as i64 + 5, 0),
+        ];
+
+        let summary = summarize(&records, ActivityPeriod::Day, Local::now());
+        assert_eq!(summary.periods.len(), DAYS_SHOWN as usize);
+
+        let today = summary.periods.last().unwrap();
+        assert_eq!(today.start, Local::now().date_naive());
+        assert_eq!(today.removes, 1);
+
+        let yesterday = &summary.periods[summary.periods.len() - 2];
+        assert_eq!((yesterday.pushes, yesterday.pops), (1, 1));
+
+        assert_eq!(summary.average_stay, Some(Duration::days(3)));
+        assert_eq!(
+            summary.busiest_tags,
+            vec![("work".to_string(), 3), ("q3".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_summarize_per_week() {
+        let records = vec![record("push", &[], 0, 0), record("restore", &[], 0, 1)];
+
+        let summary = summarize(&records, ActivityPeriod::Week, Local::now());
+        assert_eq!(summary.periods.len(), WEEKS_SHOWN as usize);
+
+        let this_week = summary.periods.last().unwrap();
+        assert_eq!(this_week.start.weekday(), chrono::Weekday::Mon);
+        assert_eq!((this_week.pushes, this_week.pops), (1, 1));
+    }
+}
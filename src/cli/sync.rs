@@ -0,0 +1,465 @@
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cli::remote::{receive_verified, Remote};
+use crate::cli::remove;
+use crate::db::{
+    establish_connection, get_data_dir, get_item_stored_path, BundleMember, ItemManager,
+    ItemMetadata, StackItem, SyncStateManager,
+};
+use crate::fs;
+use crate::utils::http::ByteCounter;
+
+/// What `sync` does about one item
+#[derive(Debug, PartialEq)]
+enum SyncAction {
+    /// New on the remote: copy it here
+    Download,
+    /// New here: copy it to the remote
+    Upload,
+    /// Popped or removed on the remote since the last sync
+    DeleteLocal,
+    /// Popped or removed here since the last sync
+    DeleteRemote,
+    /// Left out of the sync, for the given reason
+    Skip(String),
+    /// Needs a decision; both stacks are left as they are
+    Conflict(String),
+}
+
+impl SyncAction {
+    fn describe(&self) -> String {
+        match self {
+            SyncAction::Download => "copy here".to_string(),
+            SyncAction::Upload => "copy to remote".to_string(),
+            SyncAction::DeleteLocal => "remove here".to_string(),
+            SyncAction::DeleteRemote => "remove on remote".to_string(),
+            SyncAction::Skip(reason) => format!("skip ({})", reason),
+            SyncAction::Conflict(reason) => format!("CONFLICT: {}", reason),
+        }
+    }
+}
+
+/// Reconcile the active stack with the stack served by `fstk daemon --http` at `url`.
+/// Items new on either side are copied to the other, items popped or removed on one side since
+/// the last sync are removed on the other, and conflicts are reported without touching either.
+/// With `dry_run`, only the planned actions are listed.
+pub fn sync(url: &str, dry_run: bool) -> Result<()> {
+    let remote = Remote::new(url)?;
+    let mut conn = establish_connection()?;
+
+    let remote_items = remote.items(&[])?;
+    if remote_items.iter().any(|item| item.uuid.is_empty()) {
+        return Err(anyhow!(
+            "{} does not report item UUIDs; upgrade fstk there first",
+            remote.address
+        ));
+    }
+    let local_items = ItemManager::list(&conn, &[])?;
+
+    let local: HashMap<&str, &StackItem> = local_items
+        .iter()
+        .map(|item| (item.uuid.as_str(), item))
+        .collect();
+    let theirs: HashMap<&str, &StackItem> = remote_items
+        .iter()
+        .map(|item| (item.uuid.as_str(), item))
+        .collect();
+    let synced = SyncStateManager::get_synced(&conn, &remote.address)?;
+
+    let actions = plan(&local, &theirs, &synced);
+    if actions.is_empty() {
+        println!("Already in sync with {}", remote.address);
+    }
+
+    // Items on both sides stay synced; the actions below add and drop the others
+    let mut now_synced: HashSet<String> = local
+        .keys()
+        .filter(|uuid| theirs.contains_key(*uuid))
+        .map(|uuid| uuid.to_string())
+        .collect();
+    let mut failed_count = 0;
+    let mut conflict_count = 0;
+
+    for (uuid, action) in &actions {
+        let item = local
+            .get(uuid.as_str())
+            .or(theirs.get(uuid.as_str()))
+            .unwrap();
+        let line = format!("{:<18} {}", action.describe(), item.original_name);
+
+        if dry_run {
+            println!("{}", line);
+            continue;
+        }
+
+        let result = match action {
+            SyncAction::Download => remote
+                .request("GET", &format!("/items/{}/content", item.id))
+                .and_then(|body| store_received(&mut conn, item, body).map(|_| ())),
+            SyncAction::Upload => {
+                get_item_stored_path(item).and_then(|stored_path| remote.upload(item, &stored_path))
+            }
+            SyncAction::DeleteLocal => remove::remove_item(&mut conn, item),
+            SyncAction::DeleteRemote => remote
+                .request("DELETE", &format!("/items/{}", item.id))
+                .map(|_| ()),
+            SyncAction::Skip(_) => Ok(()),
+            SyncAction::Conflict(_) => {
+                conflict_count += 1;
+                Ok(())
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                println!("{}", line);
+                match action {
+                    SyncAction::Download | SyncAction::Upload => {
+                        now_synced.insert(uuid.clone());
+                    }
+                    // Keep reporting the conflict until it is resolved on one side
+                    SyncAction::Conflict(_) if synced.contains(uuid) => {
+                        now_synced.insert(uuid.clone());
+                    }
+                    _ => {}
+                }
+            }
+            Err(e) => {
+                println!("{} - failed: {}", line, e);
+                failed_count += 1;
+                // A deletion that did not go through is tried again next time
+                if matches!(action, SyncAction::DeleteLocal | SyncAction::DeleteRemote) {
+                    now_synced.insert(uuid.clone());
+                }
+            }
+        }
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    SyncStateManager::set_synced(&mut conn, &remote.address, &now_synced)?;
+
+    if !actions.is_empty() {
+        println!(
+            "Synced with {}: {} action(s), {} conflict(s), {} failed",
+            remote.address,
+            actions.len() - conflict_count - failed_count,
+            conflict_count,
+            failed_count
+        );
+    }
+
+    if failed_count > 0 {
+        Err(anyhow!("{} sync action(s) failed", failed_count))
+    } else {
+        Ok(())
+    }
+}
+
+/// Decide what to do about each item that is on only one side or differs between the sides.
+/// `synced` holds the UUIDs both sides had after the last sync: an item missing on one side
+/// was removed there if it is in `synced`, and is new on the other side otherwise.
+fn plan(
+    local: &HashMap<&str, &StackItem>,
+    remote: &HashMap<&str, &StackItem>,
+    synced: &HashSet<String>,
+) -> Vec<(String, SyncAction)> {
+    let uuids: BTreeSet<&str> = local.keys().chain(remote.keys()).copied().collect();
+    let mut actions = Vec::new();
+
+    for uuid in uuids {
+        let action = match (local.get(uuid), remote.get(uuid)) {
+            (Some(ours), Some(theirs)) => {
+                if ours.content_hash.is_some()
+                    && theirs.content_hash.is_some()
+                    && ours.content_hash != theirs.content_hash
+                {
+                    SyncAction::Conflict("content differs between the stacks".to_string())
+                } else {
+                    continue;
+                }
+            }
+            (Some(ours), None) if synced.contains(uuid) => {
+                if ours.locked {
+                    SyncAction::Conflict("removed on the remote, but locked here".to_string())
+                } else {
+                    SyncAction::DeleteLocal
+                }
+            }
+            (None, Some(theirs)) if synced.contains(uuid) => {
+                if theirs.locked {
+                    SyncAction::Conflict("removed here, but locked on the remote".to_string())
+                } else {
+                    SyncAction::DeleteRemote
+                }
+            }
+            (Some(ours), None) => match skip_reason(ours) {
+                Some(reason) => SyncAction::Skip(reason),
+                None => SyncAction::Upload,
+            },
+            (None, Some(theirs)) => match skip_reason(theirs) {
+                Some(reason) => SyncAction::Skip(reason),
+                None => SyncAction::Download,
+            },
+            (None, None) => continue,
+        };
+        actions.push((uuid.to_string(), action));
+    }
+
+    actions
+}
+
+fn skip_reason(item: &StackItem) -> Option<String> {
+    if item.is_bundle() {
+        Some("bundles are not synced".to_string())
+    } else if item.partial {
+        Some("partially popped".to_string())
+    } else {
+        None
+    }
+}
+
+/// Store an item received from another stack, keeping its UUID, push time, tags and pin state.
+/// `body` is its content as written by `write_content`.
+pub(crate) fn store_received<R: Read>(
+    conn: &mut Connection,
+    item: &StackItem,
+    body: R,
+) -> Result<i64> {
+    let (hash, target_path) = free_target(conn, item, &get_data_dir()?)?;
+    let staged_path = fs::staging_path(&target_path);
+    receive_verified(body, item, &staged_path)?;
+
+    commit_received(conn, item, Vec::new(), &hash, &staged_path, &target_path)
+}
+
+/// Pick the stored hash and path in `data_dir` for an item from another stack.
+/// The hash is always generated here: the one the other side sent names a path, and it comes
+/// from whoever sent the item. Fails if the item's name is not a plain file name, since pop
+/// and restore join it to their destination.
+pub(crate) fn free_target(
+    conn: &Connection,
+    item: &StackItem,
+    data_dir: &Path,
+) -> Result<(String, PathBuf)> {
+    if !fs::is_plain_name(&item.original_name) {
+        return Err(anyhow!(
+            "Refusing item with invalid name '{}'",
+            item.original_name
+        ));
+    }
+
+    let mut hash = String::new();
+    while hash.is_empty()
+        || ItemManager::get_by_stored_hash(conn, &hash)?.is_some()
+        || data_dir.join(&hash).exists()
+    {
+        hash = fs::generate_hash(
+            &data_dir.join(&item.original_name),
+            item.is_stored_as_directory(),
+        )?;
+    }
+
+    let target_path = data_dir.join(&hash);
+    Ok((hash, target_path))
+}
+
+/// Record an item from another stack whose verified content is at `staged_path`, then move
+/// the content to `target_path`. The staged content is removed if that fails.
+pub(crate) fn commit_received(
+    conn: &mut Connection,
+    item: &StackItem,
+    members: Vec<BundleMember>,
+    hash: &str,
+    staged_path: &Path,
+    target_path: &Path,
+) -> Result<i64> {
+    let manifest = if item.is_stored_as_directory() {
+        match fs::build_manifest(staged_path) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let _ = fs::remove_item(staged_path);
+                return Err(e);
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let metadata = ItemMetadata {
+        content_hash: item.content_hash.clone(),
+        size: item.size,
+        manifest,
+        pushed_at: Some(item.pushed_at),
+        pinned: item.pinned,
+        locked: item.locked,
+        owner: item.owner_uid.zip(item.owner_gid),
+        version_group: item.version_group.clone(),
+        members,
+        uuid: Some(item.uuid.clone()).filter(|uuid| !uuid.is_empty()),
+        note: item.note.clone(),
+        push_dir: item.push_dir.clone(),
+        copied: item.copied,
+        remote_origin: item.remote_origin.clone(),
+        file_count: item.file_count,
+        push_millis: item.push_millis,
+        packed: item.packed,
+        provenance: item.provenance.clone(),
+    };
+    let item_id = match ItemManager::insert_with_metadata(
+        conn,
+        &item.original_name,
+        &item.original_path,
+        hash,
+        &item.item_type,
+        &item.tags,
+        &metadata,
+    ) {
+        Ok(id) => id,
+        Err(e) => {
+            let _ = fs::remove_item(staged_path);
+            return Err(e);
+        }
+    };
+
+    if let Err(e) = std::fs::rename(staged_path, target_path) {
+        let _ = ItemManager::delete(conn, item_id);
+        let _ = fs::remove_item(staged_path);
+        return Err(e.into());
+    }
+
+    Ok(item_id)
+}
+
+/// Write an item's stored content for another stack: the file itself, or a tar stream of a directory
+pub(crate) fn write_content<W: Write>(stored_path: &Path, writer: &mut W) -> Result<()> {
+    if stored_path.is_dir() {
+        let mut archive = tar::Builder::new(writer);
+        archive.append_dir_all(".", stored_path)?;
+        archive.finish()?;
+    } else {
+        std::io::copy(&mut std::fs::File::open(stored_path)?, writer)?;
+    }
+    Ok(())
+}
+
+/// Number of bytes `write_content` writes for `stored_path`
+pub(crate) fn content_length(stored_path: &Path) -> Result<u64> {
+    if stored_path.is_dir() {
+        let mut counter = ByteCounter::default();
+        write_content(stored_path, &mut counter)?;
+        Ok(counter.0)
+    } else {
+        Ok(std::fs::metadata(stored_path)?.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(uuid: &str, name: &str) -> StackItem {
+        StackItem {
+            uuid: uuid.to_string(),
+            original_name: name.to_string(),
+            item_type: "file".to_string(),
+            content_hash: Some(format!("hash-{}", name)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_commit_received_keeps_pin_and_lock() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut conn = Connection::open_in_memory()?;
+        crate::db::schema::initialize_schema(&conn)?;
+
+        let received = StackItem {
+            pinned: true,
+            locked: true,
+            content_hash: None,
+            tags: vec!["keep".to_string()],
+            ..item("6f1c", "keepsake.txt")
+        };
+        let staged_path = dir.path().join("staged");
+        let target_path = dir.path().join("target");
+        std::fs::write(&staged_path, "content")?;
+
+        let id = commit_received(
+            &mut conn,
+            &received,
+            Vec::new(),
+            "target",
+            &staged_path,
+            &target_path,
+        )?;
+        let stored = ItemManager::get_by_id(&conn, id)?.unwrap();
+        assert!(stored.pinned);
+        assert!(stored.locked);
+        assert_eq!(stored.uuid, "6f1c");
+        assert_eq!(stored.tags, vec!["keep"]);
+        assert_eq!(std::fs::read_to_string(&target_path)?, "content");
+        assert!(!staged_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan() {
+        let both = item("1", "both.txt");
+        let new_here = item("2", "new-here.txt");
+        let new_there = item("3", "new-there.txt");
+        let gone_there = item("4", "gone-there.txt");
+        let gone_here = item("5", "gone-here.txt");
+        let locked = StackItem {
+            locked: true,
+            ..item("6", "locked.txt")
+        };
+        let changed = StackItem {
+            content_hash: Some("other".to_string()),
+            ..both.clone()
+        };
+        let bundle = StackItem {
+            item_type: "bundle".to_string(),
+            ..item("7", "bundle")
+        };
+
+        let local: HashMap<&str, &StackItem> = [&both, &new_here, &gone_there, &locked, &bundle]
+            .into_iter()
+            .map(|item| (item.uuid.as_str(), item))
+            .collect();
+        let remote: HashMap<&str, &StackItem> = [&changed, &new_there, &gone_here]
+            .into_iter()
+            .map(|item| (item.uuid.as_str(), item))
+            .collect();
+        let synced: HashSet<String> = ["1", "4", "5", "6"].map(String::from).into();
+
+        assert_eq!(
+            plan(&local, &remote, &synced),
+            vec![
+                (
+                    "1".to_string(),
+                    SyncAction::Conflict("content differs between the stacks".to_string())
+                ),
+                ("2".to_string(), SyncAction::Upload),
+                ("3".to_string(), SyncAction::Download),
+                ("4".to_string(), SyncAction::DeleteLocal),
+                ("5".to_string(), SyncAction::DeleteRemote),
+                (
+                    "6".to_string(),
+                    SyncAction::Conflict("removed on the remote, but locked here".to_string())
+                ),
+                (
+                    "7".to_string(),
+                    SyncAction::Skip("bundles are not synced".to_string())
+                ),
+            ]
+        );
+    }
+}
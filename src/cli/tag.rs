@@ -1,87 +1,214 @@
-use anyhow::{anyhow, Result};
-
-use crate::db::{establish_connection, ItemManager, TagManager};
-use crate::utils::display;
-
-/// Add tags to an item in the stack.
-pub fn add_tags(number: usize, tags: Vec<String>) -> Result<()> {
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::cli::{top, ListFormat};
+use crate::db::{
+    establish_connection, id_addressing, ItemManager, StackItem, TagManager, TagUsageManager,
+};
+use crate::utils::error::FstkError;
+use crate::utils::numbers::parse_number_range;
+use crate::utils::{display, nuon};
+
+/// Add tags to items in the stack.
+pub fn add_tags(numbers: &str, tags: Vec<String>) -> Result<()> {
     // Connect to database
     let mut conn = establish_connection()?;
 
-    // Get empty tags vector for display number lookup
-    let empty_tags = Vec::new();
-
-    // Important: For tag commands, always find item by number in the full list
-    // because the --tags option is used for the tags to add
-    let id = ItemManager::get_id_by_display_number(&conn, number, &empty_tags)?
-        .ok_or_else(|| anyhow!("No item found with number={}", number))?;
-
-    // Check if item exists (no need to store it since we removed the success message)
-    ItemManager::get_by_id(&conn, id)?
-        .ok_or_else(|| anyhow!("No item found with number={}", number))?;
-
-    // Add tags
-    let added = TagManager::add_to_item(&mut conn, id, &tags)?;
-
-    // Only show message for error cases
-    if added == 0 {
-        println!("No new tags were added (all tags already exist)");
+    let items = resolve_items(&conn, numbers, id_addressing())?;
+    let single = items.len() == 1;
+    for (number, item) in items {
+        let added = TagManager::add_to_item(&mut conn, item.id, &tags)?;
+        // Tagging an item later teaches the suggestions of `push --interactive` as well
+        let contexts = TagUsageManager::contexts(
+            &item.original_path,
+            &item.original_name,
+            item.item_type == "directory",
+        );
+        TagUsageManager::record(&conn, &tags, &contexts)?;
+
+        if !single {
+            println!("#{} {}: {} tag(s) added", number, item.original_name, added);
+        } else if added == 0 {
+            // Only show message for error cases
+            println!("No new tags were added (all tags already exist)");
+        }
     }
 
     Ok(())
 }
 
-/// Remove tags from an item in the stack.
-pub fn remove_tags(number: usize, tags: Vec<String>) -> Result<()> {
+/// Remove tags from items in the stack.
+pub fn remove_tags(numbers: &str, tags: Vec<String>) -> Result<()> {
     // Connect to database
     let mut conn = establish_connection()?;
 
-    // Get empty tags vector for display number lookup
-    let empty_tags = Vec::new();
-
-    // Important: For tag commands, always find item by number in the full list
-    // because the --tags option is used for the tags to remove
-    let id = ItemManager::get_id_by_display_number(&conn, number, &empty_tags)?
-        .ok_or_else(|| anyhow!("No item found with number={}", number))?;
+    let items = resolve_items(&conn, numbers, id_addressing())?;
+    let single = items.len() == 1;
+    for (number, item) in items {
+        let removed = TagManager::remove_from_item(&mut conn, item.id, &tags)?;
+
+        if !single {
+            println!(
+                "#{} {}: {} tag(s) removed",
+                number, item.original_name, removed
+            );
+        } else if removed == 0 {
+            // Only show message for error cases
+            println!("No tags were removed (tags do not exist for this item)");
+        }
+    }
 
-    // Check if item exists (no need to store it since we removed the success message)
-    ItemManager::get_by_id(&conn, id)?
-        .ok_or_else(|| anyhow!("No item found with number={}", number))?;
+    Ok(())
+}
 
-    // Remove tags
-    let removed = TagManager::remove_from_item(&mut conn, id, &tags)?;
+/// Replace the tags of items in the stack.
+pub fn set_tags(numbers: &str, tags: Vec<String>) -> Result<()> {
+    let mut conn = establish_connection()?;
 
-    // Only show message for error cases
-    if removed == 0 {
-        println!("No tags were removed (tags do not exist for this item)");
+    let items = resolve_items(&conn, numbers, id_addressing())?;
+    let single = items.len() == 1;
+    for (number, item) in items {
+        let (added, removed) = TagManager::set_for_item(&mut conn, item.id, &tags)?;
+
+        if !single {
+            println!(
+                "#{} {}: {} tag(s) added, {} removed",
+                number, item.original_name, added, removed
+            );
+        } else if added == 0 && removed == 0 {
+            println!("No tags were changed (the item already has exactly these tags)");
+        }
     }
 
     Ok(())
 }
 
-/// List all tags in the system with usage count.
-pub fn list_tags() -> Result<()> {
+/// Look up every item of a number range expression before changing any of them.
+/// For tag commands the numbers always refer to the full list, because --tags holds the tags
+/// to change rather than selecting items. With `by_id` the numbers are database IDs.
+fn resolve_items(conn: &Connection, numbers: &str, by_id: bool) -> Result<Vec<(usize, StackItem)>> {
+    let mut items = Vec::new();
+    for number in parse_number_range(numbers)? {
+        let id = ItemManager::get_id_by_number(conn, number, &[], by_id)?
+            .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+        let item = ItemManager::get_by_id(conn, id)?
+            .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+        items.push((number, item));
+    }
+    Ok(items)
+}
+
+/// Metadata of a single tag as written by `tag list --format json|nuon`
+#[derive(Debug, Serialize)]
+struct TagRecord {
+    id: i64,
+    name: String,
+    count: i64,
+    size_bytes: u64,
+    last_used: Option<String>,
+}
+
+/// List all tags in the system with usage count, as a table or as JSON or NUON records.
+pub fn list_tags(sizes: bool, format: ListFormat) -> Result<()> {
     // Connect to database
     let conn = establish_connection()?;
 
+    if sizes || format != ListFormat::Table {
+        top::backfill_sizes(&conn)?;
+    }
+
     // Clean up unused tags silently
     TagManager::delete_unused_tags(&conn)?;
 
     // Get all tags
     let tags = TagManager::list_all(&conn)?;
 
+    // Sort tags by usage count (highest usage first)
+    let mut sorted_tags = tags.clone();
+    sorted_tags.sort_by_key(|tag| std::cmp::Reverse(tag.count));
+
+    if format != ListFormat::Table {
+        let records: Vec<TagRecord> = sorted_tags
+            .iter()
+            .map(|tag| TagRecord {
+                id: tag.id,
+                name: tag.name.clone(),
+                count: tag.count,
+                size_bytes: tag.total_size,
+                last_used: tag.last_used.map(|date| date.to_rfc3339()),
+            })
+            .collect();
+
+        match format {
+            ListFormat::Nuon => println!("{}", nuon::to_nuon(&records)?),
+            ListFormat::Jsonl => {
+                for record in &records {
+                    println!("{}", serde_json::to_string(record)?);
+                }
+            }
+            _ => println!("{}", serde_json::to_string_pretty(&records)?),
+        }
+        return Ok(());
+    }
+
     // Check if there are any tags
     if tags.is_empty() {
         println!("No tags found in the system.");
         return Ok(());
     }
 
-    // Sort tags by usage count (highest usage first)
-    let mut sorted_tags = tags.clone();
-    sorted_tags.sort_by(|a, b| b.2.cmp(&a.2));
-
     // Display the tags table
-    display::display_tags_table(&sorted_tags);
+    display::display_tags_table(&sorted_tags, sizes);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+
+    #[test]
+    fn test_resolve_items() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+        let mut ids = Vec::new();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            ids.push(ItemManager::insert(
+                &mut conn,
+                name,
+                "/p",
+                name,
+                "file",
+                &[],
+            )?);
+        }
+        let names = |items: Vec<(usize, StackItem)>| {
+            items
+                .into_iter()
+                .map(|(number, item)| (number, item.original_name))
+                .collect::<Vec<_>>()
+        };
+
+        // Display numbers count from the newest item
+        assert_eq!(
+            names(resolve_items(&conn, "1,3", false)?),
+            [(1, "c.txt".to_string()), (3, "a.txt".to_string())]
+        );
+
+        // With --id, the numbers are the IDs, and a missing ID fails before anything changes
+        let range = format!("{}-{}", ids[0], ids[1]);
+        assert_eq!(
+            names(resolve_items(&conn, &range, true)?),
+            [
+                (ids[0] as usize, "a.txt".to_string()),
+                (ids[1] as usize, "b.txt".to_string())
+            ]
+        );
+        let missing = (ids[2] + 1).to_string();
+        assert!(resolve_items(&conn, &missing, true).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Result};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config;
+use crate::db::{get_fstk_dir, get_project_root};
+use crate::status;
+use crate::utils::duration::parse_duration;
+
+/// A periodic `fstk prune --policy` run for the active stack, as a systemd user timer on Linux
+/// or a launchd agent on macOS
+#[derive(Debug)]
+struct PruneTimer {
+    /// Unit or agent name: `fstk-prune`, or `fstk-prune-<hash>` for a project stack
+    name: String,
+    /// Program and arguments to run
+    command: Vec<String>,
+    /// Directory to run in, so that a project stack is found again
+    working_dir: Option<PathBuf>,
+    /// Seconds between two runs
+    interval: i64,
+    /// Where launchd writes the output of the runs (systemd keeps it in the journal)
+    log_path: PathBuf,
+}
+
+impl PruneTimer {
+    /// The timer for the active stack, running every `every` (like "1d" or "12h")
+    fn for_active_stack(every: &str) -> Result<Self> {
+        let interval = parse_duration(every)?.num_seconds();
+        if interval < 60 {
+            return Err(anyhow!("Prune at most once a minute, not every {}", every));
+        }
+
+        let program = env::current_exe()?.to_string_lossy().to_string();
+        let project_root = get_project_root();
+        let (name, scope) = match &project_root {
+            Some(root) => {
+                use sha2::{Digest, Sha256};
+                let hash = hex::encode(Sha256::digest(root.to_string_lossy().as_bytes()));
+                (format!("fstk-prune-{}", &hash[..8]), "--local")
+            }
+            None => ("fstk-prune".to_string(), "--global"),
+        };
+
+        Ok(PruneTimer {
+            name,
+            command: vec![
+                program,
+                scope.into(),
+                "prune".into(),
+                "--policy".into(),
+                "--yes".into(),
+            ],
+            working_dir: project_root,
+            interval,
+            log_path: get_fstk_dir()?.join("prune.log"),
+        })
+    }
+
+    fn systemd_service(&self) -> String {
+        let mut unit = format!(
+            "[Unit]\nDescription=Prune expired fstk items\n\n[Service]\nType=oneshot\nExecStart={}\n",
+            self.command
+                .iter()
+                .map(|arg| systemd_quote(arg))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        // WorkingDirectory= takes the path as it is, quotes included; only specifiers expand
+        if let Some(dir) = &self.working_dir {
+            unit.push_str(&format!(
+                "WorkingDirectory={}\n",
+                dir.to_string_lossy().replace('%', "%%")
+            ));
+        }
+        unit
+    }
+
+    fn systemd_timer(&self) -> String {
+        format!(
+            "[Unit]\nDescription=Prune expired fstk items periodically\n\n\
+             [Timer]\nOnBootSec=15min\nOnUnitActiveSec={}s\n\n\
+             [Install]\nWantedBy=timers.target\n",
+            self.interval
+        )
+    }
+
+    fn launchd_plist(&self) -> String {
+        let arguments: String = self
+            .command
+            .iter()
+            .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+            .collect();
+        let working_dir = self
+            .working_dir
+            .as_ref()
+            .map(|dir| {
+                format!(
+                    "    <key>WorkingDirectory</key>\n    <string>{}</string>\n",
+                    xml_escape(&dir.to_string_lossy())
+                )
+            })
+            .unwrap_or_default();
+        let log = xml_escape(&self.log_path.to_string_lossy());
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+             \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n<dict>\n\
+             \x20   <key>Label</key>\n    <string>{}</string>\n\
+             \x20   <key>ProgramArguments</key>\n    <array>\n{}    </array>\n\
+             {}\
+             \x20   <key>StartInterval</key>\n    <integer>{}</integer>\n\
+             \x20   <key>StandardOutPath</key>\n    <string>{}</string>\n\
+             \x20   <key>StandardErrorPath</key>\n    <string>{}</string>\n\
+             </dict>\n</plist>\n",
+            xml_escape(&self.name),
+            arguments,
+            working_dir,
+            self.interval,
+            log,
+            log
+        )
+    }
+
+    /// The files to write, with their contents
+    fn files(&self) -> Result<Vec<(PathBuf, String)>> {
+        if cfg!(target_os = "macos") {
+            let dir = dirs::home_dir()
+                .ok_or_else(|| anyhow!("Could not determine home directory"))?
+                .join("Library/LaunchAgents");
+            Ok(vec![(
+                dir.join(format!("{}.plist", self.name)),
+                self.launchd_plist(),
+            )])
+        } else if cfg!(unix) {
+            let dir = dirs::config_dir()
+                .ok_or_else(|| anyhow!("Could not determine the configuration directory"))?
+                .join("systemd/user");
+            Ok(vec![
+                (
+                    dir.join(format!("{}.service", self.name)),
+                    self.systemd_service(),
+                ),
+                (
+                    dir.join(format!("{}.timer", self.name)),
+                    self.systemd_timer(),
+                ),
+            ])
+        } else {
+            Err(anyhow!(
+                "Prune timers need systemd or launchd; schedule 'fstk prune --policy' yourself"
+            ))
+        }
+    }
+}
+
+/// Write timer files that run the retention policies of the active stack every `every`, and
+/// enable them. With `dry_run`, only print the files.
+pub fn install_timer(every: &str, dry_run: bool) -> Result<()> {
+    let timer = PruneTimer::for_active_stack(every)?;
+    let files = timer.files()?;
+
+    if config::load()?.retention.is_empty() {
+        status!(
+            "No retention policies configured yet; the timer prunes nothing until [[retention]] \
+             tables are added to {}",
+            config::get_config_path()?.display()
+        );
+    }
+
+    if dry_run {
+        for (path, contents) in &files {
+            println!("# {}\n{}", path.display(), contents);
+        }
+        return Ok(());
+    }
+
+    for (path, contents) in &files {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, contents)?;
+        println!("Wrote {}", path.display());
+    }
+
+    if cfg!(target_os = "macos") {
+        let plist = &files[0].0;
+        // Reloading picks up a changed interval when the agent was installed before
+        let _ = Command::new("launchctl").arg("unload").arg(plist).output();
+        run_or_explain("launchctl", &["load", "-w", &plist.to_string_lossy()]);
+    } else {
+        let timer_unit = format!("{}.timer", timer.name);
+        if run_or_explain("systemctl", &["--user", "daemon-reload"]) {
+            run_or_explain("systemctl", &["--user", "enable", "--now", &timer_unit]);
+        }
+    }
+
+    println!(
+        "Retention policies will be applied every {} ({})",
+        every, timer.name
+    );
+    Ok(())
+}
+
+/// Disable and remove the timer files of the active stack
+pub fn uninstall_timer() -> Result<()> {
+    let timer = PruneTimer::for_active_stack("1d")?;
+    let files = timer.files()?;
+    if !files.iter().any(|(path, _)| path.exists()) {
+        println!("No prune timer installed for this stack");
+        return Ok(());
+    }
+
+    if cfg!(target_os = "macos") {
+        run_or_explain(
+            "launchctl",
+            &["unload", "-w", &files[0].0.to_string_lossy()],
+        );
+    } else {
+        run_or_explain(
+            "systemctl",
+            &[
+                "--user",
+                "disable",
+                "--now",
+                &format!("{}.timer", timer.name),
+            ],
+        );
+    }
+
+    for (path, _) in &files {
+        remove_if_exists(path)?;
+    }
+    if !cfg!(target_os = "macos") {
+        run_or_explain("systemctl", &["--user", "daemon-reload"]);
+    }
+
+    println!("Removed prune timer {}", timer.name);
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {
+            println!("Removed {}", path.display());
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Run a service manager command; if it fails, say what to run by hand instead of failing,
+/// since the files are in place either way
+fn run_or_explain(program: &str, args: &[&str]) -> bool {
+    let manual = format!("{} {}", program, args.join(" "));
+    match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => true,
+        Ok(output) => {
+            status!(
+                "'{}' failed: {}",
+                manual,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            false
+        }
+        Err(e) => {
+            status!("Could not run '{}' ({}); run it yourself", manual, e);
+            false
+        }
+    }
+}
+
+/// Quote an argument for a systemd unit file, where `%` starts a specifier
+fn systemd_quote(arg: &str) -> String {
+    format!(
+        "\"{}\"",
+        arg.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_files() {
+        let timer = PruneTimer {
+            name: "fstk-prune-1234abcd".to_string(),
+            command: vec![
+                "/opt/my tools/fstk".to_string(),
+                "--local".to_string(),
+                "prune".to_string(),
+                "--policy".to_string(),
+                "--yes".to_string(),
+            ],
+            working_dir: Some(PathBuf::from("/home/me/100% <done>")),
+            interval: 86400,
+            log_path: PathBuf::from("/home/me/.fstk/prune.log"),
+        };
+
+        let service = timer.systemd_service();
+        assert!(service.contains(
+            "ExecStart=\"/opt/my tools/fstk\" \"--local\" \"prune\" \"--policy\" \"--yes\"\n"
+        ));
+        assert!(service.contains("WorkingDirectory=/home/me/100%% <done>\n"));
+        assert!(timer.systemd_timer().contains("OnUnitActiveSec=86400s\n"));
+
+        let plist = timer.launchd_plist();
+        assert!(plist.contains("<string>fstk-prune-1234abcd</string>"));
+        assert!(plist.contains("        <string>/opt/my tools/fstk</string>\n"));
+        assert!(plist.contains("<string>/home/me/100% &lt;done&gt;</string>"));
+        assert!(plist.contains("<integer>86400</integer>"));
+    }
+}
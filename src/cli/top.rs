@@ -0,0 +1,51 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+use crate::db::{establish_connection, get_stored_path, item_number, ItemManager};
+use crate::fs;
+use crate::utils::display;
+
+/// List the largest items on the stack by stored size.
+pub fn top(count: usize) -> Result<()> {
+    // Connect to database
+    let conn = establish_connection()?;
+
+    backfill_sizes(&conn)?;
+
+    let items = ItemManager::list_by_size(&conn, count)?;
+
+    if items.is_empty() {
+        println!("No items in the stack.");
+        return Ok(());
+    }
+
+    // Map database IDs to the display numbers shown by list
+    let mut all_items = ItemManager::list(&conn, &[])?;
+    all_items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+    let numbers: HashMap<i64, usize> = all_items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (item.id, item_number(index, item)))
+        .collect();
+
+    let numbered: Vec<_> = items
+        .into_iter()
+        .map(|item| (numbers.get(&item.id).copied().unwrap_or(0), item))
+        .collect();
+
+    display::display_sized_items_table(&numbered);
+
+    Ok(())
+}
+
+/// Record sizes for items pushed before sizes were tracked.
+pub fn backfill_sizes(conn: &Connection) -> Result<()> {
+    for (id, stored_hash) in ItemManager::list_ids_without_size(conn)? {
+        if let Ok(size) = fs::path_size(&get_stored_path(&stored_hash)?) {
+            ItemManager::set_size(conn, id, size)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,273 @@
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::db::{
+    establish_connection, get_item_stored_path, ItemManager, ManifestManager, StackItem,
+};
+use crate::fs::ManifestEntry;
+use crate::utils::display;
+use crate::utils::error::FstkError;
+
+/// A directory of a stored item, with the totals of everything below it
+#[derive(Debug, Default)]
+struct TreeNode {
+    dirs: BTreeMap<String, TreeNode>,
+    /// File name and size
+    files: BTreeMap<String, u64>,
+    file_count: usize,
+    size: u64,
+}
+
+impl TreeNode {
+    /// Add a file given by its `/`-separated path relative to this directory
+    fn insert_file(&mut self, relative_path: &str, size: u64) {
+        self.file_count += 1;
+        self.size += size;
+
+        match relative_path.split_once('/') {
+            Some((dir, rest)) => self
+                .dirs
+                .entry(dir.to_string())
+                .or_default()
+                .insert_file(rest, size),
+            None => {
+                self.files.insert(relative_path.to_string(), size);
+            }
+        }
+    }
+
+    /// Add a directory, so that empty directories show up as well
+    fn insert_dir(&mut self, relative_path: &str) {
+        let mut node = self;
+        for part in relative_path.split('/').filter(|part| !part.is_empty()) {
+            node = node.dirs.entry(part.to_string()).or_default();
+        }
+    }
+}
+
+/// Show the structure of a stored directory or bundle item without popping it.
+/// The recorded manifest is used when there is one; otherwise the stored blob is walked.
+pub fn tree(number: usize, depth: Option<usize>) -> Result<()> {
+    let conn = establish_connection()?;
+
+    let id = ItemManager::get_id_by_display_number(&conn, number, &[])?
+        .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+    let item = ItemManager::get_by_id(&conn, id)?
+        .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+
+    // The manifest of a directory stored as an archive lists its files
+    if !item.is_stored_as_directory() && !item.packed {
+        return Err(anyhow!(
+            "Item #{} ('{}') is a file, not a directory",
+            number,
+            item.original_name
+        ));
+    }
+
+    let root = load(&conn, &item)?;
+    for line in render(&item.original_name, &root, depth) {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+/// The top of a stored directory, as `peek` shows it
+pub(crate) struct Overview {
+    /// Entries directly inside the directory
+    pub entries: usize,
+    /// Files at any depth
+    pub files: usize,
+    pub size: u64,
+    /// The first entries as `tree -L 1` renders them
+    pub first_entries: Vec<String>,
+}
+
+/// Summarize a stored directory item, listing at most `limit` of its top-level entries
+pub(crate) fn overview(conn: &Connection, item: &StackItem, limit: usize) -> Result<Overview> {
+    let root = load(conn, item)?;
+    Ok(Overview {
+        entries: root.dirs.len() + root.files.len(),
+        files: root.file_count,
+        size: root.size,
+        first_entries: first_entries(&root, limit),
+    })
+}
+
+/// The structure of a stored directory item, from its manifest if one was recorded
+fn load(conn: &Connection, item: &StackItem) -> Result<TreeNode> {
+    let manifest = ManifestManager::get_for_item(conn, item.id)?;
+    if manifest.is_empty() {
+        walk_stored(&get_item_stored_path(item)?)
+    } else {
+        Ok(from_manifest(&manifest))
+    }
+}
+
+/// The first `limit` top-level entries, followed by how many more there are
+fn first_entries(root: &TreeNode, limit: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    render_children(root, "", 1, Some(1), &mut lines);
+
+    let count = lines.len();
+    if count > limit {
+        lines.truncate(limit);
+        lines.push(format!("└── ... {} more", count - limit));
+    }
+    lines
+}
+
+fn from_manifest(manifest: &[ManifestEntry]) -> TreeNode {
+    let mut root = TreeNode::default();
+    for entry in manifest {
+        root.insert_file(&entry.relative_path, entry.size);
+    }
+    root
+}
+
+fn walk_stored(dir: &Path) -> Result<TreeNode> {
+    if !dir.exists() {
+        return Err(FstkError::PathNotFound(dir.display().to_string()).into());
+    }
+
+    let mut root = TreeNode::default();
+    for entry in WalkDir::new(dir).min_depth(1) {
+        let entry = entry?;
+        let relative_path = entry
+            .path()
+            .strip_prefix(dir)?
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        if entry.file_type().is_dir() {
+            root.insert_dir(&relative_path);
+        } else {
+            root.insert_file(&relative_path, entry.metadata()?.len());
+        }
+    }
+
+    Ok(root)
+}
+
+/// Render the tree as lines, descending at most `depth` levels below the root.
+/// Directories that are not expanded still show how many files they hold.
+fn render(name: &str, root: &TreeNode, depth: Option<usize>) -> Vec<String> {
+    let mut lines = vec![format!("{}/ {}", name, dir_summary(root))];
+    render_children(root, "", 1, depth, &mut lines);
+    lines
+}
+
+fn render_children(
+    node: &TreeNode,
+    prefix: &str,
+    level: usize,
+    depth: Option<usize>,
+    lines: &mut Vec<String>,
+) {
+    if depth.is_some_and(|depth| level > depth) {
+        return;
+    }
+
+    let count = node.dirs.len() + node.files.len();
+    let mut index = 0;
+
+    for (name, child) in &node.dirs {
+        index += 1;
+        let (branch, indent) = branch(index == count);
+        lines.push(format!(
+            "{}{}{}/ {}",
+            prefix,
+            branch,
+            name,
+            dir_summary(child)
+        ));
+        render_children(
+            child,
+            &format!("{}{}", prefix, indent),
+            level + 1,
+            depth,
+            lines,
+        );
+    }
+
+    for (name, size) in &node.files {
+        index += 1;
+        let (branch, _) = branch(index == count);
+        lines.push(format!(
+            "{}{}{}  {}",
+            prefix,
+            branch,
+            name,
+            display::format_size(*size)
+        ));
+    }
+}
+
+fn branch(last: bool) -> (&'static str, &'static str) {
+    if last {
+        ("└── ", "    ")
+    } else {
+        ("├── ", "│   ")
+    }
+}
+
+fn dir_summary(node: &TreeNode) -> String {
+    format!(
+        "({} file{}, {})",
+        node.file_count,
+        if node.file_count == 1 { "" } else { "s" },
+        display::format_size(node.size)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(relative_path: &str, size: u64) -> ManifestEntry {
+        ManifestEntry {
+            relative_path: relative_path.to_string(),
+            size,
+            hash: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let root = from_manifest(&[
+            entry("README.md", 100),
+            entry("src/lib.rs", 2048),
+            entry("src/util/mod.rs", 10),
+        ]);
+
+        assert_eq!(
+            render("project", &root, None),
+            vec![
+                "project/ (3 files, 2.1 KiB)",
+                "├── src/ (2 files, 2.0 KiB)",
+                "│   ├── util/ (1 file, 10 B)",
+                "│   │   └── mod.rs  10 B",
+                "│   └── lib.rs  2.0 KiB",
+                "└── README.md  100 B",
+            ]
+        );
+
+        assert_eq!(
+            render("project", &root, Some(1)),
+            vec![
+                "project/ (3 files, 2.1 KiB)",
+                "├── src/ (2 files, 2.0 KiB)",
+                "└── README.md  100 B",
+            ]
+        );
+
+        assert_eq!(
+            first_entries(&root, 1),
+            vec!["├── src/ (2 files, 2.0 KiB)", "└── ... 1 more"]
+        );
+        assert_eq!(first_entries(&root, 2).len(), 2);
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+use crate::db::{
+    establish_connection, get_item_stored_path, item_by_number, ItemManager, ManifestManager,
+    StackItem,
+};
+use crate::fs::{self, ManifestMismatch};
+use crate::utils::error::FstkError;
+use crate::utils::numbers::parse_number_range;
+
+/// State of an item's stored blob, as shown in the HEALTH column of `list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemHealth {
+    Ok,
+    /// The blob is gone from storage
+    Missing,
+    /// The blob exists but cannot be read
+    Unreadable,
+    /// The blob's size or checksum differs from what was recorded
+    Corrupt,
+}
+
+impl ItemHealth {
+    /// Label for the HEALTH column (empty when the item is fine)
+    pub fn label(self) -> &'static str {
+        match self {
+            ItemHealth::Ok => "",
+            ItemHealth::Missing => "missing",
+            ItemHealth::Unreadable => "unreadable",
+            ItemHealth::Corrupt => "corrupt",
+        }
+    }
+}
+
+/// Check an item's stored blob.
+/// Existence, readability and the recorded size are always checked; `checksum` also
+/// compares the content hash, which reads the whole blob.
+pub fn check_health(item: &StackItem, checksum: bool) -> ItemHealth {
+    let Ok(stored_path) = get_item_stored_path(item) else {
+        return ItemHealth::Missing;
+    };
+    if !stored_path.exists() {
+        return ItemHealth::Missing;
+    }
+    if !is_readable(&stored_path) {
+        return ItemHealth::Unreadable;
+    }
+
+    if let Some(size) = item.size {
+        match fs::path_size(&stored_path) {
+            Ok(actual) if actual != size => return ItemHealth::Corrupt,
+            Ok(_) => {}
+            Err(_) => return ItemHealth::Unreadable,
+        }
+    }
+
+    if checksum {
+        if let Some(expected) = &item.content_hash {
+            match fs::content_hash(&stored_path) {
+                Ok(actual) if actual != *expected => return ItemHealth::Corrupt,
+                Ok(_) => {}
+                Err(_) => return ItemHealth::Unreadable,
+            }
+        }
+    }
+
+    ItemHealth::Ok
+}
+
+fn is_readable(path: &Path) -> bool {
+    if path.is_dir() {
+        std::fs::read_dir(path).is_ok()
+    } else {
+        std::fs::File::open(path).is_ok()
+    }
+}
+
+/// Verify stored items against their recorded checksums.
+pub fn verify(numbers: Option<String>) -> Result<()> {
+    // Connect to database
+    let conn = establish_connection()?;
+
+    let mut all_items = ItemManager::list(&conn, &[])?;
+    all_items.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+
+    // Verify the selected items, or every item if no numbers are given
+    let selected: Vec<usize> = match numbers {
+        Some(numbers) => parse_number_range(&numbers)?,
+        None => (1..=all_items.len()).collect(),
+    };
+
+    let mut failed_count = 0;
+
+    for number in selected {
+        let item = item_by_number(&all_items, number)
+            .ok_or_else(|| FstkError::ItemNotFound(number.to_string()))?;
+        let stored_path = get_item_stored_path(item)?;
+
+        if !stored_path.exists() {
+            println!(
+                "#{} {}: MISSING ({})",
+                number,
+                item.original_name,
+                stored_path.display()
+            );
+            failed_count += 1;
+            continue;
+        }
+
+        let problems = if item.is_stored_as_directory() {
+            let manifest = ManifestManager::get_for_item(&conn, item.id)?;
+            if manifest.is_empty() {
+                println!("#{} {}: no manifest recorded", number, item.original_name);
+                continue;
+            }
+
+            fs::verify_manifest(&stored_path, &manifest)?
+                .into_iter()
+                .map(|mismatch| match mismatch {
+                    ManifestMismatch::Missing(path) => format!("missing {}", path),
+                    ManifestMismatch::Modified(path) => format!("modified {}", path),
+                    ManifestMismatch::Unexpected(path) => format!("unexpected {}", path),
+                })
+                .collect::<Vec<_>>()
+        } else {
+            match &item.content_hash {
+                Some(expected) if *expected != fs::content_hash(&stored_path)? => {
+                    vec!["content hash differs".to_string()]
+                }
+                Some(_) => Vec::new(),
+                None => {
+                    println!("#{} {}: no checksum recorded", number, item.original_name);
+                    continue;
+                }
+            }
+        };
+
+        if problems.is_empty() {
+            println!("#{} {}: OK", number, item.original_name);
+        } else {
+            println!("#{} {}: FAILED", number, item.original_name);
+            for problem in problems {
+                println!("  {}", problem);
+            }
+            failed_count += 1;
+        }
+    }
+
+    if failed_count > 0 {
+        Err(anyhow!("{} item(s) failed verification", failed_count))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_health() -> Result<()> {
+        let storage = TempDir::new()?;
+        std::fs::write(storage.path().join("abc"), "hello")?;
+
+        // Archived items are looked up in their storage location
+        let mut item = StackItem {
+            stored_hash: "abc".to_string(),
+            storage_location: Some(storage.path().to_string_lossy().to_string()),
+            size: Some(5),
+            content_hash: Some(fs::hash_file(&storage.path().join("abc"))?),
+            ..Default::default()
+        };
+        assert_eq!(check_health(&item, true), ItemHealth::Ok);
+
+        std::fs::write(storage.path().join("abc"), "jello")?;
+        assert_eq!(check_health(&item, false), ItemHealth::Ok);
+        assert_eq!(check_health(&item, true), ItemHealth::Corrupt);
+
+        item.size = Some(4);
+        assert_eq!(check_health(&item, false), ItemHealth::Corrupt);
+
+        item.stored_hash = "gone".to_string();
+        assert_eq!(check_health(&item, false), ItemHealth::Missing);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use glob::Pattern;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::cli::push;
+use crate::fs;
+
+/// How long a new file must stay unchanged before it is pushed
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// How often pending files are checked and the shutdown flag is polled
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_shutdown_signal(_signal: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+/// A file that appeared in the watched directory and is waiting to settle
+struct PendingFile {
+    last_event: Instant,
+    last_size: Option<u64>,
+}
+
+/// Watch a directory and push every new entry that matches the given patterns.
+pub fn watch(dir: &str, tags: Option<Vec<String>>, patterns: Option<Vec<String>>) -> Result<()> {
+    let watch_dir = fs::get_absolute_path(Path::new(dir))?;
+    if !watch_dir.is_dir() {
+        return Err(anyhow!(
+            "Watch path is not a directory: {}",
+            watch_dir.display()
+        ));
+    }
+
+    let patterns = patterns
+        .unwrap_or_default()
+        .iter()
+        .map(|p| Pattern::new(p).map_err(|e| anyhow!("Invalid pattern '{}': {}", p, e)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Stop gracefully on Ctrl-C or SIGTERM instead of dying mid-push
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            handle_shutdown_signal as *const () as libc::sighandler_t,
+        );
+        libc::signal(
+            libc::SIGTERM,
+            handle_shutdown_signal as *const () as libc::sighandler_t,
+        );
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&watch_dir, RecursiveMode::NonRecursive)?;
+
+    println!("Watching {} (press Ctrl-C to stop)", watch_dir.display());
+
+    let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+    let mut pushed_count = 0;
+
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    for path in event.paths {
+                        if is_candidate(&watch_dir, &path, &patterns) {
+                            // Repeated events for the same file only reset its timer
+                            let entry = pending.entry(path).or_insert(PendingFile {
+                                last_event: Instant::now(),
+                                last_size: None,
+                            });
+                            entry.last_event = Instant::now();
+                        }
+                    }
+                }
+            }
+            Ok(Err(e)) => println!("Watch error: {}", e),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        pushed_count += push_settled_files(&mut pending, tags.as_ref());
+    }
+
+    println!("Stopped watching; {} item(s) pushed", pushed_count);
+
+    Ok(())
+}
+
+/// Check whether a path reported by the watcher should be pushed.
+fn is_candidate(watch_dir: &Path, path: &Path, patterns: &[Pattern]) -> bool {
+    // Only direct children of the watched directory; the stack storage itself is never watched
+    if path.parent() != Some(watch_dir) {
+        return false;
+    }
+
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return false,
+    };
+
+    // Hidden files are usually temporary files of downloads or editors
+    if name.starts_with('.') {
+        return false;
+    }
+
+    patterns.is_empty() || patterns.iter().any(|p| p.matches(&name))
+}
+
+/// Push every pending file whose size stopped changing, returning how many were pushed.
+fn push_settled_files(
+    pending: &mut HashMap<PathBuf, PendingFile>,
+    tags: Option<&Vec<String>>,
+) -> usize {
+    let mut pushed = 0;
+    let mut finished = Vec::new();
+
+    for (path, file) in pending.iter_mut() {
+        if file.last_event.elapsed() < SETTLE_TIME {
+            continue;
+        }
+
+        // The file was moved away or deleted before it settled
+        let size = match fs::path_size(path) {
+            Ok(size) => size,
+            Err(_) => {
+                finished.push(path.clone());
+                continue;
+            }
+        };
+
+        // Still growing: wait for another settle period
+        if file.last_size != Some(size) {
+            file.last_size = Some(size);
+            file.last_event = Instant::now();
+            continue;
+        }
+
+        let options = push::PushOptions {
+            tags: tags.cloned(),
+            quiet: true,
+            ..Default::default()
+        };
+        match push::push(&path.to_string_lossy(), options) {
+            Ok(Some(_)) => {
+                println!("Pushed {}", path.display());
+                pushed += 1;
+            }
+            Ok(None) => {}
+            Err(e) => println!("Failed to push {}: {}", path.display(), e),
+        }
+        finished.push(path.clone());
+    }
+
+    for path in finished {
+        pending.remove(&path);
+    }
+
+    pushed
+}
@@ -0,0 +1,307 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tabled::settings::Color;
+
+use crate::db::get_global_fstk_dir;
+use crate::utils::display::{
+    row_color, ColumnWidths, DEFAULT_COLUMN_WIDTH, MIN_COLUMN_WIDTH, ROW_COLORS,
+};
+use crate::utils::numbers::parse_size;
+
+/// Name of the configuration file inside the global fstk directory
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Name of the file inside the global fstk directory holding the token `daemon --http`
+/// generated because none was configured
+pub const HTTP_TOKEN_FILE_NAME: &str = "http_token";
+
+/// User configuration loaded from `~/.fstk/config.toml`.
+/// Every setting is optional; a missing file means all defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Secondary storage location used by `archive` when `--to` is not given
+    pub archive_dir: Option<String>,
+    /// Tag every push made inside a git repository with the repository and branch
+    pub git_tags: bool,
+    /// Directory `pop` restores into when `--output` is not given (instead of the current one)
+    pub pop_output_dir: Option<String>,
+    /// Where `pop` puts items when no destination option is given
+    pub pop_destination: PopDestination,
+    /// What `push` does when an item from the same path is already on the stack
+    pub duplicate_paths: DuplicatePathPolicy,
+    /// Most items the stack should hold, not counting pinned and locked ones; unbounded if not set
+    pub max_items: Option<usize>,
+    /// What `push` does when the stack already holds `max_items` items
+    pub max_items_policy: MaxItemsPolicy,
+    /// Ask before pushing more than this much data (e.g. "500MiB"); 1 GiB if not set
+    pub confirm_push_size: Option<String>,
+    /// Apply the retention policies after every push, not only on `prune --policy`
+    pub auto_prune: bool,
+    /// Retention policies (`[[retention]]` tables) evaluated by `prune --policy`
+    pub retention: Vec<RetentionPolicy>,
+    /// Narrowest NAME column of the list table, however small the terminal; 18 if not set
+    pub min_name_width: Option<usize>,
+    /// Narrowest TAGS column of the list table, however small the terminal; 18 if not set
+    pub min_tags_width: Option<usize>,
+    /// Colors of the list rows of items with a tag (`[tag_colors]` table, e.g. `urgent = "red"`)
+    pub tag_colors: BTreeMap<String, String>,
+    /// Command aliases (`[aliases]` table, e.g. `inbox = "list -t inbox"`)
+    pub aliases: BTreeMap<String, String>,
+    /// Token `daemon --http` requires from clients and `--remote` clients send;
+    /// `FSTK_HTTP_TOKEN` overrides it, and the daemon generates one if neither is set
+    pub http_token: Option<String>,
+}
+
+/// Where `pop` puts an item by default
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PopDestination {
+    /// The current directory, or `pop_output_dir` if set
+    #[default]
+    Cwd,
+    /// The directory the item originally lived in (like `restore`)
+    Original,
+    /// The directory `push` was run from
+    Pushdir,
+}
+
+/// What `push` does with a path that is already on the stack; popping both items into the same
+/// directory later is bound to conflict
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePathPolicy {
+    /// Push it as a newer version and print a warning
+    #[default]
+    Warn,
+    /// Refuse the push
+    Block,
+    /// Push it as a newer version without a warning
+    Allow,
+}
+
+/// What `push` does when the stack is full (see `max_items`)
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MaxItemsPolicy {
+    /// Refuse the push
+    Reject,
+    /// Push anyway and print a warning
+    #[default]
+    Warn,
+    /// Push, then remove the oldest unpinned and unlocked items until the stack fits again
+    Rotate,
+}
+
+/// A rule for when items expire, e.g. "items tagged tmp expire after 7 days" or
+/// "keep at most 200 items". Pinned and locked items never expire.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    /// Stack the policy applies to: "global", or the root directory of a project stack.
+    /// Applies to every stack if not set.
+    pub stack: Option<String>,
+    /// Only consider items having all of these tags
+    pub tags: Vec<String>,
+    /// Items pushed longer ago than this expire (e.g. "7d")
+    pub max_age: Option<String>,
+    /// Keep at most this many of the considered items, not counting pinned, locked and
+    /// otherwise expired ones; the oldest beyond that expire
+    pub max_items: Option<usize>,
+}
+
+impl RetentionPolicy {
+    /// Whether the policy applies to the stack of `project_root` (`None` for the global stack)
+    pub fn applies_to(&self, project_root: Option<&Path>) -> bool {
+        match (&self.stack, project_root) {
+            (None, _) => true,
+            (Some(stack), None) => stack == "global",
+            (Some(stack), Some(root)) => Path::new(stack) == root,
+        }
+    }
+}
+
+/// Push size above which confirmation is asked when `confirm_push_size` is not set
+pub const DEFAULT_CONFIRM_PUSH_SIZE: u64 = 1 << 30;
+
+impl Config {
+    /// Push size in bytes above which `push` asks for confirmation
+    pub fn confirm_push_size(&self) -> Result<u64> {
+        match &self.confirm_push_size {
+            Some(size) => parse_size(size)
+                .map_err(|e| anyhow!("Invalid confirm_push_size in configuration: {}", e)),
+            None => Ok(DEFAULT_CONFIRM_PUSH_SIZE),
+        }
+    }
+
+    /// Minimum widths of the NAME and TAGS columns of the list table
+    pub fn min_column_widths(&self) -> Result<ColumnWidths> {
+        let width = |setting: &str, width: Option<usize>| match width {
+            Some(width) if width < MIN_COLUMN_WIDTH => Err(anyhow!(
+                "Invalid {} in configuration: must be at least {}",
+                setting,
+                MIN_COLUMN_WIDTH
+            )),
+            Some(width) => Ok(width),
+            None => Ok(DEFAULT_COLUMN_WIDTH),
+        };
+        Ok(ColumnWidths {
+            name: width("min_name_width", self.min_name_width)?,
+            tags: width("min_tags_width", self.min_tags_width)?,
+        })
+    }
+
+    /// Token for the HTTP API, from `FSTK_HTTP_TOKEN`, `http_token` or the token a daemon
+    /// generated (`None` if there is none)
+    pub fn http_token(&self) -> Option<String> {
+        std::env::var("FSTK_HTTP_TOKEN")
+            .ok()
+            .or_else(|| self.http_token.clone())
+            .filter(|token| !token.is_empty())
+            .or_else(|| read_generated_http_token().ok().flatten())
+    }
+
+    /// Row color for each tag in `tag_colors`
+    pub fn tag_colors(&self) -> Result<HashMap<String, Color>> {
+        self.tag_colors
+            .iter()
+            .map(|(tag, name)| {
+                let color = row_color(name).ok_or_else(|| {
+                    anyhow!(
+                        "Invalid color '{}' for tag '{}' in configuration (expected one of {})",
+                        name,
+                        tag,
+                        ROW_COLORS.join(", ")
+                    )
+                })?;
+                Ok((tag.clone(), color))
+            })
+            .collect()
+    }
+}
+
+/// Get the path of the configuration file
+pub fn get_config_path() -> Result<PathBuf> {
+    Ok(get_global_fstk_dir()?.join(CONFIG_FILE_NAME))
+}
+
+/// Get the path of the file holding a generated HTTP token
+pub fn get_http_token_path() -> Result<PathBuf> {
+    Ok(get_global_fstk_dir()?.join(HTTP_TOKEN_FILE_NAME))
+}
+
+/// The token a daemon generated earlier, if any
+fn read_generated_http_token() -> Result<Option<String>> {
+    let path = get_http_token_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let token = std::fs::read_to_string(path)?.trim().to_string();
+    Ok((!token.is_empty()).then_some(token))
+}
+
+/// Load the user configuration, falling back to defaults when no file exists
+pub fn load() -> Result<Config> {
+    let path = get_config_path()?;
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    parse(&content).map_err(|e| anyhow!("Invalid configuration in {}: {}", path.display(), e))
+}
+
+/// Parse configuration from TOML text
+pub fn parse(content: &str) -> Result<Config> {
+    Ok(toml::from_str(content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_empty_config() {
+        let config = parse("").unwrap();
+        assert!(config.archive_dir.is_none());
+        assert!(!config.git_tags);
+        assert_eq!(
+            config.confirm_push_size().unwrap(),
+            DEFAULT_CONFIRM_PUSH_SIZE
+        );
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse("archive_dir = \"/mnt/backup/fstk\"\ngit_tags = true").unwrap();
+        assert_eq!(config.archive_dir.as_deref(), Some("/mnt/backup/fstk"));
+        assert!(config.git_tags);
+
+        let config = parse("pop_output_dir = \"/srv/inbox\"").unwrap();
+        assert_eq!(config.pop_output_dir.as_deref(), Some("/srv/inbox"));
+        assert_eq!(config.pop_destination, PopDestination::Cwd);
+
+        let config = parse("duplicate_paths = \"block\"").unwrap();
+        assert_eq!(config.duplicate_paths, DuplicatePathPolicy::Block);
+
+        let config = parse("max_items = 100\nmax_items_policy = \"rotate\"").unwrap();
+        assert_eq!(config.max_items, Some(100));
+        assert_eq!(config.max_items_policy, MaxItemsPolicy::Rotate);
+        assert_eq!(parse("").unwrap().max_items_policy, MaxItemsPolicy::Warn);
+
+        let config = parse("pop_destination = \"pushdir\"").unwrap();
+        assert_eq!(config.pop_destination, PopDestination::Pushdir);
+        assert!(parse("pop_destination = \"home\"").is_err());
+
+        let config = parse("min_name_width = 40").unwrap();
+        assert_eq!(config.min_column_widths().unwrap().name, 40);
+        assert_eq!(
+            config.min_column_widths().unwrap().tags,
+            DEFAULT_COLUMN_WIDTH
+        );
+        assert!(parse("min_tags_width = 2")
+            .unwrap()
+            .min_column_widths()
+            .is_err());
+
+        let config = parse("confirm_push_size = \"200MiB\"").unwrap();
+        assert_eq!(config.confirm_push_size().unwrap(), 200 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_parse_retention() {
+        let config = parse(
+            "auto_prune = true\n\
+             [[retention]]\ntags = [\"tmp\"]\nmax_age = \"7d\"\n\
+             [[retention]]\nstack = \"/home/me/project\"\nmax_items = 200",
+        )
+        .unwrap();
+
+        assert!(config.auto_prune);
+        assert_eq!(config.retention.len(), 2);
+        assert_eq!(config.retention[0].tags, vec!["tmp".to_string()]);
+        assert_eq!(config.retention[0].max_age.as_deref(), Some("7d"));
+        assert!(config.retention[0].applies_to(None));
+        assert_eq!(config.retention[1].max_items, Some(200));
+        assert!(config.retention[1].applies_to(Some(Path::new("/home/me/project"))));
+        assert!(!config.retention[1].applies_to(None));
+    }
+
+    #[test]
+    fn test_parse_tag_colors() {
+        let config = parse("[tag_colors]\nurgent = \"red\"\narchive = \"dim\"").unwrap();
+        let colors = config.tag_colors().unwrap();
+        assert_eq!(colors.len(), 2);
+        assert_eq!(colors["urgent"], Color::FG_RED);
+
+        let config = parse("[tag_colors]\nurgent = \"reddish\"").unwrap();
+        assert!(config.tag_colors().is_err());
+    }
+
+    #[test]
+    fn test_parse_unknown_key() {
+        assert!(parse("no_such_setting = 1").is_err());
+    }
+}
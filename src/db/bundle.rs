@@ -0,0 +1,113 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+/// A path stored as part of a bundle item
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleMember {
+    /// File or directory name, also its name inside the stored bundle
+    pub name: String,
+    /// Parent directory the member was pushed from
+    pub original_path: String,
+}
+
+pub struct BundleManager;
+
+impl BundleManager {
+    /// Store the members of a bundle item
+    pub fn insert(conn: &Connection, item_id: i64, members: &[BundleMember]) -> Result<()> {
+        let mut stmt = conn.prepare(
+            "INSERT INTO bundle_members (item_id, name, original_path) VALUES (?, ?, ?)",
+        )?;
+
+        for member in members {
+            stmt.execute(params![item_id, member.name, member.original_path])?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the members still stored in a bundle item, sorted by name
+    pub fn get_for_item(conn: &Connection, item_id: i64) -> Result<Vec<BundleMember>> {
+        let mut stmt = conn.prepare(
+            "SELECT name, original_path FROM bundle_members
+             WHERE item_id = ?
+             ORDER BY name",
+        )?;
+
+        let rows = stmt.query_map(params![item_id], |row| {
+            Ok(BundleMember {
+                name: row.get(0)?,
+                original_path: row.get(1)?,
+            })
+        })?;
+
+        let mut members = Vec::new();
+        for member in rows {
+            members.push(member?);
+        }
+
+        Ok(members)
+    }
+
+    /// Forget a member that was taken out of a bundle
+    pub fn remove(conn: &Connection, item_id: i64, name: &str) -> Result<bool> {
+        let removed = conn.execute(
+            "DELETE FROM bundle_members WHERE item_id = ? AND name = ?",
+            params![item_id, name],
+        )?;
+
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{schema, ItemManager, ItemMetadata};
+
+    #[test]
+    fn test_members_follow_item() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        schema::initialize_schema(&conn)?;
+
+        let metadata = ItemMetadata {
+            members: vec![
+                BundleMember {
+                    name: "report.pdf".to_string(),
+                    original_path: "/home/user/docs".to_string(),
+                },
+                BundleMember {
+                    name: "data.csv".to_string(),
+                    original_path: "/tmp".to_string(),
+                },
+            ],
+            ..Default::default()
+        };
+        let item_id = ItemManager::insert_with_metadata(
+            &mut conn,
+            "q3-review",
+            "/home/user",
+            "hash_bundle",
+            "bundle",
+            &[],
+            &metadata,
+        )?;
+
+        let members = BundleManager::get_for_item(&conn, item_id)?;
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].name, "data.csv");
+        assert_eq!(members[1].original_path, "/home/user/docs");
+
+        assert!(BundleManager::remove(&conn, item_id, "data.csv")?);
+        assert!(!BundleManager::remove(&conn, item_id, "data.csv")?);
+        assert_eq!(BundleManager::get_for_item(&conn, item_id)?.len(), 1);
+
+        // Members go away with their item
+        ItemManager::delete(&mut conn, item_id)?;
+        assert!(BundleManager::get_for_item(&conn, item_id)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,21 +1,199 @@
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Local};
-use rusqlite::{params, Connection, Row};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::db::bundle::{BundleManager, BundleMember};
+use crate::db::manifest::ManifestManager;
+use crate::db::provenance::Provenance;
 use crate::db::tag::{find_or_create_tag, TagManager};
+use crate::db::tag_query::TagQuery;
+use crate::fs::ManifestEntry;
+
+/// Columns selected for every `StackItem` query, in the order `StackItem::from_row` reads them.
+pub const ITEM_COLUMNS: &str =
+    "id, original_name, original_path, stored_hash, type, pushed_at, pinned, content_hash, size_bytes, storage_location, partial, owner_uid, owner_gid, version_group, locked, push_seq, uuid, note, push_dir, copied, remote_origin, stack_order, file_count, push_millis, packed, provenance";
+
+/// Parse a timestamp column into local time.
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Local>> {
+    // The date in SQLite is stored as UTC without timezone info, so we need to parse it as UTC
+    // and then convert to local time
+    let naive_dt = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .map_err(|e| anyhow!("Error parsing date: {}", e))?;
+    // First interpret as UTC, then convert to local time
+    Ok(
+        chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(naive_dt, chrono::Utc)
+            .with_timezone(&Local),
+    )
+}
+
+/// Whether item numbers given and shown on this run are database IDs (`--id`)
+static ID_ADDRESSING: AtomicBool = AtomicBool::new(false);
+
+/// Address items by database ID instead of display number for the rest of this run.
+/// IDs never change, so scripts can refer to items while the stack changes.
+pub fn enable_id_addressing() {
+    ID_ADDRESSING.store(true, Ordering::SeqCst);
+}
+
+pub fn id_addressing() -> bool {
+    ID_ADDRESSING.load(Ordering::SeqCst)
+}
 
-#[derive(Debug, Clone)]
+/// Number that addresses the item at `index` of a list in display order:
+/// its 1-based position, or its database ID with `--id`
+pub fn item_number(index: usize, item: &StackItem) -> usize {
+    item_number_with(index, item, id_addressing())
+}
+
+/// `item_number`, giving the database ID when `by_id` is set
+pub fn item_number_with(index: usize, item: &StackItem, by_id: bool) -> usize {
+    if by_id {
+        item.id as usize
+    } else {
+        index + 1
+    }
+}
+
+/// Find the item that `number` addresses in a list in display order
+pub fn item_by_number(items: &[StackItem], number: usize) -> Option<&StackItem> {
+    item_by_number_with(items, number, id_addressing())
+}
+
+/// `item_by_number`, taking `number` as a database ID when `by_id` is set
+pub fn item_by_number_with(items: &[StackItem], number: usize, by_id: bool) -> Option<&StackItem> {
+    if by_id {
+        items.iter().find(|item| item.id as usize == number)
+    } else {
+        number.checked_sub(1).and_then(|index| items.get(index))
+    }
+}
+
+/// Order of display numbers: top of the stack first (the newest push, unless items were moved)
+const DISPLAY_ORDER: &str = "ORDER BY stack_order DESC";
+
+/// `WHERE` clause and parameters selecting items that match all of the tag queries in `tags`
+/// (everything if empty; see `TagQuery`). The condition is parenthesized so that callers can
+/// append further conditions with `AND`.
+fn tag_filter(tags: &[String]) -> Result<(String, Vec<rusqlite::types::Value>)> {
+    let mut params = Vec::new();
+    let filter = match TagQuery::all(tags)? {
+        Some(query) => format!("WHERE ({})", query.to_sql(&mut params)),
+        None => String::new(),
+    };
+
+    Ok((filter, params))
+}
+
+/// Format a local time the way timestamp columns store it (UTC, second precision).
+pub fn format_timestamp(time: DateTime<Local>) -> String {
+    time.with_timezone(&chrono::Utc)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StackItem {
     pub id: i64,
     pub original_name: String,
     pub original_path: String,
     pub stored_hash: String,
-    pub item_type: String, // "file" or "directory"
+    pub item_type: String, // "file", "directory" or "bundle"
     pub pushed_at: DateTime<Local>,
     pub tags: Vec<String>,
+    pub pinned: bool,
+    pub content_hash: Option<String>,
+    pub size: Option<u64>,
+    /// Secondary storage directory holding the blob, or `None` for the data directory
+    pub storage_location: Option<String>,
+    /// Whether entries were extracted from this directory item
+    pub partial: bool,
+    /// Owner (user ID) of the original path, where the platform has one
+    pub owner_uid: Option<u32>,
+    /// Group ID of the original path, where the platform has one
+    pub owner_gid: Option<u32>,
+    /// Full original path shared by all versions of the same file or directory
+    pub version_group: Option<String>,
+    /// Locked items cannot be removed or popped without `--force`
+    pub locked: bool,
+    /// Increases with every push; orders items pushed within the same second
+    pub push_seq: i64,
+    /// Identifies the item across stacks and machines (kept by merge)
+    pub uuid: String,
+    /// Free-form note kept with the item (see `adopt`)
+    pub note: Option<String>,
+    /// Working directory `push` was run from (see `pop --to-pushdir`)
+    pub push_dir: Option<String>,
+    /// Whether the item is a copy pushed with `--keep`, leaving the original in place
+    pub copied: bool,
+    /// `[user@]host:path` the item was fetched from by `push` over SSH
+    pub remote_origin: Option<String>,
+    /// Position on the stack; new items go on top, `mv` and `swap` reorder them
+    pub stack_order: i64,
+    /// Number of files pushed (1 for a file item)
+    pub file_count: Option<u64>,
+    /// How long the push took, in milliseconds
+    pub push_millis: Option<u64>,
+    /// Whether a directory item is stored as a single tar archive (`push --as-archive`)
+    pub packed: bool,
+    /// Host, user, terminal, directory and fstk version of the push (see `peek --provenance`)
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+/// Optional metadata recorded alongside a new stack item
+#[derive(Debug, Clone, Default)]
+pub struct ItemMetadata {
+    /// SHA-256 of the item's content (see `fs::content_hash`)
+    pub content_hash: Option<String>,
+    /// Total size of the stored content in bytes
+    pub size: Option<u64>,
+    /// Per-file manifest of a directory item
+    pub manifest: Vec<ManifestEntry>,
+    /// Original push time when importing an item (defaults to now)
+    pub pushed_at: Option<DateTime<Local>>,
+    /// Whether the item starts out pinned
+    pub pinned: bool,
+    /// Whether the item starts out locked
+    pub locked: bool,
+    /// Owner (uid, gid) of the original path
+    pub owner: Option<(u32, u32)>,
+    /// Full original path linking this item to earlier versions
+    pub version_group: Option<String>,
+    /// Paths stored together in a bundle item
+    pub members: Vec<BundleMember>,
+    /// UUID of an item imported from another stack (a new one is generated otherwise)
+    pub uuid: Option<String>,
+    /// Free-form note about the item
+    pub note: Option<String>,
+    /// Working directory the item was pushed from
+    pub push_dir: Option<String>,
+    /// Whether the content was copied rather than moved into the stack
+    pub copied: bool,
+    /// Remote path the content was fetched from
+    pub remote_origin: Option<String>,
+    /// Number of files in the content
+    pub file_count: Option<u64>,
+    /// Time taken to push the item, in milliseconds
+    pub push_millis: Option<u64>,
+    /// Whether the directory content is stored as a tar archive
+    pub packed: bool,
+    /// Where and by whom the item was pushed
+    pub provenance: Option<Provenance>,
 }
 
 impl StackItem {
+    /// Whether the item holds several paths pushed together with `push --bundle`
+    pub fn is_bundle(&self) -> bool {
+        self.item_type == "bundle"
+    }
+
+    /// Whether the stored blob is a directory (directory and bundle items, unless packed)
+    pub fn is_stored_as_directory(&self) -> bool {
+        (self.item_type == "directory" && !self.packed) || self.is_bundle()
+    }
+
     pub fn from_row(row: &Row) -> Result<Self> {
         let id = row.get(0)?;
         let original_name = row.get(1)?;
@@ -24,14 +202,27 @@ impl StackItem {
         let item_type = row.get(4)?;
 
         let pushed_at_str: String = row.get(5)?;
-        // The date in SQLite is stored as UTC without timezone info, so we need to parse it as UTC
-        // and then convert to local time
-        let naive_dt = chrono::NaiveDateTime::parse_from_str(&pushed_at_str, "%Y-%m-%d %H:%M:%S")
-            .map_err(|e| anyhow!("Error parsing date: {}", e))?;
-        // First interpret as UTC, then convert to local time
-        let pushed_at =
-            chrono::DateTime::<chrono::Utc>::from_naive_utc_and_offset(naive_dt, chrono::Utc)
-                .with_timezone(&Local);
+        let pushed_at = parse_timestamp(&pushed_at_str)?;
+        let pinned = row.get(6)?;
+        let content_hash = row.get(7)?;
+        let size = row.get(8)?;
+        let storage_location = row.get(9)?;
+        let partial = row.get(10)?;
+        let owner_uid = row.get(11)?;
+        let owner_gid = row.get(12)?;
+        let version_group = row.get(13)?;
+        let locked = row.get(14)?;
+        let push_seq = row.get(15)?;
+        let uuid = row.get(16)?;
+        let note = row.get(17)?;
+        let push_dir = row.get(18)?;
+        let copied = row.get(19)?;
+        let remote_origin = row.get(20)?;
+        let stack_order = row.get(21)?;
+        let file_count = row.get(22)?;
+        let push_millis = row.get(23)?;
+        let packed = row.get(24)?;
+        let provenance: Option<String> = row.get(25)?;
 
         Ok(StackItem {
             id,
@@ -41,13 +232,40 @@ impl StackItem {
             item_type,
             pushed_at,
             tags: Vec::new(), // We'll populate tags later
+            pinned,
+            content_hash,
+            size,
+            storage_location,
+            partial,
+            owner_uid,
+            owner_gid,
+            version_group,
+            locked,
+            push_seq,
+            uuid,
+            note,
+            push_dir,
+            copied,
+            remote_origin,
+            stack_order,
+            file_count,
+            push_millis,
+            packed,
+            provenance: provenance.as_deref().and_then(Provenance::from_json),
         })
     }
+
+    /// Position on the stack; larger is nearer the top. Sort by this rather than `pushed_at`,
+    /// which has second precision and ignores reordering.
+    pub fn stack_position(&self) -> i64 {
+        self.stack_order
+    }
 }
 
 pub struct ItemManager;
 
 impl ItemManager {
+    #[allow(dead_code)]
     pub fn insert(
         conn: &mut Connection,
         original_name: &str,
@@ -55,18 +273,65 @@ impl ItemManager {
         stored_hash: &str,
         item_type: &str,
         tags: &[String],
+    ) -> Result<i64> {
+        Self::insert_with_metadata(
+            conn,
+            original_name,
+            original_path,
+            stored_hash,
+            item_type,
+            tags,
+            &ItemMetadata::default(),
+        )
+    }
+
+    /// Insert a new item along with its optional metadata
+    pub fn insert_with_metadata(
+        conn: &mut Connection,
+        original_name: &str,
+        original_path: &str,
+        stored_hash: &str,
+        item_type: &str,
+        tags: &[String],
+        metadata: &ItemMetadata,
     ) -> Result<i64> {
         // Start a transaction for atomicity
         let tx = conn.transaction()?;
 
         // Insert the stack item
         tx.execute(
-            "INSERT INTO stack_items (original_name, original_path, stored_hash, type) VALUES (?, ?, ?, ?)",
-            params![original_name, original_path, stored_hash, item_type],
+            "INSERT INTO stack_items (original_name, original_path, stored_hash, type, content_hash, size_bytes, pinned, locked, owner_uid, owner_gid, version_group, uuid, note, push_dir, copied, remote_origin, file_count, push_millis, packed, provenance, pushed_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, CURRENT_TIMESTAMP))",
+            params![
+                original_name,
+                original_path,
+                stored_hash,
+                item_type,
+                metadata.content_hash,
+                metadata.size,
+                metadata.pinned,
+                metadata.locked,
+                metadata.owner.map(|(uid, _)| uid),
+                metadata.owner.map(|(_, gid)| gid),
+                metadata.version_group,
+                metadata.uuid,
+                metadata.note,
+                metadata.push_dir,
+                metadata.copied,
+                metadata.remote_origin,
+                metadata.file_count,
+                metadata.push_millis,
+                metadata.packed,
+                metadata.provenance.as_ref().map(Provenance::to_json),
+                metadata.pushed_at.map(format_timestamp)
+            ],
         )?;
 
         let item_id = tx.last_insert_rowid();
 
+        ManifestManager::insert(&tx, item_id, &metadata.manifest)?;
+        BundleManager::insert(&tx, item_id, &metadata.members)?;
+
         // Process tags if provided
         if !tags.is_empty() {
             for tag in tags {
@@ -93,10 +358,10 @@ impl ItemManager {
     }
 
     pub fn get_by_id(conn: &Connection, id: i64) -> Result<Option<StackItem>> {
-        let mut stmt = conn.prepare(
-            "SELECT id, original_name, original_path, stored_hash, type, pushed_at 
-             FROM stack_items WHERE id = ?",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM stack_items WHERE id = ?",
+            ITEM_COLUMNS
+        ))?;
 
         let mut rows = stmt.query(params![id])?;
 
@@ -109,11 +374,47 @@ impl ItemManager {
         }
     }
 
+    /// Get the item stored under the given hash
+    pub fn get_by_stored_hash(conn: &Connection, stored_hash: &str) -> Result<Option<StackItem>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM stack_items WHERE stored_hash = ?",
+            ITEM_COLUMNS
+        ))?;
+
+        let mut rows = stmt.query(params![stored_hash])?;
+
+        if let Some(row) = rows.next()? {
+            let mut item = StackItem::from_row(row)?;
+            item.tags = TagManager::get_for_item(conn, item.id)?;
+            Ok(Some(item))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_by_uuid(conn: &Connection, uuid: &str) -> Result<Option<StackItem>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM stack_items WHERE uuid = ?",
+            ITEM_COLUMNS
+        ))?;
+
+        let mut rows = stmt.query(params![uuid])?;
+
+        if let Some(row) = rows.next()? {
+            let mut item = StackItem::from_row(row)?;
+            item.tags = TagManager::get_for_item(conn, item.id)?;
+            Ok(Some(item))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Get the most recent item that is not pinned
     pub fn get_latest(conn: &Connection) -> Result<Option<StackItem>> {
-        let mut stmt = conn.prepare(
-            "SELECT id, original_name, original_path, stored_hash, type, pushed_at 
-             FROM stack_items ORDER BY pushed_at DESC LIMIT 1",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM stack_items WHERE pinned = 0 {} LIMIT 1",
+            ITEM_COLUMNS, DISPLAY_ORDER
+        ))?;
 
         let mut rows = stmt.query([])?;
 
@@ -126,41 +427,19 @@ impl ItemManager {
         }
     }
 
+    /// Get the most recent unpinned item that matches the given tag queries
     pub fn get_latest_by_tags(conn: &Connection, tags: &[String]) -> Result<Option<StackItem>> {
-        if tags.is_empty() {
+        let (filter, params) = tag_filter(tags)?;
+        if filter.is_empty() {
             return Self::get_latest(conn);
         }
 
-        // Build a query that finds items with ALL the specified tags
-        let placeholders = std::iter::repeat("?")
-            .take(tags.len())
-            .collect::<Vec<_>>()
-            .join(",");
         let sql = format!(
-            "SELECT si.id, si.original_name, si.original_path, si.stored_hash, si.type, si.pushed_at
-             FROM stack_items si
-             WHERE si.id IN (
-                 SELECT item_id 
-                 FROM item_tags it
-                 JOIN tags t ON it.tag_id = t.id
-                 WHERE t.name IN ({})
-                 GROUP BY item_id
-                 HAVING COUNT(DISTINCT t.name) = ?
-             )
-             ORDER BY si.pushed_at DESC
-             LIMIT 1",
-            placeholders
+            "SELECT {} FROM stack_items {} AND pinned = 0 {} LIMIT 1",
+            ITEM_COLUMNS, filter, DISPLAY_ORDER
         );
 
         let mut stmt = conn.prepare(&sql)?;
-
-        // Prepare params: all tag names followed by the count of tags
-        let mut params: Vec<rusqlite::types::Value> = tags
-            .iter()
-            .map(|t| rusqlite::types::Value::Text(t.clone()))
-            .collect();
-        params.push(rusqlite::types::Value::Integer(tags.len() as i64));
-
         let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
 
         if let Some(row) = rows.next()? {
@@ -172,94 +451,104 @@ impl ItemManager {
         }
     }
 
+    /// Items matching the tag queries in `tags`, newest first
     pub fn list(conn: &Connection, tags: &[String]) -> Result<Vec<StackItem>> {
         let mut items = Vec::new();
+        Self::query_by_tags(conn, tags, DISPLAY_ORDER, |item| {
+            items.push(item);
+            Ok(())
+        })?;
 
-        let sql = if tags.is_empty() {
-            // No tag filtering, get all items without sorting
-            "SELECT id, original_name, original_path, stored_hash, type, pushed_at 
-             FROM stack_items"
-                .to_string()
-        } else {
-            // Filter by tags
-            let placeholders = std::iter::repeat("?")
-                .take(tags.len())
-                .collect::<Vec<_>>()
-                .join(",");
-            format!(
-                "SELECT si.id, si.original_name, si.original_path, si.stored_hash, si.type, si.pushed_at
-                 FROM stack_items si
-                 WHERE si.id IN (
-                     SELECT item_id 
-                     FROM item_tags it
-                     JOIN tags t ON it.tag_id = t.id
-                     WHERE t.name IN ({})
-                     GROUP BY item_id
-                     HAVING COUNT(DISTINCT t.name) = ?
-                 )",
-                placeholders
-            )
-        };
+        Ok(items)
+    }
 
-        let mut stmt = conn.prepare(&sql)?;
+    /// Call `f` with each item matching `tags`, newest first, as rows are read.
+    /// Unlike `list`, only one item is held in memory at a time.
+    pub fn for_each<F>(conn: &Connection, tags: &[String], f: F) -> Result<()>
+    where
+        F: FnMut(StackItem) -> Result<()>,
+    {
+        Self::query_by_tags(conn, tags, DISPLAY_ORDER, f)
+    }
 
-        let rows = if tags.is_empty() {
-            stmt.query([])?
-        } else {
-            // Prepare params: all tag names followed by the count of tags
-            let mut params: Vec<rusqlite::types::Value> = tags
-                .iter()
-                .map(|t| rusqlite::types::Value::Text(t.clone()))
-                .collect();
-            params.push(rusqlite::types::Value::Integer(tags.len() as i64));
-
-            stmt.query(rusqlite::params_from_iter(params))?
-        };
+    fn query_by_tags<F>(conn: &Connection, tags: &[String], order_by: &str, mut f: F) -> Result<()>
+    where
+        F: FnMut(StackItem) -> Result<()>,
+    {
+        let (filter, params) = tag_filter(tags)?;
+        let sql = format!(
+            "SELECT {} FROM stack_items {} {}",
+            ITEM_COLUMNS, filter, order_by
+        );
 
-        let mut rows = rows;
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
 
         while let Some(row) = rows.next()? {
             let mut item = StackItem::from_row(row)?;
             item.tags = TagManager::get_for_item(conn, item.id)?;
-            items.push(item);
+            f(item)?;
         }
 
-        Ok(items)
+        Ok(())
     }
 
-    /// Get database ID by display number
+    /// Get database ID by display number (1 = newest among items matching `tags`).
+    /// With `--id` the number is the ID itself, checked to exist and to have the tags.
     pub fn get_id_by_display_number(
         conn: &Connection,
         display_number: usize,
         tags: &[String],
     ) -> Result<Option<i64>> {
-        // Get all items, sort them and find the ID by display number
-        let mut items = Self::list(conn, tags)?;
-
-        // No items found
-        if items.is_empty() {
-            return Ok(None);
-        }
+        Self::get_id_by_number(conn, display_number, tags, id_addressing())
+    }
 
-        // Sort by pushed_at descending (newest first)
-        items.sort_by(|a, b| b.pushed_at.cmp(&a.pushed_at));
+    /// `get_id_by_display_number`, taking the number as a database ID when `by_id` is set
+    pub fn get_id_by_number(
+        conn: &Connection,
+        display_number: usize,
+        tags: &[String],
+        by_id: bool,
+    ) -> Result<Option<i64>> {
+        let (filter, mut params) = tag_filter(tags)?;
 
-        // Find item by display number (display numbers start at 1)
-        if display_number <= items.len() && display_number > 0 {
-            let item = &items[display_number - 1];
-            Ok(Some(item.id))
+        let sql = if by_id {
+            params.push(rusqlite::types::Value::Integer(display_number as i64));
+            format!(
+                "SELECT id FROM stack_items {} {} id = ?",
+                filter,
+                if filter.is_empty() { "WHERE" } else { "AND" }
+            )
         } else {
-            Ok(None)
-        }
+            // Display numbers start at 1
+            let Some(offset) = display_number.checked_sub(1) else {
+                return Ok(None);
+            };
+            params.push(rusqlite::types::Value::Integer(offset as i64));
+            format!(
+                "SELECT id FROM stack_items {} {} LIMIT 1 OFFSET ?",
+                filter, DISPLAY_ORDER
+            )
+        };
+
+        Ok(conn
+            .query_row(&sql, rusqlite::params_from_iter(params), |row| row.get(0))
+            .optional()?)
     }
 
     /// Delete an item from the stack and clean up any orphaned tags
     pub fn delete(conn: &mut Connection, id: i64) -> Result<bool> {
-        // First, identify tags associated with this item for cleanup later
-        let tag_ids = Self::get_tag_ids_for_item(conn, id)?;
-
-        // Start a transaction
         let tx = conn.transaction()?;
+        let deleted = Self::delete_row(&tx, id)?;
+        tx.commit()?;
+
+        Ok(deleted)
+    }
+
+    /// Delete an item inside a transaction the caller manages (see `delete`)
+    pub fn delete_row(tx: &Connection, id: i64) -> Result<bool> {
+        // First, identify tags associated with this item for cleanup later
+        let tag_ids = Self::get_tag_ids_for_item(tx, id)?;
 
         // Delete the item
         let result = tx.execute("DELETE FROM stack_items WHERE id = ?", params![id])?;
@@ -268,12 +557,201 @@ impl ItemManager {
 
         // Clean up any orphaned tags
         if result > 0 && !tag_ids.is_empty() {
-            TagManager::cleanup_orphaned_tags(&tx, &tag_ids)?;
+            TagManager::cleanup_orphaned_tags(tx, &tag_ids)?;
         }
 
-        // Commit the transaction
+        Ok(result > 0)
+    }
+
+    /// Find all items whose content hash matches the given one
+    pub fn find_by_content_hash(conn: &Connection, content_hash: &str) -> Result<Vec<StackItem>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM stack_items WHERE content_hash = ? {}",
+            ITEM_COLUMNS, DISPLAY_ORDER
+        ))?;
+
+        let mut rows = stmt.query(params![content_hash])?;
+        let mut items = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            let mut item = StackItem::from_row(row)?;
+            item.tags = TagManager::get_for_item(conn, item.id)?;
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+
+    /// List items ordered by stored size (largest first); items without a known size come last
+    pub fn list_by_size(conn: &Connection, limit: usize) -> Result<Vec<StackItem>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM stack_items
+             ORDER BY size_bytes IS NULL, size_bytes DESC, stack_order DESC
+             LIMIT ?",
+            ITEM_COLUMNS
+        ))?;
+
+        let mut rows = stmt.query(params![limit as i64])?;
+        let mut items = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            let mut item = StackItem::from_row(row)?;
+            item.tags = TagManager::get_for_item(conn, item.id)?;
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+
+    /// List IDs of items whose stored size has not been recorded yet
+    pub fn list_ids_without_size(conn: &Connection) -> Result<Vec<(i64, String)>> {
+        let mut stmt =
+            conn.prepare("SELECT id, stored_hash FROM stack_items WHERE size_bytes IS NULL")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut ids = Vec::new();
+        for row in rows {
+            ids.push(row?);
+        }
+
+        Ok(ids)
+    }
+
+    /// Record the stored size of an item
+    pub fn set_size(conn: &Connection, id: i64, size: u64) -> Result<bool> {
+        let result = conn.execute(
+            "UPDATE stack_items SET size_bytes = ? WHERE id = ?",
+            params![size, id],
+        )?;
+
+        Ok(result > 0)
+    }
+
+    /// Change the name an item is popped under
+    pub fn set_name(conn: &Connection, id: i64, name: &str) -> Result<bool> {
+        let result = conn.execute(
+            "UPDATE stack_items SET original_name = ? WHERE id = ?",
+            params![name, id],
+        )?;
+
+        Ok(result > 0)
+    }
+
+    /// Replace an item's note (`None` clears it)
+    pub fn set_note(conn: &Connection, id: i64, note: Option<&str>) -> Result<bool> {
+        let result = conn.execute(
+            "UPDATE stack_items SET note = ? WHERE id = ?",
+            params![note, id],
+        )?;
+
+        Ok(result > 0)
+    }
+
+    /// List items pushed before the given time, oldest first
+    pub fn list_pushed_before(
+        conn: &Connection,
+        cutoff: DateTime<Local>,
+    ) -> Result<Vec<StackItem>> {
+        // pushed_at is stored as UTC text, which compares correctly as a string
+        let cutoff = format_timestamp(cutoff);
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM stack_items WHERE pushed_at < ? ORDER BY pushed_at, push_seq",
+            ITEM_COLUMNS
+        ))?;
+
+        let mut rows = stmt.query(params![cutoff])?;
+        let mut items = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            let mut item = StackItem::from_row(row)?;
+            item.tags = TagManager::get_for_item(conn, item.id)?;
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+
+    /// Record where an item's blob is stored (`None` for the data directory)
+    pub fn set_storage_location(
+        conn: &Connection,
+        id: i64,
+        location: Option<&str>,
+    ) -> Result<bool> {
+        let result = conn.execute(
+            "UPDATE stack_items SET storage_location = ? WHERE id = ?",
+            params![location, id],
+        )?;
+
+        Ok(result > 0)
+    }
+
+    /// Mark a directory item as partially extracted and record its remaining size and content
+    pub fn mark_partial(
+        conn: &Connection,
+        id: i64,
+        size: u64,
+        content_hash: Option<&str>,
+    ) -> Result<bool> {
+        let result = conn.execute(
+            "UPDATE stack_items SET partial = 1, size_bytes = ?, content_hash = ? WHERE id = ?",
+            params![size, content_hash, id],
+        )?;
+
+        Ok(result > 0)
+    }
+
+    /// List all versions of an item's original path, oldest first
+    pub fn list_versions(conn: &Connection, version_group: &str) -> Result<Vec<StackItem>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM stack_items WHERE version_group = ? ORDER BY pushed_at, push_seq",
+            ITEM_COLUMNS
+        ))?;
+
+        let mut rows = stmt.query(params![version_group])?;
+        let mut items = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            let mut item = StackItem::from_row(row)?;
+            item.tags = TagManager::get_for_item(conn, item.id)?;
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+
+    /// Put the items in the order of `ids`, top of the stack first. Every item of the stack
+    /// must be listed.
+    pub fn reorder(conn: &mut Connection, ids: &[i64]) -> Result<()> {
+        let tx = conn.transaction()?;
+        for (index, id) in ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE stack_items SET stack_order = ? WHERE id = ?",
+                params![(ids.len() - index) as i64, id],
+            )?;
+        }
         tx.commit()?;
 
+        Ok(())
+    }
+
+    /// Pin or unpin an item
+    pub fn set_pinned(conn: &Connection, id: i64, pinned: bool) -> Result<bool> {
+        let result = conn.execute(
+            "UPDATE stack_items SET pinned = ? WHERE id = ?",
+            params![pinned, id],
+        )?;
+
+        Ok(result > 0)
+    }
+
+    /// Lock or unlock an item
+    pub fn set_locked(conn: &Connection, id: i64, locked: bool) -> Result<bool> {
+        let result = conn.execute(
+            "UPDATE stack_items SET locked = ? WHERE id = ?",
+            params![locked, id],
+        )?;
+
         Ok(result > 0)
     }
 
@@ -319,10 +797,8 @@ mod tests {
         )?;
 
         // Retrieve the row directly to a StackItem
-        let mut stmt = conn.prepare(
-            "SELECT id, original_name, original_path, stored_hash, type, pushed_at 
-             FROM stack_items LIMIT 1",
-        )?;
+        let mut stmt =
+            conn.prepare(&format!("SELECT {} FROM stack_items LIMIT 1", ITEM_COLUMNS))?;
 
         // Use map_row to avoid lifetime issues
         let item = stmt.query_row([], |row| {
@@ -339,6 +815,7 @@ mod tests {
         assert_eq!(item.stored_hash, "abcdef1234567890");
         assert_eq!(item.item_type, "file");
         assert!(item.tags.is_empty());
+        assert!(!item.pinned);
 
         Ok(())
     }
@@ -367,6 +844,12 @@ mod tests {
         assert_eq!(item.tags.len(), 1);
         assert_eq!(item.tags[0], "test-tag");
 
+        // The same item can be looked up by its stored hash
+        let by_hash =
+            ItemManager::get_by_stored_hash(&conn, "abcdef1234567890")?.expect("Item should exist");
+        assert_eq!(by_hash.id, item_id);
+        assert!(ItemManager::get_by_stored_hash(&conn, "missing")?.is_none());
+
         Ok(())
     }
 
@@ -480,6 +963,18 @@ mod tests {
         let items = ItemManager::list(&conn, &["common".to_string()])?;
         assert_eq!(items.len(), 2);
 
+        // Streaming yields the same items in display order
+        conn.execute(
+            "UPDATE stack_items SET pushed_at = '2024-01-01 00:00:00' WHERE stored_hash = 'hash1'",
+            [],
+        )?;
+        let mut names = Vec::new();
+        ItemManager::for_each(&conn, &["common".to_string()], |item| {
+            names.push(item.original_name);
+            Ok(())
+        })?;
+        assert_eq!(names, vec!["file2.txt", "file1.txt"]);
+
         Ok(())
     }
 
@@ -569,9 +1064,358 @@ mod tests {
 
         assert_eq!(id, item1_id);
 
-        // Test invalid display number
+        // Test invalid display numbers
         let result = ItemManager::get_id_by_display_number(&conn, 999, &[])?;
         assert!(result.is_none());
+        assert!(ItemManager::get_id_by_display_number(&conn, 0, &[])?.is_none());
+
+        // Numbers count only the items having all of the tags
+        let other_tag = find_or_create_tag(&conn, "other")?;
+        conn.execute(
+            "INSERT INTO item_tags (item_id, tag_id) VALUES (?, ?)",
+            params![item1_id, other_tag],
+        )?;
+        let tags = vec!["test".to_string(), "other".to_string()];
+        assert_eq!(
+            ItemManager::get_id_by_display_number(&conn, 1, &tags)?,
+            Some(item1_id)
+        );
+        assert!(ItemManager::get_id_by_display_number(&conn, 2, &tags)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_second_pushes_keep_push_order() -> Result<()> {
+        let mut conn = setup_test_db()?;
+
+        // Both items share a pushed_at; the later push must still come first everywhere
+        let metadata = ItemMetadata {
+            pushed_at: Some(Local::now()),
+            ..Default::default()
+        };
+        let first = ItemManager::insert_with_metadata(
+            &mut conn,
+            "a.txt",
+            "/tmp",
+            "hash_a",
+            "file",
+            &[],
+            &metadata,
+        )?;
+        let second = ItemManager::insert_with_metadata(
+            &mut conn,
+            "b.txt",
+            "/tmp",
+            "hash_b",
+            "file",
+            &[],
+            &metadata,
+        )?;
+
+        let items = ItemManager::list(&conn, &[])?;
+        assert_eq!(items[0].id, second);
+        assert!(items[0].push_seq > items[1].push_seq);
+        assert_eq!(
+            ItemManager::get_id_by_display_number(&conn, 1, &[])?,
+            Some(second)
+        );
+        assert_eq!(
+            ItemManager::get_id_by_display_number(&conn, 2, &[])?,
+            Some(first)
+        );
+        assert_eq!(ItemManager::get_latest(&conn)?.unwrap().id, second);
+
+        let mut sorted = items.clone();
+        sorted.sort_by_key(|item| std::cmp::Reverse(item.stack_position()));
+        assert_eq!(sorted[0].id, second);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reorder() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let mut ids = Vec::new();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            ids.push(ItemManager::insert(
+                &mut conn,
+                name,
+                "/tmp",
+                name,
+                "file",
+                &[],
+            )?);
+        }
+
+        ItemManager::reorder(&mut conn, &[ids[0], ids[2], ids[1]])?;
+        let order: Vec<i64> = ItemManager::list(&conn, &[])?
+            .iter()
+            .map(|item| item.id)
+            .collect();
+        assert_eq!(order, vec![ids[0], ids[2], ids[1]]);
+        assert_eq!(ItemManager::get_latest(&conn)?.unwrap().id, ids[0]);
+
+        // New items still go on top
+        let newest = ItemManager::insert(&mut conn, "d.txt", "/tmp", "d", "file", &[])?;
+        assert_eq!(ItemManager::get_latest(&conn)?.unwrap().id, newest);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_by_content_hash() -> Result<()> {
+        let mut conn = setup_test_db()?;
+
+        let metadata = ItemMetadata {
+            content_hash: Some("content_a".to_string()),
+            ..Default::default()
+        };
+        let id = ItemManager::insert_with_metadata(
+            &mut conn,
+            "a.txt",
+            "/path/to",
+            "hash_content_1",
+            "file",
+            &[],
+            &metadata,
+        )?;
+        ItemManager::insert(
+            &mut conn,
+            "b.txt",
+            "/path/to",
+            "hash_content_2",
+            "file",
+            &[],
+        )?;
+
+        let matches = ItemManager::find_by_content_hash(&conn, "content_a")?;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].id, id);
+        assert_eq!(matches[0].content_hash.as_deref(), Some("content_a"));
+
+        assert!(ItemManager::find_by_content_hash(&conn, "content_b")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_with_imported_metadata() -> Result<()> {
+        let mut conn = setup_test_db()?;
+
+        let pushed_at = Local::now() - chrono::Duration::days(3);
+        let metadata = ItemMetadata {
+            pushed_at: Some(pushed_at),
+            pinned: true,
+            locked: true,
+            owner: Some((1000, 100)),
+            push_dir: Some("/home/user/work".to_string()),
+            ..Default::default()
+        };
+        let id = ItemManager::insert_with_metadata(
+            &mut conn,
+            "old.txt",
+            "/p",
+            "hash_old",
+            "file",
+            &[],
+            &metadata,
+        )?;
+
+        let item = ItemManager::get_by_id(&conn, id)?.expect("Item should exist");
+        assert_eq!(item.pushed_at.timestamp(), pushed_at.timestamp());
+        assert!(item.pinned);
+        assert!(item.locked);
+        assert_eq!(item.owner_uid, Some(1000));
+        assert_eq!(item.owner_gid, Some(100));
+        assert_eq!(item.push_dir.as_deref(), Some("/home/user/work"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_versions() -> Result<()> {
+        let mut conn = setup_test_db()?;
+
+        for (hash, group) in [
+            ("hash_v1", "/docs/report.pdf"),
+            ("hash_other", "/docs/other.pdf"),
+            ("hash_v2", "/docs/report.pdf"),
+        ] {
+            let metadata = ItemMetadata {
+                version_group: Some(group.to_string()),
+                ..Default::default()
+            };
+            ItemManager::insert_with_metadata(
+                &mut conn,
+                "x",
+                "/docs",
+                hash,
+                "file",
+                &[],
+                &metadata,
+            )?;
+        }
+
+        let versions = ItemManager::list_versions(&conn, "/docs/report.pdf")?;
+        let hashes: Vec<&str> = versions.iter().map(|i| i.stored_hash.as_str()).collect();
+        assert_eq!(hashes, vec!["hash_v1", "hash_v2"]);
+
+        assert!(ItemManager::list_versions(&conn, "/docs/none.pdf")?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_by_size() -> Result<()> {
+        let mut conn = setup_test_db()?;
+
+        for (name, hash, size) in [("small", "hash_s", 10), ("large", "hash_l", 1000)] {
+            let metadata = ItemMetadata {
+                size: Some(size),
+                ..Default::default()
+            };
+            ItemManager::insert_with_metadata(&mut conn, name, "/p", hash, "file", &[], &metadata)?;
+        }
+        let unknown_id = ItemManager::insert(&mut conn, "unknown", "/p", "hash_u", "file", &[])?;
+
+        let items = ItemManager::list_by_size(&conn, 10)?;
+        let names: Vec<&str> = items.iter().map(|i| i.original_name.as_str()).collect();
+        assert_eq!(names, vec!["large", "small", "unknown"]);
+
+        assert_eq!(ItemManager::list_by_size(&conn, 1)?.len(), 1);
+
+        // Backfill the missing size
+        let missing = ItemManager::list_ids_without_size(&conn)?;
+        assert_eq!(missing, vec![(unknown_id, "hash_u".to_string())]);
+        assert!(ItemManager::set_size(&conn, unknown_id, 5000)?);
+        assert_eq!(
+            ItemManager::list_by_size(&conn, 1)?[0].original_name,
+            "unknown"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_pushed_before() -> Result<()> {
+        let conn = setup_test_db()?;
+
+        conn.execute(
+            "INSERT INTO stack_items (original_name, original_path, stored_hash, type, pushed_at) 
+             VALUES ('old.txt', '/p', 'hash_old', 'file', datetime('now', '-100 days'))",
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO stack_items (original_name, original_path, stored_hash, type) 
+             VALUES ('new.txt', '/p', 'hash_new', 'file')",
+            [],
+        )?;
+
+        let cutoff = Local::now() - chrono::Duration::days(90);
+        let items = ItemManager::list_pushed_before(&conn, cutoff)?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].original_name, "old.txt");
+
+        // Archive the old item
+        assert!(ItemManager::set_storage_location(
+            &conn,
+            items[0].id,
+            Some("/mnt/cold")
+        )?);
+        let item = ItemManager::get_by_id(&conn, items[0].id)?.expect("Item should exist");
+        assert_eq!(item.storage_location.as_deref(), Some("/mnt/cold"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_items_are_skipped_by_latest() -> Result<()> {
+        let conn = setup_test_db()?;
+
+        conn.execute(
+            "INSERT INTO stack_items (original_name, original_path, stored_hash, type, pushed_at) 
+             VALUES ('older.txt', '/path/to', 'hash_pin_1', 'file', datetime('now', '-1 minute'))",
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO stack_items (original_name, original_path, stored_hash, type) 
+             VALUES ('newer.txt', '/path/to', 'hash_pin_2', 'file')",
+            [],
+        )?;
+        let newer_id = conn.last_insert_rowid();
+        let tag_id = find_or_create_tag(&conn, "shared")?;
+        conn.execute(
+            "INSERT INTO item_tags (item_id, tag_id) SELECT id, ? FROM stack_items",
+            params![tag_id],
+        )?;
+
+        assert!(ItemManager::set_pinned(&conn, newer_id, true)?);
+
+        let latest = ItemManager::get_latest(&conn)?.expect("Item should exist");
+        assert_eq!(latest.original_name, "older.txt");
+
+        let latest = ItemManager::get_latest_by_tags(&conn, &["shared".to_string()])?
+            .expect("Item should exist");
+        assert_eq!(latest.original_name, "older.txt");
+
+        // Pinned items are still listed and can be looked up directly
+        let pinned = ItemManager::get_by_id(&conn, newer_id)?.expect("Item should exist");
+        assert!(pinned.pinned);
+        assert_eq!(ItemManager::list(&conn, &[])?.len(), 2);
+
+        assert!(ItemManager::set_pinned(&conn, newer_id, false)?);
+        let latest = ItemManager::get_latest(&conn)?.expect("Item should exist");
+        assert_eq!(latest.original_name, "newer.txt");
+
+        // Locked items still count as the latest item
+        assert!(ItemManager::set_locked(&conn, newer_id, true)?);
+        let latest = ItemManager::get_latest(&conn)?.expect("Item should exist");
+        assert_eq!(latest.original_name, "newer.txt");
+        assert!(latest.locked);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_or_queries_do_not_bypass_other_conditions() -> Result<()> {
+        let conn = setup_test_db()?;
+
+        for (name, hash) in [("a.txt", "hash_or_1"), ("b.txt", "hash_or_2")] {
+            conn.execute(
+                "INSERT INTO stack_items (original_name, original_path, stored_hash, type) 
+                 VALUES (?, '/path/to', ?, 'file')",
+                params![name, hash],
+            )?;
+            let tag_id = find_or_create_tag(&conn, name.trim_end_matches(".txt"))?;
+            conn.execute(
+                "INSERT INTO item_tags (item_id, tag_id) VALUES (?, ?)",
+                params![conn.last_insert_rowid(), tag_id],
+            )?;
+        }
+        let a_id = ItemManager::list(&conn, &["a".to_string()])?[0].id;
+        let b_id = ItemManager::list(&conn, &["b".to_string()])?[0].id;
+
+        // The newest item matches only the second branch of the OR and is pinned
+        assert!(ItemManager::set_pinned(&conn, b_id, true)?);
+        let query = ["a OR b".to_string()];
+        let latest = ItemManager::get_latest_by_tags(&conn, &query)?.expect("Item should exist");
+        assert_eq!(latest.id, a_id);
+
+        // An ID lookup only finds the item with that ID
+        assert_eq!(
+            ItemManager::get_id_by_number(&conn, a_id as usize, &query, true)?,
+            Some(a_id)
+        );
+        assert_eq!(
+            ItemManager::get_id_by_number(&conn, b_id as usize, &query, true)?,
+            Some(b_id)
+        );
+        let query = ["nothing OR b".to_string()];
+        assert_eq!(
+            ItemManager::get_id_by_number(&conn, a_id as usize, &query, true)?,
+            None
+        );
 
         Ok(())
     }
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+
+/// A file operation that was started but not yet marked as complete
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub id: i64,
+    /// `push`, `link`, `copy`, `pack`, `bundle`, `pop`, `merge` or `restore`
+    pub operation: String,
+    /// Stored hash of the item being moved
+    pub stored_hash: String,
+    /// Content hash of the item, used to tell a finished copy from a partial one
+    pub content_hash: Option<String>,
+    pub source: String,
+    pub destination: String,
+    /// Process that started the operation
+    pub pid: u32,
+}
+
+pub struct JournalManager;
+
+impl JournalManager {
+    /// Record the intent to move `source` to `destination` before touching any files
+    pub fn begin(
+        conn: &Connection,
+        operation: &str,
+        stored_hash: &str,
+        content_hash: Option<&str>,
+        source: &str,
+        destination: &str,
+    ) -> Result<i64> {
+        conn.execute(
+            "INSERT INTO journal (operation, stored_hash, content_hash, source, destination, pid)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                operation,
+                stored_hash,
+                content_hash,
+                source,
+                destination,
+                std::process::id()
+            ],
+        )?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Record the content hash of an operation once it is known (e.g. computed during the copy)
+    pub fn set_content_hash(conn: &Connection, id: i64, content_hash: &str) -> Result<()> {
+        conn.execute(
+            "UPDATE journal SET content_hash = ? WHERE id = ?",
+            params![content_hash, id],
+        )?;
+        Ok(())
+    }
+
+    /// Mark an operation as finished, whether it completed or was rolled back
+    pub fn complete(conn: &Connection, id: i64) -> Result<()> {
+        conn.execute("DELETE FROM journal WHERE id = ?", params![id])?;
+        Ok(())
+    }
+
+    /// List operations that were never marked as finished, oldest first
+    pub fn pending(conn: &Connection) -> Result<Vec<JournalEntry>> {
+        let mut stmt = conn.prepare(
+            "SELECT id, operation, stored_hash, content_hash, source, destination, pid
+             FROM journal ORDER BY id",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(JournalEntry {
+                id: row.get(0)?,
+                operation: row.get(1)?,
+                stored_hash: row.get(2)?,
+                content_hash: row.get(3)?,
+                source: row.get(4)?,
+                destination: row.get(5)?,
+                pid: row.get(6)?,
+            })
+        })?;
+
+        let mut entries = Vec::new();
+        for entry in rows {
+            entries.push(entry?);
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+
+    #[test]
+    fn test_begin_and_complete() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+
+        let first = JournalManager::begin(
+            &conn,
+            "push",
+            "hash1",
+            Some("content1"),
+            "/src/a",
+            "/data/hash1.staging",
+        )?;
+        let second = JournalManager::begin(&conn, "pop", "hash2", None, "/data/hash2", "/dst/b")?;
+
+        let pending = JournalManager::pending(&conn)?;
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].id, first);
+        assert_eq!(pending[0].operation, "push");
+        assert_eq!(pending[0].content_hash.as_deref(), Some("content1"));
+        assert_eq!(pending[0].destination, "/data/hash1.staging");
+        assert_eq!(pending[0].pid, std::process::id());
+
+        JournalManager::complete(&conn, first)?;
+        let pending = JournalManager::pending(&conn)?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, second);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+
+use crate::fs::ManifestEntry;
+
+pub struct ManifestManager;
+
+impl ManifestManager {
+    /// Store the manifest of a directory item
+    pub fn insert(conn: &Connection, item_id: i64, entries: &[ManifestEntry]) -> Result<()> {
+        let mut stmt = conn.prepare(
+            "INSERT INTO item_manifest (item_id, relative_path, size, hash) VALUES (?, ?, ?, ?)",
+        )?;
+
+        for entry in entries {
+            stmt.execute(params![
+                item_id,
+                entry.relative_path,
+                entry.size,
+                entry.hash
+            ])?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove the entry at `path` and everything below it from an item's manifest
+    pub fn remove_path(conn: &Connection, item_id: i64, path: &str) -> Result<usize> {
+        let removed = conn.execute(
+            "DELETE FROM item_manifest
+             WHERE item_id = ? AND (relative_path = ? OR substr(relative_path, 1, length(?) + 1) = ? || '/')",
+            params![item_id, path, path, path],
+        )?;
+
+        Ok(removed)
+    }
+
+    /// Get the manifest of an item, sorted by relative path (empty if none was recorded)
+    pub fn get_for_item(conn: &Connection, item_id: i64) -> Result<Vec<ManifestEntry>> {
+        let mut stmt = conn.prepare(
+            "SELECT relative_path, size, hash FROM item_manifest
+             WHERE item_id = ?
+             ORDER BY relative_path",
+        )?;
+
+        let rows = stmt.query_map(params![item_id], |row| {
+            Ok(ManifestEntry {
+                relative_path: row.get(0)?,
+                size: row.get(1)?,
+                hash: row.get(2)?,
+            })
+        })?;
+
+        let mut entries = Vec::new();
+        for entry in rows {
+            entries.push(entry?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Find files with the given content in the manifests of items other than `exclude_item`,
+    /// as `(item_id, relative_path)`
+    pub fn find_by_hash(
+        conn: &Connection,
+        hash: &str,
+        size: u64,
+        exclude_item: i64,
+    ) -> Result<Vec<(i64, String)>> {
+        let mut stmt = conn.prepare(
+            "SELECT item_id, relative_path FROM item_manifest
+             WHERE hash = ? AND size = ? AND item_id != ?
+             ORDER BY item_id, relative_path",
+        )?;
+
+        let rows = stmt.query_map(params![hash, size, exclude_item], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+
+        let mut files = Vec::new();
+        for file in rows {
+            files.push(file?);
+        }
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{schema, ItemManager};
+
+    fn setup_test_db() -> Result<Connection> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        schema::initialize_schema(&conn)?;
+        Ok(conn)
+    }
+
+    fn entry(path: &str, size: u64) -> ManifestEntry {
+        ManifestEntry {
+            relative_path: path.to_string(),
+            size,
+            hash: format!("hash_of_{}", path),
+        }
+    }
+
+    #[test]
+    fn test_insert_and_get_manifest() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let item_id = ItemManager::insert(&mut conn, "dir", "/p", "hash_dir", "directory", &[])?;
+
+        ManifestManager::insert(&conn, item_id, &[entry("b/c.txt", 2), entry("a.txt", 1)])?;
+
+        let manifest = ManifestManager::get_for_item(&conn, item_id)?;
+        assert_eq!(manifest, vec![entry("a.txt", 1), entry("b/c.txt", 2)]);
+
+        // Removing a directory path removes all entries below it
+        ManifestManager::insert(&conn, item_id, &[entry("b/d.txt", 3), entry("bb.txt", 4)])?;
+        assert_eq!(ManifestManager::remove_path(&conn, item_id, "b")?, 2);
+        let manifest = ManifestManager::get_for_item(&conn, item_id)?;
+        assert_eq!(manifest, vec![entry("a.txt", 1), entry("bb.txt", 4)]);
+
+        // The manifest is removed together with its item
+        ItemManager::delete(&mut conn, item_id)?;
+        assert!(ManifestManager::get_for_item(&conn, item_id)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_by_hash() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let first = ItemManager::insert(&mut conn, "one", "/p", "hash_one", "directory", &[])?;
+        let second = ItemManager::insert(&mut conn, "two", "/p", "hash_two", "directory", &[])?;
+        ManifestManager::insert(&conn, first, &[entry("a.txt", 1)])?;
+        ManifestManager::insert(&conn, second, &[entry("a.txt", 1)])?;
+
+        assert_eq!(
+            ManifestManager::find_by_hash(&conn, "hash_of_a.txt", 1, second)?,
+            vec![(first, "a.txt".to_string())]
+        );
+        // Sizes must match too
+        assert!(ManifestManager::find_by_hash(&conn, "hash_of_a.txt", 2, second)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,24 +1,128 @@
+mod bundle;
 mod item;
+mod journal;
+mod manifest;
+mod operation;
+mod provenance;
 pub mod schema;
+mod state;
+mod sync;
 mod tag;
+mod tag_query;
+mod tag_usage;
 
-pub use item::{ItemManager, StackItem};
-pub use tag::TagManager;
+pub use bundle::{BundleManager, BundleMember};
+pub use item::{
+    enable_id_addressing, id_addressing, item_by_number, item_by_number_with, item_number,
+    item_number_with, ItemManager, ItemMetadata, StackItem,
+};
+pub use journal::{JournalEntry, JournalManager};
+pub use manifest::ManifestManager;
+pub use operation::{OperationLog, OperationRecord};
+pub use provenance::Provenance;
+pub use state::{StateManager, LAST_POP_OUTPUT};
+pub use sync::SyncStateManager;
+pub use tag::{TagInfo, TagManager};
+pub use tag_query::TagQuery;
+pub use tag_usage::TagUsageManager;
 
 use anyhow::{anyhow, Result};
 use rusqlite::Connection;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Name of the directory holding a stack's database and storage
+pub const FSTK_DIR_NAME: &str = ".fstk";
+
+/// Name of the database file inside an fstk directory
+pub const DB_FILE_NAME: &str = "fstk.db";
+
+/// Which stack a command operates on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackScope {
+    /// Use the project stack if one exists above the current directory, else the global one
+    Auto,
+    /// Always use the global stack in the home directory
+    Global,
+    /// Use (or create) the stack of the current project
+    Local,
+}
+
+/// The stack selected for this invocation: (fstk directory, project root if local)
+static ACTIVE_STACK: OnceLock<(PathBuf, Option<PathBuf>)> = OnceLock::new();
+
+/// Get the global fstk directory (`~/.fstk`)
+pub fn get_global_fstk_dir() -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
+    Ok(home_dir.join(FSTK_DIR_NAME))
+}
+
+/// Find the nearest ancestor of `start` that contains a project stack directory.
+/// The global fstk directory in the home directory is never treated as a project stack.
+pub fn find_project_stack(start: &Path, global_dir: &Path) -> Option<PathBuf> {
+    start.ancestors().find_map(|dir| {
+        let candidate = dir.join(FSTK_DIR_NAME);
+        (candidate.is_dir() && candidate != global_dir).then(|| dir.to_path_buf())
+    })
+}
+
+/// Find the root of the git repository containing `start`, if any.
+pub fn find_git_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(|dir| dir.to_path_buf())
+}
+
+/// Select the stack used for the rest of this invocation.
+pub fn select_stack(scope: StackScope) -> Result<()> {
+    let global_dir = get_global_fstk_dir()?;
+    let cwd = std::env::current_dir()?;
+
+    let project_root = match scope {
+        StackScope::Global => None,
+        StackScope::Auto => find_project_stack(&cwd, &global_dir),
+        StackScope::Local => Some(
+            find_project_stack(&cwd, &global_dir)
+                .or_else(|| find_git_root(&cwd))
+                .ok_or_else(|| {
+                    anyhow!("No project root found (no .fstk directory or git repository)")
+                })?,
+        ),
+    };
+
+    let selected = match project_root {
+        Some(root) => (root.join(FSTK_DIR_NAME), Some(root)),
+        None => (global_dir, None),
+    };
+
+    ACTIVE_STACK
+        .set(selected)
+        .map_err(|_| anyhow!("Stack has already been selected"))
+}
+
+/// Get the fstk directory of the active stack (the global one unless a project stack was selected)
+pub fn get_fstk_dir() -> Result<PathBuf> {
+    match ACTIVE_STACK.get() {
+        Some((dir, _)) => Ok(dir.clone()),
+        None => get_global_fstk_dir(),
+    }
+}
+
+/// Get the project root of the active stack, or `None` when using the global stack
+pub fn get_project_root() -> Option<PathBuf> {
+    ACTIVE_STACK.get().and_then(|(_, root)| root.clone())
+}
 
 // Path operations
 pub fn get_db_path() -> Result<PathBuf> {
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
-    let fstk_dir = home_dir.join(".fstk");
+    let fstk_dir = get_fstk_dir()?;
 
     // Create directories if they don't exist
     std::fs::create_dir_all(&fstk_dir)?;
     std::fs::create_dir_all(fstk_dir.join(".data"))?;
 
-    Ok(fstk_dir.join("fstk.db"))
+    Ok(fstk_dir.join(DB_FILE_NAME))
 }
 
 pub fn establish_connection() -> Result<Connection> {
@@ -36,8 +140,7 @@ pub fn establish_connection() -> Result<Connection> {
 }
 
 pub fn get_data_dir() -> Result<PathBuf> {
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
-    let data_dir = home_dir.join(".fstk").join(".data");
+    let data_dir = get_fstk_dir()?.join(".data");
 
     // Create directory if it doesn't exist
     std::fs::create_dir_all(&data_dir)?;
@@ -49,3 +152,51 @@ pub fn get_stored_path(hash: &str) -> Result<PathBuf> {
     let data_dir = get_data_dir()?;
     Ok(data_dir.join(hash))
 }
+
+/// Get the path of an item's stored blob, which lives in secondary storage once archived
+pub fn get_item_stored_path(item: &StackItem) -> Result<PathBuf> {
+    match &item.storage_location {
+        Some(location) => Ok(PathBuf::from(location).join(&item.stored_hash)),
+        None => get_stored_path(&item.stored_hash),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_project_stack() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let project = temp_dir.path().join("project");
+        let nested = project.join("src/module");
+        std::fs::create_dir_all(&nested)?;
+
+        let global_dir = temp_dir.path().join(FSTK_DIR_NAME);
+        std::fs::create_dir_all(&global_dir)?;
+
+        // Only the global directory exists, which is never a project stack
+        assert_eq!(find_project_stack(&nested, &global_dir), None);
+
+        std::fs::create_dir_all(project.join(FSTK_DIR_NAME))?;
+        assert_eq!(find_project_stack(&nested, &global_dir), Some(project));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_git_root() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let repo = temp_dir.path().join("repo");
+        let nested = repo.join("a/b");
+        std::fs::create_dir_all(&nested)?;
+
+        assert_eq!(find_git_root(&nested), None);
+
+        std::fs::create_dir_all(repo.join(".git"))?;
+        assert_eq!(find_git_root(&nested), Some(repo));
+
+        Ok(())
+    }
+}
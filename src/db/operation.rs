@@ -0,0 +1,131 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection};
+
+use crate::db::item::{format_timestamp, parse_timestamp};
+use crate::db::StackItem;
+
+/// A change to the stack, kept after the item itself is gone
+#[derive(Debug, Clone)]
+pub struct OperationRecord {
+    /// `push`, `pop`, `restore` or `remove`
+    pub operation: String,
+    /// Database ID the item had (IDs are not reused)
+    pub item_id: i64,
+    pub item_name: String,
+    /// Parent directory the item was pushed from
+    pub original_path: String,
+    /// Tags the item had at the time of the operation
+    pub tags: Vec<String>,
+    pub item_pushed_at: DateTime<Local>,
+    pub performed_at: DateTime<Local>,
+}
+
+pub struct OperationLog;
+
+impl OperationLog {
+    /// Record that `operation` was performed on `item` just now
+    pub fn record(conn: &Connection, operation: &str, item: &StackItem) -> Result<()> {
+        conn.execute(
+            "INSERT INTO operations (operation, item_id, item_name, original_path, tags, item_pushed_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                operation,
+                item.id,
+                item.original_name,
+                item.original_path,
+                item.tags.join(","),
+                format_timestamp(item.pushed_at)
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// List operations performed at or after `since` (all of them if `None`), oldest first
+    pub fn list_since(
+        conn: &Connection,
+        since: Option<DateTime<Local>>,
+    ) -> Result<Vec<OperationRecord>> {
+        let since = since.map(format_timestamp).unwrap_or_default();
+        let mut stmt = conn.prepare(
+            "SELECT operation, item_id, item_name, original_path, tags, item_pushed_at, performed_at
+             FROM operations WHERE performed_at >= ? ORDER BY performed_at, id",
+        )?;
+
+        let rows = stmt.query_map(params![since], |row| {
+            Ok((
+                OperationRecord {
+                    operation: row.get(0)?,
+                    item_id: row.get(1)?,
+                    item_name: row.get(2)?,
+                    original_path: row.get(3)?,
+                    tags: Vec::new(),
+                    item_pushed_at: Local::now(),
+                    performed_at: Local::now(),
+                },
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+            ))
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            let (record, tags, pushed_at, performed_at) = row?;
+            records.push(OperationRecord {
+                tags: tags
+                    .split(',')
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                item_pushed_at: parse_timestamp(&pushed_at)?,
+                performed_at: parse_timestamp(&performed_at)?,
+                ..record
+            });
+        }
+
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+
+    #[test]
+    fn test_record_and_list() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+
+        let item = StackItem {
+            id: 3,
+            original_name: "notes.md".to_string(),
+            original_path: "/home/user".to_string(),
+            tags: vec!["work".to_string(), "q3".to_string()],
+            pushed_at: Local::now() - chrono::Duration::days(2),
+            ..Default::default()
+        };
+        OperationLog::record(&conn, "push", &item)?;
+        OperationLog::record(&conn, "pop", &item)?;
+
+        let records = OperationLog::list_since(&conn, None)?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].operation, "push");
+        assert_eq!(records[1].operation, "pop");
+        assert_eq!(records[1].tags, vec!["work", "q3"]);
+        assert_eq!(records[1].item_id, 3);
+        assert_eq!(records[1].item_name, "notes.md");
+        assert_eq!(records[1].original_path, "/home/user");
+        assert_eq!(
+            format_timestamp(records[1].item_pushed_at),
+            format_timestamp(item.pushed_at)
+        );
+
+        let future = Local::now() + chrono::Duration::days(1);
+        assert!(OperationLog::list_since(&conn, Some(future))?.is_empty());
+
+        Ok(())
+    }
+}
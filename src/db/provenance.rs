@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// Where and by whom an item was pushed, kept with the item through merge and sync so that
+/// items from several machines can be told apart
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub hostname: Option<String>,
+    pub username: Option<String>,
+    /// Terminal the push was run from, if any (`None` for scripts, timers and the daemon)
+    pub tty: Option<String>,
+    pub cwd: Option<String>,
+    /// Version of fstk that pushed the item
+    pub version: String,
+}
+
+impl Provenance {
+    /// The provenance of a push happening now in `cwd`
+    pub fn current(cwd: Option<String>) -> Self {
+        Provenance {
+            hostname: hostname(),
+            username: ["USER", "LOGNAME", "USERNAME"]
+                .iter()
+                .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+                .or_else(account_name),
+            tty: tty(),
+            cwd,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// The JSON stored in the `provenance` column
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Read the `provenance` column; unreadable values (e.g. written by a newer fstk) are ignored
+    pub fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).to_string()).filter(|name| !name.is_empty())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    env::var("COMPUTERNAME").ok()
+}
+
+/// Name of the account the process runs as, for when the environment does not say
+#[cfg(unix)]
+fn account_name() -> Option<String> {
+    let entry = unsafe { libc::getpwuid(libc::geteuid()) };
+    if entry.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr((*entry).pw_name) };
+    Some(name.to_string_lossy().to_string())
+}
+
+#[cfg(not(unix))]
+fn account_name() -> Option<String> {
+    None
+}
+
+/// The terminal standard input is attached to
+#[cfg(unix)]
+fn tty() -> Option<String> {
+    let name = unsafe { libc::ttyname(libc::STDIN_FILENO) };
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(name) };
+    Some(name.to_string_lossy().to_string())
+}
+
+#[cfg(not(unix))]
+fn tty() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance_round_trip() {
+        let provenance = Provenance::current(Some("/home/me".to_string()));
+        assert_eq!(provenance.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            Provenance::from_json(&provenance.to_json()),
+            Some(provenance)
+        );
+
+        // Fields added later are optional
+        assert_eq!(
+            Provenance::from_json(r#"{"version":"0.1.0"}"#),
+            Some(Provenance {
+                version: "0.1.0".to_string(),
+                ..Default::default()
+            })
+        );
+        assert_eq!(Provenance::from_json("not json"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use rusqlite::Connection;
 
 pub const SCHEMA_SQL: &str = r#"
@@ -24,16 +24,235 @@ CREATE TABLE IF NOT EXISTS item_tags (
     FOREIGN KEY(tag_id) REFERENCES tags(id) ON DELETE CASCADE
 );
 
+CREATE TABLE IF NOT EXISTS item_manifest (
+    item_id INTEGER NOT NULL,
+    relative_path TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY(item_id, relative_path),
+    FOREIGN KEY(item_id) REFERENCES stack_items(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS bundle_members (
+    item_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    original_path TEXT NOT NULL,
+    PRIMARY KEY(item_id, name),
+    FOREIGN KEY(item_id) REFERENCES stack_items(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS journal (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    operation TEXT NOT NULL,
+    stored_hash TEXT NOT NULL,
+    content_hash TEXT,
+    source TEXT NOT NULL,
+    destination TEXT NOT NULL,
+    pid INTEGER NOT NULL,
+    started_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS operations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    operation TEXT NOT NULL,
+    item_id INTEGER NOT NULL,
+    item_name TEXT NOT NULL,
+    original_path TEXT NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    item_pushed_at DATETIME NOT NULL,
+    performed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS stack_state (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sync_state (
+    peer TEXT NOT NULL,
+    uuid TEXT NOT NULL,
+    PRIMARY KEY(peer, uuid)
+);
+
+CREATE TABLE IF NOT EXISTS tag_usage (
+    tag TEXT NOT NULL,
+    context TEXT NOT NULL,
+    uses INTEGER NOT NULL,
+    last_used DATETIME NOT NULL,
+    PRIMARY KEY(tag, context)
+);
+
+CREATE INDEX IF NOT EXISTS idx_operations_performed_at ON operations(performed_at);
 CREATE INDEX IF NOT EXISTS idx_stack_items_pushed_at ON stack_items(pushed_at);
 CREATE INDEX IF NOT EXISTS idx_stack_items_stored_hash ON stack_items(stored_hash);
 CREATE INDEX IF NOT EXISTS idx_tags_name ON tags(name);
 "#;
 
+/// Columns added to `stack_items` after the initial schema, as (name, definition) pairs.
+/// Existing databases are upgraded by adding any column that is missing.
+const STACK_ITEM_COLUMNS: &[(&str, &str)] = &[
+    ("pinned", "INTEGER NOT NULL DEFAULT 0"),
+    ("content_hash", "TEXT"),
+    ("size_bytes", "INTEGER"),
+    ("storage_location", "TEXT"),
+    ("partial", "INTEGER NOT NULL DEFAULT 0"),
+    ("owner_uid", "INTEGER"),
+    ("owner_gid", "INTEGER"),
+    ("version_group", "TEXT"),
+    ("locked", "INTEGER NOT NULL DEFAULT 0"),
+    ("push_seq", "INTEGER NOT NULL DEFAULT 0"),
+    ("uuid", "TEXT"),
+    ("note", "TEXT"),
+    ("push_dir", "TEXT"),
+    ("copied", "INTEGER NOT NULL DEFAULT 0"),
+    ("remote_origin", "TEXT"),
+    ("stack_order", "INTEGER NOT NULL DEFAULT 0"),
+    ("file_count", "INTEGER"),
+    ("push_millis", "INTEGER"),
+    ("packed", "INTEGER NOT NULL DEFAULT 0"),
+    ("provenance", "TEXT"),
+];
+
+/// SQL expression generating a random (version 4) UUID, e.g. `1b4e28ba-2fa1-41d2-883f-0016d3cca427`
+const UUID_SQL: &str = "lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4'
+    || substr(lower(hex(randomblob(2))), 2) || '-' || substr('89ab', abs(random()) % 4 + 1, 1)
+    || substr(lower(hex(randomblob(2))), 2) || '-' || lower(hex(randomblob(6)))";
+
+/// Statements filling a column for existing rows right after it was added, as (column, SQL) pairs
+const COLUMN_BACKFILLS: &[(&str, &str)] = &[
+    (
+        "version_group",
+        "UPDATE stack_items SET version_group = CASE WHEN original_path = '/'
+             THEN '/' || original_name ELSE original_path || '/' || original_name END",
+    ),
+    // IDs already follow push order
+    ("push_seq", "UPDATE stack_items SET push_seq = id"),
+    ("uuid", "UPDATE stack_items SET uuid = {uuid}"),
+    // Until items are reordered, the stack is in push order
+    (
+        "stack_order",
+        "UPDATE stack_items SET stack_order = (SELECT COUNT(*) FROM stack_items AS other
+             WHERE other.pushed_at < stack_items.pushed_at
+                OR (other.pushed_at = stack_items.pushed_at
+                    AND other.push_seq <= stack_items.push_seq))",
+    ),
+    // Directories count the files left in their manifest; older items without one stay unknown
+    (
+        "file_count",
+        "UPDATE stack_items SET file_count = CASE WHEN type = 'file' THEN 1
+             ELSE (SELECT NULLIF(COUNT(*), 0) FROM item_manifest
+                   WHERE item_manifest.item_id = stack_items.id) END",
+    ),
+];
+
 pub fn initialize_schema(conn: &Connection) -> Result<()> {
     conn.execute_batch(SCHEMA_SQL)?;
+    migrate(conn)?;
     Ok(())
 }
 
+/// Tables whose items another stack's database must have to be read (see `check_schema`)
+const ITEM_TABLES: &[&str] = &[
+    "stack_items",
+    "tags",
+    "item_tags",
+    "item_manifest",
+    "bundle_members",
+];
+
+/// Check that a database opened read-only, which cannot be migrated, has the current schema
+/// for its items
+pub fn check_schema(conn: &Connection) -> Result<()> {
+    let mut missing = Vec::new();
+    for table in ITEM_TABLES {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+            [table],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            missing.push(table.to_string());
+        }
+    }
+    if missing.is_empty() {
+        for (column, _) in STACK_ITEM_COLUMNS {
+            if !has_column(conn, "stack_items", column)? {
+                missing.push(format!("stack_items.{}", column));
+            }
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "The database lacks {} (written by an older fstk?)",
+            missing.join(", ")
+        ))
+    }
+}
+
+/// Bring an existing database up to date with the current schema.
+fn migrate(conn: &Connection) -> Result<()> {
+    for (column, definition) in STACK_ITEM_COLUMNS {
+        if !has_column(conn, "stack_items", column)? {
+            conn.execute_batch(&format!(
+                "ALTER TABLE stack_items ADD COLUMN {} {}",
+                column, definition
+            ))?;
+
+            for (_, backfill) in COLUMN_BACKFILLS.iter().filter(|(c, _)| c == column) {
+                conn.execute_batch(&backfill.replace("{uuid}", UUID_SQL))?;
+            }
+        }
+    }
+
+    // Every new item gets the next push sequence number, which orders items pushed in the same
+    // second (pushed_at has second precision), goes on top of the stack, and gets a UUID unless
+    // it brings one along (merge)
+    conn.execute_batch(&format!(
+        "CREATE INDEX IF NOT EXISTS idx_stack_items_content_hash ON stack_items(content_hash);
+         CREATE INDEX IF NOT EXISTS idx_stack_items_version_group ON stack_items(version_group);
+         CREATE INDEX IF NOT EXISTS idx_item_manifest_hash ON item_manifest(hash);
+         CREATE UNIQUE INDEX IF NOT EXISTS idx_stack_items_uuid ON stack_items(uuid);
+         CREATE TRIGGER IF NOT EXISTS stack_items_push_seq AFTER INSERT ON stack_items
+         WHEN NEW.push_seq = 0
+         BEGIN
+             UPDATE stack_items
+             SET push_seq = (SELECT MAX(push_seq) FROM stack_items) + 1
+             WHERE id = NEW.id;
+         END;
+         CREATE TRIGGER IF NOT EXISTS stack_items_stack_order AFTER INSERT ON stack_items
+         WHEN NEW.stack_order = 0
+         BEGIN
+             UPDATE stack_items
+             SET stack_order = (SELECT MAX(stack_order) FROM stack_items) + 1
+             WHERE id = NEW.id;
+         END;
+         CREATE TRIGGER IF NOT EXISTS stack_items_uuid AFTER INSERT ON stack_items
+         WHEN NEW.uuid IS NULL
+         BEGIN
+             UPDATE stack_items SET uuid = {} WHERE id = NEW.id;
+         END;",
+        UUID_SQL
+    ))?;
+
+    Ok(())
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -52,6 +271,13 @@ mod tests {
         assert!(tables.contains(&"stack_items".to_string()));
         assert!(tables.contains(&"tags".to_string()));
         assert!(tables.contains(&"item_tags".to_string()));
+        assert!(tables.contains(&"item_manifest".to_string()));
+        assert!(tables.contains(&"bundle_members".to_string()));
+        assert!(tables.contains(&"journal".to_string()));
+        assert!(tables.contains(&"operations".to_string()));
+        assert!(tables.contains(&"stack_state".to_string()));
+        assert!(tables.contains(&"sync_state".to_string()));
+        assert!(tables.contains(&"tag_usage".to_string()));
 
         // Verify indices exist
         let indices = get_indices(&conn)?;
@@ -68,6 +294,142 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_migrate_existing_database() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+
+        // Simulate a database created before any added columns existed
+        conn.execute_batch(
+            "CREATE TABLE stack_items (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                original_name TEXT NOT NULL,
+                original_path TEXT NOT NULL,
+                stored_hash TEXT NOT NULL UNIQUE,
+                type TEXT NOT NULL,
+                pushed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO stack_items (original_name, original_path, stored_hash, type)
+            VALUES ('old.txt', '/path/to', 'old_hash', 'file');",
+        )?;
+        // Such a database cannot be read without migrating it
+        assert!(check_schema(&conn).is_err());
+
+        initialize_schema(&conn)?;
+        check_schema(&conn)?;
+
+        for (column, _) in STACK_ITEM_COLUMNS {
+            assert!(has_column(&conn, "stack_items", column)?);
+        }
+
+        // Running the initialization again must be a no-op
+        initialize_schema(&conn)?;
+
+        let count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM stack_items", [], |row| row.get(0))?;
+        assert_eq!(count, 1);
+
+        // Existing items are grouped by their original full path
+        let group: String = conn.query_row("SELECT version_group FROM stack_items", [], |row| {
+            row.get(0)
+        })?;
+        assert_eq!(group, "/path/to/old.txt");
+
+        // Existing and new items get distinct version 4 UUIDs
+        conn.execute(
+            "INSERT INTO stack_items (original_name, original_path, stored_hash, type)
+             VALUES ('new.txt', '/path/to', 'new_hash', 'file')",
+            [],
+        )?;
+        let mut stmt = conn.prepare("SELECT uuid FROM stack_items ORDER BY id")?;
+        let uuids = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        assert_eq!(uuids.len(), 2);
+        assert_ne!(uuids[0], uuids[1]);
+        for uuid in &uuids {
+            let parts: Vec<&str> = uuid.split('-').collect();
+            assert_eq!(
+                parts.iter().map(|part| part.len()).collect::<Vec<_>>(),
+                vec![8, 4, 4, 4, 12]
+            );
+            assert!(parts[2].starts_with('4'));
+            assert!("89ab".contains(&parts[3][..1]));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_backfills_uuids() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+
+        // A database from just before items had UUIDs, with pinned and locked items
+        conn.execute_batch(
+            "CREATE TABLE stack_items (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                original_name TEXT NOT NULL,
+                original_path TEXT NOT NULL,
+                stored_hash TEXT NOT NULL UNIQUE,
+                type TEXT NOT NULL,
+                pushed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                pinned INTEGER NOT NULL DEFAULT 0,
+                content_hash TEXT,
+                size_bytes INTEGER,
+                storage_location TEXT,
+                partial INTEGER NOT NULL DEFAULT 0,
+                owner_uid INTEGER,
+                owner_gid INTEGER,
+                version_group TEXT,
+                locked INTEGER NOT NULL DEFAULT 0,
+                push_seq INTEGER NOT NULL DEFAULT 0
+            );",
+        )?;
+        for i in 0..20 {
+            conn.execute(
+                "INSERT INTO stack_items (original_name, original_path, stored_hash, type,
+                     pushed_at, pinned, locked, version_group, push_seq)
+                 VALUES ('same.txt', '/p', ?1, 'file', '2024-01-01 10:00:00', ?2, ?3,
+                     '/p/same.txt', ?4)",
+                rusqlite::params![format!("hash_{}", i), i % 2, i % 3 == 0, i + 1],
+            )?;
+        }
+
+        initialize_schema(&conn)?;
+
+        // Every existing item gets its own UUID, even those sharing path and push time
+        let uuids = |conn: &Connection| -> Result<Vec<String>> {
+            let mut stmt = conn.prepare("SELECT uuid FROM stack_items ORDER BY id")?;
+            let uuids = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(uuids)
+        };
+        let backfilled = uuids(&conn)?;
+        assert_eq!(backfilled.len(), 20);
+        let distinct: std::collections::HashSet<&String> = backfilled.iter().collect();
+        assert_eq!(distinct.len(), 20);
+
+        // Other columns are untouched, and UUIDs stay the same on later runs
+        let (pinned, locked): (i64, i64) = conn.query_row(
+            "SELECT SUM(pinned), SUM(locked) FROM stack_items",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!((pinned, locked), (10, 7));
+        initialize_schema(&conn)?;
+        assert_eq!(uuids(&conn)?, backfilled);
+
+        // The unique index refuses a second item with an existing UUID
+        let duplicate = conn.execute(
+            "INSERT INTO stack_items (original_name, original_path, stored_hash, type, uuid)
+             VALUES ('copy.txt', '/p', 'hash_copy', 'file', ?1)",
+            [&backfilled[0]],
+        );
+        assert!(duplicate.is_err());
+
+        Ok(())
+    }
+
     fn get_tables(conn: &Connection) -> Result<Vec<String>> {
         let mut stmt = conn.prepare(
             "SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%'",
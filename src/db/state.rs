@@ -0,0 +1,55 @@
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// Key of the last directory given to `pop --output`
+pub const LAST_POP_OUTPUT: &str = "last_pop_output";
+
+/// Small pieces of state remembered per stack between runs
+pub struct StateManager;
+
+impl StateManager {
+    /// Get a remembered value
+    pub fn get(conn: &Connection, key: &str) -> Result<Option<String>> {
+        Ok(conn
+            .query_row(
+                "SELECT value FROM stack_state WHERE key = ?",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Remember a value, replacing the previous one
+    pub fn set(conn: &Connection, key: &str, value: &str) -> Result<()> {
+        conn.execute(
+            "INSERT INTO stack_state (key, value) VALUES (?, ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+
+    #[test]
+    fn test_get_and_set() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+
+        assert_eq!(StateManager::get(&conn, LAST_POP_OUTPUT)?, None);
+
+        StateManager::set(&conn, LAST_POP_OUTPUT, "/srv/inbox")?;
+        StateManager::set(&conn, LAST_POP_OUTPUT, "/srv/outbox")?;
+        assert_eq!(
+            StateManager::get(&conn, LAST_POP_OUTPUT)?.as_deref(),
+            Some("/srv/outbox")
+        );
+
+        Ok(())
+    }
+}
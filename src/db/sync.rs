@@ -0,0 +1,63 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+
+/// Remembers which items (by UUID) both stacks had after the last sync with each peer,
+/// so that an item missing on one side can be told apart from one that is new on the other.
+pub struct SyncStateManager;
+
+impl SyncStateManager {
+    /// UUIDs of the items present on both sides after the last sync with `peer`
+    pub fn get_synced(conn: &Connection, peer: &str) -> Result<HashSet<String>> {
+        let mut stmt = conn.prepare("SELECT uuid FROM sync_state WHERE peer = ?")?;
+        let rows = stmt.query_map(params![peer], |row| row.get::<_, String>(0))?;
+
+        let mut synced = HashSet::new();
+        for uuid in rows {
+            synced.insert(uuid?);
+        }
+
+        Ok(synced)
+    }
+
+    /// Replace the items remembered for `peer`
+    pub fn set_synced(conn: &mut Connection, peer: &str, uuids: &HashSet<String>) -> Result<()> {
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM sync_state WHERE peer = ?", params![peer])?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO sync_state (peer, uuid) VALUES (?, ?)")?;
+            for uuid in uuids {
+                stmt.execute(params![peer, uuid])?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+
+    #[test]
+    fn test_synced_per_peer() -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+
+        let uuids: HashSet<String> = ["a".to_string(), "b".to_string()].into();
+        SyncStateManager::set_synced(&mut conn, "desktop:7878", &uuids)?;
+        SyncStateManager::set_synced(&mut conn, "laptop:7878", &["c".to_string()].into())?;
+        assert_eq!(SyncStateManager::get_synced(&conn, "desktop:7878")?, uuids);
+
+        SyncStateManager::set_synced(&mut conn, "desktop:7878", &["b".to_string()].into())?;
+        assert_eq!(
+            SyncStateManager::get_synced(&conn, "desktop:7878")?,
+            ["b".to_string()].into()
+        );
+        assert!(SyncStateManager::get_synced(&conn, "other:1")?.is_empty());
+
+        Ok(())
+    }
+}
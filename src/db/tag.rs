@@ -1,6 +1,22 @@
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use rusqlite::{params, Connection};
 
+use crate::db::item::parse_timestamp;
+
+/// A tag together with statistics about the items that carry it
+#[derive(Debug, Clone)]
+pub struct TagInfo {
+    pub id: i64,
+    pub name: String,
+    /// Number of items with this tag
+    pub count: i64,
+    /// Total stored size of those items in bytes (items without a recorded size count as 0)
+    pub total_size: u64,
+    /// When the most recent of those items was pushed
+    pub last_used: Option<DateTime<Local>>,
+}
+
 pub fn find_or_create_tag(conn: &Connection, tag_name: &str) -> Result<i64> {
     let mut stmt = conn.prepare("SELECT id FROM tags WHERE name = ?")?;
     let mut rows = stmt.query(params![tag_name])?;
@@ -95,6 +111,66 @@ impl TagManager {
         Ok(total_removed)
     }
 
+    /// Replace the tags of an item with `tags` in one transaction, adding only the missing ones
+    /// and removing only the ones not listed. Returns the number of tags added and removed.
+    pub fn set_for_item(
+        conn: &mut Connection,
+        item_id: i64,
+        tags: &[String],
+    ) -> Result<(usize, usize)> {
+        let tx = conn.transaction()?;
+        let changes = Self::replace_for_item(&tx, item_id, tags)?;
+        tx.commit()?;
+        Ok(changes)
+    }
+
+    /// `set_for_item` for callers that make it part of their own transaction
+    pub fn replace_for_item(
+        tx: &Connection,
+        item_id: i64,
+        tags: &[String],
+    ) -> Result<(usize, usize)> {
+        let mut wanted: Vec<&str> = Vec::new();
+        for tag in tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !wanted.contains(&tag) {
+                wanted.push(tag);
+            }
+        }
+        let current = Self::get_for_item(tx, item_id)?;
+
+        let mut total_added = 0;
+        for tag in &wanted {
+            if current.iter().any(|name| name == tag) {
+                continue;
+            }
+            let tag_id = find_or_create_tag(tx, tag)?;
+            total_added += tx.execute(
+                "INSERT OR IGNORE INTO item_tags (item_id, tag_id) VALUES (?, ?)",
+                params![item_id, tag_id],
+            )?;
+        }
+
+        let mut removed_tag_ids = Vec::new();
+        for tag in &current {
+            if wanted.contains(&tag.as_str()) {
+                continue;
+            }
+            let tag_id: i64 =
+                tx.query_row("SELECT id FROM tags WHERE name = ?", params![tag], |row| {
+                    row.get(0)
+                })?;
+            tx.execute(
+                "DELETE FROM item_tags WHERE item_id = ? AND tag_id = ?",
+                params![item_id, tag_id],
+            )?;
+            removed_tag_ids.push(tag_id);
+        }
+
+        Self::cleanup_orphaned_tags(tx, &removed_tag_ids)?;
+        Ok((total_added, removed_tag_ids.len()))
+    }
+
     /// Clean up orphaned tags - tags that no longer have any items associated with them
     pub fn cleanup_orphaned_tags(conn: &Connection, tag_ids: &[i64]) -> Result<usize> {
         let mut cleaned_up = 0;
@@ -141,26 +217,30 @@ impl TagManager {
         Ok(result)
     }
 
-    pub fn list_all(conn: &Connection) -> Result<Vec<(i64, String, i64)>> {
+    /// List all tags with usage statistics, sorted by name
+    pub fn list_all(conn: &Connection) -> Result<Vec<TagInfo>> {
         let mut stmt = conn.prepare(
-            "SELECT t.id, t.name, COUNT(it.item_id) as usage_count
+            "SELECT t.id, t.name, COUNT(it.item_id) as usage_count,
+                    COALESCE(SUM(si.size_bytes), 0), MAX(si.pushed_at)
              FROM tags t
              LEFT JOIN item_tags it ON t.id = it.tag_id
+             LEFT JOIN stack_items si ON si.id = it.item_id
              GROUP BY t.id
              ORDER BY t.name",
         )?;
 
-        let rows = stmt.query_map([], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, i64>(2)?,
-            ))
-        })?;
-
+        let mut rows = stmt.query([])?;
         let mut tags = Vec::new();
-        for tag_result in rows {
-            tags.push(tag_result?);
+
+        while let Some(row) = rows.next()? {
+            let last_used: Option<String> = row.get(4)?;
+            tags.push(TagInfo {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                count: row.get(2)?,
+                total_size: row.get(3)?,
+                last_used: last_used.as_deref().map(parse_timestamp).transpose()?,
+            });
         }
 
         Ok(tags)
@@ -317,8 +397,8 @@ mod tests {
         // Verify tag1 still exists but tag2 is gone
         let all_tags = TagManager::list_all(&conn)?;
         assert_eq!(all_tags.len(), 1);
-        assert_eq!(all_tags[0].0, tag_id1);
-        assert_eq!(all_tags[0].1, "tag1");
+        assert_eq!(all_tags[0].id, tag_id1);
+        assert_eq!(all_tags[0].name, "tag1");
 
         Ok(())
     }
@@ -342,8 +422,8 @@ mod tests {
         // Verify only the used tag remains
         let tags = TagManager::list_all(&conn)?;
         assert_eq!(tags.len(), 1);
-        assert_eq!(tags[0].0, tag_id);
-        assert_eq!(tags[0].1, "used");
+        assert_eq!(tags[0].id, tag_id);
+        assert_eq!(tags[0].name, "used");
 
         Ok(())
     }
@@ -396,21 +476,72 @@ mod tests {
         // Find common tag
         let common_tag = tags
             .iter()
-            .find(|t| t.1 == "common")
+            .find(|t| t.name == "common")
             .expect("Common tag should exist");
-        assert_eq!(common_tag.2, 2, "Common tag should be used by 2 items");
+        assert_eq!(common_tag.count, 2, "Common tag should be used by 2 items");
 
         // Find unique tags
         let tag1 = tags
             .iter()
-            .find(|t| t.1 == "tag1")
+            .find(|t| t.name == "tag1")
             .expect("tag1 should exist");
         let tag2 = tags
             .iter()
-            .find(|t| t.1 == "tag2")
+            .find(|t| t.name == "tag2")
             .expect("tag2 should exist");
-        assert_eq!(tag1.2, 1, "tag1 should be used by 1 item");
-        assert_eq!(tag2.2, 1, "tag2 should be used by 1 item");
+        assert_eq!(tag1.count, 1, "tag1 should be used by 1 item");
+        assert_eq!(tag2.count, 1, "tag2 should be used by 1 item");
+
+        // Sizes are summed per tag; the last use is the latest push
+        conn.execute(
+            "UPDATE stack_items SET size_bytes = 100 WHERE id = ?",
+            params![item1_id],
+        )?;
+        conn.execute(
+            "UPDATE stack_items SET size_bytes = 50 WHERE id = ?",
+            params![item2_id],
+        )?;
+        let tags = TagManager::list_all(&conn)?;
+        let common_tag = tags.iter().find(|t| t.name == "common").unwrap();
+        assert_eq!(common_tag.total_size, 150);
+        assert!(common_tag.last_used.is_some());
+        let tag2 = tags.iter().find(|t| t.name == "tag2").unwrap();
+        assert_eq!(tag2.total_size, 50);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_for_item() -> Result<()> {
+        let mut conn = setup_test_db()?;
+        let item_id =
+            setup_test_item_with_tags(&mut conn, &["keep".to_string(), "drop".to_string()])?;
+
+        let (added, removed) = TagManager::set_for_item(
+            &mut conn,
+            item_id,
+            &["keep".to_string(), "new".to_string(), " new ".to_string()],
+        )?;
+        assert_eq!((added, removed), (1, 1));
+        assert_eq!(
+            TagManager::get_for_item(&conn, item_id)?,
+            vec!["keep", "new"]
+        );
+
+        // The dropped tag is no longer used by any item
+        let count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM tags WHERE name = 'drop'", [], |row| {
+                row.get(0)
+            })?;
+        assert_eq!(count, 0);
+
+        // Setting the same tags again changes nothing; an empty set clears them
+        assert_eq!(
+            TagManager::set_for_item(&mut conn, item_id, &["new".to_string(), "keep".to_string()])?,
+            (0, 0)
+        );
+        assert_eq!(TagManager::set_for_item(&mut conn, item_id, &[])?, (0, 2));
+        assert!(TagManager::get_for_item(&conn, item_id)?.is_empty());
 
         Ok(())
     }
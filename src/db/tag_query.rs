@@ -0,0 +1,347 @@
+use anyhow::{anyhow, Result};
+use rusqlite::types::Value;
+
+/// A boolean expression over tag names, as given to `-t` when selecting items:
+/// `work AND NOT done`, `(inbox OR todo) AND 2024`. Keywords are case-insensitive, `NOT` binds
+/// tighter than `AND`, which binds tighter than `OR`. Words next to each other form one tag
+/// name (`-t "tax return"`); a tag named like a keyword or containing parentheses is quoted
+/// (`'and'`), with a quote inside doubled (`'bob''s'`). A value that is not a valid expression,
+/// such as `bob's`, is taken as a tag name. Several `-t` values (`-t a,b`) must all match,
+/// as before.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagQuery {
+    Tag(String),
+    Not(Box<TagQuery>),
+    And(Vec<TagQuery>),
+    Or(Vec<TagQuery>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    /// A bare word, as its byte range in the input so that adjacent words can be joined
+    Word(usize, usize),
+    Quoted(String),
+}
+
+impl TagQuery {
+    /// Parse a single expression
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            input,
+            tokens: &tokens,
+            pos: 0,
+        };
+        let query = parser.or()?;
+        match tokens.get(parser.pos) {
+            None => Ok(query),
+            Some(Token::Close) => Err(anyhow!("Unmatched ')' in tag query '{}'", input)),
+            Some(_) => Err(anyhow!(
+                "Expected AND or OR between the tags of '{}'",
+                input
+            )),
+        }
+    }
+
+    /// The query matching items that satisfy every one of `filters` (`None` if there are none).
+    /// A filter that does not parse is a tag name, since tags may contain quotes and parentheses.
+    pub fn all(filters: &[String]) -> Result<Option<Self>> {
+        let mut queries: Vec<Self> = filters
+            .iter()
+            .filter(|filter| !filter.trim().is_empty())
+            .map(|filter| {
+                Self::parse(filter).unwrap_or_else(|_| TagQuery::Tag(filter.trim().to_string()))
+            })
+            .collect();
+
+        Ok(match queries.len() {
+            0 => None,
+            1 => queries.pop(),
+            _ => Some(TagQuery::And(queries)),
+        })
+    }
+
+    /// A single filter matching items with any of `tags` (for `--any-tags`)
+    pub fn any_of(tags: &[String]) -> Vec<String> {
+        let names: Vec<String> = tags
+            .iter()
+            .filter(|tag| !tag.trim().is_empty())
+            .map(|tag| quote(tag.trim()))
+            .collect();
+        if names.is_empty() {
+            Vec::new()
+        } else {
+            vec![names.join(" OR ")]
+        }
+    }
+
+    /// Whether an item with `tags` matches
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            TagQuery::Tag(name) => tags.contains(name),
+            TagQuery::Not(query) => !query.matches(tags),
+            TagQuery::And(queries) => queries.iter().all(|query| query.matches(tags)),
+            TagQuery::Or(queries) => queries.iter().any(|query| query.matches(tags)),
+        }
+    }
+
+    /// SQL condition on `stack_items.id` selecting the matching items, adding its parameters
+    pub fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        match self {
+            TagQuery::Tag(name) => {
+                params.push(Value::Text(name.clone()));
+                "id IN (SELECT it.item_id FROM item_tags it JOIN tags t ON it.tag_id = t.id \
+                 WHERE t.name = ?)"
+                    .to_string()
+            }
+            TagQuery::Not(query) => format!("NOT ({})", query.to_sql(params)),
+            TagQuery::And(queries) => join_sql(queries, " AND ", params),
+            TagQuery::Or(queries) => join_sql(queries, " OR ", params),
+        }
+    }
+}
+
+fn join_sql(queries: &[TagQuery], operator: &str, params: &mut Vec<Value>) -> String {
+    let parts: Vec<String> = queries
+        .iter()
+        .map(|query| format!("({})", query.to_sql(params)))
+        .collect();
+    parts.join(operator)
+}
+
+/// Quote a tag name where it would otherwise be read as part of an expression
+fn quote(tag: &str) -> String {
+    let plain = !tag.contains(['(', ')', '"', '\''])
+        && !tag.split_whitespace().any(|word| keyword(word).is_some());
+    if plain {
+        tag.to_string()
+    } else if tag.contains('"') {
+        format!("'{}'", tag.replace('\'', "''"))
+    } else {
+        format!("\"{}\"", tag)
+    }
+}
+
+fn keyword(word: &str) -> Option<Token> {
+    match word.to_ascii_uppercase().as_str() {
+        "AND" => Some(Token::And),
+        "OR" => Some(Token::Or),
+        "NOT" => Some(Token::Not),
+        _ => None,
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut name = String::new();
+                let mut closed = false;
+                while let Some((_, ch)) = chars.next() {
+                    if ch != c {
+                        name.push(ch);
+                    } else if chars.next_if(|&(_, next)| next == c).is_some() {
+                        // A doubled quote stands for itself
+                        name.push(c);
+                    } else {
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed {
+                    return Err(anyhow!("Unterminated quote in tag query '{}'", input));
+                }
+                tokens.push(Token::Quoted(name));
+            }
+            _ => {
+                let mut end = start;
+                while let Some(&(index, ch)) = chars.peek() {
+                    if ch.is_whitespace() || matches!(ch, '(' | ')' | '"' | '\'') {
+                        break;
+                    }
+                    end = index + ch.len_utf8();
+                    chars.next();
+                }
+                tokens.push(keyword(&input[start..end]).unwrap_or(Token::Word(start, end)));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens: `or := and (OR and)*`, `and := unary (AND unary)*`,
+/// `unary := NOT unary | '(' or ')' | name`
+struct Parser<'a> {
+    input: &'a str,
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<TagQuery> {
+        let mut operands = vec![self.and()?];
+        while self.eat(&Token::Or) {
+            operands.push(self.and()?);
+        }
+        Ok(combine(operands, TagQuery::Or))
+    }
+
+    fn and(&mut self) -> Result<TagQuery> {
+        let mut operands = vec![self.unary()?];
+        while self.eat(&Token::And) {
+            operands.push(self.unary()?);
+        }
+        Ok(combine(operands, TagQuery::And))
+    }
+
+    fn unary(&mut self) -> Result<TagQuery> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Not) => Ok(TagQuery::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let query = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err(anyhow!("Missing ')' in tag query '{}'", self.input));
+                }
+                Ok(query)
+            }
+            Some(Token::Quoted(name)) => Ok(TagQuery::Tag(name)),
+            Some(Token::Word(start, mut end)) => {
+                // Adjacent words are one tag name
+                while let Some(&Token::Word(_, next_end)) = self.tokens.get(self.pos) {
+                    end = next_end;
+                    self.pos += 1;
+                }
+                Ok(TagQuery::Tag(self.input[start..end].to_string()))
+            }
+            _ => Err(anyhow!("Expected a tag in tag query '{}'", self.input)),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn combine(mut operands: Vec<TagQuery>, group: fn(Vec<TagQuery>) -> TagQuery) -> TagQuery {
+    if operands.len() == 1 {
+        operands.pop().unwrap()
+    } else {
+        group(operands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str) -> TagQuery {
+        TagQuery::Tag(name.to_string())
+    }
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(TagQuery::parse("work").unwrap(), tag("work"));
+        assert_eq!(
+            TagQuery::parse("work AND NOT done").unwrap(),
+            TagQuery::And(vec![tag("work"), TagQuery::Not(Box::new(tag("done")))])
+        );
+        // AND binds tighter than OR
+        assert_eq!(
+            TagQuery::parse("a or b and c").unwrap(),
+            TagQuery::Or(vec![tag("a"), TagQuery::And(vec![tag("b"), tag("c")])])
+        );
+        assert_eq!(
+            TagQuery::parse("(a OR b) AND c").unwrap(),
+            TagQuery::And(vec![TagQuery::Or(vec![tag("a"), tag("b")]), tag("c")])
+        );
+        assert_eq!(
+            TagQuery::parse("tax  return OR 'or'").unwrap(),
+            TagQuery::Or(vec![tag("tax  return"), tag("or")])
+        );
+
+        for invalid in ["", "a AND", "(a OR b", "a)", "NOT", "\"a", "\"a\" b"] {
+            assert!(TagQuery::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let query = TagQuery::all(&tags(&["work AND NOT done", "2024 OR 2025"]))
+            .unwrap()
+            .unwrap();
+        assert!(query.matches(&tags(&["work", "2024"])));
+        assert!(!query.matches(&tags(&["work", "done", "2024"])));
+        assert!(!query.matches(&tags(&["work"])));
+        assert_eq!(TagQuery::all(&tags(&["", " "])).unwrap(), None);
+
+        let any = TagQuery::all(&TagQuery::any_of(&tags(&["a", "not", "x(y)"])))
+            .unwrap()
+            .unwrap();
+        assert_eq!(any, TagQuery::Or(vec![tag("a"), tag("not"), tag("x(y)")]));
+        assert!(any.matches(&tags(&["not"])));
+        assert!(!any.matches(&tags(&["b"])));
+    }
+
+    #[test]
+    fn test_tags_with_quotes_and_parentheses() {
+        // Values that are not expressions are tag names
+        for name in ["bob's", "(draft", "say \"hi", "a)"] {
+            assert_eq!(
+                TagQuery::all(&tags(&[name])).unwrap(),
+                Some(tag(name)),
+                "{}",
+                name
+            );
+        }
+        assert!(TagQuery::parse("bob's").is_err());
+
+        for name in [
+            "bob's",
+            "say \"hi\"",
+            "bob's \"hi\"",
+            "it''s",
+            "and",
+            "x(y)",
+        ] {
+            assert_eq!(
+                TagQuery::parse(&quote(name)).unwrap(),
+                tag(name),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(quote("bob's \"hi\""), "'bob''s \"hi\"'");
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use rusqlite::{params_from_iter, Connection};
+use std::path::Path;
+
+use crate::db::item::parse_timestamp;
+
+/// How often and how recently a tag was given to pushes in one context
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagUsage {
+    pub tag: String,
+    /// `ext:<extension>` (`ext:/` for directories), `dir:<directory>`, or empty for any push
+    pub context: String,
+    pub uses: u64,
+    pub last_used: DateTime<Local>,
+}
+
+impl TagUsage {
+    /// Frecency score: uses weighted by how recent the last one was and by how closely the
+    /// context matches the item (the same extension says more than the same directory)
+    fn score(&self, now: DateTime<Local>) -> f64 {
+        let recency = match (now - self.last_used).num_hours() {
+            hours if hours < 1 => 4.0,
+            hours if hours < 24 => 2.0,
+            hours if hours < 24 * 7 => 1.0,
+            _ => 0.5,
+        };
+        let context = if self.context.starts_with("ext:") {
+            4.0
+        } else if self.context.starts_with("dir:") {
+            2.0
+        } else {
+            1.0
+        };
+
+        self.uses as f64 * recency * context
+    }
+}
+
+pub struct TagUsageManager;
+
+impl TagUsageManager {
+    /// The contexts a push of `name` from the directory `parent` counts in
+    pub fn contexts(parent: &str, name: &str, is_dir: bool) -> Vec<String> {
+        let mut contexts = vec![String::new(), format!("dir:{}", parent)];
+        if is_dir {
+            contexts.push("ext:/".to_string());
+        } else if let Some(extension) = Path::new(name).extension() {
+            contexts.push(format!(
+                "ext:{}",
+                extension.to_string_lossy().to_lowercase()
+            ));
+        }
+        contexts
+    }
+
+    /// Count one use of each tag in each of the contexts
+    pub fn record(conn: &Connection, tags: &[String], contexts: &[String]) -> Result<()> {
+        let mut stmt = conn.prepare(
+            "INSERT INTO tag_usage (tag, context, uses, last_used)
+             VALUES (?1, ?2, 1, CURRENT_TIMESTAMP)
+             ON CONFLICT(tag, context) DO UPDATE
+             SET uses = uses + 1, last_used = CURRENT_TIMESTAMP",
+        )?;
+
+        for tag in tags {
+            for context in contexts {
+                stmt.execute([tag, context])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Usage of every tag in any of the contexts
+    pub fn for_contexts(conn: &Connection, contexts: &[String]) -> Result<Vec<TagUsage>> {
+        if contexts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; contexts.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT tag, context, uses, last_used FROM tag_usage WHERE context IN ({})",
+            placeholders
+        ))?;
+
+        let mut rows = stmt.query(params_from_iter(contexts))?;
+        let mut usages = Vec::new();
+        while let Some(row) = rows.next()? {
+            let last_used: String = row.get(3)?;
+            usages.push(TagUsage {
+                tag: row.get(0)?,
+                context: row.get(1)?,
+                uses: row.get(2)?,
+                last_used: parse_timestamp(&last_used)?,
+            });
+        }
+
+        Ok(usages)
+    }
+
+    /// The tags most likely to fit a push in the given contexts, best first, leaving out the
+    /// ones it already has
+    pub fn suggest(
+        conn: &Connection,
+        contexts: &[String],
+        exclude: &[String],
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let usages = Self::for_contexts(conn, contexts)?;
+        Ok(rank(&usages, Local::now(), exclude, limit))
+    }
+}
+
+/// Add up the scores of each tag over its contexts and return the `limit` best tags
+fn rank(
+    usages: &[TagUsage],
+    now: DateTime<Local>,
+    exclude: &[String],
+    limit: usize,
+) -> Vec<String> {
+    let mut scores: Vec<(&str, f64)> = Vec::new();
+    for usage in usages {
+        if exclude.contains(&usage.tag) {
+            continue;
+        }
+        match scores.iter_mut().find(|(tag, _)| *tag == usage.tag) {
+            Some((_, score)) => *score += usage.score(now),
+            None => scores.push((&usage.tag, usage.score(now))),
+        }
+    }
+
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    scores
+        .into_iter()
+        .take(limit)
+        .map(|(tag, _)| tag.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::schema;
+    use chrono::Duration;
+
+    fn usage(tag: &str, context: &str, uses: u64, age: Duration) -> TagUsage {
+        TagUsage {
+            tag: tag.to_string(),
+            context: context.to_string(),
+            uses,
+            last_used: Local::now() - age,
+        }
+    }
+
+    #[test]
+    fn test_rank() {
+        let usages = vec![
+            // Used a lot, but long ago and only in general
+            usage("old", "", 20, Duration::days(60)),
+            // Matches the extension and was used today
+            usage("invoices", "ext:pdf", 3, Duration::hours(3)),
+            usage("invoices", "", 3, Duration::hours(3)),
+            usage(
+                "downloads",
+                "dir:/home/me/Downloads",
+                2,
+                Duration::minutes(5),
+            ),
+            usage("given", "ext:pdf", 50, Duration::minutes(1)),
+        ];
+
+        let ranked = rank(&usages, Local::now(), &["given".to_string()], 3);
+        assert_eq!(ranked, vec!["invoices", "downloads", "old"]);
+        assert_eq!(rank(&usages, Local::now(), &[], 1), vec!["given"]);
+    }
+
+    #[test]
+    fn test_record_and_suggest() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        schema::initialize_schema(&conn)?;
+
+        let contexts = TagUsageManager::contexts("/home/me/Downloads", "Bill.PDF", false);
+        assert_eq!(contexts, vec!["", "dir:/home/me/Downloads", "ext:pdf"]);
+
+        TagUsageManager::record(&conn, &["invoices".to_string()], &contexts)?;
+        TagUsageManager::record(&conn, &["invoices".to_string()], &contexts)?;
+        let other = TagUsageManager::contexts("/srv", "build", true);
+        TagUsageManager::record(&conn, &["ci".to_string()], &other)?;
+
+        let usages = TagUsageManager::for_contexts(&conn, &contexts)?;
+        assert_eq!(usages.len(), 4);
+        assert!(usages.iter().all(|usage| match usage.tag.as_str() {
+            "invoices" => usage.uses == 2,
+            _ => usage.context.is_empty() && usage.uses == 1,
+        }));
+        // Every push counts in the general context
+        assert_eq!(
+            TagUsageManager::suggest(&conn, &contexts, &[], 5)?,
+            vec!["invoices", "ci"]
+        );
+        assert_eq!(
+            TagUsageManager::suggest(&conn, &other, &["ci".to_string()], 5)?,
+            vec!["invoices"]
+        );
+
+        Ok(())
+    }
+}
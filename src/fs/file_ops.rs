@@ -1,22 +1,40 @@
 use anyhow::{anyhow, Result};
+use std::collections::HashMap;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+use unicode_normalization::UnicodeNormalization;
 use walkdir::WalkDir;
 
+use crate::cli::{MergePolicy, SymlinkFallback};
+use crate::utils::error::FstkError;
+
 /// Move or copy a file or directory from source to destination.
 /// If the move operation fails with EXDEV (cross-device) error, it will fallback to copy+delete.
 pub fn move_or_copy<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
     let src = src.as_ref();
     let dst = dst.as_ref();
 
+    // An interrupted copy at the destination is picked up where it stopped
+    if can_resume(src, dst) {
+        copy_dir_across(src, dst)?;
+        fs::remove_dir_all(src)?;
+        return Ok(());
+    }
+
     match fs::rename(src, dst) {
         Ok(_) => Ok(()),
         Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
-            if src.is_dir() {
-                copy_dir_recursive(src, dst)?;
+            if src.is_symlink() {
+                copy_symlink(src, dst)?;
+                fs::remove_file(src)?;
+            } else if src.is_dir() {
+                copy_dir_across(src, dst)?;
                 fs::remove_dir_all(src)?;
             } else {
-                fs::copy(src, dst)?;
+                copy_file(src, dst)?;
                 fs::remove_file(src)?;
             }
             Ok(())
@@ -30,6 +48,617 @@ pub fn move_or_copy<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()
     }
 }
 
+/// Copy a file or directory from source to destination, leaving the source untouched.
+pub fn copy_item<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let src = src.as_ref();
+    let dst = dst.as_ref();
+
+    if src.is_symlink() {
+        copy_symlink(src, dst)
+    } else if src.is_dir() {
+        copy_dir_recursive(src, dst)
+    } else {
+        copy_file(src, dst).map_err(|e| {
+            anyhow!(
+                "Failed to copy '{}' to '{}': {}",
+                src.display(),
+                dst.display(),
+                e
+            )
+        })?;
+        Ok(())
+    }
+}
+
+/// Whether copies leave out symlinks they cannot recreate (`--symlink-fallback skip`)
+static SKIP_UNCREATABLE_SYMLINKS: AtomicBool = AtomicBool::new(false);
+
+/// Choose what copies do with symlinks they cannot recreate, for the rest of this run.
+pub fn set_symlink_fallback(fallback: SymlinkFallback) {
+    SKIP_UNCREATABLE_SYMLINKS.store(fallback == SymlinkFallback::Skip, Ordering::SeqCst);
+}
+
+/// Recreate the symlink (or Windows junction) `src` at `dst`, pointing at the same target.
+/// Where that is not possible, e.g. on Windows without Developer Mode or administrator rights,
+/// what the link points to is copied instead, or the link is left out with
+/// `--symlink-fallback skip`.
+pub fn copy_symlink(src: &Path, dst: &Path) -> Result<()> {
+    let target = fs::read_link(src)?;
+    let is_dir = src.is_dir();
+
+    let error = match create_symlink(&target, dst, is_dir) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    if SKIP_UNCREATABLE_SYMLINKS.load(Ordering::SeqCst) {
+        crate::status!(
+            "Skipped symlink {} ({}): {}",
+            src.display(),
+            target.display(),
+            error
+        );
+        return Ok(());
+    }
+
+    if is_dir {
+        copy_dir_recursive(src, dst)
+    } else if src.exists() {
+        Ok(copy_file(src, dst)?)
+    } else {
+        Err(anyhow!(
+            "Cannot recreate symlink {} to the missing {}: {}",
+            src.display(),
+            target.display(),
+            error
+        ))
+    }
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path, _is_dir: bool) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Junctions are recreated as directory symlinks, which Windows resolves the same way.
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_target: &Path, _link: &Path, _is_dir: bool) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Copy a single file. Where the filesystem supports it (Btrfs, XFS, APFS, ...) the copy is a
+/// clone sharing the source's blocks, which is instant and takes no extra space until either
+/// side changes; otherwise the data is copied.
+pub fn copy_file(src: &Path, dst: &Path) -> io::Result<()> {
+    if reflink(src, dst).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(src, dst)?;
+    Ok(())
+}
+
+/// Size of the buffer used where data has to pass through user space anyway: moves hashed on
+/// the way (`move_file_hashed`) and tar streams. Plain copies are left to `fs::copy`.
+const COPY_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Copy the rest of `source` to `target` through a large buffer, passing every chunk to
+/// `inspect` (e.g. a hasher)
+fn copy_buffered<F: FnMut(&[u8])>(
+    source: &mut fs::File,
+    target: &mut fs::File,
+    mut inspect: F,
+) -> io::Result<u64> {
+    use std::io::{Read, Write};
+
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    let mut copied = 0;
+    loop {
+        let n = match source.read(&mut buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        target.write_all(&buffer[..n])?;
+        inspect(&buffer[..n]);
+        copied += n as u64;
+    }
+}
+
+/// Clone `src` to the new file `dst` with the FICLONE ioctl.
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = fs::File::open(src)?;
+    let permissions = source.metadata()?.permissions();
+    let target = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE as _, source.as_raw_fd()) } != 0 {
+        let error = io::Error::last_os_error();
+        drop(target);
+        let _ = fs::remove_file(dst);
+        return Err(error);
+    }
+
+    target.set_permissions(permissions)
+}
+
+/// Clone `src` to the new file `dst` with clonefile(2), which also keeps its metadata.
+#[cfg(target_os = "macos")]
+fn reflink(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let src = CString::new(src.as_os_str().as_bytes())?;
+    let dst = CString::new(dst.as_os_str().as_bytes())?;
+    if unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Cloning files is not supported on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Move a file like `move_or_copy` and return the SHA-256 of its contents (see `hash_file`).
+/// When the move has to copy the data to another filesystem, the data is hashed as it is
+/// copied, so the file is read only once either way. `copied` receives the hash after the copy,
+/// before the source is removed.
+pub fn move_file_hashed<F>(src: &Path, dst: &Path, copied: F) -> Result<String>
+where
+    F: FnOnce(&str) -> Result<()>,
+{
+    use sha2::{Digest, Sha256};
+
+    match fs::rename(src, dst) {
+        Ok(_) => hash_file(dst),
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            let mut source = fs::File::open(src)?;
+            let permissions = source.metadata()?.permissions();
+            let mut target = fs::File::create(dst)?;
+
+            let mut hasher = Sha256::new();
+            copy_buffered(&mut source, &mut target, |chunk| hasher.update(chunk))?;
+            target.set_permissions(permissions)?;
+            let hash = hex::encode(hasher.finalize());
+
+            copied(&hash)?;
+            fs::remove_file(src)?;
+            Ok(hash)
+        }
+        Err(e) => Err(anyhow!(
+            "Failed to move '{}' to '{}': {}",
+            src.display(),
+            dst.display(),
+            e
+        )),
+    }
+}
+
+/// Remove a file or a directory with all its contents.
+pub fn remove_item(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Get the owner (uid, gid) of a path without following symlinks.
+#[cfg(unix)]
+pub fn get_owner(path: &Path) -> Result<Option<(u32, u32)>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path)?;
+    Ok(Some((metadata.uid(), metadata.gid())))
+}
+
+/// Get the owner (uid, gid) of a path; not available on this platform.
+#[cfg(not(unix))]
+pub fn get_owner(_path: &Path) -> Result<Option<(u32, u32)>> {
+    Ok(None)
+}
+
+/// Change the owner of a path and everything below it.
+/// Returns `false` without changing anything when the process lacks the privileges.
+#[cfg(unix)]
+pub fn set_owner(path: &Path, uid: u32, gid: u32) -> Result<bool> {
+    if unsafe { libc::geteuid() } != 0 {
+        return Ok(false);
+    }
+
+    for entry in WalkDir::new(path) {
+        std::os::unix::fs::lchown(entry?.path(), Some(uid), Some(gid))?;
+    }
+
+    Ok(true)
+}
+
+/// Change the owner of a path; not supported on this platform.
+#[cfg(not(unix))]
+pub fn set_owner(_path: &Path, _uid: u32, _gid: u32) -> Result<bool> {
+    Ok(false)
+}
+
+/// Whether writing `path` needs privileges the process lacks, because the nearest existing
+/// directory above it is not writable.
+#[cfg(unix)]
+pub fn needs_privileges(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Some(dir) = path
+        .parent()
+        .and_then(|parent| parent.ancestors().find(|dir| dir.exists()))
+    else {
+        return false;
+    };
+    let Ok(dir) = CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(dir.as_ptr(), libc::W_OK) != 0 }
+}
+
+/// Whether writing `path` needs elevated privileges; never detected on this platform.
+#[cfg(not(unix))]
+pub fn needs_privileges(_path: &Path) -> bool {
+    false
+}
+
+/// Suffix of content that has been moved into storage but not yet committed
+pub const STAGING_SUFFIX: &str = ".staging";
+
+/// Get the staging path used while `target` is being stored.
+pub fn staging_path(target: &Path) -> PathBuf {
+    let mut staged = target.as_os_str().to_os_string();
+    staged.push(STAGING_SUFFIX);
+    PathBuf::from(staged)
+}
+
+/// Suffix of a destination an overwriting pop moved aside until the pop is committed
+pub const REPLACED_SUFFIX: &str = ".fstk-replaced";
+
+/// A destination that was moved out of the way by `move_aside`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovedAside {
+    /// Where the entry was
+    pub path: PathBuf,
+    /// Where it is now
+    pub aside: PathBuf,
+}
+
+/// Move the entry at `path` (or the one `existing_entry` finds for it) aside to an unused
+/// `<name>.fstk-replaced`, so that it can be put back if what was to replace it never arrives.
+pub fn move_aside(path: &Path) -> Result<MovedAside> {
+    let path = existing_entry(path)
+        .ok_or_else(|| anyhow!("Nothing to move aside at {}", path.display()))?;
+    let mut aside = path.as_os_str().to_os_string();
+    aside.push(REPLACED_SUFFIX);
+    let mut aside = PathBuf::from(aside);
+    if check_destination_conflict(&aside) {
+        aside = free_path(&aside);
+    }
+    fs::rename(&path, &aside)?;
+    Ok(MovedAside { path, aside })
+}
+
+/// Write the directory `src` into a tar archive at `dest`, keeping symlinks as links
+pub fn pack_dir(src: &Path, dest: &Path) -> Result<()> {
+    let mut builder = tar::Builder::new(fs::File::create(dest)?);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", src)?;
+    builder.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// Unpack an archive written by `pack_dir` into the directory `dest`, which is created
+pub fn unpack_dir(archive: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    let mut archive = tar::Archive::new(fs::File::open(archive)?);
+    archive.set_preserve_permissions(true);
+    archive.unpack(dest).map_err(|e| {
+        // Leave nothing half unpacked behind
+        let _ = fs::remove_dir_all(dest);
+        anyhow!(
+            "Failed to unpack the archive into '{}': {}",
+            dest.display(),
+            e
+        )
+    })?;
+    inherit_setgid(dest)
+}
+
+/// Undo a staging step: move staged content back to its original location,
+/// or simply drop it when it was a link to or a copy of content that never moved.
+pub fn unstage(staged: &Path, original: &Path, linked: bool) -> Result<()> {
+    if linked {
+        remove_item(staged)
+    } else {
+        move_or_copy(staged, original)
+    }
+}
+
+/// Replace `target` with a clone of `existing`, a file with the same content, so the two share
+/// their blocks where the filesystem supports it (Btrfs, XFS, APFS, ...). Unlike a hard link,
+/// the clone is a file of its own: it keeps the permissions, modification time and owner of
+/// `target`, and writing to either file later leaves the other alone. The clone is created
+/// next to `target` and renamed over it, so `target` never goes missing. Returns `false` and
+/// leaves `target` alone if the two differ in size or are already the same file, or if the
+/// filesystem cannot clone.
+pub fn clone_identical(existing: &Path, target: &Path) -> Result<bool> {
+    let existing_meta = fs::metadata(existing)?;
+    let target_meta = fs::symlink_metadata(target)?;
+    if !target_meta.is_file()
+        || existing_meta.len() != target_meta.len()
+        || same_file(&existing_meta, &target_meta)
+    {
+        return Ok(false);
+    }
+
+    let mut temp = target.as_os_str().to_os_string();
+    temp.push(".clone");
+    let temp = PathBuf::from(temp);
+    if !clone_like(existing, target, &temp)? {
+        return Ok(false);
+    }
+    if let Err(e) = fs::rename(&temp, target) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(true)
+}
+
+/// Create `dst` as a clone of `existing` that carries the permissions, modification time and
+/// owner of `like`, a file with the same content, as if `like` had been moved there. Returns
+/// `false` without creating `dst` if the filesystem cannot clone, or if the process may not
+/// give the clone the owner of `like`.
+pub fn clone_like(existing: &Path, like: &Path, dst: &Path) -> Result<bool> {
+    let like_meta = fs::symlink_metadata(like)?;
+    if reflink(existing, dst).is_err() {
+        return Ok(false);
+    }
+
+    let adopt = || -> io::Result<()> {
+        let file = fs::OpenOptions::new().write(true).open(dst)?;
+        file.set_permissions(like_meta.permissions())?;
+        file.set_modified(like_meta.modified()?)?;
+        copy_owner(&like_meta, dst)
+    };
+    match adopt() {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            let _ = fs::remove_file(dst);
+            if e.kind() == io::ErrorKind::PermissionDenied {
+                Ok(false)
+            } else {
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// Give `path` the owner recorded in `metadata`, if it differs
+#[cfg(unix)]
+fn copy_owner(metadata: &fs::Metadata, path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let current = fs::symlink_metadata(path)?;
+    if (current.uid(), current.gid()) == (metadata.uid(), metadata.gid()) {
+        return Ok(());
+    }
+    std::os::unix::fs::lchown(path, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_metadata: &fs::Metadata, _path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &fs::Metadata, _b: &fs::Metadata) -> bool {
+    false
+}
+
+/// Suffix of the file listing what an unfinished cross-device directory copy has copied so far
+pub const PROGRESS_SUFFIX: &str = ".progress";
+
+/// Suffix of an interrupted push's partial copy, kept in storage until the push is run again
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Path of the progress marker of a directory copy to `dst`
+pub fn progress_path(dst: &Path) -> PathBuf {
+    let mut marker = dst.as_os_str().to_os_string();
+    marker.push(PROGRESS_SUFFIX);
+    PathBuf::from(marker)
+}
+
+/// Where an interrupted push of `source` keeps its partial copy inside the storage directory
+/// `dir`, under a name that the next push of the same path finds again
+pub fn partial_path(dir: &Path, source: &Path) -> PathBuf {
+    use sha2::{Digest, Sha256};
+
+    let hash = hex::encode(Sha256::digest(source.to_string_lossy().as_bytes()));
+    dir.join(format!("{}{}", &hash[..16], PARTIAL_SUFFIX))
+}
+
+/// Whether `dst` holds an interrupted copy of the directory `src`, which copying `src` to
+/// `dst` again resumes
+pub fn can_resume(src: &Path, dst: &Path) -> bool {
+    read_progress(src, dst).is_some()
+}
+
+/// Move an interrupted copy and its progress marker from `from` to `to`
+pub fn move_partial(from: &Path, to: &Path) -> Result<()> {
+    fs::rename(from, to)?;
+    fs::rename(progress_path(from), progress_path(to))?;
+    Ok(())
+}
+
+/// Size and modification time (in whole seconds, as tar keeps it) of a copied file
+type CopiedFile = (u64, u64);
+
+fn copied_file(meta: &fs::Metadata) -> Option<CopiedFile> {
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((meta.len(), modified.as_secs()))
+}
+
+/// The files an interrupted copy of `src` to `dst` finished, by relative path, with their
+/// size and modification time. `None` unless `dst` is a copy of `src` in progress.
+///
+/// The marker holds the source path on its first line, then one `size<TAB>mtime<TAB>path`
+/// line per file, written as soon as the file is complete.
+fn read_progress(src: &Path, dst: &Path) -> Option<HashMap<String, CopiedFile>> {
+    let text = fs::read_to_string(progress_path(dst)).ok()?;
+    let mut lines = text.lines();
+    if lines.next()? != src.to_string_lossy() || !dst.is_dir() {
+        return None;
+    }
+
+    Some(
+        lines
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let size = fields.next()?.parse().ok()?;
+                let mtime = fields.next()?.parse().ok()?;
+                Some((fields.next()?.to_string(), (size, mtime)))
+            })
+            .collect(),
+    )
+}
+
+/// Copy a directory to another filesystem as a tar stream (see `copy_dir_streamed`), resuming
+/// an interrupted copy that is already at `dst`. Trees with entries tar cannot recreate are
+/// copied entry by entry with `copy_dir_recursive`.
+fn copy_dir_across(src: &Path, dst: &Path) -> Result<()> {
+    let copied = read_progress(src, dst);
+    let existed = dst.exists() && copied.is_none();
+    let result = copy_dir_streamed(src, dst, copied.unwrap_or_default());
+    let _ = fs::remove_file(progress_path(dst));
+
+    match result {
+        Ok(()) => inherit_setgid(dst),
+        Err(_) => {
+            if !existed && dst.exists() {
+                fs::remove_dir_all(dst)?;
+            }
+            copy_dir_recursive(src, dst)
+        }
+    }
+}
+
+/// Copy a directory through a tar pipe: one thread archives `src` while the other unpacks the
+/// stream into `dst`. For trees of many small files this takes far fewer calls than creating and
+/// copying every file on its own.
+///
+/// Every finished file is recorded in the progress marker of `dst`, so that a copy that is
+/// killed halfway can be resumed. Files in `copied` whose size and modification time still
+/// match on both sides are not copied again.
+fn copy_dir_streamed(
+    src: &Path,
+    dst: &Path,
+    mut copied: HashMap<String, CopiedFile>,
+) -> Result<()> {
+    use std::io::Write;
+
+    fs::create_dir_all(dst)?;
+    copied.retain(|path, file| {
+        fs::symlink_metadata(dst.join(path))
+            .is_ok_and(|meta| meta.is_file() && copied_file(&meta) == Some(*file))
+    });
+    let mut progress = if copied.is_empty() {
+        let mut marker = fs::File::create(progress_path(dst))?;
+        writeln!(marker, "{}", src.to_string_lossy())?;
+        marker
+    } else {
+        fs::OpenOptions::new()
+            .append(true)
+            .open(progress_path(dst))?
+    };
+
+    let (reader, writer) = io::pipe()?;
+    let source = src.to_path_buf();
+    let archiver = std::thread::spawn(move || -> io::Result<()> {
+        let mut builder = tar::Builder::new(io::BufWriter::with_capacity(COPY_BUFFER_SIZE, writer));
+        builder.follow_symlinks(false);
+        // Directories come after their contents, so that their permissions and times are set
+        // once nothing more is written into them
+        for entry in WalkDir::new(&source)
+            .contents_first(true)
+            .sort_by_file_name()
+        {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(&source).unwrap_or(entry.path());
+            let name = relative.to_string_lossy().replace('\\', "/");
+            if entry.file_type().is_file()
+                && copied.get(&name).is_some_and(|file| {
+                    entry
+                        .metadata()
+                        .is_ok_and(|meta| copied_file(&meta) == Some(*file))
+                })
+            {
+                continue;
+            }
+            let name = if name.is_empty() {
+                ".".to_string()
+            } else {
+                name
+            };
+            builder.append_path_with_name(entry.path(), name)?;
+        }
+        builder.into_inner()?.flush()
+    });
+
+    let mut archive = tar::Archive::new(io::BufReader::with_capacity(COPY_BUFFER_SIZE, reader));
+    archive.set_preserve_permissions(true);
+    let unpacked = archive.entries().and_then(|entries| {
+        for entry in entries {
+            let mut entry = entry?;
+            let is_file = entry.header().entry_type().is_file();
+            let name = entry.path()?.to_string_lossy().replace('\\', "/");
+            let size = entry.size();
+            let mtime = entry.header().mtime()?;
+            entry.unpack_in(dst)?;
+            if is_file {
+                writeln!(progress, "{}\t{}\t{}", size, mtime, name)?;
+            }
+        }
+        Ok(())
+    });
+    // Closing the pipe stops an archiver that is still writing
+    drop(archive);
+    let archived = archiver
+        .join()
+        .map_err(|_| anyhow!("Archiving '{}' failed", src.display()))?;
+
+    archived.map_err(|e| anyhow!("Failed to archive '{}': {}", src.display(), e))?;
+    unpacked.map_err(|e| anyhow!("Failed to unpack into '{}': {}", dst.display(), e))?;
+    Ok(())
+}
+
 /// Recursively copy a directory and all its contents.
 pub fn copy_dir_recursive<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
     let src = src.as_ref();
@@ -50,13 +679,15 @@ pub fn copy_dir_recursive<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Res
         let relative_path = path.strip_prefix(src)?;
         let target_path = dst.join(relative_path);
 
-        if path.is_dir() {
+        if entry.path_is_symlink() {
+            copy_symlink(path, &target_path)?;
+        } else if path.is_dir() {
             fs::create_dir_all(&target_path)?;
         } else {
             if let Some(parent) = target_path.parent() {
                 fs::create_dir_all(parent)?;
             }
-            fs::copy(path, &target_path)?;
+            copy_file(path, &target_path)?;
         }
     }
 
@@ -83,10 +714,210 @@ pub fn generate_hash(path: &Path, is_dir: bool) -> Result<String> {
     Ok(hash_str[..16].to_string())
 }
 
+/// Compute the SHA-256 of a single file's contents as a hex string.
+pub fn hash_file(path: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// A single file recorded in a directory manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path relative to the directory root, using `/` separators
+    pub relative_path: String,
+    pub size: u64,
+    /// SHA-256 of the file contents
+    pub hash: String,
+}
+
+/// A difference between a directory and its recorded manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestMismatch {
+    Missing(String),
+    Modified(String),
+    Unexpected(String),
+}
+
+/// Build a manifest of every file inside a directory, sorted by relative path.
+pub fn build_manifest(dir: &Path) -> Result<Vec<ManifestEntry>> {
+    let mut entries = Vec::new();
+
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative_path = entry.path().strip_prefix(dir)?;
+        entries.push(ManifestEntry {
+            relative_path: relative_path.to_string_lossy().replace('\\', "/"),
+            size: entry.metadata()?.len(),
+            hash: hash_file(entry.path())?,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Combine manifest entries into a single content hash.
+pub fn manifest_hash(entries: &[ManifestEntry]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        hasher.update(entry.relative_path.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(entry.hash.as_bytes());
+    }
+
+    hex::encode(hasher.finalize())
+}
+
+/// Compare a directory against its recorded manifest.
+pub fn verify_manifest(dir: &Path, manifest: &[ManifestEntry]) -> Result<Vec<ManifestMismatch>> {
+    let current = build_manifest(dir)?;
+    let mut mismatches = Vec::new();
+
+    for expected in manifest {
+        match current
+            .iter()
+            .find(|entry| entry.relative_path == expected.relative_path)
+        {
+            None => mismatches.push(ManifestMismatch::Missing(expected.relative_path.clone())),
+            Some(actual) if actual.size != expected.size || actual.hash != expected.hash => {
+                mismatches.push(ManifestMismatch::Modified(expected.relative_path.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+
+    for actual in &current {
+        if !manifest
+            .iter()
+            .any(|entry| entry.relative_path == actual.relative_path)
+        {
+            mismatches.push(ManifestMismatch::Unexpected(actual.relative_path.clone()));
+        }
+    }
+
+    Ok(mismatches)
+}
+
+/// Compute a content hash for a file or directory.
+/// Directories hash the manifest of their files, so two trees with identical
+/// files at identical relative paths produce the same value.
+pub fn content_hash(path: &Path) -> Result<String> {
+    if path.is_dir() {
+        Ok(manifest_hash(&build_manifest(path)?))
+    } else {
+        hash_file(path)
+    }
+}
+
+/// Get the total size in bytes of a file, or of all files inside a directory.
+pub fn path_size(path: &Path) -> Result<u64> {
+    Ok(path_usage(path)?.0)
+}
+
+/// Get the total size in bytes and the number of files of a file or directory.
+pub fn path_usage(path: &Path) -> Result<(u64, u64)> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok((metadata.len(), 1));
+    }
+
+    let mut total = 0;
+    let mut files = 0;
+    for entry in WalkDir::new(path) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            total += entry.metadata()?.len();
+            files += 1;
+        }
+    }
+
+    Ok((total, files))
+}
+
+/// The closest ancestor of `path` (or `path` itself) that exists, which tells the filesystem a
+/// destination that is still to be created will end up on
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .find(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.exists())
+}
+
+/// Identifier of the filesystem `path` is on (or would be created on), where the platform
+/// tells
+#[cfg(unix)]
+pub fn filesystem_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(fs::metadata(existing_ancestor(path)?).ok()?.dev())
+}
+
+#[cfg(not(unix))]
+pub fn filesystem_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Bytes available to this user on the filesystem `path` is on (or would be created on);
+/// `None` where that cannot be told
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(existing_ancestor(path)?.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    Some((stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Bytes that putting `size` bytes from `src` at `dest` takes on the destination filesystem:
+/// nothing for a move within one filesystem, which is a rename, all of it for a copy or a move
+/// across filesystems, which copies before deleting
+pub fn space_needed(src: &Path, dest: &Path, size: u64, copy: bool) -> u64 {
+    let same_filesystem = matches!(
+        (filesystem_id(src), filesystem_id(dest)),
+        (Some(a), Some(b)) if a == b
+    );
+    if copy || !same_filesystem {
+        size
+    } else {
+        0
+    }
+}
+
+/// Fail before anything is written if `needed` bytes do not fit on the filesystem of `dest`.
+/// Passes where the free space cannot be told.
+pub fn ensure_space(dest: &Path, needed: u64) -> Result<()> {
+    match available_space(dest) {
+        Some(available) if available < needed => Err(FstkError::InsufficientSpace(format!(
+            "{} needs {}, but only {} is free there",
+            dest.display(),
+            crate::utils::display::format_size(needed),
+            crate::utils::display::format_size(available)
+        ))
+        .into()),
+        _ => Ok(()),
+    }
+}
+
 /// Check if a path exists and is accessible.
 pub fn is_path_accessible(path: &Path) -> Result<bool> {
     if !path.exists() {
-        return Err(anyhow!("Path does not exist: {}", path.display()));
+        return Err(FstkError::PathNotFound(path.display().to_string()).into());
     }
 
     match path.metadata() {
@@ -95,9 +926,222 @@ pub fn is_path_accessible(path: &Path) -> Result<bool> {
     }
 }
 
+/// What `merge_dir` did with each file, by `/`-separated path relative to the merged directories
+#[derive(Debug, Default, PartialEq)]
+pub struct MergeReport {
+    /// Files moved to a path that was free
+    pub moved: Vec<String>,
+    /// Files dropped because an identical copy already existed
+    pub identical: Vec<String>,
+    /// Files that replaced a different one
+    pub overwritten: Vec<String>,
+    /// Files moved next to a different one, as (path, new path)
+    pub renamed: Vec<(String, String)>,
+    /// Conflicting files and directories left in the source
+    pub skipped: Vec<String>,
+}
+
+impl MergeReport {
+    /// Paths that are no longer in the source directory
+    pub fn taken(&self) -> impl Iterator<Item = &String> {
+        self.moved
+            .iter()
+            .chain(&self.identical)
+            .chain(&self.overwritten)
+            .chain(self.renamed.iter().map(|(path, _)| path))
+    }
+}
+
+/// Merge the contents of `src` into the existing directory `dst`, file by file.
+/// Files that differ from an existing one are handled by `policy`; a file and a directory
+/// with the same name are always skipped. Whatever was not skipped is removed from `src`,
+/// along with subdirectories that end up empty (`src` itself is kept).
+/// If merging a file fails, `report` holds what was done before the error.
+pub fn merge_dir(
+    src: &Path,
+    dst: &Path,
+    policy: MergePolicy,
+    report: &mut MergeReport,
+) -> Result<()> {
+    merge_entries(src, dst, "", policy, report)
+}
+
+fn merge_entries(
+    src: &Path,
+    dst: &Path,
+    prefix: &str,
+    policy: MergePolicy,
+    report: &mut MergeReport,
+) -> Result<()> {
+    let mut entries = fs::read_dir(src)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative = format!("{}{}", prefix, name);
+        let src_path = entry.path();
+        let dst_path = dst.join(&name);
+
+        if entry.file_type()?.is_dir() {
+            if !dst_path.exists() {
+                fs::create_dir(&dst_path)?;
+            } else if !dst_path.is_dir() {
+                report.skipped.push(relative);
+                continue;
+            }
+
+            merge_entries(
+                &src_path,
+                &dst_path,
+                &format!("{}/", relative),
+                policy,
+                report,
+            )?;
+            if fs::read_dir(&src_path)?.next().is_none() {
+                fs::remove_dir(&src_path)?;
+            }
+            continue;
+        }
+
+        if !dst_path.exists() {
+            move_or_copy(&src_path, &dst_path)?;
+            report.moved.push(relative);
+        } else if dst_path.is_dir() {
+            report.skipped.push(relative);
+        } else if hash_file(&src_path)? == hash_file(&dst_path)? {
+            fs::remove_file(&src_path)?;
+            report.identical.push(relative);
+        } else {
+            match policy {
+                MergePolicy::Skip => report.skipped.push(relative),
+                MergePolicy::Overwrite => {
+                    overwrite_file(&src_path, &dst_path)?;
+                    report.overwritten.push(relative);
+                }
+                MergePolicy::Rename => {
+                    let free = free_path(&dst_path);
+                    move_or_copy(&src_path, &free)?;
+                    let new_name = free
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    report
+                        .renamed
+                        .push((relative, format!("{}{}", prefix, new_name)));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace the file at `dst` with `src`. Within one filesystem the rename replaces it in one
+/// step; across filesystems the old file is moved aside and only deleted once `src` is in place,
+/// so a failed copy leaves it where it was.
+fn overwrite_file(src: &Path, dst: &Path) -> Result<()> {
+    match fs::rename(src, dst) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {}
+        Err(e) => {
+            return Err(anyhow!(
+                "Failed to move '{}' to '{}': {}",
+                src.display(),
+                dst.display(),
+                e
+            ))
+        }
+    }
+
+    let replaced = move_aside(dst)?;
+    if let Err(e) = move_or_copy(src, dst) {
+        // Whatever is at `dst` now is what the failed copy left
+        if fs::symlink_metadata(dst).is_ok() {
+            let _ = remove_item(dst);
+        }
+        crate::cli::pop::restore_replaced(Some(&replaced), dst);
+        return Err(e);
+    }
+    if let Err(e) = fs::remove_file(&replaced.aside) {
+        crate::status!(
+            "Could not remove the replaced {}: {}",
+            replaced.aside.display(),
+            e
+        );
+    }
+    Ok(())
+}
+
+/// Find a free name next to `path` by adding a counter: `notes.txt` becomes `notes-1.txt`.
+pub fn free_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| path.with_file_name(format!("{}-{}{}", stem, n, extension)))
+        .find(|candidate| !check_destination_conflict(candidate))
+        .expect("an unused name exists")
+}
+
 /// Check if a file or directory already exists at the destination path.
 pub fn check_destination_conflict(path: &Path) -> bool {
-    path.exists()
+    existing_entry(path).is_some()
+}
+
+/// The entry on disk that `path` names: `path` itself, or a sibling whose name differs only in
+/// Unicode normalization. Such a name looks identical, and normalization-insensitive
+/// filesystems (APFS, HFS+) treat it as the same file.
+pub fn existing_entry(path: &Path) -> Option<PathBuf> {
+    if path.exists() {
+        return Some(path.to_path_buf());
+    }
+
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str()))
+    else {
+        return None;
+    };
+    if name.is_ascii() {
+        return None;
+    }
+    let entries = fs::read_dir(if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    })
+    .ok()?;
+
+    entries
+        .flatten()
+        .find(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|other| same_name(other, name))
+        })
+        .map(|entry| parent.join(entry.file_name()))
+}
+
+/// Whether two file names are the same once Unicode-normalized, e.g. "é" written as one code
+/// point (NFC, usual on Linux) and as "e" plus a combining accent (NFD, from macOS)
+pub fn same_name(a: &str, b: &str) -> bool {
+    a == b || a.nfc().eq(b.nfc())
+}
+
+/// A file name or path as recorded on the stack. macOS hands out decomposed (NFD) names on
+/// some filesystems; those are stored precomposed (NFC), the form Linux and Windows use, so
+/// items restore under the same name elsewhere. Other platforms keep names as they are.
+pub fn normalize_name(name: &str) -> String {
+    if cfg!(target_os = "macos") {
+        name.nfc().collect()
+    } else {
+        name.to_string()
+    }
 }
 
 /// Get the file name from a path.
@@ -107,6 +1151,15 @@ pub fn get_file_name(path: &Path) -> Result<String> {
         .map(|name| name.to_string_lossy().to_string())
 }
 
+/// Whether `name` is a single plain file name that stays inside the directory it is joined to:
+/// not empty, `.` or `..`, and free of path separators and drive or root prefixes.
+pub fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(std::path::Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\'])
+}
+
 /// Get the absolute path of a path, optionally resolving symlinks.
 pub fn get_absolute_path(path: &Path) -> Result<PathBuf> {
     match path.canonicalize() {
@@ -119,17 +1172,93 @@ pub fn get_absolute_path(path: &Path) -> Result<PathBuf> {
     }
 }
 
-/// Create parent directories for a file if they don't exist.
-#[allow(dead_code)]
+/// Create parent directories for a file if they don't exist (see `create_dirs`).
 pub fn ensure_parent_dirs(path: &Path) -> Result<()> {
     if let Some(parent) = path.parent() {
         if !parent.exists() {
-            fs::create_dir_all(parent)?;
+            create_dirs(parent)?;
         }
     }
     Ok(())
 }
 
+/// Create a directory and its missing parents like `mkdir -p`: with the permissions the umask
+/// allows and, inside a setgid directory, with its group and setgid bit, which the kernel passes
+/// on. Created directories that every user may write to are reported rather than left silently.
+pub fn create_dirs(dir: &Path) -> Result<()> {
+    let missing: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .collect();
+    fs::create_dir_all(dir)?;
+
+    for created in missing.into_iter().rev() {
+        warn_world_writable(created);
+    }
+    Ok(())
+}
+
+/// Warn if every user may write to `path` (see `is_world_writable`)
+pub fn warn_world_writable(path: &Path) {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| is_world_writable(&metadata)) {
+        crate::status!(
+            "Warning: {} is writable by every user (check your umask, or run chmod o-w)",
+            path.display()
+        );
+    }
+}
+
+/// Whether every user may change the file or directory: world-writable, and for a directory
+/// without the sticky bit that keeps users from removing each other's files (like /tmp has)
+#[cfg(unix)]
+fn is_world_writable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
+    !metadata.file_type().is_symlink()
+        && mode & 0o002 != 0
+        && !(metadata.is_dir() && mode & 0o1000 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_world_writable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Give the directories of a tree copied into a setgid directory the setgid bit, as if they had
+/// been created there. Copies that keep the permissions of their source set every directory's
+/// mode, which drops the bit the kernel gave them.
+#[cfg(unix)]
+fn inherit_setgid(dst: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let setgid_parent = dst
+        .parent()
+        .and_then(|parent| fs::metadata(parent).ok())
+        .is_some_and(|metadata| metadata.permissions().mode() & 0o2000 != 0);
+    if !setgid_parent || !dst.is_dir() {
+        return Ok(());
+    }
+
+    for entry in WalkDir::new(dst) {
+        let entry = entry?;
+        if !entry.file_type().is_dir() {
+            continue;
+        }
+        let mut permissions = entry.metadata()?.permissions();
+        if permissions.mode() & 0o2000 == 0 {
+            permissions.set_mode(permissions.mode() | 0o2000);
+            fs::set_permissions(entry.path(), permissions)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn inherit_setgid(_dst: &Path) -> Result<()> {
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,6 +1284,332 @@ mod tests {
         assert_eq!(content, "Test content\n");
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn test_get_owner() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("owned.txt");
+        std::fs::write(&path, "content").unwrap();
+
+        let (uid, gid) = get_owner(&path).unwrap().unwrap();
+        assert_eq!(uid, unsafe { libc::geteuid() });
+        assert_eq!(gid, unsafe { libc::getegid() });
+
+        // Setting the current owner is a no-op that only needs privileges when run as root
+        let changed = set_owner(&path, uid, gid).unwrap();
+        assert_eq!(changed, uid == 0);
+    }
+
+    #[test]
+    fn test_copy_item_keeps_source() {
+        let temp_dir = tempdir().unwrap();
+        let source_path = temp_dir.path().join("source.txt");
+        let dest_path = temp_dir.path().join("copy.txt");
+
+        let mut file = File::create(&source_path).unwrap();
+        writeln!(file, "Test content").unwrap();
+
+        copy_item(&source_path, &dest_path).unwrap();
+
+        assert!(source_path.exists());
+        assert_eq!(
+            std::fs::read_to_string(&dest_path).unwrap(),
+            "Test content\n"
+        );
+    }
+
+    #[test]
+    fn test_copy_file_overwrites_and_keeps_permissions() {
+        let temp_dir = tempdir().unwrap();
+        let source_path = temp_dir.path().join("source.sh");
+        let dest_path = temp_dir.path().join("copy.sh");
+        std::fs::write(&source_path, "new").unwrap();
+        std::fs::write(&dest_path, "old content").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&source_path, std::fs::Permissions::from_mode(0o750)).unwrap();
+        }
+
+        copy_file(&source_path, &dest_path).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&dest_path).unwrap(), "new");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&dest_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o750);
+        }
+    }
+
+    #[test]
+    fn test_copy_buffered_spans_several_buffers() {
+        let temp_dir = tempdir().unwrap();
+        let source_path = temp_dir.path().join("large.bin");
+        let content: Vec<u8> = (0..COPY_BUFFER_SIZE * 2 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(&source_path, &content).unwrap();
+
+        let buffered_path = temp_dir.path().join("buffered.bin");
+        let mut source = File::open(&source_path).unwrap();
+        let mut target = File::create(&buffered_path).unwrap();
+        let mut inspected = 0;
+        let copied =
+            copy_buffered(&mut source, &mut target, |chunk| inspected += chunk.len()).unwrap();
+        assert_eq!(copied, content.len() as u64);
+        assert_eq!(inspected, content.len());
+        assert_eq!(std::fs::read(&buffered_path).unwrap(), content);
+    }
+
+    #[test]
+    fn test_move_file_hashed() {
+        let temp_dir = tempdir().unwrap();
+        let source_path = temp_dir.path().join("source.txt");
+        let dest_path = temp_dir.path().join("moved.txt");
+        std::fs::write(&source_path, "content").unwrap();
+        let expected = hash_file(&source_path).unwrap();
+
+        // A rename within one filesystem copies nothing, so `copied` is not called
+        let hash = move_file_hashed(&source_path, &dest_path, |_| {
+            panic!("renamed files are not copied")
+        })
+        .unwrap();
+
+        assert_eq!(hash, expected);
+        assert!(!source_path.exists());
+        assert_eq!(std::fs::read_to_string(&dest_path).unwrap(), "content");
+    }
+
+    #[test]
+    fn test_destination_conflict_ignores_unicode_normalization() {
+        let temp_dir = tempdir().unwrap();
+        // Decomposed name, as written by older macOS filesystems
+        std::fs::write(temp_dir.path().join("cafe\u{301}.txt"), "").unwrap();
+
+        assert!(same_name("caf\u{e9}.txt", "cafe\u{301}.txt"));
+        assert!(check_destination_conflict(
+            &temp_dir.path().join("caf\u{e9}.txt")
+        ));
+        assert!(!check_destination_conflict(
+            &temp_dir.path().join("cafe.txt")
+        ));
+
+        // What is moved aside is the entry on disk, under its own name
+        let moved = move_aside(&temp_dir.path().join("caf\u{e9}.txt")).unwrap();
+        assert_eq!(moved.path, temp_dir.path().join("cafe\u{301}.txt"));
+        assert_eq!(
+            moved.aside,
+            temp_dir.path().join("cafe\u{301}.txt.fstk-replaced")
+        );
+        assert!(moved.aside.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_dir_recursive_keeps_symlinks() {
+        let temp_dir = tempdir().unwrap();
+        let src_dir = temp_dir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("real")).unwrap();
+        std::fs::write(src_dir.join("real/file.txt"), "content").unwrap();
+        std::os::unix::fs::symlink("real", src_dir.join("dir-link")).unwrap();
+        std::os::unix::fs::symlink("real/file.txt", src_dir.join("file-link")).unwrap();
+
+        let dst_dir = temp_dir.path().join("dst");
+        copy_dir_recursive(&src_dir, &dst_dir).unwrap();
+
+        assert_eq!(
+            std::fs::read_link(dst_dir.join("dir-link")).unwrap(),
+            Path::new("real")
+        );
+        assert_eq!(
+            std::fs::read_link(dst_dir.join("file-link")).unwrap(),
+            Path::new("real/file.txt")
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst_dir.join("dir-link/file.txt")).unwrap(),
+            "content"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_clone_identical() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let temp_dir = tempdir().unwrap();
+        let existing = temp_dir.path().join("existing.txt");
+        let target = temp_dir.path().join("target.sh");
+        std::fs::write(&existing, "same").unwrap();
+        std::fs::write(&target, "same").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1 << 30);
+        std::fs::File::options()
+            .write(true)
+            .open(&target)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let before = std::fs::metadata(&target).unwrap();
+
+        // Whether or not the filesystem can clone, the files never share an inode and the
+        // target keeps its own metadata
+        let cloned = clone_identical(&existing, &target).unwrap();
+        let after = std::fs::metadata(&target).unwrap();
+        assert_eq!(std::fs::metadata(&existing).unwrap().nlink(), 1);
+        assert_eq!(after.nlink(), 1);
+        assert_ne!(after.ino(), std::fs::metadata(&existing).unwrap().ino());
+        assert_eq!(after.mode(), before.mode());
+        assert_eq!(after.modified().unwrap(), modified);
+        assert_eq!(cloned, after.ino() != before.ino());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "same");
+        assert!(!temp_dir.path().join("target.sh.clone").exists());
+
+        // A new clone takes the metadata of the file it stands in for, or is not created
+        let staged = temp_dir.path().join("staged");
+        let cloned = clone_like(&existing, &target, &staged).unwrap();
+        assert_eq!(staged.exists(), cloned);
+        if cloned {
+            assert_eq!(std::fs::metadata(&staged).unwrap().mode(), before.mode());
+        }
+
+        // Writing to one leaves the other alone
+        std::fs::write(&target, "edit").unwrap();
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "same");
+
+        // Different content is never replaced
+        let other = temp_dir.path().join("other.txt");
+        std::fs::write(&other, "longer").unwrap();
+        assert!(!clone_identical(&existing, &other).unwrap());
+        assert!(!clone_identical(&existing, &existing).unwrap());
+    }
+
+    #[test]
+    fn test_copy_dir_streamed() {
+        let temp_dir = tempdir().unwrap();
+        let src_dir = temp_dir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("sub/empty")).unwrap();
+        for index in 0..100 {
+            std::fs::write(
+                src_dir.join(format!("sub/{}.txt", index)),
+                index.to_string(),
+            )
+            .unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::os::unix::fs::symlink("sub/1.txt", src_dir.join("link")).unwrap();
+            let script = src_dir.join("run.sh");
+            std::fs::write(&script, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let dst_dir = temp_dir.path().join("dst");
+        copy_dir_streamed(&src_dir, &dst_dir, HashMap::new()).unwrap();
+
+        assert_eq!(
+            manifest_hash(&build_manifest(&dst_dir).unwrap()),
+            manifest_hash(&build_manifest(&src_dir).unwrap())
+        );
+        assert!(dst_dir.join("sub/empty").is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert!(dst_dir.join("link").is_symlink());
+            let mode = std::fs::metadata(dst_dir.join("run.sh"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        // A missing source is an error rather than an empty copy
+        assert!(
+            copy_dir_streamed(&temp_dir.path().join("missing"), &dst_dir, HashMap::new()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_copy_dir_across_resumes() {
+        let temp_dir = tempdir().unwrap();
+        let src_dir = temp_dir.path().join("src");
+        std::fs::create_dir_all(src_dir.join("sub")).unwrap();
+        std::fs::write(src_dir.join("done.txt"), "done").unwrap();
+        std::fs::write(src_dir.join("sub/half.txt"), "half copied").unwrap();
+
+        // An interrupted copy: one file finished, one cut off
+        let dst_dir = temp_dir.path().join("dst");
+        std::fs::create_dir_all(dst_dir.join("sub")).unwrap();
+        std::fs::write(dst_dir.join("done.txt"), "DONE").unwrap();
+        std::fs::write(dst_dir.join("sub/half.txt"), "half").unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1 << 30);
+        for path in [src_dir.join("done.txt"), dst_dir.join("done.txt")] {
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        // Same size as recorded, but modified since: copied again
+        std::fs::write(src_dir.join("changed.txt"), "new").unwrap();
+        std::fs::write(dst_dir.join("changed.txt"), "old").unwrap();
+        std::fs::write(
+            progress_path(&dst_dir),
+            format!(
+                "{}\n4\t{}\tdone.txt\n3\t0\tchanged.txt\n",
+                src_dir.display(),
+                1u64 << 30
+            ),
+        )
+        .unwrap();
+        assert!(can_resume(&src_dir, &dst_dir));
+        assert!(!can_resume(&temp_dir.path().join("other"), &dst_dir));
+
+        move_or_copy(&src_dir, &dst_dir).unwrap();
+
+        // The finished file was not copied again
+        assert_eq!(
+            std::fs::read_to_string(dst_dir.join("done.txt")).unwrap(),
+            "DONE"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst_dir.join("sub/half.txt")).unwrap(),
+            "half copied"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst_dir.join("changed.txt")).unwrap(),
+            "new"
+        );
+        assert!(!progress_path(&dst_dir).exists());
+        assert!(!src_dir.exists());
+    }
+
+    #[test]
+    fn test_stage_and_unstage() {
+        let temp_dir = tempdir().unwrap();
+        let original = temp_dir.path().join("file.txt");
+        let target = temp_dir.path().join("abcdef");
+        std::fs::write(&original, "content").unwrap();
+
+        let staged = staging_path(&target);
+        assert_eq!(staged, temp_dir.path().join("abcdef.staging"));
+
+        // A moved item goes back to where it came from
+        move_or_copy(&original, &staged).unwrap();
+        unstage(&staged, &original, false).unwrap();
+        assert!(original.exists());
+        assert!(!staged.exists());
+
+        // A linked item only drops the link
+        std::fs::hard_link(&original, &staged).unwrap();
+        unstage(&staged, &original, true).unwrap();
+        assert!(original.exists());
+        assert!(!staged.exists());
+    }
+
     #[test]
     fn test_copy_dir_recursive() {
         let temp_dir = tempdir().unwrap();
@@ -190,10 +1645,10 @@ mod tests {
         assert!(dst_dir.join("subdir/subfile.txt").exists());
 
         // Check file contents
-        let content = std::fs::read_to_string(&dst_dir.join("file.txt")).unwrap();
+        let content = std::fs::read_to_string(dst_dir.join("file.txt")).unwrap();
         assert_eq!(content, "Test content\n");
 
-        let subcontent = std::fs::read_to_string(&dst_dir.join("subdir/subfile.txt")).unwrap();
+        let subcontent = std::fs::read_to_string(dst_dir.join("subdir/subfile.txt")).unwrap();
         assert_eq!(subcontent, "Subdir test content\n");
     }
 
@@ -221,6 +1676,96 @@ mod tests {
         assert_ne!(hash1, dir_hash);
     }
 
+    #[test]
+    fn test_content_hash() {
+        let dir = tempdir().unwrap();
+
+        let file_a = dir.path().join("a.txt");
+        let file_b = dir.path().join("b.txt");
+        let file_c = dir.path().join("c.txt");
+        std::fs::write(&file_a, "same").unwrap();
+        std::fs::write(&file_b, "same").unwrap();
+        std::fs::write(&file_c, "different").unwrap();
+
+        // Files hash by content only
+        assert_eq!(
+            content_hash(&file_a).unwrap(),
+            content_hash(&file_b).unwrap()
+        );
+        assert_ne!(
+            content_hash(&file_a).unwrap(),
+            content_hash(&file_c).unwrap()
+        );
+
+        // Directories hash by layout and content
+        let tree_1 = dir.path().join("tree1");
+        let tree_2 = dir.path().join("tree2");
+        for tree in [&tree_1, &tree_2] {
+            fs::create_dir_all(tree.join("sub")).unwrap();
+            std::fs::write(tree.join("sub/file.txt"), "content").unwrap();
+        }
+        assert_eq!(
+            content_hash(&tree_1).unwrap(),
+            content_hash(&tree_2).unwrap()
+        );
+
+        std::fs::write(tree_2.join("extra.txt"), "").unwrap();
+        assert_ne!(
+            content_hash(&tree_1).unwrap(),
+            content_hash(&tree_2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_build_and_verify_manifest() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("tree");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("a.txt"), "aaa").unwrap();
+        std::fs::write(root.join("sub/b.txt"), "bb").unwrap();
+
+        let manifest = build_manifest(&root).unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest[0].relative_path, "a.txt");
+        assert_eq!(manifest[0].size, 3);
+        assert_eq!(manifest[1].relative_path, "sub/b.txt");
+        assert_eq!(
+            manifest[1].hash,
+            hash_file(&root.join("sub/b.txt")).unwrap()
+        );
+
+        assert!(verify_manifest(&root, &manifest).unwrap().is_empty());
+
+        std::fs::write(root.join("a.txt"), "changed").unwrap();
+        std::fs::remove_file(root.join("sub/b.txt")).unwrap();
+        std::fs::write(root.join("c.txt"), "new").unwrap();
+
+        let mismatches = verify_manifest(&root, &manifest).unwrap();
+        assert_eq!(
+            mismatches,
+            vec![
+                ManifestMismatch::Modified("a.txt".to_string()),
+                ManifestMismatch::Missing("sub/b.txt".to_string()),
+                ManifestMismatch::Unexpected("c.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_path_size() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("test.txt");
+        std::fs::write(&file_path, "12345").unwrap();
+
+        let sub_dir = dir.path().join("sub");
+        fs::create_dir(&sub_dir).unwrap();
+        std::fs::write(sub_dir.join("nested.txt"), "123").unwrap();
+
+        assert_eq!(path_size(&file_path).unwrap(), 5);
+        assert_eq!(path_size(dir.path()).unwrap(), 8);
+        assert!(path_size(&dir.path().join("missing")).is_err());
+    }
+
     #[test]
     fn test_is_path_accessible() {
         let dir = tempdir().unwrap();
@@ -240,6 +1785,133 @@ mod tests {
         assert!(result.unwrap());
     }
 
+    #[test]
+    fn test_pack_and_unpack_dir() -> Result<()> {
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("sub/empty"))?;
+        fs::write(src.join("a.txt"), "a")?;
+        fs::write(src.join("sub/b.txt"), "b")?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a.txt", src.join("link"))?;
+
+        let archive = dir.path().join("src.tar");
+        pack_dir(&src, &archive)?;
+        let unpacked = dir.path().join("unpacked");
+        unpack_dir(&archive, &unpacked)?;
+
+        assert_eq!(
+            manifest_hash(&build_manifest(&unpacked)?),
+            manifest_hash(&build_manifest(&src)?)
+        );
+        assert!(unpacked.join("sub/empty").is_dir());
+        #[cfg(unix)]
+        assert!(unpacked.join("link").is_symlink());
+
+        // A broken archive leaves nothing behind
+        fs::write(
+            &archive,
+            "not a tar archive, but long enough to be read as a header",
+        )?;
+        assert!(unpack_dir(&archive, &dir.path().join("broken")).is_err());
+        assert!(!dir.path().join("broken").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_free_path() {
+        let temp_dir = tempdir().unwrap();
+        let taken = temp_dir.path().join("notes.txt");
+        std::fs::write(&taken, "a").unwrap();
+        std::fs::write(temp_dir.path().join("notes-1.txt"), "b").unwrap();
+
+        assert_eq!(free_path(&taken), temp_dir.path().join("notes-2.txt"));
+        assert_eq!(
+            free_path(&temp_dir.path().join("photos")),
+            temp_dir.path().join("photos-1")
+        );
+    }
+
+    #[test]
+    fn test_merge_dir() {
+        let temp_dir = tempdir().unwrap();
+        let merge = |policy| {
+            let src = temp_dir.path().join("src");
+            let dst = temp_dir.path().join("dst");
+            for dir in [&src, &dst] {
+                if dir.exists() {
+                    std::fs::remove_dir_all(dir).unwrap();
+                }
+                std::fs::create_dir_all(dir.join("sub")).unwrap();
+            }
+            std::fs::write(src.join("new.txt"), "new").unwrap();
+            std::fs::write(src.join("same.txt"), "same").unwrap();
+            std::fs::write(src.join("sub/diff.txt"), "ours").unwrap();
+            std::fs::create_dir(src.join("clash")).unwrap();
+            std::fs::write(src.join("clash/inner.txt"), "x").unwrap();
+            std::fs::write(dst.join("same.txt"), "same").unwrap();
+            std::fs::write(dst.join("sub/diff.txt"), "theirs").unwrap();
+            std::fs::write(dst.join("clash"), "a file").unwrap();
+
+            let mut report = MergeReport::default();
+            merge_dir(&src, &dst, policy, &mut report).unwrap();
+            (report, src, dst)
+        };
+
+        let (report, src, dst) = merge(MergePolicy::Skip);
+        assert_eq!(report.moved, vec!["new.txt"]);
+        assert_eq!(report.identical, vec!["same.txt"]);
+        assert_eq!(report.skipped, vec!["clash", "sub/diff.txt"]);
+        assert_eq!(std::fs::read_to_string(dst.join("new.txt")).unwrap(), "new");
+        assert!(!src.join("new.txt").exists() && !src.join("same.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(src.join("sub/diff.txt")).unwrap(),
+            "ours"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst.join("sub/diff.txt")).unwrap(),
+            "theirs"
+        );
+
+        let (report, src, dst) = merge(MergePolicy::Overwrite);
+        assert_eq!(report.overwritten, vec!["sub/diff.txt"]);
+        assert_eq!(
+            std::fs::read_to_string(dst.join("sub/diff.txt")).unwrap(),
+            "ours"
+        );
+        // Emptied subdirectories are cleaned up
+        assert!(!src.join("sub").exists());
+
+        let (report, _, dst) = merge(MergePolicy::Rename);
+        assert_eq!(
+            report.renamed,
+            vec![("sub/diff.txt".to_string(), "sub/diff-1.txt".to_string())]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dst.join("sub/diff-1.txt")).unwrap(),
+            "ours"
+        );
+        assert_eq!(report.taken().count(), 3);
+    }
+
+    #[test]
+    fn test_overwrite_file_keeps_destination_on_failure() {
+        let temp_dir = tempdir().unwrap();
+        let src = temp_dir.path().join("ours.txt");
+        let dst = temp_dir.path().join("theirs.txt");
+        std::fs::write(&dst, "theirs").unwrap();
+
+        // The source is gone, so nothing can replace the destination
+        assert!(overwrite_file(&src, &dst).is_err());
+        assert_eq!(std::fs::read_to_string(&dst).unwrap(), "theirs");
+
+        std::fs::write(&src, "ours").unwrap();
+        overwrite_file(&src, &dst).unwrap();
+        assert_eq!(std::fs::read_to_string(&dst).unwrap(), "ours");
+        assert!(!src.exists());
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
     #[test]
     fn test_check_destination_conflict() {
         let dir = tempdir().unwrap();
@@ -268,6 +1940,15 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_is_plain_name() {
+        assert!(is_plain_name("report.pdf"));
+        assert!(is_plain_name("a b..c"));
+        for name in ["", ".", "..", "../x", "a/b", "/etc/passwd", "a\\b", "x/"] {
+            assert!(!is_plain_name(name), "{}", name);
+        }
+    }
+
     #[test]
     fn test_ensure_parent_dirs() {
         let dir = tempdir().unwrap();
@@ -282,4 +1963,78 @@ mod tests {
         // Parent directories should now exist
         assert!(nested_path.parent().unwrap().exists());
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_dirs_follows_umask_and_setgid() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        let dir = tempdir().unwrap();
+        // A directory made by a plain mkdir shows what the umask allows
+        let reference = dir.path().join("reference");
+        std::fs::create_dir(&reference).unwrap();
+        create_dirs(&dir.path().join("a/b")).unwrap();
+        assert_eq!(mode(&dir.path().join("a")), mode(&reference));
+        assert_eq!(mode(&dir.path().join("a/b")), mode(&reference));
+
+        // Directories created in a setgid directory keep the bit
+        let shared = dir.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o2775)).unwrap();
+        create_dirs(&shared.join("x/y")).unwrap();
+        assert_ne!(mode(&shared.join("x/y")) & 0o2000, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copies_into_setgid_dir_inherit_the_bit() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("sub/file.txt"), "content").unwrap();
+        std::fs::set_permissions(src.join("sub"), std::fs::Permissions::from_mode(0o750)).unwrap();
+        let archive = dir.path().join("src.tar");
+        pack_dir(&src, &archive).unwrap();
+
+        let shared = dir.path().join("shared");
+        std::fs::create_dir(&shared).unwrap();
+        std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o2775)).unwrap();
+
+        copy_dir_across(&src, &shared.join("copy")).unwrap();
+        assert_eq!(mode(&shared.join("copy/sub")), 0o2750);
+        unpack_dir(&archive, &shared.join("unpacked")).unwrap();
+        assert_eq!(mode(&shared.join("unpacked/sub")), 0o2750);
+
+        // Outside a setgid directory the permissions are copied as they are
+        copy_dir_across(&src, &dir.path().join("plain")).unwrap();
+        assert_eq!(mode(&dir.path().join("plain/sub")), 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_world_writable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("entry");
+        let world_writable = |mode: u32| {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            is_world_writable(&std::fs::metadata(&path).unwrap())
+        };
+
+        std::fs::write(&path, "content").unwrap();
+        assert!(world_writable(0o666));
+        assert!(!world_writable(0o664));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::create_dir(&path).unwrap();
+        assert!(world_writable(0o777));
+        // Sticky directories like /tmp are meant to be shared
+        assert!(!world_writable(0o1777));
+        assert!(!world_writable(0o755));
+    }
 }
@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Result};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::cell::OnceCell;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+
+use crate::db::{get_global_fstk_dir, StackItem};
+use crate::status;
+
+/// Name of the hook script inside the global fstk directory.
+///
+/// Hooks are user functions run at defined points of an operation, written in Rhai
+/// (<https://rhai.rs>) in `~/.fstk/hooks.rhai`. Each hook takes the item as a map:
+///
+/// ```text
+/// fn before_push(item) {
+///     if item.size > 5 * 1024 * 1024 * 1024 && !item.tags.contains("big") {
+///         throw "pushes over 5 GiB need the tag 'big'";
+///     }
+///     item.note = run("file", ["-b", item.path]);
+///     item
+/// }
+/// ```
+///
+/// Hooks are `before_push`, `after_push`, `before_pop`, `after_pop`, `before_restore` and
+/// `after_restore`. Throwing in a `before_` hook cancels the operation with the thrown message;
+/// `before_push` may also return the item map to change its `tags` and `note`. Pop and restore
+/// hooks get the `destination` path, except for bundles, whose members go to several places.
+/// Errors in `after_` hooks are only reported, since the operation already happened.
+pub const HOOKS_FILE_NAME: &str = "hooks.rhai";
+
+/// Run `before_<operation>` for an item about to be pushed, popped or restored to `destination`.
+/// Returns the item with the tags and note the hook set, or an error if the hook rejected it.
+pub fn before(operation: &str, item: &StackItem, destination: Option<&Path>) -> Result<StackItem> {
+    let hook = format!("before_{}", operation);
+    let Some(result) = call(&hook, item, destination)? else {
+        return Ok(item.clone());
+    };
+
+    let mut item = item.clone();
+    if let Some(map) = result.try_cast::<Map>() {
+        if let Some(tags) = map.get("tags") {
+            item.tags = tags
+                .clone()
+                .into_typed_array::<String>()
+                .map_err(|_| anyhow!("{} returned tags that are not all strings", hook))?;
+        }
+        if let Some(note) = map.get("note") {
+            item.note = match note.clone().into_string() {
+                Ok(note) if note.trim().is_empty() => None,
+                Ok(note) => Some(note.trim().to_string()),
+                Err(_) if note.is_unit() => None,
+                Err(_) => return Err(anyhow!("{} returned a note that is not a string", hook)),
+            };
+        }
+    }
+    Ok(item)
+}
+
+/// Run `after_<operation>` for an item that was pushed, popped or restored to `destination`
+pub fn after(operation: &str, item: &StackItem, destination: Option<&Path>) {
+    if let Err(e) = call(&format!("after_{}", operation), item, destination) {
+        status!("{}", e);
+    }
+}
+
+/// The compiled hook script and the engine that runs it
+struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+thread_local! {
+    /// The hook script, compiled on first use and kept for the rest of the run (`None` if there
+    /// is none, the message if it does not compile)
+    static SCRIPT: OnceCell<Result<Option<Rc<Script>>, String>> = const { OnceCell::new() };
+}
+
+/// The hook script, compiled once per run
+fn script() -> Result<Option<Rc<Script>>> {
+    SCRIPT
+        .with(|script| {
+            script
+                .get_or_init(|| compile().map_err(|e| e.to_string()))
+                .clone()
+        })
+        .map_err(|e| anyhow!(e))
+}
+
+fn compile() -> Result<Option<Rc<Script>>> {
+    let path = get_global_fstk_dir()?.join(HOOKS_FILE_NAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let engine = engine();
+    let ast = engine
+        .compile_file(path.clone())
+        .map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))?;
+    Ok(Some(Rc::new(Script { engine, ast })))
+}
+
+/// Call `hook` with the item if the hook script defines it; `None` if it does not
+fn call(hook: &str, item: &StackItem, destination: Option<&Path>) -> Result<Option<Dynamic>> {
+    let Some(script) = script()? else {
+        return Ok(None);
+    };
+    if !defines(&script.ast, hook) {
+        return Ok(None);
+    }
+
+    script
+        .engine
+        .call_fn::<Dynamic>(
+            &mut Scope::new(),
+            &script.ast,
+            hook,
+            (item_map(item, destination),),
+        )
+        .map(Some)
+        .map_err(|e| match *e {
+            EvalAltResult::ErrorRuntime(message, _) => {
+                anyhow!("{} in {}: {}", hook, HOOKS_FILE_NAME, message)
+            }
+            e => anyhow!("{} in {} failed: {}", hook, HOOKS_FILE_NAME, e),
+        })
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.register_fn("run", run);
+    engine
+}
+
+/// Whether the script defines `hook` taking the item
+fn defines(ast: &AST, hook: &str) -> bool {
+    ast.iter_functions()
+        .any(|function| function.name == hook && function.params.len() == 1)
+}
+
+/// `run(program, args)` for scripts: the trimmed standard output of a program
+fn run(program: &str, args: Array) -> Result<String, Box<EvalAltResult>> {
+    let args: Vec<String> = args.into_iter().map(|arg| arg.to_string()).collect();
+    let output = Command::new(program)
+        .args(&args)
+        .output()
+        .map_err(|e| format!("cannot run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("{} failed ({})", program, output.status).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The item as handed to hooks
+fn item_map(item: &StackItem, destination: Option<&Path>) -> Map {
+    let mut map = Map::new();
+    map.insert("id".into(), item.id.into());
+    map.insert("name".into(), item.original_name.clone().into());
+    map.insert(
+        "path".into(),
+        PathBuf::from(&item.original_path)
+            .join(&item.original_name)
+            .to_string_lossy()
+            .to_string()
+            .into(),
+    );
+    map.insert("type".into(), item.item_type.clone().into());
+    map.insert(
+        "size".into(),
+        item.size.map_or(Dynamic::UNIT, |size| {
+            (size.min(i64::MAX as u64) as i64).into()
+        }),
+    );
+    map.insert(
+        "tags".into(),
+        item.tags
+            .iter()
+            .cloned()
+            .map(Dynamic::from)
+            .collect::<Array>()
+            .into(),
+    );
+    map.insert(
+        "note".into(),
+        item.note.clone().map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    map.insert("pinned".into(), item.pinned.into());
+    map.insert("locked".into(), item.locked.into());
+    map.insert(
+        "destination".into(),
+        destination.map_or(Dynamic::UNIT, |path| {
+            path.to_string_lossy().to_string().into()
+        }),
+    );
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_sees_and_changes_item() {
+        let engine = engine();
+        let ast = engine
+            .compile(
+                "fn before_push(item) {\n\
+                     if item.size > 100 && !item.tags.contains(\"big\") { throw \"too big\"; }\n\
+                     item.tags.push(\"checked\");\n\
+                     item.note = item.path;\n\
+                     item\n\
+                 }",
+            )
+            .unwrap();
+        assert!(defines(&ast, "before_push"));
+        assert!(!defines(&ast, "after_push"));
+
+        let item = StackItem {
+            original_name: "a.txt".to_string(),
+            original_path: "/tmp".to_string(),
+            size: Some(10),
+            ..Default::default()
+        };
+        let result: Map = engine
+            .call_fn(
+                &mut Scope::new(),
+                &ast,
+                "before_push",
+                (item_map(&item, None),),
+            )
+            .unwrap();
+        assert_eq!(result["tags"].to_string(), "[\"checked\"]");
+        assert_eq!(result["note"].to_string(), "/tmp/a.txt");
+
+        let big = StackItem {
+            size: Some(1000),
+            ..item
+        };
+        let error = engine
+            .call_fn::<Dynamic>(
+                &mut Scope::new(),
+                &ast,
+                "before_push",
+                (item_map(&big, None),),
+            )
+            .unwrap_err();
+        assert!(error.to_string().contains("too big"));
+    }
+}
@@ -1,14 +1,65 @@
 mod cli;
+mod config;
 mod db;
 mod fs;
+mod hooks;
 mod utils;
 
-use anyhow::Result;
-use cli::{Commands, TagCommands};
+use anyhow::{anyhow, Result};
+use cli::{BackupCommands, Cli, Commands, TagCommands};
+use config::PopDestination;
+use db::StackScope;
+use utils::ssh::RemotePath;
+use utils::{error, output};
 
 fn main() -> Result<()> {
     // Parse command line arguments
-    let cli = cli::parse_cli();
+    let cli = cli::parse_cli()?;
+
+    // Wrapping tools asking for JSON get errors as JSON too
+    if cli.command.wants_json() {
+        output::enable_json_mode();
+    }
+
+    match run(cli) {
+        Err(e) if output::is_json_mode() => {
+            error::print_json_error(&e);
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    // Numbers given and shown are database IDs for the whole run
+    if cli.by_id {
+        db::enable_id_addressing();
+    }
+
+    fs::set_symlink_fallback(cli.symlink_fallback);
+
+    // A remote stack is served by another machine; nothing local is touched
+    if let Some(url) = cli.remote {
+        return cli::remote::run(&url, cli.command);
+    }
+
+    // Decide between the global and the project-local stack
+    let scope = if cli.global {
+        StackScope::Global
+    } else if cli.local {
+        StackScope::Local
+    } else {
+        StackScope::Auto
+    };
+    db::select_stack(scope)?;
+
+    // Clean up after runs that were interrupted while moving files
+    if !matches!(
+        cli.command,
+        Commands::Completion { .. } | Commands::CompleteNames
+    ) {
+        cli::recovery::recover_incomplete_operations()?;
+    }
 
     // Match command and execute appropriate function
     match cli.command {
@@ -16,46 +67,362 @@ fn main() -> Result<()> {
             cli::completion::completion(shell)?;
         }
 
-        Commands::Push { path, tags } => {
-            cli::push::push(&path, tags)?;
+        Commands::CompleteNames => {
+            cli::completion::complete_names()?;
+        }
+
+        Commands::Push {
+            paths,
+            tags,
+            skip_duplicates,
+            link_duplicates,
+            git_tags,
+            bundle,
+            name,
+            yes,
+            note,
+            keep,
+            quiet,
+            as_archive,
+            interactive,
+        } => {
+            let config = config::load()?;
+            let options = cli::push::PushOptions {
+                tags,
+                skip_duplicates,
+                link_duplicates,
+                git_tags: git_tags || config.git_tags,
+                confirm_above: if yes {
+                    None
+                } else {
+                    Some(config.confirm_push_size()?)
+                },
+                keep,
+                remote_origin: None,
+                note,
+                duplicate_paths: config.duplicate_paths,
+                max_items: config.max_items,
+                max_items_policy: config.max_items_policy,
+                quiet,
+                as_archive,
+                interactive,
+            };
+            match (bundle, name) {
+                (true, Some(name)) => {
+                    cli::push::push_bundle(&paths, &name, options)?;
+                }
+                _ if paths.len() > 1 => {
+                    return Err(anyhow!(
+                        "Pushing several paths as one item requires --bundle and --name"
+                    ));
+                }
+                _ => match RemotePath::parse(&paths[0]) {
+                    // Like scp, `host:path` is remote unless such a local path exists
+                    Some(remote) if !std::path::Path::new(&paths[0]).exists() => {
+                        cli::push::push_remote(&remote, options)?;
+                    }
+                    _ => {
+                        cli::push::push(&paths[0], options)?;
+                    }
+                },
+            }
+            cli::prune::auto_prune(&config)?;
+        }
+
+        Commands::Env => {
+            cli::env::env()?;
+        }
+
+        Commands::Prune {
+            policy,
+            before,
+            tags,
+            dry_run,
+            yes,
+            install_timer,
+            every,
+            uninstall_timer,
+        } => {
+            if install_timer {
+                cli::timer::install_timer(&every, dry_run)?;
+            } else if uninstall_timer {
+                cli::timer::uninstall_timer()?;
+            } else {
+                cli::prune::prune(policy, before, tags.unwrap_or_default(), dry_run, yes)?;
+            }
+        }
+
+        Commands::Adopt { dir, tags, dry_run } => {
+            cli::adopt::adopt(&dir, tags, dry_run)?;
+        }
+
+        Commands::Stash { tags, dry_run } => {
+            cli::stash::stash(tags, dry_run)?;
         }
 
         Commands::Pop {
             numbers,
             tags,
+            any_tags,
             output,
+            rename,
+            print_path,
+            subpath,
+            version,
+            force,
+            merge,
+            on_conflict,
+            last_out,
+            here,
+            to_original,
+            to_pushdir,
+            rollback_on_error,
+            out_map,
+            ask_output,
         } => {
-            cli::pop::pop(numbers, tags, output)?;
+            let options = cli::pop::PopOptions {
+                tags: cli::tag_filters(tags, any_tags),
+                output,
+                rename,
+                print_path,
+                subpath,
+                version,
+                force,
+                merge: merge.then_some(on_conflict),
+                last_out,
+                destination: if here {
+                    Some(PopDestination::Cwd)
+                } else if to_original {
+                    Some(PopDestination::Original)
+                } else if to_pushdir {
+                    Some(PopDestination::Pushdir)
+                } else {
+                    None
+                },
+                rollback_on_error,
+                out_map,
+                ask_output,
+            };
+            cli::pop::pop(numbers, options)?;
         }
 
-        Commands::List { tags } => {
-            cli::list::list(tags)?;
+        Commands::List {
+            tags,
+            any_tags,
+            untagged,
+            group_by,
+            versions,
+            format,
+            verify,
+            no_truncate,
+            paths,
+        } => {
+            let tags = cli::tag_filters(tags, any_tags);
+            match versions {
+                Some(path) => cli::list::list_versions(&path)?,
+                None if paths => cli::list::list_paths(tags, untagged)?,
+                None => cli::list::list(tags, untagged, group_by, format, verify, no_truncate)?,
+            }
         }
 
         Commands::Tag(tag_cmd) => match tag_cmd {
-            TagCommands::Add { number, tags } => {
-                cli::tag::add_tags(number, tags)?;
+            TagCommands::Add { numbers, tags } => {
+                cli::tag::add_tags(&numbers, tags)?;
             }
 
-            TagCommands::Remove { number, tags } => {
-                cli::tag::remove_tags(number, tags)?;
+            TagCommands::Remove { numbers, tags } => {
+                cli::tag::remove_tags(&numbers, tags)?;
             }
 
-            TagCommands::List | TagCommands::Ls => {
-                cli::tag::list_tags()?;
+            TagCommands::Set { numbers, tags } => {
+                cli::tag::set_tags(&numbers, tags)?;
+            }
+
+            TagCommands::List { sizes, format } | TagCommands::Ls { sizes, format } => {
+                cli::tag::list_tags(sizes, format)?;
+            }
+        },
+
+        Commands::Edit { number } => {
+            cli::edit::edit(number)?;
+        }
+
+        Commands::Remove {
+            numbers,
+            tags,
+            any_tags,
+            older_than,
+            force,
+        } => {
+            let tags = cli::tag_filters(tags, any_tags);
+            cli::remove::remove(numbers, tags, older_than, force)?;
+        }
+
+        Commands::Move { from, to } => {
+            cli::reorder::move_item(from, to)?;
+        }
+
+        Commands::Swap { first, second } => {
+            cli::reorder::swap(first, second)?;
+        }
+
+        Commands::Pin { numbers } => {
+            cli::pin::pin(numbers)?;
+        }
+
+        Commands::Unpin { numbers } => {
+            cli::pin::unpin(numbers)?;
+        }
+
+        Commands::Lock { numbers } => {
+            cli::lock::lock(numbers)?;
+        }
+
+        Commands::Unlock { numbers } => {
+            cli::lock::unlock(numbers)?;
+        }
+
+        Commands::Restore {
+            number,
+            tags,
+            to,
+            keep,
+            print_path,
+            skip_existing,
+            only,
+            from_dir,
+            overwrite,
+            force,
+        } => match from_dir {
+            Some(dir) => cli::restore::restore_from_dir(
+                &dir,
+                tags,
+                keep,
+                print_path,
+                skip_existing,
+                overwrite,
+                force,
+            )?,
+            None => cli::restore::restore(
+                number,
+                tags,
+                to,
+                keep,
+                print_path,
+                skip_existing,
+                overwrite,
+                only,
+                force,
+            )?,
+        },
+
+        Commands::Archive { older_than, to } => {
+            cli::archive::archive(older_than, to)?;
+        }
+
+        Commands::Grep {
+            pattern,
+            numbers,
+            tags,
+            any_tags,
+            ignore_case,
+        } => {
+            let tags = cli::tag_filters(tags, any_tags);
+            cli::grep::grep(pattern, numbers, tags, ignore_case)?;
+        }
+
+        Commands::Du { by } => {
+            cli::du::du(by)?;
+        }
+
+        Commands::Stats { activity, per } => {
+            cli::stats::stats(activity, per)?;
+        }
+
+        Commands::History { format, since } => {
+            cli::history::history(format, since)?;
+        }
+
+        Commands::Query { sql, format } => {
+            cli::query::query(sql, format)?;
+        }
+
+        Commands::Backup { dest, command } => match command {
+            Some(BackupCommands::Restore { archive }) => {
+                cli::backup::restore_backup(&archive)?;
+            }
+            None => {
+                if let Some(dest) = dest {
+                    cli::backup::backup(&dest)?;
+                }
             }
         },
 
-        Commands::Remove { numbers, tags } => {
-            cli::remove::remove(numbers, tags)?;
+        Commands::Merge { source } => {
+            cli::merge::merge(&source)?;
         }
 
-        Commands::Restore { number, tags } => {
-            cli::restore::restore(number, tags)?;
+        Commands::Sync { url, dry_run } => {
+            cli::sync::sync(&url, dry_run)?;
+        }
+
+        Commands::ExportItem { number, out } => {
+            cli::item_archive::export_item(number, &out)?;
+        }
+
+        Commands::ImportItem { archive } => {
+            cli::item_archive::import_item(&archive)?;
+        }
+
+        Commands::ExportMeta { format, out } => {
+            cli::export_meta::export_meta(format, out)?;
+        }
+
+        Commands::Top { count } => {
+            cli::top::top(count)?;
+        }
+
+        Commands::Verify { numbers } => {
+            cli::verify::verify(numbers)?;
+        }
+
+        Commands::CheckSpace {
+            numbers,
+            to,
+            original,
+            keep,
+        } => {
+            cli::check_space::check_space(numbers, to, original, keep)?;
+        }
+
+        Commands::Heal { number, from, keep } => {
+            cli::heal::heal(number, from, keep)?;
+        }
+
+        Commands::Daemon { http } => {
+            cli::daemon::daemon(http)?;
+        }
+
+        Commands::Watch {
+            dir,
+            tags,
+            patterns,
+        } => {
+            cli::watch::watch(&dir, tags, patterns)?;
+        }
+
+        Commands::Peek {
+            number,
+            tags,
+            field,
+            preview,
+            provenance,
+        } => {
+            cli::peek::peek(number, tags, field, preview, provenance)?;
         }
 
-        Commands::Peek { number, tags } => {
-            cli::peek::peek(number, tags)?;
+        Commands::Tree { number, depth } => {
+            cli::tree::tree(number, depth)?;
         }
     }
 
@@ -1,9 +1,95 @@
-use crate::db::StackItem;
+use crate::cli::du::Usage;
+use crate::cli::stats::PeriodActivity;
+use crate::cli::verify::ItemHealth;
+use crate::db::{id_addressing, item_number, OperationRecord, StackItem, TagInfo};
+use chrono::{DateTime, Duration, Local};
+use std::collections::HashMap;
 use tabled::{
-    settings::{Alignment, Padding, Style},
+    builder::Builder,
+    settings::{
+        object::{Cell, Rows},
+        Alignment, Color, Format, Padding, Style,
+    },
     Table, Tabled,
 };
 
+/// Position of the HEALTH column in the items table
+const HEALTH_COLUMN: usize = 3;
+
+/// Width of the NAME and TAGS columns when the terminal width is unknown
+pub const DEFAULT_COLUMN_WIDTH: usize = 18;
+
+/// Narrowest NAME or TAGS column that still fits a character and "..."
+pub const MIN_COLUMN_WIDTH: usize = 4;
+
+/// Widths the NAME and TAGS columns of the items table are truncated to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnWidths {
+    pub name: usize,
+    pub tags: usize,
+}
+
+impl Default for ColumnWidths {
+    fn default() -> Self {
+        ColumnWidths {
+            name: DEFAULT_COLUMN_WIDTH,
+            tags: DEFAULT_COLUMN_WIDTH,
+        }
+    }
+}
+
+impl ColumnWidths {
+    /// No truncation at all
+    pub const UNLIMITED: ColumnWidths = ColumnWidths {
+        name: usize::MAX,
+        tags: usize::MAX,
+    };
+
+    /// Widths for names and tags needing `name` and `tags` columns in `available` columns.
+    /// Names get the space first, since mangled file names are the worse loss; neither column
+    /// shrinks below `self`, even if the table then overflows a narrow terminal.
+    fn fit(self, available: usize, name: usize, tags: usize) -> ColumnWidths {
+        if name + tags <= available {
+            return ColumnWidths { name, tags };
+        }
+
+        let tags = tags.min(self.tags.max(available.saturating_sub(name)));
+        ColumnWidths {
+            name: name.min(self.name.max(available.saturating_sub(tags))),
+            tags,
+        }
+    }
+}
+
+/// Color names accepted in `tag_colors` of the configuration
+pub const ROW_COLORS: [&str; 10] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white", "dim", "bold",
+];
+
+/// Row color for a color name from `tag_colors` in the configuration
+pub fn row_color(name: &str) -> Option<Color> {
+    match name.to_lowercase().as_str() {
+        "black" => Some(Color::FG_BLACK),
+        "red" => Some(Color::FG_RED),
+        "green" => Some(Color::FG_GREEN),
+        "yellow" => Some(Color::FG_YELLOW),
+        "blue" => Some(Color::FG_BLUE),
+        "magenta" => Some(Color::FG_MAGENTA),
+        "cyan" => Some(Color::FG_CYAN),
+        "white" => Some(Color::FG_WHITE),
+        "dim" => Some(Color::new("\u{1b}[2m", "\u{1b}[22m")),
+        "bold" => Some(Color::BOLD),
+        _ => None,
+    }
+}
+
+/// Label the number column "ID" when items are addressed by database ID (`--id`)
+fn label_number_column(table: &mut Table) {
+    if id_addressing() {
+        table.modify(Cell::new(0, 0), Format::content(|_| "ID".to_string()));
+    }
+}
+
 #[derive(Tabled)]
 pub struct DisplayItem {
     #[tabled(rename = "NO")]
@@ -12,6 +98,12 @@ pub struct DisplayItem {
     #[tabled(rename = "T")]
     pub item_type: String,
 
+    #[tabled(rename = "FLAGS")]
+    pub flags: String,
+
+    #[tabled(rename = "HEALTH")]
+    pub health: String,
+
     #[tabled(rename = "NAME")]
     pub name: String,
 
@@ -22,63 +114,235 @@ pub struct DisplayItem {
     pub pushed_at: String,
 }
 
+/// Format a count with thousands separators (e.g. "80,321")
+pub fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Format a byte count using binary units (e.g. "12.4 GiB")
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Format the time elapsed since a timestamp as a compact age (e.g. "5m", "3h", "12d")
+pub fn format_age(since: DateTime<Local>) -> String {
+    format_duration(Local::now() - since)
+}
+
+/// Format a duration compactly (e.g. "5m", "3h", "12d")
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+/// Format the time an operation took, with tenths of a second below a minute (e.g. "0.4s",
+/// "12.3s", "2m 5s")
+pub fn format_elapsed(elapsed: std::time::Duration) -> String {
+    let seconds = elapsed.as_secs();
+    if seconds < 60 {
+        format!("{:.1}s", elapsed.as_secs_f64())
+    } else if seconds < 3600 {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}h {}m", seconds / 3600, seconds / 60 % 60)
+    }
+}
+
 fn truncate(s: &str, max_len: usize) -> String {
     if s.chars().count() <= max_len {
         return s.to_string();
     }
 
-    let visible: String = s.chars().take(max_len - 3).collect();
+    let visible: String = s.chars().take(max_len.saturating_sub(3)).collect();
     format!("{}...", visible)
 }
 
 /// Create a DisplayItem from a database StackItem and a display number
 pub fn create_display_item(item: &StackItem, number: usize) -> DisplayItem {
-    let type_indicator = if item.item_type == "directory" {
-        "d"
-    } else {
-        "f"
+    create_fitted_display_item(item, number, ColumnWidths::default())
+}
+
+/// Create a DisplayItem whose name and tags are truncated to `widths`
+fn create_fitted_display_item(
+    item: &StackItem,
+    number: usize,
+    widths: ColumnWidths,
+) -> DisplayItem {
+    let type_indicator = match item.item_type.as_str() {
+        "directory" => "d",
+        "bundle" => "b",
+        _ => "f",
     };
-    let name = truncate(&item.original_name, 18);
+    let name = truncate(&item.original_name, widths.name);
     let item_type = type_indicator.to_string();
 
     let tags_str = if item.tags.is_empty() {
         String::new()
     } else {
         let tags_joined = item.tags.join(", ");
-        truncate(&tags_joined, 18)
+        truncate(&tags_joined, widths.tags)
     };
 
+    // Single-letter markers for item state
+    // (P = pinned, L = locked, A = archived, X = partially extracted, C = copied with --keep)
+    let mut flags = String::new();
+    if item.pinned {
+        flags.push('P');
+    }
+    if item.locked {
+        flags.push('L');
+    }
+    if item.storage_location.is_some() {
+        flags.push('A');
+    }
+    if item.partial {
+        flags.push('X');
+    }
+    if item.copied {
+        flags.push('C');
+    }
+
     DisplayItem {
         display_number: number,
         item_type,
+        flags,
+        health: String::new(),
         name,
         tags: tags_str,
         pushed_at: item.pushed_at.format("%Y-%m-%d %H:%M:%S").to_string(),
     }
 }
 
-/// Create and display a table of stack items
-pub fn display_items_table(items: &[StackItem]) {
+/// Create and display a table of stack items, flagging unhealthy ones in red and coloring
+/// the rows of items with a tag in `tag_colors`
+pub fn display_items_table(
+    items: &[StackItem],
+    health: &HashMap<i64, ItemHealth>,
+    tag_colors: &HashMap<String, Color>,
+) {
+    let numbered: Vec<(usize, StackItem)> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (item_number(index, item), item.clone()))
+        .collect();
+
+    display_numbered_items_table(&numbered, health, tag_colors, Some(ColumnWidths::default()));
+}
+
+/// Display a table of stack items that keep their display numbers from the full list.
+/// Names and tags are truncated to fit the terminal, but not below `min_widths`; `None` shows
+/// them in full.
+pub fn display_numbered_items_table(
+    items: &[(usize, StackItem)],
+    health: &HashMap<i64, ItemHealth>,
+    tag_colors: &HashMap<String, Color>,
+    min_widths: Option<ColumnWidths>,
+) {
     if items.is_empty() {
         return;
     }
 
-    let display_items: Vec<DisplayItem> = items
+    let display_row = |number: usize, item: &StackItem, widths: ColumnWidths| {
+        let mut display_item = create_fitted_display_item(item, number, widths);
+        if let Some(state) = health.get(&item.id) {
+            display_item.health = state.label().to_string();
+        }
+        display_item
+    };
+    let mut display_items: Vec<DisplayItem> = items
         .iter()
-        .enumerate()
-        .map(|(index, item)| create_display_item(item, index + 1))
+        .map(|(number, item)| display_row(*number, item, ColumnWidths::UNLIMITED))
         .collect();
 
+    if let Some(min_widths) = min_widths {
+        let widths = match crate::utils::output::terminal_width() {
+            Some(terminal_width) => {
+                let (name, tags, others) = measure_columns(&display_items);
+                min_widths.fit(terminal_width.saturating_sub(others), name, tags)
+            }
+            None => min_widths,
+        };
+        display_items = items
+            .iter()
+            .map(|(number, item)| display_row(*number, item, widths))
+            .collect();
+    }
+
     let mut table = Table::new(display_items);
 
     table
         .with(Style::modern_rounded())
         .with(Padding::new(1, 1, 0, 0))
         .with(Alignment::left());
+    label_number_column(&mut table);
+
+    // Color through the table so that escape codes do not count towards column widths
+    for (row, (_, item)) in items.iter().enumerate() {
+        // The first of the item's tags (alphabetically) that has a color decides
+        if let Some(color) = item.tags.iter().find_map(|tag| tag_colors.get(tag)) {
+            table.modify(Rows::single(row + 1), color.clone());
+        }
+        if health
+            .get(&item.id)
+            .is_some_and(|state| *state != ItemHealth::Ok)
+        {
+            table.modify(Cell::new(row + 1, HEALTH_COLUMN), Color::FG_RED);
+        }
+    }
 
     println!("{}", table);
 }
 
+/// Widths the untruncated rows need for the NAME and TAGS columns, and the width of the rest of
+/// the table: the other columns, their padding and the borders
+fn measure_columns(rows: &[DisplayItem]) -> (usize, usize, usize) {
+    let width = |header: &str, cell: &dyn Fn(&DisplayItem) -> String| {
+        rows.iter()
+            .map(|row| cell(row).chars().count())
+            .chain([header.chars().count()])
+            .max()
+            .unwrap_or(0)
+    };
+
+    let name = width("NAME", &|row| row.name.clone());
+    let tags = width("TAGS", &|row| row.tags.clone());
+    let others = width("NO", &|row| row.display_number.to_string())
+        + width("T", &|row| row.item_type.clone())
+        + width("FLAGS", &|row| row.flags.clone())
+        + width("HEALTH", &|row| row.health.clone())
+        + width("PUSHED AT", &|row| row.pushed_at.clone());
+
+    // Seven columns padded by a space on each side, between eight borders
+    (name, tags, others + 7 * 2 + 8)
+}
+
 /// Create a display-ready tag for the tag list command
 #[derive(Tabled)]
 pub struct DisplayTag {
@@ -92,35 +356,352 @@ pub struct DisplayTag {
     pub count: i64,
 }
 
-/// Create and display a table of tags
-pub fn display_tags_table(tags: &[(i64, String, i64)]) {
+/// A row of `tag list --sizes`
+#[derive(Tabled)]
+pub struct DisplayTagWithSize {
+    #[tabled(rename = "ID")]
+    pub id: i64,
+
+    #[tabled(rename = "NAME")]
+    pub name: String,
+
+    #[tabled(rename = "COUNT")]
+    pub count: i64,
+
+    #[tabled(rename = "SIZE")]
+    pub size: String,
+
+    #[tabled(rename = "LAST USED")]
+    pub last_used: String,
+}
+
+/// Create and display a table of tags, optionally with their total size and last use
+pub fn display_tags_table(tags: &[TagInfo], sizes: bool) {
     if tags.is_empty() {
         return;
     }
 
-    let display_tags: Vec<DisplayTag> = tags
+    let mut table = if sizes {
+        Table::new(tags.iter().map(|tag| {
+            DisplayTagWithSize {
+                id: tag.id,
+                name: truncate(&tag.name, 18),
+                count: tag.count,
+                size: format_size(tag.total_size),
+                last_used: tag
+                    .last_used
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            }
+        }))
+    } else {
+        Table::new(tags.iter().map(|tag| DisplayTag {
+            id: tag.id,
+            name: truncate(&tag.name, 18),
+            count: tag.count,
+        }))
+    };
+
+    table
+        .with(Style::modern_rounded())
+        .with(Padding::new(1, 1, 0, 0))
+        .with(Alignment::left());
+
+    println!("{}", table);
+}
+
+/// A row of `list --versions`
+#[derive(Tabled)]
+pub struct DisplayVersion {
+    #[tabled(rename = "NO")]
+    pub display_number: usize,
+
+    #[tabled(rename = "VER")]
+    pub version: usize,
+
+    #[tabled(rename = "T")]
+    pub item_type: String,
+
+    #[tabled(rename = "SIZE")]
+    pub size: String,
+
+    #[tabled(rename = "TAGS")]
+    pub tags: String,
+
+    #[tabled(rename = "PUSHED AT")]
+    pub pushed_at: String,
+}
+
+/// Display the versions of an original path as (display number, version, item) rows
+pub fn display_versions_table(versions: &[(usize, usize, StackItem)]) {
+    if versions.is_empty() {
+        return;
+    }
+
+    let display_versions: Vec<DisplayVersion> = versions
         .iter()
-        .map(|(id, name, count)| DisplayTag {
-            id: *id,
-            name: truncate(name, 18),
-            count: *count,
+        .map(|(number, version, item)| {
+            let base = create_display_item(item, *number);
+            DisplayVersion {
+                display_number: base.display_number,
+                version: *version,
+                item_type: base.item_type,
+                size: item
+                    .size
+                    .map(format_size)
+                    .unwrap_or_else(|| "-".to_string()),
+                tags: base.tags,
+                pushed_at: base.pushed_at,
+            }
         })
         .collect();
 
-    let mut table = Table::new(display_tags);
+    let mut table = Table::new(display_versions);
 
     table
         .with(Style::modern_rounded())
         .with(Padding::new(1, 1, 0, 0))
         .with(Alignment::left());
+    label_number_column(&mut table);
 
     println!("{}", table);
 }
 
+/// A row of the largest-items report
+#[derive(Tabled)]
+pub struct DisplaySizedItem {
+    #[tabled(rename = "NO")]
+    pub display_number: usize,
+
+    #[tabled(rename = "T")]
+    pub item_type: String,
+
+    #[tabled(rename = "NAME")]
+    pub name: String,
+
+    #[tabled(rename = "SIZE")]
+    pub size: String,
+
+    #[tabled(rename = "TAGS")]
+    pub tags: String,
+
+    #[tabled(rename = "AGE")]
+    pub age: String,
+}
+
+/// Create and display a table of items with their sizes, paired with their display numbers
+pub fn display_sized_items_table(items: &[(usize, StackItem)]) {
+    if items.is_empty() {
+        return;
+    }
+
+    let display_items: Vec<DisplaySizedItem> = items
+        .iter()
+        .map(|(number, item)| {
+            let base = create_display_item(item, *number);
+            DisplaySizedItem {
+                display_number: base.display_number,
+                item_type: base.item_type,
+                name: base.name,
+                size: item
+                    .size
+                    .map(format_size)
+                    .unwrap_or_else(|| "-".to_string()),
+                tags: base.tags,
+                age: format_age(item.pushed_at),
+            }
+        })
+        .collect();
+
+    let mut table = Table::new(display_items);
+
+    table
+        .with(Style::modern_rounded())
+        .with(Padding::new(1, 1, 0, 0))
+        .with(Alignment::left());
+    label_number_column(&mut table);
+
+    println!("{}", table);
+}
+
+/// A row of `stats --activity`
+#[derive(Tabled)]
+pub struct DisplayActivity {
+    #[tabled(rename = "PERIOD")]
+    pub period: String,
+
+    #[tabled(rename = "PUSHES")]
+    pub pushes: usize,
+
+    #[tabled(rename = "POPS")]
+    pub pops: usize,
+
+    #[tabled(rename = "REMOVED")]
+    pub removes: usize,
+}
+
+/// Display operation counts per period, oldest first
+pub fn display_activity_table(periods: &[PeriodActivity]) {
+    if periods.is_empty() {
+        return;
+    }
+
+    let mut table = Table::new(periods.iter().map(|period| DisplayActivity {
+        period: period.start.format("%Y-%m-%d").to_string(),
+        pushes: period.pushes,
+        pops: period.pops,
+        removes: period.removes,
+    }));
+
+    table
+        .with(Style::modern_rounded())
+        .with(Padding::new(1, 1, 0, 0))
+        .with(Alignment::left());
+
+    println!("{}", table);
+}
+
+/// A row of the busiest tags in `stats --activity`
+#[derive(Tabled)]
+pub struct DisplayTagActivity {
+    #[tabled(rename = "TAG")]
+    pub name: String,
+
+    #[tabled(rename = "OPERATIONS")]
+    pub count: usize,
+}
+
+/// Display tags with the number of operations on their items
+pub fn display_tag_activity_table(tags: &[(String, usize)]) {
+    if tags.is_empty() {
+        return;
+    }
+
+    let mut table = Table::new(tags.iter().map(|(name, count)| DisplayTagActivity {
+        name: truncate(name, 18),
+        count: *count,
+    }));
+
+    table
+        .with(Style::modern_rounded())
+        .with(Padding::new(1, 1, 0, 0))
+        .with(Alignment::left());
+
+    println!("{}", table);
+}
+
+/// A row of `history`
+#[derive(Tabled)]
+pub struct DisplayOperation {
+    #[tabled(rename = "TIME")]
+    pub performed_at: String,
+
+    #[tabled(rename = "OPERATION")]
+    pub operation: String,
+
+    #[tabled(rename = "NAME")]
+    pub name: String,
+
+    #[tabled(rename = "TAGS")]
+    pub tags: String,
+
+    #[tabled(rename = "ON STACK")]
+    pub stay: String,
+}
+
+/// Display recorded operations, oldest first
+pub fn display_history_table(records: &[OperationRecord]) {
+    let mut table = Table::new(records.iter().map(|record| DisplayOperation {
+        performed_at: record.performed_at.format("%Y-%m-%d %H:%M").to_string(),
+        operation: record.operation.clone(),
+        name: truncate(&record.item_name, 30),
+        tags: truncate(&record.tags.join(", "), 20),
+        stay: if record.operation == "push" {
+            String::new()
+        } else {
+            format_duration(record.performed_at - record.item_pushed_at)
+        },
+    }));
+
+    table
+        .with(Style::modern_rounded())
+        .with(Padding::new(1, 1, 0, 0))
+        .with(Alignment::left());
+
+    println!("{}", table);
+}
+
+/// Display the result of `query` with the result columns as headers
+pub fn display_query_table(columns: &[String], rows: &[Vec<String>]) {
+    let mut builder = Builder::default();
+    builder.push_record(columns);
+    for row in rows {
+        builder.push_record(row.iter().map(|cell| truncate(cell, 40)));
+    }
+
+    let mut table = builder.build();
+    table
+        .with(Style::modern_rounded())
+        .with(Padding::new(1, 1, 0, 0))
+        .with(Alignment::left());
+
+    println!("{}", table);
+}
+
+/// Width of the longest bar drawn by `display_usage_bars`
+const BAR_WIDTH: usize = 30;
+
+/// Draw a bar of up to `width` cells for `value` relative to `max`
+pub fn bar(value: u64, max: u64, width: usize) -> String {
+    if max == 0 {
+        return String::new();
+    }
+
+    let cells = ((value as f64 / max as f64) * width as f64).round() as usize;
+    // Anything non-empty gets at least one cell so it stays visible
+    let cells = if value > 0 { cells.max(1) } else { 0 };
+    "█".repeat(cells.min(width))
+}
+
+/// Display storage usage as `du`-style lines with a bar per group, in the given order
+pub fn display_usage_bars(usage: &[Usage], show_counts: bool) {
+    let max = usage.iter().map(|u| u.size).max().unwrap_or(0);
+
+    for u in usage {
+        let mut counts = Vec::new();
+        if show_counts {
+            counts.push(match u.count {
+                1 => "1 item".to_string(),
+                count => format!("{} items", count),
+            });
+        }
+        // Single files go without saying
+        if u.files > 1 || (show_counts && u.files > 0) {
+            counts.push(match u.files {
+                1 => "1 file".to_string(),
+                files => format!("{} files", format_count(files)),
+            });
+        }
+        let count = if counts.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", counts.join(", "))
+        };
+
+        println!(
+            "{:>10}  {:<width$}  {}{}",
+            format_size(u.size),
+            bar(u.size, max, BAR_WIDTH),
+            u.label,
+            count,
+            width = BAR_WIDTH
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Local;
 
     fn create_test_item() -> StackItem {
         StackItem {
@@ -131,6 +712,7 @@ mod tests {
             item_type: "file".to_string(),
             pushed_at: Local::now(),
             tags: vec!["tag1".to_string(), "tag2".to_string()],
+            ..Default::default()
         }
     }
 
@@ -148,6 +730,65 @@ mod tests {
         assert_eq!(result, "abcdefg...");
     }
 
+    #[test]
+    fn test_fit_column_widths() {
+        let min = ColumnWidths::default();
+        // Everything fits
+        assert_eq!(min.fit(80, 40, 20), ColumnWidths { name: 40, tags: 20 });
+        // Tags give way to names, down to their minimum
+        assert_eq!(min.fit(60, 50, 30), ColumnWidths { name: 42, tags: 18 });
+        assert_eq!(min.fit(60, 30, 40), ColumnWidths { name: 30, tags: 30 });
+        // Short columns keep their natural width
+        assert_eq!(min.fit(30, 40, 6), ColumnWidths { name: 24, tags: 6 });
+        // Narrow terminals overflow rather than go below the minimums
+        assert_eq!(min.fit(10, 40, 30), min);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(13_314_398_618), "12.4 GiB");
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1000), "1,000");
+        assert_eq!(format_count(80_321), "80,321");
+        assert_eq!(format_count(1_234_567), "1,234,567");
+    }
+
+    #[test]
+    fn test_format_elapsed() {
+        use std::time::Duration;
+        assert_eq!(format_elapsed(Duration::from_millis(420)), "0.4s");
+        assert_eq!(format_elapsed(Duration::from_millis(12_340)), "12.3s");
+        assert_eq!(format_elapsed(Duration::from_secs(125)), "2m 5s");
+        assert_eq!(format_elapsed(Duration::from_secs(7_380)), "2h 3m");
+    }
+
+    #[test]
+    fn test_bar() {
+        assert_eq!(bar(100, 100, 10), "█".repeat(10));
+        assert_eq!(bar(50, 100, 10), "█".repeat(5));
+        assert_eq!(bar(1, 1000, 10), "█");
+        assert_eq!(bar(0, 100, 10), "");
+        assert_eq!(bar(0, 0, 10), "");
+    }
+
+    #[test]
+    fn test_format_age() {
+        let now = Local::now();
+        assert_eq!(format_age(now - chrono::Duration::seconds(30)), "30s");
+        assert_eq!(format_age(now - chrono::Duration::minutes(5)), "5m");
+        assert_eq!(format_age(now - chrono::Duration::hours(3)), "3h");
+        assert_eq!(format_age(now - chrono::Duration::days(12)), "12d");
+    }
+
     #[test]
     fn test_create_display_item() {
         // Test file item
@@ -158,6 +799,7 @@ mod tests {
         assert_eq!(display_item.item_type, "f");
         assert_eq!(display_item.name, "test_file.txt");
         assert_eq!(display_item.tags, "tag1, tag2");
+        assert_eq!(display_item.flags, "");
 
         // Create directory item
         let mut dir_item = create_test_item();
@@ -166,6 +808,27 @@ mod tests {
 
         assert_eq!(display_dir.item_type, "d");
 
+        let mut bundle_item = create_test_item();
+        bundle_item.item_type = "bundle".to_string();
+        assert_eq!(create_display_item(&bundle_item, 3).item_type, "b");
+
+        // Pinned items are flagged
+        let mut pinned_item = create_test_item();
+        pinned_item.pinned = true;
+        assert_eq!(create_display_item(&pinned_item, 1).flags, "P");
+        pinned_item.locked = true;
+        assert_eq!(create_display_item(&pinned_item, 1).flags, "PL");
+
+        // Archived items are flagged
+        let mut archived_item = create_test_item();
+        archived_item.storage_location = Some("/mnt/cold".to_string());
+        assert_eq!(create_display_item(&archived_item, 1).flags, "A");
+
+        // Copies pushed with --keep are flagged
+        let mut copied_item = create_test_item();
+        copied_item.copied = true;
+        assert_eq!(create_display_item(&copied_item, 1).flags, "C");
+
         // Test long name truncation
         let mut long_name_item = create_test_item();
         long_name_item.original_name = "this_is_a_very_long_filename.txt".to_string();
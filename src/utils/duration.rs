@@ -0,0 +1,54 @@
+use anyhow::{anyhow, Result};
+use chrono::Duration;
+
+/// Parse a compact duration like "30s", "15m", "12h", "90d" or "2w"
+pub fn parse_duration(duration_str: &str) -> Result<Duration> {
+    let duration_str = duration_str.trim();
+    let unit_start = duration_str
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| {
+            anyhow!(
+                "Missing unit in duration: {} (use s, m, h, d or w)",
+                duration_str
+            )
+        })?;
+
+    let (amount, unit) = duration_str.split_at(unit_start);
+    let amount = amount
+        .parse::<i64>()
+        .map_err(|_| anyhow!("Invalid number in duration: {}", duration_str))?;
+
+    match unit {
+        "s" => Ok(Duration::seconds(amount)),
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(anyhow!(
+            "Invalid unit in duration: {} (use s, m, h, d or w)",
+            duration_str
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_units() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::seconds(30));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::minutes(15));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::hours(12));
+        assert_eq!(parse_duration("90d").unwrap(), Duration::days(90));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::weeks(2));
+    }
+
+    #[test]
+    fn test_invalid_durations() {
+        assert!(parse_duration("90").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("10y").is_err());
+        assert!(parse_duration("-5d").is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use serde::Serialize;
+use std::io::{self, Write};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,15 +17,24 @@ pub enum FstkError {
     #[error("Tag error: {0}")]
     TagError(String),
 
-    #[error("Destination conflict: {0}")]
+    #[error("Destination already exists: {0}")]
     DestinationConflict(String),
 
+    #[error("Path does not exist: {0}")]
+    PathNotFound(String),
+
+    #[error("Item '{0}' is locked; use --force to override")]
+    ItemLocked(String),
+
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
 
     #[error("Invalid argument: {0}")]
     InvalidArgument(String),
 
+    #[error("Not enough space: {0}")]
+    InsufficientSpace(String),
+
     #[error("IO error: {0}")]
     IoError(String),
 
@@ -31,6 +42,93 @@ pub enum FstkError {
     Other(String),
 }
 
+impl FstkError {
+    /// Stable machine-readable name of the error kind
+    pub fn code(&self) -> &'static str {
+        match self {
+            FstkError::DatabaseError(_) => "database_error",
+            FstkError::FileSystemError(_) => "file_system_error",
+            FstkError::ItemNotFound(_) => "item_not_found",
+            FstkError::TagError(_) => "tag_error",
+            FstkError::DestinationConflict(_) => "destination_conflict",
+            FstkError::PathNotFound(_) => "path_not_found",
+            FstkError::ItemLocked(_) => "item_locked",
+            FstkError::PermissionDenied(_) => "permission_denied",
+            FstkError::InvalidArgument(_) => "invalid_argument",
+            FstkError::InsufficientSpace(_) => "insufficient_space",
+            FstkError::IoError(_) => "io_error",
+            FstkError::Other(_) => "other",
+        }
+    }
+
+    /// The item the error refers to, if any
+    pub fn item(&self) -> Option<&str> {
+        match self {
+            FstkError::ItemNotFound(item) | FstkError::ItemLocked(item) => Some(item),
+            _ => None,
+        }
+    }
+
+    /// The filesystem path the error refers to, if any
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            FstkError::DestinationConflict(path) | FstkError::PathNotFound(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// An error as reported on stderr in JSON mode
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub code: &'static str,
+    pub message: String,
+    pub item: Option<String>,
+    pub path: Option<String>,
+}
+
+impl ErrorReport {
+    /// Build a report from the first `FstkError` (or known library error) in the error chain.
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        let mut report = ErrorReport {
+            code: "other",
+            message: error.to_string(),
+            item: None,
+            path: None,
+        };
+
+        for cause in error.chain() {
+            if let Some(fstk_error) = cause.downcast_ref::<FstkError>() {
+                report.code = fstk_error.code();
+                report.item = fstk_error.item().map(str::to_string);
+                report.path = fstk_error.path().map(str::to_string);
+                break;
+            }
+            if cause.is::<io::Error>() {
+                report.code = "io_error";
+                break;
+            }
+            if cause.is::<rusqlite::Error>() {
+                report.code = "database_error";
+                break;
+            }
+        }
+
+        report
+    }
+}
+
+/// Print an error as a single line of JSON on stderr.
+pub fn print_json_error(error: &anyhow::Error) {
+    let report = ErrorReport::from_error(error);
+    let mut stderr = io::stderr();
+    // Fall back to the plain message if the report cannot be written as JSON
+    if serde_json::to_writer(&mut stderr, &report).is_err() {
+        eprint!("{}", report.message);
+    }
+    let _ = writeln!(stderr);
+}
+
 impl From<std::io::Error> for FstkError {
     fn from(error: std::io::Error) -> Self {
         FstkError::IoError(error.to_string())
@@ -89,6 +187,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_error_report() {
+        let error: anyhow::Error = FstkError::ItemNotFound("3".to_string()).into();
+        let report = ErrorReport::from_error(&error);
+        assert_eq!(report.code, "item_not_found");
+        assert_eq!(report.message, "Item not found: 3");
+        assert_eq!(report.item.as_deref(), Some("3"));
+        assert_eq!(report.path, None);
+
+        // Context keeps the message but the code comes from the underlying error
+        let error = anyhow::Error::from(FstkError::DestinationConflict("/tmp/a".to_string()))
+            .context("Choose another destination");
+        let report = ErrorReport::from_error(&error);
+        assert_eq!(report.code, "destination_conflict");
+        assert_eq!(report.message, "Choose another destination");
+        assert_eq!(report.path.as_deref(), Some("/tmp/a"));
+
+        let error: anyhow::Error = IoError::new(ErrorKind::NotFound, "gone").into();
+        assert_eq!(ErrorReport::from_error(&error).code, "io_error");
+
+        let report = ErrorReport::from_error(&anyhow!("plain"));
+        assert_eq!(report.code, "other");
+        assert_eq!(report.message, "plain");
+    }
+
     #[test]
     fn test_error_display() {
         let error = FstkError::ItemNotFound("test item".to_string());
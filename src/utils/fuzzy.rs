@@ -0,0 +1,98 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Score how well `query` matches `candidate`, case-insensitively and regardless of Unicode
+/// normalization.
+///
+/// Every character of the query must appear in the candidate in order. Consecutive
+/// matches, matches at word boundaries and contiguous substrings score higher, and shorter
+/// candidates win ties. Returns `None` if the query does not match at all.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query
+        .nfc()
+        .collect::<String>()
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if query.is_empty() {
+        return None;
+    }
+
+    let candidate: Vec<char> = candidate
+        .nfc()
+        .collect::<String>()
+        .to_lowercase()
+        .chars()
+        .collect();
+    let mut score = 0;
+    let mut matched = 0;
+    let mut previous: Option<usize> = None;
+
+    for (index, &c) in candidate.iter().enumerate() {
+        if matched == query.len() {
+            break;
+        }
+        if c != query[matched] {
+            continue;
+        }
+
+        score += 1;
+        if index > 0 && previous == Some(index - 1) {
+            score += 5;
+        }
+        if index == 0 || !candidate[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+
+        previous = Some(index);
+        matched += 1;
+    }
+
+    if matched < query.len() {
+        return None;
+    }
+
+    let query_str: String = query.iter().collect();
+    let candidate_str: String = candidate.iter().collect();
+    if candidate_str.contains(&query_str) {
+        score += 10;
+    }
+
+    // Prefer candidates with fewer unmatched characters
+    score -= ((candidate.len() - query.len()) / 4) as i64;
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_score("quarterly", "quarterly_report_final.xlsx").is_some());
+        assert!(fuzzy_score("QRF", "quarterly_report_final.xlsx").is_some());
+        assert!(fuzzy_score("xyz", "quarterly_report_final.xlsx").is_none());
+        assert!(fuzzy_score("", "anything").is_none());
+    }
+
+    #[test]
+    fn test_fuzzy_match_ignores_unicode_normalization() {
+        // "résumé" typed decomposed (as macOS may hand it out) matches the precomposed name
+        assert!(fuzzy_score("re\u{301}sume\u{301}", "r\u{e9}sum\u{e9}.pdf").is_some());
+        assert!(fuzzy_score("r\u{e9}sum\u{e9}", "re\u{301}sume\u{301}.pdf").is_some());
+    }
+
+    #[test]
+    fn test_fuzzy_ranking() {
+        // A contiguous match beats a scattered one
+        let contiguous = fuzzy_score("report", "report.pdf").unwrap();
+        let scattered = fuzzy_score("report", "r_e_p_o_r_t.txt").unwrap();
+        assert!(contiguous > scattered);
+
+        // Shorter names win when the match is otherwise equal
+        let short = fuzzy_score("notes", "notes.md").unwrap();
+        let long = fuzzy_score("notes", "notes-from-the-long-meeting.md").unwrap();
+        assert!(short > long);
+    }
+}
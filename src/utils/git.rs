@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::db::find_git_root;
+
+/// The git repository a path belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitContext {
+    /// Name of the repository's top-level directory
+    pub repo: String,
+    /// Checked-out branch, or `None` for a detached HEAD
+    pub branch: Option<String>,
+}
+
+impl GitContext {
+    /// Tags describing this context: the repository name and `<repo>/<branch>`
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags = vec![self.repo.clone()];
+        if let Some(branch) = &self.branch {
+            tags.push(format!("{}/{}", self.repo, branch));
+        }
+        tags
+    }
+}
+
+/// Find the git repository containing `start` by reading `.git/HEAD` directly,
+/// without running git.
+pub fn discover(start: &Path) -> Option<GitContext> {
+    let root = find_git_root(start)?;
+    let repo = root.file_name()?.to_string_lossy().to_string();
+
+    let git_dir = resolve_git_dir(&root.join(".git"))?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let branch = head
+        .trim()
+        .strip_prefix("ref: refs/heads/")
+        .map(|branch| branch.to_string());
+
+    Some(GitContext { repo, branch })
+}
+
+/// Resolve the git directory; in worktrees and submodules `.git` is a file pointing to it.
+fn resolve_git_dir(dot_git: &Path) -> Option<PathBuf> {
+    if dot_git.is_dir() {
+        return Some(dot_git.to_path_buf());
+    }
+
+    let content = fs::read_to_string(dot_git).ok()?;
+    let target = PathBuf::from(content.trim().strip_prefix("gitdir:")?.trim());
+    if target.is_absolute() {
+        Some(target)
+    } else {
+        Some(dot_git.parent()?.join(target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_discover_branch() {
+        let temp_dir = tempdir().unwrap();
+        let repo = temp_dir.path().join("myrepo");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(repo.join("src")).unwrap();
+        std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/feature-x\n").unwrap();
+
+        let context = discover(&repo.join("src")).unwrap();
+        assert_eq!(context.repo, "myrepo");
+        assert_eq!(context.branch.as_deref(), Some("feature-x"));
+        assert_eq!(context.tags(), vec!["myrepo", "myrepo/feature-x"]);
+    }
+
+    #[test]
+    fn test_discover_worktree_and_detached_head() {
+        let temp_dir = tempdir().unwrap();
+        let git_dir = temp_dir.path().join("main/.git/worktrees/wt");
+        std::fs::create_dir_all(&git_dir).unwrap();
+        std::fs::write(git_dir.join("HEAD"), "0123456789abcdef\n").unwrap();
+
+        let worktree = temp_dir.path().join("wt");
+        std::fs::create_dir_all(&worktree).unwrap();
+        std::fs::write(
+            worktree.join(".git"),
+            format!("gitdir: {}\n", git_dir.display()),
+        )
+        .unwrap();
+
+        let context = discover(&worktree).unwrap();
+        assert_eq!(context.repo, "wt");
+        assert_eq!(context.branch, None);
+        assert_eq!(context.tags(), vec!["wt"]);
+    }
+}
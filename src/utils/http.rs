@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// How long either side waits for the other before giving up
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Method, target, credentials and body length of an HTTP request; other headers are not used
+#[derive(Debug, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Token of an `Authorization: Bearer <token>` header
+    pub token: Option<String>,
+    /// Length of the body following the headers
+    pub content_length: u64,
+}
+
+/// The headers of a message that are used
+#[derive(Debug, Default)]
+struct Headers {
+    token: Option<String>,
+    content_length: u64,
+}
+
+/// Read the request line and headers of an HTTP/1.x request, leaving the body in `reader`
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<HttpRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(anyhow!("Malformed request line: {}", line.trim()));
+    };
+    let path = target.split('?').next().unwrap_or_default();
+
+    let headers = read_headers(reader)?;
+
+    Ok(HttpRequest {
+        method: method.to_string(),
+        path: path.to_string(),
+        token: headers.token,
+        content_length: headers.content_length,
+    })
+}
+
+/// Write a response status line and headers; the connection is closed after the body,
+/// so `length` may be left out for streamed bodies
+pub fn write_head<W: Write>(
+    writer: &mut W,
+    status: u16,
+    content_type: &str,
+    length: Option<u64>,
+) -> Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nConnection: close\r\n",
+        status,
+        reason(status),
+        content_type
+    )?;
+    if let Some(length) = length {
+        write!(writer, "Content-Length: {}\r\n", length)?;
+    }
+    write!(writer, "\r\n")?;
+    Ok(())
+}
+
+/// Write a complete response with a JSON body
+pub fn write_json<W: Write>(writer: &mut W, status: u16, body: &serde_json::Value) -> Result<()> {
+    let body = serde_json::to_vec(body)?;
+    write_head(writer, status, "application/json", Some(body.len() as u64))?;
+    writer.write_all(&body)?;
+    Ok(())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Turn `http://host:port[/]` into the `host:port` to connect to
+pub fn parse_url(url: &str) -> Result<String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("Only http:// URLs are supported: {}", url))?;
+    let address = rest.trim_end_matches('/');
+    if address.is_empty() || address.contains('/') {
+        return Err(anyhow!("Expected a URL like http://host:7878, got {}", url));
+    }
+
+    Ok(if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:80", address)
+    })
+}
+
+/// Send a request without a body and return the response status and a reader for the body
+pub fn send(
+    address: &str,
+    token: Option<&str>,
+    method: &str,
+    path: &str,
+) -> Result<(u16, BufReader<TcpStream>)> {
+    send_with_body(address, token, method, path, 0, |_| Ok(()))
+}
+
+/// Send a request whose body of `length` bytes is written by `write_body`, authenticated with
+/// `token` if given, and return the response status and a reader for the response body
+pub fn send_with_body<F>(
+    address: &str,
+    token: Option<&str>,
+    method: &str,
+    path: &str,
+    length: u64,
+    write_body: F,
+) -> Result<(u16, BufReader<TcpStream>)>
+where
+    F: FnOnce(&mut TcpStream) -> Result<()>,
+{
+    let mut stream =
+        TcpStream::connect(address).with_context(|| format!("Cannot connect to {}", address))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method, path, address, length
+    )?;
+    if let Some(token) = token {
+        write!(stream, "Authorization: Bearer {}\r\n", token)?;
+    }
+    write!(stream, "\r\n")?;
+    write_body(&mut stream)?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let status = read_status(&mut reader)?;
+    Ok((status, reader))
+}
+
+/// Read the status line and skip the headers of a response
+pub fn read_status<R: BufRead>(reader: &mut R) -> Result<u16> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow!("Malformed response: {}", line.trim()))?;
+
+    read_headers(reader)?;
+    Ok(status)
+}
+
+/// Read the message of an error response (`{"error": "..."}`), falling back to the raw body
+pub fn read_error<R: Read>(status: u16, mut body: R) -> anyhow::Error {
+    let mut text = String::new();
+    let _ = body.read_to_string(&mut text);
+    let message = serde_json::from_str::<serde_json::Value>(&text)
+        .ok()
+        .and_then(|value| value["error"].as_str().map(str::to_string))
+        .unwrap_or(text);
+
+    anyhow!("Server responded with {}: {}", status, message.trim())
+}
+
+/// Read headers up to the blank line ending them (a missing Content-Length counts as 0)
+fn read_headers<R: BufRead>(reader: &mut R) -> Result<Headers> {
+    let mut headers = Headers::default();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            return Ok(headers);
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                headers.content_length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid Content-Length: {}", value.trim()))?;
+            } else if name.eq_ignore_ascii_case("authorization") {
+                headers.token = value
+                    .trim()
+                    .strip_prefix("Bearer ")
+                    .map(|token| token.trim().to_string());
+            }
+        }
+    }
+}
+
+/// Whether `given` is `expected`, taking the same time wherever they differ
+pub fn token_matches(given: Option<&str>, expected: &str) -> bool {
+    let Some(given) = given else {
+        return false;
+    };
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// A writer that only counts the bytes written to it, to learn the length of a streamed body
+#[derive(Debug, Default)]
+pub struct ByteCounter(pub u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_response() -> Result<()> {
+        let mut request = "GET /items/3/content?x=1 HTTP/1.1\r\nHost: a\r\n\r\n".as_bytes();
+        assert_eq!(
+            read_request(&mut request)?,
+            HttpRequest {
+                method: "GET".to_string(),
+                path: "/items/3/content".to_string(),
+                token: None,
+                content_length: 0,
+            }
+        );
+
+        let mut request =
+            "POST /items HTTP/1.1\r\ncontent-length: 5\r\nAuthorization: Bearer s3cret\r\n\r\nhello"
+                .as_bytes();
+        let parsed = read_request(&mut request)?;
+        assert_eq!(parsed.content_length, 5);
+        assert!(token_matches(parsed.token.as_deref(), "s3cret"));
+        assert!(!token_matches(parsed.token.as_deref(), "s3cre"));
+        assert!(!token_matches(None, "s3cret"));
+        assert_eq!(request, b"hello");
+
+        let mut response = Vec::new();
+        write_json(
+            &mut response,
+            404,
+            &serde_json::json!({"error": "No item 3"}),
+        )?;
+        let mut reader = response.as_slice();
+        let status = read_status(&mut reader)?;
+        assert_eq!(status, 404);
+        assert_eq!(
+            read_error(status, reader).to_string(),
+            "Server responded with 404: No item 3"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(parse_url("http://desktop:7878/").unwrap(), "desktop:7878");
+        assert_eq!(parse_url("http://10.0.0.2").unwrap(), "10.0.0.2:80");
+        assert!(parse_url("https://desktop:7878").is_err());
+        assert!(parse_url("http://desktop:7878/fstk").is_err());
+    }
+}
@@ -0,0 +1,397 @@
+use anyhow::Result;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+/// Number of leading bytes read to recognize a format
+const HEAD_LEN: usize = 512;
+
+/// Boxes (MP4) and segments (JPEG) walked at most while looking for the header with the
+/// dimensions or duration, so that a corrupt file cannot keep peek busy
+const MAX_BLOCKS: usize = 1024;
+
+/// What the header of a stored file tells about its content
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaInfo {
+    pub mime: &'static str,
+    pub size: u64,
+    /// Width and height of an image or video
+    pub dimensions: Option<(u32, u32)>,
+    /// Playing time of audio or video
+    pub duration: Option<Duration>,
+}
+
+/// Recognize a file from its magic bytes, reading image dimensions and audio or video duration
+/// from the headers of common formats. Unknown content is `text/plain` when it looks like
+/// UTF-8 text and `application/octet-stream` otherwise.
+pub fn probe(path: &Path) -> Result<MediaInfo> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    probe_reader(&mut file, size)
+}
+
+fn probe_reader<R: Read + Seek>(reader: &mut R, size: u64) -> Result<MediaInfo> {
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    reader
+        .by_ref()
+        .take(HEAD_LEN as u64)
+        .read_to_end(&mut head)?;
+
+    let mut info = MediaInfo {
+        mime: "application/octet-stream",
+        size,
+        dimensions: None,
+        duration: None,
+    };
+
+    let h = head.as_slice();
+    if h.starts_with(b"\x89PNG\r\n\x1a\n") {
+        info.mime = "image/png";
+        info.dimensions = Some((be32(h, 16).unwrap_or(0), be32(h, 20).unwrap_or(0)));
+    } else if h.starts_with(b"GIF87a") || h.starts_with(b"GIF89a") {
+        info.mime = "image/gif";
+        info.dimensions = Some((
+            u32::from(le16(h, 6).unwrap_or(0)),
+            u32::from(le16(h, 8).unwrap_or(0)),
+        ));
+    } else if h.starts_with(b"\xff\xd8\xff") {
+        info.mime = "image/jpeg";
+        info.dimensions = jpeg_dimensions(reader)?;
+    } else if h.starts_with(b"BM") && h.len() >= 26 {
+        info.mime = "image/bmp";
+        info.dimensions = Some((
+            le32(h, 18).unwrap_or(0),
+            (le32(h, 22).unwrap_or(0) as i32).unsigned_abs(),
+        ));
+    } else if h.starts_with(b"RIFF") && h.get(8..12) == Some(b"WEBP") {
+        info.mime = "image/webp";
+        info.dimensions = webp_dimensions(h);
+    } else if h.starts_with(b"RIFF") && h.get(8..12) == Some(b"WAVE") {
+        info.mime = "audio/wav";
+        info.duration = wav_duration(reader)?;
+    } else if h.starts_with(b"RIFF") && h.get(8..12) == Some(b"AVI ") {
+        info.mime = "video/x-msvideo";
+        // The main AVI header (avih) opens the hdrl list
+        if h.get(24..28) == Some(b"avih") {
+            let micros_per_frame = le32(h, 32).unwrap_or(0);
+            let frames = le32(h, 48).unwrap_or(0);
+            info.duration = Some(Duration::from_micros(
+                u64::from(micros_per_frame) * u64::from(frames),
+            ));
+            info.dimensions = Some((le32(h, 64).unwrap_or(0), le32(h, 68).unwrap_or(0)));
+        }
+    } else if h.starts_with(b"fLaC") {
+        info.mime = "audio/flac";
+        info.duration = flac_duration(h);
+    } else if h.get(4..8) == Some(b"ftyp") {
+        info.mime = match h.get(8..12) {
+            Some(b"qt  ") => "video/quicktime",
+            Some(b"M4A ") | Some(b"M4B ") => "audio/mp4",
+            Some(b"heic") | Some(b"heix") | Some(b"mif1") => "image/heic",
+            Some(b"avif") => "image/avif",
+            _ => "video/mp4",
+        };
+        if !info.mime.starts_with("image/") {
+            info.duration = mp4_duration(reader, size)?;
+        }
+    } else if h.starts_with(b"ID3") || (h.len() >= 2 && h[0] == 0xff && h[1] & 0xe0 == 0xe0) {
+        info.mime = "audio/mpeg";
+    } else if h.starts_with(b"OggS") {
+        info.mime = "audio/ogg";
+    } else if h.starts_with(b"\x1a\x45\xdf\xa3") {
+        info.mime = "video/x-matroska";
+    } else if h.starts_with(b"II*\0") || h.starts_with(b"MM\0*") {
+        info.mime = "image/tiff";
+    } else if h.starts_with(b"\0\0\x01\0") {
+        info.mime = "image/x-icon";
+    } else if h.starts_with(b"%PDF-") {
+        info.mime = "application/pdf";
+    } else if h.starts_with(b"PK\x03\x04") {
+        info.mime = "application/zip";
+    } else if h.starts_with(b"\x1f\x8b") {
+        info.mime = "application/gzip";
+    } else if h.starts_with(b"\xfd7zXZ\0") {
+        info.mime = "application/x-xz";
+    } else if h.starts_with(b"(\xb5\x2f\xfd") {
+        info.mime = "application/zstd";
+    } else if h.starts_with(b"7z\xbc\xaf\x27\x1c") {
+        info.mime = "application/x-7z-compressed";
+    } else if h.get(257..262) == Some(b"ustar") {
+        info.mime = "application/x-tar";
+    } else if h.starts_with(b"\x7fELF") {
+        info.mime = "application/x-executable";
+    } else if h.starts_with(b"SQLite format 3\0") {
+        info.mime = "application/vnd.sqlite3";
+    } else if looks_like_text(h) {
+        info.mime = "text/plain";
+    }
+
+    Ok(info)
+}
+
+/// Whether the content is free of NUL bytes and valid UTF-8 (except for a character cut off
+/// at the end of the head)
+fn looks_like_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// Read the frame size from the first start-of-frame segment
+fn jpeg_dimensions<R: Read + Seek>(reader: &mut R) -> Result<Option<(u32, u32)>> {
+    let mut offset = 2;
+    for _ in 0..MAX_BLOCKS {
+        let mut marker = [0u8; 4];
+        reader.seek(SeekFrom::Start(offset))?;
+        if reader.read_exact(&mut marker).is_err() || marker[0] != 0xff {
+            return Ok(None);
+        }
+
+        let length = u64::from(u16::from_be_bytes([marker[2], marker[3]]));
+        // SOF0-SOF15, except DHT (C4), JPG (C8) and DAC (CC)
+        if (0xc0..=0xcf).contains(&marker[1]) && ![0xc4, 0xc8, 0xcc].contains(&marker[1]) {
+            let mut frame = [0u8; 5];
+            reader.read_exact(&mut frame)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]);
+            let width = u16::from_be_bytes([frame[3], frame[4]]);
+            return Ok(Some((u32::from(width), u32::from(height))));
+        }
+
+        offset += 2 + length;
+    }
+    Ok(None)
+}
+
+fn webp_dimensions(h: &[u8]) -> Option<(u32, u32)> {
+    match h.get(12..16)? {
+        b"VP8 " => Some((
+            u32::from(le16(h, 26)? & 0x3fff),
+            u32::from(le16(h, 28)? & 0x3fff),
+        )),
+        b"VP8L" => {
+            let bits = le32(h, 21)?;
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        b"VP8X" => Some((le24(h, 24)? + 1, le24(h, 27)? + 1)),
+        _ => None,
+    }
+}
+
+/// Size of the data chunk divided by the byte rate from the fmt chunk
+fn wav_duration<R: Read + Seek>(reader: &mut R) -> Result<Option<Duration>> {
+    let mut offset = 12;
+    let mut byte_rate = None;
+    for _ in 0..MAX_BLOCKS {
+        let mut chunk = [0u8; 8];
+        reader.seek(SeekFrom::Start(offset))?;
+        if reader.read_exact(&mut chunk).is_err() {
+            break;
+        }
+        let length = u64::from(u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]));
+
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut format = [0u8; 12];
+                reader.read_exact(&mut format)?;
+                byte_rate = le32(&format, 8);
+            }
+            b"data" => {
+                return Ok(byte_rate
+                    .filter(|rate| *rate > 0)
+                    .map(|rate| Duration::from_secs_f64(length as f64 / f64::from(rate))));
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length
+        offset += 8 + length + (length & 1);
+    }
+    Ok(None)
+}
+
+/// Total samples divided by the sample rate from the STREAMINFO block
+fn flac_duration(h: &[u8]) -> Option<Duration> {
+    let info = h.get(8..26)?;
+    let sample_rate =
+        (u32::from(info[10]) << 12) | (u32::from(info[11]) << 4) | (u32::from(info[12]) >> 4);
+    let samples = (u64::from(info[13] & 0x0f) << 32)
+        | u64::from(u32::from_be_bytes([info[14], info[15], info[16], info[17]]));
+    (sample_rate > 0 && samples > 0)
+        .then(|| Duration::from_secs_f64(samples as f64 / f64::from(sample_rate)))
+}
+
+/// Duration and time scale from the movie header (`moov/mvhd`), which may follow the media
+/// data at the end of the file
+fn mp4_duration<R: Read + Seek>(reader: &mut R, size: u64) -> Result<Option<Duration>> {
+    let Some((moov_start, moov_end)) = find_box(reader, 0, size, b"moov")? else {
+        return Ok(None);
+    };
+    let Some((mvhd_start, _)) = find_box(reader, moov_start, moov_end, b"mvhd")? else {
+        return Ok(None);
+    };
+
+    let mut header = Vec::with_capacity(32);
+    reader.seek(SeekFrom::Start(mvhd_start))?;
+    reader.by_ref().take(32).read_to_end(&mut header)?;
+    if header.is_empty() {
+        return Ok(None);
+    }
+    let (timescale, duration) = if header[0] == 1 {
+        (be32(&header, 20), be64(&header, 24))
+    } else {
+        (be32(&header, 12), be32(&header, 16).map(u64::from))
+    };
+
+    Ok(match (timescale, duration) {
+        (Some(timescale), Some(duration)) if timescale > 0 => Some(Duration::from_secs_f64(
+            duration as f64 / f64::from(timescale),
+        )),
+        _ => None,
+    })
+}
+
+/// Find a box among the boxes between `start` and `end`, returning the range of its content
+fn find_box<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+    name: &[u8; 4],
+) -> Result<Option<(u64, u64)>> {
+    let mut offset = start;
+    for _ in 0..MAX_BLOCKS {
+        if offset + 8 > end {
+            break;
+        }
+        let mut header = [0u8; 8];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut header)?;
+
+        let mut length = u64::from(u32::from_be_bytes([
+            header[0], header[1], header[2], header[3],
+        ]));
+        let mut content = offset + 8;
+        if length == 1 {
+            // 64-bit size after the type
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large)?;
+            length = u64::from_be_bytes(large);
+            content += 8;
+        } else if length == 0 {
+            // The box extends to the end
+            length = end - offset;
+        }
+        if length < content - offset {
+            break;
+        }
+
+        if &header[4..8] == name {
+            return Ok(Some((content, (offset + length).min(end))));
+        }
+        offset += length;
+    }
+    Ok(None)
+}
+
+/// Format a playing time as "m:ss" or "h:mm:ss"
+pub fn format_playing_time(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+fn le16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le24(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 3)?;
+    Some(u32::from(b[0]) | (u32::from(b[1]) << 8) | (u32::from(b[2]) << 16))
+}
+
+fn le32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn probe_bytes(bytes: &[u8]) -> MediaInfo {
+        probe_reader(&mut Cursor::new(bytes), bytes.len() as u64).unwrap()
+    }
+
+    #[test]
+    fn test_probe_images() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        let info = probe_bytes(&png);
+        assert_eq!(info.mime, "image/png");
+        assert_eq!(info.dimensions, Some((640, 480)));
+
+        let gif = b"GIF89a\x20\x03\x58\x02";
+        assert_eq!(probe_bytes(gif).dimensions, Some((800, 600)));
+
+        // SOI, an APP0 segment, then SOF0 with 8-bit samples, 1080 rows of 1920 pixels
+        let mut jpeg = b"\xff\xd8\xff\xe0\0\x06JFIF".to_vec();
+        jpeg.extend_from_slice(b"\xff\xc0\0\x11\x08\x04\x38\x07\x80");
+        let info = probe_bytes(&jpeg);
+        assert_eq!(info.mime, "image/jpeg");
+        assert_eq!(info.dimensions, Some((1920, 1080)));
+    }
+
+    #[test]
+    fn test_probe_durations() {
+        // 2 seconds of 8 kHz 16-bit mono audio
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0".to_vec();
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(b"\x02\0\x10\0data");
+        wav.extend_from_slice(&32000u32.to_le_bytes());
+        let info = probe_bytes(&wav);
+        assert_eq!(info.mime, "audio/wav");
+        assert_eq!(info.duration, Some(Duration::from_secs(2)));
+
+        // ftyp, then a moov box holding an mvhd with time scale 1000 and duration 90500
+        let mut mp4 = b"\0\0\0\x10ftypisom\0\0\0\0".to_vec();
+        mp4.extend_from_slice(b"\0\0\0\x24moov\0\0\0\x1cmvhd\0\0\0\0\0\0\0\0\0\0\0\0");
+        mp4.extend_from_slice(&1000u32.to_be_bytes());
+        mp4.extend_from_slice(&90500u32.to_be_bytes());
+        let info = probe_bytes(&mp4);
+        assert_eq!(info.mime, "video/mp4");
+        assert_eq!(info.duration, Some(Duration::from_millis(90500)));
+        assert_eq!(format_playing_time(info.duration.unwrap()), "1:30");
+    }
+
+    #[test]
+    fn test_probe_text_and_unknown() {
+        assert_eq!(probe_bytes("naïve text\n".as_bytes()).mime, "text/plain");
+        assert_eq!(
+            probe_bytes(b"\x01\x02\0\x03").mime,
+            "application/octet-stream"
+        );
+        assert_eq!(probe_bytes(b"%PDF-1.7").mime, "application/pdf");
+    }
+}
@@ -1,3 +1,12 @@
 pub mod display;
+pub mod duration;
 pub mod error;
+pub mod fuzzy;
+pub mod git;
+pub mod http;
+pub mod media;
 pub mod numbers;
+pub mod nuon;
+pub mod output;
+pub mod ssh;
+pub mod template;
@@ -52,10 +52,70 @@ pub fn parse_number_range(range_str: &str) -> Result<Vec<usize>> {
     Ok(result)
 }
 
+/// Check whether an argument looks like a number range expression rather than a name
+pub fn is_number_range(arg: &str) -> bool {
+    arg.chars().any(|c| c.is_ascii_digit())
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == ',' || c == '-' || c.is_whitespace())
+}
+
+/// Parse a size like "500MiB", "2G" or "1024" (bytes); units are binary (K = 1024 bytes)
+pub fn parse_size(size_str: &str) -> Result<u64> {
+    let size_str = size_str.trim();
+    let unit_start = size_str
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size_str.len());
+
+    let (amount, unit) = size_str.split_at(unit_start);
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_| anyhow!("Invalid number in size: {}", size_str))?;
+
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        "t" | "tb" | "tib" => 40,
+        _ => {
+            return Err(anyhow!(
+                "Invalid unit in size: {} (use B, KiB, MiB, GiB or TiB)",
+                size_str
+            ))
+        }
+    };
+
+    amount
+        .checked_mul(1 << shift)
+        .ok_or_else(|| anyhow!("Size is too large: {}", size_str))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("10B").unwrap(), 10);
+        assert_eq!(parse_size("500MiB").unwrap(), 500 * 1024 * 1024);
+        assert_eq!(parse_size("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("3 kb").unwrap(), 3 * 1024);
+        assert!(parse_size("GiB").is_err());
+        assert!(parse_size("5 parsecs").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn test_is_number_range() {
+        assert!(is_number_range("5"));
+        assert!(is_number_range("1,3-5"));
+        assert!(!is_number_range("report"));
+        assert!(!is_number_range("2024-report"));
+        assert!(!is_number_range("-"));
+    }
+
     #[test]
     fn test_single_number() {
         assert_eq!(parse_number_range("5").unwrap(), vec![5]);
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Serialize a value as NUON (Nushell Object Notation), so that `from nuon` yields native data.
+/// A list of records with the same columns is written in table form: `[[a, b]; [1, 2], [3, 4]]`.
+pub fn to_nuon<T: Serialize>(value: &T) -> Result<String> {
+    let mut out = String::new();
+    write_value(&serde_json::to_value(value)?, &mut out);
+    Ok(out)
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(flag) => out.push_str(if *flag { "true" } else { "false" }),
+        Value::Number(number) => out.push_str(&number.to_string()),
+        Value::String(text) => out.push_str(&Value::String(text.clone()).to_string()),
+        Value::Array(values) => match table_columns(values) {
+            Some(columns) => write_table(&columns, values, out),
+            None => {
+                out.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        out.push_str(", ");
+                    }
+                    write_value(value, out);
+                }
+                out.push(']');
+            }
+        },
+        Value::Object(fields) => {
+            out.push('{');
+            for (index, (key, value)) in fields.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_key(key, out);
+                out.push_str(": ");
+                write_value(value, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Columns shared by every record of a non-empty list, if it is one
+fn table_columns(values: &[Value]) -> Option<Vec<&String>> {
+    let Some(Value::Object(first)) = values.first() else {
+        return None;
+    };
+    let columns: Vec<&String> = first.keys().collect();
+
+    values
+        .iter()
+        .all(|value| match value {
+            Value::Object(fields) => fields.keys().eq(columns.iter().copied()),
+            _ => false,
+        })
+        .then_some(columns)
+}
+
+fn write_table(columns: &[&String], rows: &[Value], out: &mut String) {
+    out.push_str("[[");
+    for (index, column) in columns.iter().enumerate() {
+        if index > 0 {
+            out.push_str(", ");
+        }
+        write_key(column, out);
+    }
+    out.push_str("];");
+
+    for (index, row) in rows.iter().enumerate() {
+        out.push_str(if index > 0 { ", [" } else { " [" });
+        for (position, column) in columns.iter().enumerate() {
+            if position > 0 {
+                out.push_str(", ");
+            }
+            write_value(&row[column.as_str()], out);
+        }
+        out.push(']');
+    }
+    out.push(']');
+}
+
+/// Write a record key or column name, quoting it unless it is a plain identifier
+fn write_key(key: &str, out: &mut String) {
+    let plain = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if plain {
+        out.push_str(key);
+    } else {
+        out.push_str(&Value::String(key.to_string()).to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_table() {
+        let value = json!([
+            {"number": 1, "name": "notes \"v2\".md", "tags": ["work"], "size": null},
+            {"number": 2, "name": "photos", "tags": [], "size": 1024},
+        ]);
+
+        assert_eq!(
+            to_nuon(&value).unwrap(),
+            r#"[[number, name, tags, size]; [1, "notes \"v2\".md", ["work"], null], [2, "photos", [], 1024]]"#
+        );
+    }
+
+    #[test]
+    fn test_records_and_lists() {
+        assert_eq!(to_nuon(&json!([])).unwrap(), "[]");
+        assert_eq!(
+            to_nuon(&json!([{"a": 1}, {"b": true}])).unwrap(),
+            "[{a: 1}, {b: true}]"
+        );
+        assert_eq!(
+            to_nuon(&json!({"last used": "2024-01-01"})).unwrap(),
+            r#"{"last used": "2024-01-01"}"#
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether stdout is reserved for machine-readable output
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Whether errors are reported as JSON (`--format json`)
+static JSON_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Switch to JSON mode: stdout is reserved and errors are reported as JSON on stderr.
+pub fn enable_json_mode() {
+    JSON_MODE.store(true, Ordering::SeqCst);
+    reserve_stdout();
+}
+
+/// Check whether errors are reported as JSON.
+pub fn is_json_mode() -> bool {
+    JSON_MODE.load(Ordering::SeqCst)
+}
+
+/// Reserve stdout for machine-readable output, sending status messages and prompts to stderr.
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::SeqCst);
+}
+
+/// Check whether status messages currently go to stderr.
+pub fn is_stdout_reserved() -> bool {
+    STDOUT_RESERVED.load(Ordering::SeqCst)
+}
+
+/// Print a status message line, to stderr if stdout is reserved.
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::utils::output::is_stdout_reserved() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Width of the terminal in columns: `COLUMNS` if set, otherwise the size of the terminal
+/// stdout is connected to; `None` when output goes to a pipe or file
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|columns| *columns > 0)
+    {
+        return Some(columns);
+    }
+
+    #[cfg(unix)]
+    {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+        {
+            return Some(usize::from(size.ws_col));
+        }
+    }
+    None
+}
+
+/// Show a prompt and read the user's answer, trimmed and lowercased.
+pub fn prompt(message: &str) -> Result<String> {
+    Ok(prompt_text(message)?.to_lowercase())
+}
+
+/// Show a prompt and read the user's answer trimmed, keeping its case (for paths and names).
+pub fn prompt_text(message: &str) -> Result<String> {
+    if is_stdout_reserved() {
+        eprint!("{}", message);
+        io::stderr().flush()?;
+    } else {
+        print!("{}", message);
+        io::stdout().flush()?;
+    }
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+
+    Ok(input.trim().to_string())
+}
+
+/// Show a prompt and read a single keypress, without waiting for Enter when stdin is a
+/// terminal. Returns the key lowercased, or `None` for Enter or end of input.
+pub fn read_key(message: &str) -> Result<Option<char>> {
+    if is_stdout_reserved() {
+        eprint!("{}", message);
+        io::stderr().flush()?;
+    } else {
+        print!("{}", message);
+        io::stdout().flush()?;
+    }
+
+    let key = match read_raw_key()? {
+        Some(key) => {
+            // The key was not echoed; finish the prompt line
+            let shown = if key.is_ascii_graphic() {
+                key.to_string()
+            } else {
+                String::new()
+            };
+            if is_stdout_reserved() {
+                eprintln!("{}", shown);
+            } else {
+                println!("{}", shown);
+            }
+            Some(key)
+        }
+        None => {
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            input.trim().chars().next()
+        }
+    };
+
+    Ok(key
+        .filter(|key| !key.is_whitespace())
+        .map(|key| key.to_ascii_lowercase()))
+}
+
+/// Read one byte from a terminal on stdin with line buffering and echo switched off; `None` if
+/// stdin is not a terminal
+#[cfg(unix)]
+fn read_raw_key() -> Result<Option<char>> {
+    use std::io::Read;
+
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+        return Ok(None);
+    }
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+        return Ok(None);
+    }
+
+    let mut byte = [0u8; 1];
+    let read = io::stdin().read(&mut byte);
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original) };
+
+    Ok(Some(match read? {
+        0 => '\n',
+        _ => char::from(byte[0]),
+    }))
+}
+
+#[cfg(not(unix))]
+fn read_raw_key() -> Result<Option<char>> {
+    Ok(None)
+}
@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// A path on another machine in scp syntax: `[user@]host:path`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemotePath {
+    /// `host` or `user@host`
+    pub host: String,
+    /// Path on the host; relative paths are relative to the remote home directory
+    pub path: String,
+}
+
+impl RemotePath {
+    /// Parse `[user@]host:path`. Anything with a slash before the first colon is a local path,
+    /// as is a single-letter host on Windows (`C:\file`).
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (host, path) = spec.split_once(':')?;
+        if host.is_empty() || host.contains(['/', '\\']) || path.is_empty() {
+            return None;
+        }
+        if cfg!(windows) && host.len() == 1 {
+            return None;
+        }
+
+        Some(RemotePath {
+            host: host.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// Name of the remote file or directory
+    pub fn name(&self) -> Option<&str> {
+        self.path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+    }
+
+    /// Copy the remote file or directory to `dest` with `scp`, keeping modification times
+    pub fn fetch(&self, dest: &Path) -> Result<()> {
+        let status = Command::new("scp")
+            .args(["-r", "-p", "--"])
+            .arg(self.to_string())
+            .arg(dest)
+            .status()
+            .map_err(|e| anyhow!("Cannot run scp: {}", e))?;
+        if !status.success() {
+            return Err(anyhow!("scp could not copy {} ({})", self, status));
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for RemotePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_path() {
+        let remote = RemotePath::parse("me@box:/srv/logs/app.log").unwrap();
+        assert_eq!(remote.host, "me@box");
+        assert_eq!(remote.path, "/srv/logs/app.log");
+        assert_eq!(remote.name(), Some("app.log"));
+        assert_eq!(remote.to_string(), "me@box:/srv/logs/app.log");
+
+        assert_eq!(
+            RemotePath::parse("box:notes/").unwrap().name(),
+            Some("notes")
+        );
+
+        // Local paths
+        assert_eq!(RemotePath::parse("report.pdf"), None);
+        assert_eq!(RemotePath::parse("./a:b"), None);
+        assert_eq!(RemotePath::parse("/tmp/a:b"), None);
+        assert_eq!(RemotePath::parse("box:"), None);
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use std::path::Path;
+
+use crate::db::StackItem;
+
+/// Render a destination name template for an item.
+///
+/// Supported placeholders:
+/// - `{name}`: the original file name (e.g. `report.pdf`)
+/// - `{stem}`: the original name without its extension (e.g. `report`)
+/// - `{ext}`: the original extension without the dot (e.g. `pdf`)
+/// - `{date}`: the current date (`YYYY-MM-DD`)
+/// - `{time}`: the current time (`HHMMSS`)
+/// - `{pushed}`: the date the item was pushed (`YYYY-MM-DD`)
+///
+/// A template without placeholders is used as a literal name.
+pub fn render_name(template: &str, item: &StackItem) -> Result<String> {
+    let original = Path::new(&item.original_name);
+    let stem = original
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| item.original_name.clone());
+    let ext = original
+        .extension()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let now = Local::now();
+
+    let result = expand(template, "name template", true, |placeholder| {
+        Ok(match placeholder {
+            "name" => Some(item.original_name.clone()),
+            "stem" => Some(stem.clone()),
+            "ext" => Some(ext.clone()),
+            "date" => Some(now.format("%Y-%m-%d").to_string()),
+            "time" => Some(now.format("%H%M%S").to_string()),
+            "pushed" => Some(item.pushed_at.format("%Y-%m-%d").to_string()),
+            _ => None,
+        })
+    })?;
+
+    // The rendered name must stay inside the destination directory
+    if result.is_empty() || result == "." || result == ".." || result.contains('/') {
+        return Err(anyhow!("Invalid destination name: '{}'", result));
+    }
+
+    Ok(result)
+}
+
+/// What the placeholders in the tags and note given on push expand to
+#[derive(Debug, Clone, Default)]
+pub struct PushVariables {
+    /// Directory `push` was run from
+    pub cwd: String,
+    /// Branch checked out in the git repository of the pushed path
+    pub git_branch: Option<String>,
+}
+
+/// Render a tag or note given on push.
+///
+/// Supported placeholders:
+/// - `{date}`: the current date (`YYYY-MM-DD`)
+/// - `{time}`: the current time (`HHMMSS`)
+/// - `{host}`: the name of this machine
+/// - `{cwd}`: the directory `push` was run from
+/// - `{git_branch}`: the git branch of the pushed path
+///
+/// Unknown placeholders are errors in tags, but are kept as they are in notes, which may well
+/// contain braces of their own.
+pub fn render_push_text(text: &str, is_note: bool, vars: &PushVariables) -> Result<String> {
+    let now = Local::now();
+    let what = if is_note { "note" } else { "tag" };
+
+    expand(text, what, !is_note, |placeholder| {
+        Ok(match placeholder {
+            "date" => Some(now.format("%Y-%m-%d").to_string()),
+            "time" => Some(now.format("%H%M%S").to_string()),
+            "host" => Some(hostname()),
+            "cwd" => Some(vars.cwd.clone()),
+            "git_branch" => Some(vars.git_branch.clone().ok_or_else(|| {
+                anyhow!(
+                    "{{git_branch}} in a {} needs a git repository with a checked-out branch",
+                    what
+                )
+            })?),
+            _ => None,
+        })
+    })
+}
+
+/// Replace every `{placeholder}` in `template` with its value. `value` returns `None` for
+/// unknown placeholders, which are errors if `strict` and left in place otherwise.
+fn expand<F>(template: &str, what: &str, strict: bool, value: F) -> Result<String>
+where
+    F: Fn(&str) -> Result<Option<String>>,
+{
+    let mut result = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+
+        let Some(end) = rest[start..].find('}').map(|offset| start + offset) else {
+            if strict {
+                return Err(anyhow!("Unclosed placeholder in {}: {}", what, template));
+            }
+            result.push_str(&rest[start..]);
+            return Ok(result);
+        };
+
+        match value(&rest[start + 1..end])? {
+            Some(value) => result.push_str(&value),
+            None if strict => {
+                return Err(anyhow!(
+                    "Unknown placeholder in {}: {}",
+                    what,
+                    &rest[start..=end]
+                ))
+            }
+            None => result.push_str(&rest[start..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+/// Name of this machine
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buffer = [0u8; 256];
+        if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } == 0 {
+            let len = buffer.iter().position(|&byte| byte == 0).unwrap_or(0);
+            return String::from_utf8_lossy(&buffer[..len]).to_string();
+        }
+    }
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "localhost".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_item(name: &str) -> StackItem {
+        StackItem {
+            id: 1,
+            original_name: name.to_string(),
+            original_path: "/path/to".to_string(),
+            stored_hash: "abcdef1234567890".to_string(),
+            item_type: "file".to_string(),
+            pushed_at: Local::now(),
+            tags: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_literal_name() {
+        let item = create_test_item("report.pdf");
+        assert_eq!(render_name("final.pdf", &item).unwrap(), "final.pdf");
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let item = create_test_item("report.pdf");
+        let today = Local::now().format("%Y-%m-%d").to_string();
+
+        assert_eq!(
+            render_name("{name}.{date}", &item).unwrap(),
+            format!("report.pdf.{}", today)
+        );
+        assert_eq!(
+            render_name("{stem}-old.{ext}", &item).unwrap(),
+            "report-old.pdf"
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let item = create_test_item("report.pdf");
+        assert!(render_name("{unknown}", &item).is_err());
+        assert!(render_name("{name", &item).is_err());
+        assert!(render_name("sub/{name}", &item).is_err());
+        assert!(render_name("..", &item).is_err());
+    }
+
+    #[test]
+    fn test_push_text() {
+        let vars = PushVariables {
+            cwd: "/home/me".to_string(),
+            git_branch: Some("main".to_string()),
+        };
+        let today = Local::now().format("%Y-%m-%d").to_string();
+
+        assert_eq!(
+            render_push_text("backup-{date}", false, &vars).unwrap(),
+            format!("backup-{}", today)
+        );
+        assert_eq!(
+            render_push_text("{git_branch}@{cwd}", false, &vars).unwrap(),
+            "main@/home/me"
+        );
+        assert!(render_push_text("{dat}", false, &vars).is_err());
+        assert!(render_push_text("{git_branch}", false, &PushVariables::default()).is_err());
+
+        // Notes keep braces that are not placeholders
+        assert_eq!(
+            render_push_text("fn main() {} on {host}", true, &vars).unwrap(),
+            format!("fn main() {{}} on {}", hostname())
+        );
+    }
+}
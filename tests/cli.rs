@@ -0,0 +1,153 @@
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::tempdir;
+
+/// Run fstk in `cwd` with `home` as the home directory, so the stack lives in a temporary place
+fn run(home: &Path, cwd: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fstk"))
+        .args(args)
+        .current_dir(cwd)
+        .env("HOME", home)
+        .output()
+        .expect("failed to run fstk")
+}
+
+/// `run`, expecting fstk to succeed
+fn fstk(home: &Path, cwd: &Path, args: &[&str]) -> Output {
+    let output = run(home, cwd, args);
+    assert!(
+        output.status.success(),
+        "fstk {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn test_print_path_keeps_stdout_for_the_destination() {
+    let dir = tempdir().unwrap();
+    let home = dir.path().join("home");
+    let work = dir.path().join("work");
+    let out = dir.path().join("out");
+    for path in [&home, &work, &out] {
+        std::fs::create_dir(path).unwrap();
+    }
+    std::fs::write(work.join("notes.txt"), "hello").unwrap();
+
+    fstk(&home, &work, &["push", "notes.txt"]);
+    let popped = fstk(&home, &out, &["pop", "--print-path"]);
+    let dest = out.join("notes.txt");
+    assert_eq!(
+        String::from_utf8_lossy(&popped.stdout),
+        format!("{}\n", dest.display())
+    );
+    assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello");
+
+    // Status messages go to stderr
+    std::fs::rename(&dest, work.join("notes.txt")).unwrap();
+    fstk(&home, &work, &["push", "notes.txt"]);
+    let restored = fstk(&home, &out, &["restore", "--keep", "--print-path"]);
+    assert_eq!(
+        String::from_utf8_lossy(&restored.stdout),
+        format!("{}\n", work.join("notes.txt").display())
+    );
+    assert!(String::from_utf8_lossy(&restored.stderr).contains("kept on the stack"));
+}
+
+#[test]
+fn test_pop_to_pushdir() {
+    let dir = tempdir().unwrap();
+    let home = dir.path().join("home");
+    let work = dir.path().join("work");
+    let out = dir.path().join("out");
+    for path in [&home, &work.join("docs"), &out] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    std::fs::write(work.join("docs/notes.txt"), "hello").unwrap();
+
+    // The item goes back to where push was run, not to the directory it came from
+    fstk(&home, &work, &["push", "docs/notes.txt"]);
+    fstk(&home, &out, &["pop", "--to-pushdir"]);
+    assert_eq!(
+        std::fs::read_to_string(work.join("notes.txt")).unwrap(),
+        "hello"
+    );
+    assert!(!work.join("docs/notes.txt").exists());
+    assert!(!out.join("notes.txt").exists());
+}
+
+#[test]
+fn test_pop_destination_setting_and_overrides() {
+    let dir = tempdir().unwrap();
+    let home = dir.path().join("home");
+    let work = dir.path().join("work");
+    let out = dir.path().join("out");
+    let inbox = dir.path().join("inbox");
+    for path in [&home.join(".fstk"), &work, &out, &inbox] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    let push = |name: &str| {
+        std::fs::write(work.join(name), name).unwrap();
+        fstk(&home, &work, &["push", name]);
+    };
+
+    // By default a bare pop uses the current directory; --to-original overrides it
+    push("a.txt");
+    fstk(&home, &out, &["pop", "--to-original"]);
+    assert!(work.join("a.txt").exists());
+
+    // With pop_destination = "original", a bare pop restores and --here pops into the current
+    // directory, even when pop_output_dir is set
+    std::fs::write(
+        home.join(".fstk/config.toml"),
+        format!(
+            "pop_destination = \"original\"\npop_output_dir = \"{}\"\n",
+            inbox.display()
+        ),
+    )
+    .unwrap();
+    push("b.txt");
+    fstk(&home, &out, &["pop"]);
+    assert!(work.join("b.txt").exists());
+    push("c.txt");
+    fstk(&home, &out, &["pop", "--here"]);
+    assert!(out.join("c.txt").exists());
+    assert_eq!(std::fs::read_dir(&inbox).unwrap().count(), 0);
+}
+
+#[test]
+fn test_hooks_guard_every_pop_and_restore() {
+    let dir = tempdir().unwrap();
+    let home = dir.path().join("home");
+    let work = dir.path().join("work");
+    let out = dir.path().join("out");
+    for path in [
+        &home.join(".fstk"),
+        &work.join("project"),
+        &out.join("project"),
+    ] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    std::fs::write(work.join("project/a.txt"), "a").unwrap();
+    fstk(&home, &work, &["push", "project"]);
+    std::fs::write(
+        home.join(".fstk/hooks.rhai"),
+        "fn before_pop(item) { throw \"not now\"; }\n\
+         fn before_restore(item) { throw \"not now\"; }\n",
+    )
+    .unwrap();
+
+    for args in [
+        &["pop", "--path", "a.txt"][..],
+        &["pop", "--merge"],
+        &["restore", "--keep"],
+    ] {
+        let output = run(&home, &out, args);
+        assert!(!output.status.success(), "{}", args.join(" "));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("not now"));
+    }
+    assert!(!out.join("a.txt").exists());
+    assert!(!out.join("project/a.txt").exists());
+    assert!(!work.join("project").exists());
+}